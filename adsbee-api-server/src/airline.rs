@@ -0,0 +1,113 @@
+//! Airline lookup by ICAO designator.
+//!
+//! The table can be loaded from a local CSV file with the header
+//! `icao,name,callsign,country`, e.g.:
+//!
+//! ```plain
+//! icao,name,callsign,country
+//! DLH,Lufthansa,LUFTHANSA,Germany
+//! EZY,easyJet,EASY,United Kingdom
+//! ```
+//!
+//! `callsign` (the telephony designator) and `country` may be empty.
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    fs::File,
+    io::{
+        BufReader,
+        Read,
+    },
+    path::Path,
+};
+
+use adsbee_types::AirlineDesignator;
+use serde::Deserialize;
+
+use crate::Error;
+
+#[derive(Clone, Debug)]
+pub struct Airline {
+    pub designator: AirlineDesignator,
+    pub name: String,
+    /// Telephony designator, e.g. `SPEEDBIRD` for `BAW`
+    pub telephony: Option<String>,
+    pub country: Option<String>,
+}
+
+/// Something that can resolve airline designators.
+///
+/// This is implemented by [`AirlineTable`], but can be implemented for other
+/// sources as well.
+pub trait AirlineLookup: Debug + Send + Sync + 'static {
+    fn get(&self, designator: &AirlineDesignator) -> Option<&Airline>;
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AirlineTable {
+    airlines: HashMap<AirlineDesignator, Airline>,
+}
+
+impl AirlineTable {
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        tracing::info!(path = %path.display(), "loading airline table");
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, Error> {
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            icao: String,
+            name: String,
+            callsign: Option<String>,
+            country: Option<String>,
+        }
+
+        let mut table = Self::default();
+
+        let reader = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(reader);
+
+        for result in reader.into_deserialize::<Row>() {
+            let row = result?;
+
+            let Ok(designator) = row.icao.trim().parse()
+            else {
+                tracing::debug!(icao = %row.icao, "skipping airline with invalid designator");
+                continue;
+            };
+
+            let non_empty = |s: Option<String>| s.filter(|s| !s.is_empty());
+
+            table.insert(Airline {
+                designator,
+                name: row.name,
+                telephony: non_empty(row.callsign),
+                country: non_empty(row.country),
+            });
+        }
+
+        Ok(table)
+    }
+
+    pub fn insert(&mut self, airline: Airline) {
+        self.airlines.insert(airline.designator, airline);
+    }
+
+    pub fn len(&self) -> usize {
+        self.airlines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.airlines.is_empty()
+    }
+}
+
+impl AirlineLookup for AirlineTable {
+    fn get(&self, designator: &AirlineDesignator) -> Option<&Airline> {
+        self.airlines.get(designator)
+    }
+}
@@ -5,6 +5,7 @@ use adsbee_api_types::flights::{
     SearchResults,
 };
 use adsbee_types::{
+    Callsign,
     IcaoAddress,
     Squawk,
 };
@@ -21,9 +22,12 @@ use chrono::{
 };
 use futures_util::TryStreamExt;
 
-use crate::api::{
-    Api,
-    ApiError,
+use crate::{
+    api::{
+        Api,
+        ApiError,
+    },
+    metrics::QueryTimer,
 };
 
 pub async fn get_search_flights(
@@ -45,6 +49,20 @@ async fn search_impl(api: Api, query: PostSearchQuery) -> Result<SearchResults,
     if query.area.is_empty() {
         let mut transaction = api.database.transaction().await?;
 
+        // callsigns are stored without padding
+        let callsigns = query
+            .aircraft
+            .callsign
+            .iter()
+            .map(|callsign| callsign.trimmed().to_owned())
+            .collect::<Vec<_>>();
+        let airlines = query
+            .aircraft
+            .airline
+            .iter()
+            .map(|airline| airline.as_str().to_owned())
+            .collect::<Vec<_>>();
+
         #[derive(Debug)]
         struct Row {
             time: DateTime<Utc>,
@@ -53,6 +71,7 @@ async fn search_impl(api: Api, query: PostSearchQuery) -> Result<SearchResults,
             squawk: Option<Squawk>,
         }
 
+        let _timer = QueryTimer::start("search_flights");
         let mut stream = sqlx::query_as_unchecked!(
             Row,
             r#"
@@ -70,24 +89,41 @@ async fn search_impl(api: Api, query: PostSearchQuery) -> Result<SearchResults,
                     and (
                         icao_address = any($3) or array_length($3, 1) = 0
                         or callsign = any($4) or array_length($4, 1) = 0
+                        or (
+                            left(callsign, 3) = any($6)
+                            and substr(callsign, 4, 1) between '0' and '9'
+                        )
+                        or array_length($6, 1) = 0
                         or squawk = any($5) or array_length($5, 1) = 0
                     )
             "#,
             query.time.start,
             query.time.end,
             query.aircraft.icao,
-            query.aircraft.callsign,
-            query.aircraft.squawk
+            callsigns,
+            query.aircraft.squawk,
+            airlines
         )
         .fetch(&mut *transaction);
 
         let mut results = vec![];
 
         while let Some(row) = stream.try_next().await? {
+            let flight = row
+                .callsign
+                .as_deref()
+                .and_then(|callsign| callsign.parse::<Callsign>().ok())
+                .and_then(|callsign| callsign.flight_designator());
+            let airline_name = flight
+                .and_then(|flight| api.airlines.get(&flight.airline))
+                .map(|airline| airline.name.clone());
+
             results.push(SearchResult {
                 time: row.time,
                 icao: row.icao_address,
                 callsign: row.callsign,
+                flight,
+                airline_name,
                 squawk: row.squawk,
             });
         }
@@ -20,7 +20,10 @@ use serde::{
 };
 use tokio::sync::mpsc;
 
-use crate::api::Api;
+use crate::{
+    api::Api,
+    metrics,
+};
 
 pub async fn get_live(State(api): State<Api>, upgrade: WebSocketUpgrade) -> impl IntoResponse {
     // todo: add query with options (format, compression)
@@ -56,6 +59,8 @@ impl WebSocketHandler {
     }
 
     async fn run(mut self) {
+        let _client_guard = metrics::WebsocketClientGuard::connected();
+
         loop {
             tokio::select! {
                             _ = self.api.shutdown.cancelled() => {
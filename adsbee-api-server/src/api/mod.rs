@@ -1,11 +1,15 @@
 pub mod flights;
 pub mod live;
+pub mod tar1090;
+pub mod vrs;
 
 use std::sync::Arc;
 
 use axum::{
     Json,
     Router,
+    extract::State,
+    http::header,
     response::{
         IntoResponse,
         Response,
@@ -21,7 +25,12 @@ use tokio::net::{
 use tokio_util::sync::CancellationToken;
 
 use crate::{
+    airline::{
+        AirlineLookup,
+        AirlineTable,
+    },
     database::Database,
+    metrics::Metrics,
     tracker::Tracker,
     util::AtomicIdGenerator,
 };
@@ -46,6 +55,8 @@ pub struct Api {
     pub shutdown: CancellationToken,
     pub client_ids: Arc<AtomicIdGenerator>,
     pub config: Arc<Config>,
+    pub airlines: Arc<dyn AirlineLookup>,
+    pub metrics: Option<Metrics>,
 }
 
 impl Api {
@@ -56,9 +67,23 @@ impl Api {
             shutdown: CancellationToken::new(),
             client_ids: Default::default(),
             config: Arc::new(config),
+            airlines: Arc::new(AirlineTable::default()),
+            metrics: None,
         }
     }
 
+    /// Use this airline table to resolve airline designators.
+    pub fn with_airlines(mut self, airlines: impl AirlineLookup) -> Self {
+        self.airlines = Arc::new(airlines);
+        self
+    }
+
+    /// Serve these metrics at `/metrics`.
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
     pub fn router(&self) -> Router<()> {
         Router::new()
             .nest(
@@ -68,6 +93,23 @@ impl Api {
                     .route("flights", routing::post(flights::post_search_flights))
                     .route("live", routing::get(live::get_live)),
             )
+            .route("/metrics", routing::get(get_metrics))
+            .route(
+                "/data/aircraft.json",
+                routing::get(tar1090::get_aircraft_json),
+            )
+            .route(
+                "/data/receiver.json",
+                routing::get(tar1090::get_receiver_json),
+            )
+            .route(
+                "/data/traces/{directory}/{file_name}",
+                routing::get(tar1090::get_trace),
+            )
+            .route(
+                "/VirtualRadar/AircraftList.json",
+                routing::get(vrs::get_aircraft_list),
+            )
             .fallback(routing::get(not_found))
             .with_state(self.clone())
     }
@@ -90,6 +132,20 @@ async fn not_found() -> impl IntoResponse {
     (StatusCode::NOT_FOUND, "not found")
 }
 
+/// Prometheus text exposition of all metrics
+async fn get_metrics(State(api): State<Api>) -> Response {
+    if let Some(metrics) = &api.metrics {
+        (
+            [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+            metrics.render(),
+        )
+            .into_response()
+    }
+    else {
+        not_found().await.into_response()
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
     error: ErrorResponseInner,
@@ -0,0 +1,610 @@
+//! tar1090 compatible endpoints
+//!
+//! Serves the files an unmodified tar1090 web UI fetches from readsb, in
+//! readsb's format:
+//!
+//! - `data/aircraft.json`: all aircraft in the tracker's state
+//! - `data/receiver.json`: what tar1090 needs to know about the "receiver"
+//! - `data/traces/<xx>/trace_recent_<hex>.json` and `trace_full_<hex>.json`: an
+//!   aircraft's trail, in the same format as the archived traces we index in
+//!   [`source::history`][crate::source::history]. Positions aren't stored, so
+//!   the full trace is partial: it only goes back as far as the trail kept in
+//!   memory (see [`TRAIL_MAX_AGE`][crate::tracker::state::TRAIL_MAX_AGE]).
+//!
+//! - [readsb documentation][1]
+//!
+//! [1]: https://github.com/wiedehopf/readsb/blob/75decb53c0e66f4c12cf24127578a3fe7d919219/README-json.md
+
+use adsbee_mode_s::{
+    VerticalStatus,
+    adsb::{
+        EmergencyPriorityStatus,
+        WakeVortexCategory,
+    },
+};
+use adsbee_types::{
+    IcaoAddress,
+    Squawk,
+};
+use axum::{
+    Json,
+    extract::{
+        Path,
+        State,
+    },
+    response::{
+        IntoResponse,
+        Response,
+    },
+};
+use chrono::{
+    DateTime,
+    TimeDelta,
+    Utc,
+};
+use serde::Serialize;
+
+use crate::{
+    api::{
+        Api,
+        ApiError,
+        not_found,
+    },
+    database::AircraftInfo,
+    source::history::{
+        self,
+        Altitude,
+        TraceFile,
+        TracePoint,
+    },
+    tracker::state::{
+        AircraftState,
+        PositionSource,
+        State as TrackerState,
+        TrailPoint,
+    },
+};
+
+/// Interval in milliseconds at which tar1090 fetches `aircraft.json`.
+const REFRESH_INTERVAL: u32 = 1000;
+
+/// Recent traces contain the positions of this last time span. tar1090 merges
+/// them into the full trace it loaded before.
+const TRACE_RECENT: TimeDelta = TimeDelta::minutes(2);
+
+/// Aircraft that weren't seen for this long are left out of `aircraft.json`,
+/// like readsb does.
+const AIRCRAFT_TIMEOUT: TimeDelta = TimeDelta::seconds(300);
+
+/// `dbFlags` bit for military aircraft
+const DB_FLAG_MILITARY: u32 = 1;
+
+/// readsb's `aircraft.json`
+#[derive(Debug, Serialize)]
+pub struct AircraftJson {
+    /// Time the file was generated, in seconds since the Unix epoch
+    pub now: f64,
+    pub aircraft: Vec<Aircraft>,
+}
+
+/// An aircraft in `aircraft.json`.
+#[derive(Debug, Serialize)]
+pub struct Aircraft {
+    pub hex: IcaoAddress,
+    /// Type of the best source of messages, e.g. `adsb_icao` or `mlat`
+    #[serde(rename = "type")]
+    pub source_type: &'static str,
+    /// Callsign, padded with spaces
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flight: Option<String>,
+    /// Barometric altitude in ft, or `"ground"`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alt_baro: Option<Altitude>,
+    /// Geometric altitude in ft
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alt_geom: Option<i32>,
+    /// Ground speed in kt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gs: Option<f64>,
+    /// Indicated airspeed in kt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ias: Option<f64>,
+    /// True airspeed in kt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tas: Option<f64>,
+    /// True track over ground in degrees
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub track: Option<f64>,
+    /// Magnetic heading in degrees
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mag_heading: Option<f64>,
+    /// True heading in degrees
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub true_heading: Option<f64>,
+    /// Barometric vertical rate in ft/min
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baro_rate: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub squawk: Option<Squawk>,
+    /// Emergency status, e.g. `none` or `general`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub emergency: Option<&'static str>,
+    /// Emitter category, e.g. `A3`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lat: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lon: Option<f64>,
+    /// Seconds since the position was last updated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seen_pos: Option<f64>,
+    /// Fields derived from MLAT
+    pub mlat: Vec<&'static str>,
+    /// Fields derived from TIS-B
+    pub tisb: Vec<&'static str>,
+    /// Seconds since any message was last received
+    pub seen: f64,
+}
+
+/// readsb's `receiver.json`
+#[derive(Debug, Serialize)]
+pub struct ReceiverJson {
+    pub version: String,
+    /// Interval in milliseconds at which `aircraft.json` is updated
+    pub refresh: u32,
+    /// Number of `history_<n>.json` files. We don't write any.
+    pub history: u32,
+    /// Tells tar1090 to expect readsb's fields
+    pub readsb: bool,
+}
+
+pub async fn get_aircraft_json(State(api): State<Api>) -> Result<Json<AircraftJson>, ApiError> {
+    let time = Utc::now();
+    let aircraft_json = api
+        .tracker
+        .with_state(move |state| aircraft_json(state, time))
+        .await
+        .ok_or(ApiError::InternalServerError)?;
+    Ok(Json(aircraft_json))
+}
+
+pub async fn get_receiver_json() -> Json<ReceiverJson> {
+    Json(ReceiverJson {
+        version: format!("adsbee {}", env!("CARGO_PKG_VERSION")),
+        refresh: REFRESH_INTERVAL,
+        history: 0,
+        readsb: true,
+    })
+}
+
+/// Serves `trace_recent_<hex>.json` and `trace_full_<hex>.json`. The
+/// directory tar1090 puts in front of the file name is ignored.
+pub async fn get_trace(
+    State(api): State<Api>,
+    Path((_directory, file_name)): Path<(String, String)>,
+) -> Result<Response, ApiError> {
+    let Some((recent, icao_address)) = parse_trace_file_name(&file_name)
+    else {
+        return Ok(not_found().await.into_response());
+    };
+
+    let time = Utc::now();
+    let since = recent.then(|| time - TRACE_RECENT);
+    let Some(Some(mut trace)) = api
+        .tracker
+        .with_state(move |state| {
+            state
+                .get_aircraft(&icao_address)
+                .map(|aircraft| trace_file(aircraft, since, time))
+        })
+        .await
+    else {
+        return Ok(not_found().await.into_response());
+    };
+
+    if !icao_address.non_icao() {
+        let mut transaction = api.database.transaction().await?;
+        let info = transaction.get_aircraft_info(&[icao_address]).await?;
+        transaction.commit().await?;
+        if let Some(info) = info.get(&icao_address) {
+            set_aircraft_info(&mut trace, info);
+        }
+    }
+
+    Ok(Json(trace).into_response())
+}
+
+/// Parses `trace_recent_<hex>.json` or `trace_full_<hex>.json`, and returns
+/// whether it's a recent trace, and the address.
+fn parse_trace_file_name(file_name: &str) -> Option<(bool, IcaoAddress)> {
+    let file_name = file_name.strip_suffix(".json")?;
+    let (recent, hex) = if let Some(hex) = file_name.strip_prefix("trace_recent_") {
+        (true, hex)
+    }
+    else {
+        (false, file_name.strip_prefix("trace_full_")?)
+    };
+    Some((recent, hex.parse().ok()?))
+}
+
+/// Generates `aircraft.json` from the tracker's state, without aircraft that
+/// weren't seen for [`AIRCRAFT_TIMEOUT`].
+pub fn aircraft_json(state: &TrackerState, time: DateTime<Utc>) -> AircraftJson {
+    AircraftJson {
+        now: seconds(time),
+        aircraft: state
+            .iter_aircraft()
+            .filter(|aircraft| {
+                time.signed_duration_since(aircraft.last_seen.last_update) <= AIRCRAFT_TIMEOUT
+            })
+            .map(|aircraft| json_aircraft(aircraft, time))
+            .collect(),
+    }
+}
+
+fn json_aircraft(aircraft: &AircraftState, time: DateTime<Utc>) -> Aircraft {
+    let position = aircraft.position.as_ref();
+    let is_mlat = position.is_some_and(|position| position.value.source == PositionSource::Mlat);
+    let degrees = |radians: f64| radians.to_degrees().rem_euclid(360.0);
+
+    Aircraft {
+        hex: aircraft.icao_address,
+        source_type: source_type(
+            position.map(|position| position.value.source),
+            aircraft.icao_address.non_icao(),
+        ),
+        flight: aircraft
+            .callsign
+            .as_ref()
+            .map(|callsign| callsign.value.as_str().to_owned()),
+        alt_baro: altitude(
+            aircraft
+                .altitude_barometric
+                .as_ref()
+                .map(|altitude| altitude.value),
+            aircraft.vertical_status,
+        ),
+        alt_geom: aircraft
+            .altitude_gnss
+            .as_ref()
+            .map(|altitude| altitude.value),
+        gs: aircraft
+            .ground_speed
+            .as_ref()
+            .map(|ground_speed| ground_speed.value),
+        ias: aircraft
+            .indicated_airspeed
+            .as_ref()
+            .map(|airspeed| airspeed.value),
+        tas: aircraft
+            .true_airspeed
+            .as_ref()
+            .map(|airspeed| airspeed.value),
+        track: aircraft.track.as_ref().map(|track| degrees(track.value)),
+        mag_heading: aircraft
+            .magnetic_heading
+            .as_ref()
+            .map(|heading| degrees(heading.value)),
+        true_heading: aircraft
+            .true_heading
+            .as_ref()
+            .map(|heading| degrees(heading.value)),
+        baro_rate: aircraft
+            .vertical_rate
+            .as_ref()
+            .map(|vertical_rate| vertical_rate.value),
+        squawk: aircraft.squawk.as_ref().map(|squawk| squawk.value),
+        emergency: aircraft.squawk.as_ref().map(|squawk| {
+            emergency_name(
+                EmergencyPriorityStatus::from_squawk(squawk.value)
+                    .unwrap_or(EmergencyPriorityStatus::NO_EMERGENCY),
+            )
+        }),
+        category: aircraft.wake_vortex_category.and_then(category),
+        lat: position.map(|position| position.value.latitude),
+        lon: position.map(|position| position.value.longitude),
+        seen_pos: position.map(|position| seconds_since(position.last_update, time)),
+        mlat: if is_mlat { vec!["lat", "lon"] } else { vec![] },
+        tisb: vec![],
+        seen: seconds_since(aircraft.last_seen.last_update, time),
+    }
+}
+
+/// Generates a trace from the aircraft's trail, without the values from the
+/// aircraft database. If `since` is set, only positions after it are
+/// included.
+///
+/// Without `since` this is not the aircraft's full trace, only the positions
+/// of the last [`TRAIL_MAX_AGE`][crate::tracker::state::TRAIL_MAX_AGE].
+pub fn trace_file(
+    aircraft: &AircraftState,
+    since: Option<DateTime<Utc>>,
+    time: DateTime<Utc>,
+) -> TraceFile {
+    let points = aircraft
+        .trail
+        .iter()
+        .filter(|point| since.is_none_or(|since| point.time >= since))
+        .collect::<Vec<_>>();
+    let timestamp = points.first().map_or(time, |point| point.time);
+
+    let mut trace = points
+        .iter()
+        .map(|point| trace_point(point, timestamp, aircraft.icao_address.non_icao()))
+        .collect::<Vec<_>>();
+
+    // readsb includes the aircraft's details in some of the points
+    if let Some(last) = trace.last_mut() {
+        last.aircraft = Some(history::Aircraft {
+            squawk: aircraft
+                .squawk
+                .as_ref()
+                .map(|squawk| squawk.value.to_string()),
+            flight: aircraft
+                .callsign
+                .as_ref()
+                .map(|callsign| callsign.value.as_str().to_owned()),
+            ..Default::default()
+        });
+    }
+
+    TraceFile {
+        icao: aircraft.icao_address.to_string(),
+        r: None,
+        t: None,
+        db_flags: 0,
+        desc: None,
+        own_op: None,
+        year: None,
+        version: None,
+        timestamp: seconds(timestamp),
+        trace,
+    }
+}
+
+/// Sets the values from the aircraft database.
+pub fn set_aircraft_info(trace: &mut TraceFile, info: &AircraftInfo) {
+    trace.r = info.registration.clone();
+    trace.t = info.model.clone();
+    trace.desc = info.model_name.clone();
+    if info.military {
+        trace.db_flags |= DB_FLAG_MILITARY;
+    }
+}
+
+fn trace_point(point: &TrailPoint, timestamp: DateTime<Utc>, non_icao: bool) -> TracePoint {
+    TracePoint {
+        dt: seconds_since(timestamp, point.time) as f32,
+        lat: point.position.latitude as f32,
+        long: point.position.longitude as f32,
+        altitude: altitude(point.altitude_barometric, point.vertical_status),
+        ground_speed: point.ground_speed.map(|ground_speed| ground_speed as f32),
+        track: point
+            .track
+            .map(|track| track.to_degrees().rem_euclid(360.0) as f32),
+        db_flags: 0,
+        vertical_rate: point.vertical_rate,
+        aircraft: None,
+        source: Some(source_type(Some(point.position.source), non_icao).to_owned()),
+        geo_alt: point.altitude_gnss,
+        geo_vr: None,
+        ind_airspeed: None,
+        roll_angle: None,
+    }
+}
+
+fn altitude(altitude: Option<i32>, vertical_status: Option<VerticalStatus>) -> Option<Altitude> {
+    if vertical_status == Some(VerticalStatus::Ground) {
+        Some(Altitude::Ground)
+    }
+    else {
+        altitude.map(|altitude| Altitude::Value(altitude as f32))
+    }
+}
+
+/// readsb's name for the source of a position. Addresses that aren't ICAO
+/// addresses can only be received from ADS-B (or TIS-B and ADS-R, which we
+/// don't distinguish).
+fn source_type(source: Option<PositionSource>, non_icao: bool) -> &'static str {
+    match source {
+        Some(PositionSource::Gnss) if non_icao => "adsb_other",
+        Some(PositionSource::Gnss) => "adsb_icao",
+        Some(PositionSource::Mlat) => "mlat",
+        Some(PositionSource::Sbs | PositionSource::AircraftJson) => "other",
+        None => "mode_s",
+    }
+}
+
+/// readsb's name for an emergency status
+fn emergency_name(status: EmergencyPriorityStatus) -> &'static str {
+    match status {
+        EmergencyPriorityStatus::NO_EMERGENCY => "none",
+        EmergencyPriorityStatus::GENERAL_EMERGENCY => "general",
+        EmergencyPriorityStatus::LIFEGUARD_MEDICAL_EMERGENCY => "lifeguard",
+        EmergencyPriorityStatus::MINIMAL_FUEL => "minfuel",
+        EmergencyPriorityStatus::NO_COMMUNICATIONS => "nordo",
+        EmergencyPriorityStatus::UNLAWFUL_INTERFERENCE => "unlawful",
+        EmergencyPriorityStatus::DOWNED_AIRCRAFT => "downed",
+        _ => "reserved",
+    }
+}
+
+/// readsb's name for an emitter category, e.g. `A3`. Sets A to D are type
+/// codes 4 to 1.
+fn category(wake_vortex_category: WakeVortexCategory) -> Option<String> {
+    let (type_code, category) = wake_vortex_category.type_code_and_category();
+    let set = match type_code {
+        4 => 'A',
+        3 => 'B',
+        2 => 'C',
+        1 => 'D',
+        _ => return None,
+    };
+    Some(format!("{set}{category}"))
+}
+
+fn seconds(time: DateTime<Utc>) -> f64 {
+    time.timestamp_millis() as f64 / 1000.0
+}
+
+fn seconds_since(earlier: DateTime<Utc>, time: DateTime<Utc>) -> f64 {
+    time.signed_duration_since(earlier).num_milliseconds() as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use adsbee_types::IcaoAddress;
+    use chrono::{
+        DateTime,
+        TimeDelta,
+    };
+    use serde_json::json;
+
+    use crate::{
+        api::tar1090::{
+            aircraft_json,
+            parse_trace_file_name,
+            source_type,
+            trace_file,
+        },
+        source::{
+            aircraft_json::AircraftJson,
+            history::TraceFile,
+        },
+        tracker::state::{
+            PositionSource,
+            State,
+            fixtures,
+        },
+        util::json::json_decode,
+    };
+
+    fn update(state: &mut State, now: i64, latitude: f64) {
+        fixtures::update(
+            state,
+            DateTime::from_timestamp(now, 0).unwrap(),
+            [fixtures::aircraft(json!({
+                "lat": latitude,
+                "alt_geom": 37450,
+                "baro_rate": -64,
+            }))],
+        );
+    }
+
+    #[test]
+    fn it_generates_aircraft_json() {
+        let mut state = State::default();
+        update(&mut state, 1750000000, 51.47);
+        let time = DateTime::from_timestamp(1750000001, 0).unwrap();
+
+        let json = serde_json::to_value(aircraft_json(&state, time)).unwrap();
+        assert_eq!(json["now"], 1750000001.0);
+        let aircraft = &json["aircraft"][0];
+        assert_eq!(aircraft["hex"], "4ca7b5");
+        assert_eq!(aircraft["type"], "other");
+        assert_eq!(aircraft["flight"], "RYR4WJ  ");
+        assert_eq!(aircraft["alt_baro"], 37000);
+        assert_eq!(aircraft["squawk"], "1000");
+        assert_eq!(aircraft["lat"], 51.47);
+        assert_eq!(aircraft["seen_pos"], 1.0);
+        assert_eq!(aircraft["mlat"], json!([]));
+        assert_eq!(aircraft["baro_rate"], -64);
+        assert_eq!(aircraft["emergency"], "none");
+
+        // tar1090 reads it the way we read readsb's
+        let decoded: AircraftJson = json_decode(json.to_string()).unwrap();
+        assert_eq!(decoded.aircraft[0].gs, Some(450.0));
+    }
+
+    #[test]
+    fn it_generates_airspeeds_category_and_emergency() {
+        let mut state = State::default();
+        fixtures::update(
+            &mut state,
+            DateTime::from_timestamp(1750000000, 0).unwrap(),
+            [fixtures::aircraft(json!({
+                "squawk": "7700",
+                "ias": 280.0,
+                "tas": 460.0,
+                "category": "A3",
+            }))],
+        );
+        let time = DateTime::from_timestamp(1750000001, 0).unwrap();
+
+        let json = serde_json::to_value(aircraft_json(&state, time)).unwrap();
+        let aircraft = &json["aircraft"][0];
+        assert_eq!(aircraft["ias"], 280.0);
+        assert_eq!(aircraft["tas"], 460.0);
+        assert_eq!(aircraft["category"], "A3");
+        assert_eq!(aircraft["emergency"], "general");
+    }
+
+    #[test]
+    fn it_leaves_out_aircraft_that_werent_seen() {
+        let mut state = State::default();
+        update(&mut state, 1750000000, 51.47);
+        fixtures::update(
+            &mut state,
+            DateTime::from_timestamp(1750000200, 0).unwrap(),
+            [fixtures::aircraft(json!({"hex": "400f01"}))],
+        );
+        let time = DateTime::from_timestamp(1750000301, 0).unwrap();
+
+        let json = serde_json::to_value(aircraft_json(&state, time)).unwrap();
+        assert_eq!(json["aircraft"].as_array().unwrap().len(), 1);
+        assert_eq!(json["aircraft"][0]["hex"], "400f01");
+    }
+
+    #[test]
+    fn it_marks_non_icao_adsb_sources() {
+        assert_eq!(source_type(Some(PositionSource::Gnss), false), "adsb_icao");
+        assert_eq!(source_type(Some(PositionSource::Gnss), true), "adsb_other");
+        assert_eq!(source_type(Some(PositionSource::Mlat), true), "mlat");
+    }
+
+    #[test]
+    fn it_generates_traces() {
+        let mut state = State::default();
+        update(&mut state, 1750000000, 51.47);
+        update(&mut state, 1750000010, 51.48);
+        update(&mut state, 1750000200, 51.49);
+        let time = DateTime::from_timestamp(1750000201, 0).unwrap();
+        let aircraft = state
+            .get_aircraft(&IcaoAddress::from_u32_unchecked(0x4ca7b5))
+            .unwrap();
+
+        let full = trace_file(aircraft, None, time);
+        assert_eq!(full.trace.len(), 3);
+        assert_eq!(full.timestamp, 1750000000.0);
+
+        // it's the format we parse in source::history
+        let decoded: TraceFile = json_decode(serde_json::to_string(&full).unwrap()).unwrap();
+        assert_eq!(decoded.icao, "4ca7b5");
+        assert_eq!(decoded.trace[1].dt, 10.0);
+        assert_eq!(decoded.trace[1].lat, 51.48);
+        assert_eq!(decoded.trace[1].track, Some(270.0));
+        assert_eq!(decoded.trace[1].vertical_rate, Some(-64));
+        assert_eq!(decoded.trace[1].geo_alt, Some(37450));
+        let details = decoded.trace[2].aircraft.as_ref().unwrap();
+        assert_eq!(details.flight.as_deref(), Some("RYR4WJ  "));
+
+        let recent = trace_file(aircraft, Some(time - TimeDelta::minutes(2)), time);
+        assert_eq!(recent.trace.len(), 1);
+        assert_eq!(recent.timestamp, 1750000200.0);
+    }
+
+    #[test]
+    fn it_parses_trace_file_names() {
+        let icao_address = IcaoAddress::from_u32_unchecked(0x4ca7b5);
+        assert_eq!(
+            parse_trace_file_name("trace_recent_4ca7b5.json"),
+            Some((true, icao_address))
+        );
+        assert_eq!(
+            parse_trace_file_name("trace_full_4ca7b5.json"),
+            Some((false, icao_address))
+        );
+        assert_eq!(parse_trace_file_name("trace_full_4ca7b5.json.gz"), None);
+    }
+}
@@ -0,0 +1,704 @@
+//! Virtual Radar Server compatible aircraft list
+//!
+//! Serves `/VirtualRadar/AircraftList.json` in the format VRS and its web
+//! frontends use. Clients send the `lastDv` of the previous response as `ldv`,
+//! and then only receive the values that were updated since.
+//!
+//! - [VRS aircraft list JSON](https://www.virtualradarserver.co.uk/Documentation/Formats/AircraftList.aspx)
+
+use std::collections::HashMap;
+
+use adsbee_api_types::Wtc;
+use adsbee_mode_s::VerticalStatus;
+use adsbee_types::{
+    IcaoAddress,
+    Squawk,
+    geo::LatLon,
+};
+use axum::{
+    Json,
+    extract::{
+        Query,
+        State,
+    },
+};
+use chrono::{
+    DateTime,
+    TimeDelta,
+    Utc,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use serde_with::skip_serializing_none;
+
+use crate::{
+    api::{
+        Api,
+        ApiError,
+    },
+    database::AircraftInfo,
+    tracker::state::{
+        AircraftState,
+        PositionSource,
+        State as TrackerState,
+        TrailPoint,
+    },
+};
+
+/// Length of short trails in seconds.
+pub const SHORT_TRAIL_SECONDS: i64 = 30;
+
+/// Updates are timestamped with the time a message was generated, which can be
+/// a bit before we received it. Values updated within this margin before the
+/// client's last data version are sent again.
+const CHANGE_MARGIN: TimeDelta = TimeDelta::seconds(10);
+
+/// Aircraft that weren't seen for this long are removed from the list, like
+/// VRS's default display timeout.
+pub const DISPLAY_TIMEOUT: TimeDelta = TimeDelta::seconds(30);
+
+/// VRS's ID of the only feed we have.
+const FEED_ID: i32 = 1;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AircraftListQuery {
+    /// `lastDv` of the previous response
+    pub ldv: Option<i64>,
+
+    #[serde(rename = "trFmt")]
+    pub trail_format: Option<TrailFormat>,
+
+    /// Set to `1` to send complete trails, even if `ldv` is set
+    #[serde(rename = "refreshTrails")]
+    pub refresh_trails: Option<u8>,
+
+    /// Latitude of the browser, to calculate distance and bearing
+    pub lat: Option<f64>,
+
+    /// Longitude of the browser, to calculate distance and bearing
+    pub lng: Option<f64>,
+}
+
+impl AircraftListQuery {
+    fn since(&self) -> Option<DateTime<Utc>> {
+        self.ldv.and_then(DateTime::from_timestamp_millis)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum TrailFormat {
+    #[serde(rename = "s")]
+    Short,
+    #[serde(rename = "sa")]
+    ShortWithAltitude,
+    #[serde(rename = "ss")]
+    ShortWithSpeed,
+    #[serde(rename = "f")]
+    Full,
+    #[serde(rename = "fa")]
+    FullWithAltitude,
+    #[serde(rename = "fs")]
+    FullWithSpeed,
+}
+
+impl TrailFormat {
+    fn is_short(&self) -> bool {
+        matches!(
+            self,
+            Self::Short | Self::ShortWithAltitude | Self::ShortWithSpeed
+        )
+    }
+
+    fn trail_type(&self) -> Option<&'static str> {
+        match self {
+            Self::ShortWithAltitude | Self::FullWithAltitude => Some("a"),
+            Self::ShortWithSpeed | Self::FullWithSpeed => Some("s"),
+            Self::Short | Self::Full => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AircraftList {
+    pub src: i32,
+    pub feeds: Vec<Feed>,
+    #[serde(rename = "srcFeed")]
+    pub source_feed: i32,
+    #[serde(rename = "showSil")]
+    pub show_silhouettes: bool,
+    #[serde(rename = "showFlg")]
+    pub show_flags: bool,
+    #[serde(rename = "showPic")]
+    pub show_pictures: bool,
+    #[serde(rename = "flgH")]
+    pub flag_height: i32,
+    #[serde(rename = "flgW")]
+    pub flag_width: i32,
+    #[serde(rename = "acList")]
+    pub aircraft: Vec<Aircraft>,
+    #[serde(rename = "totalAc")]
+    pub total_aircraft: usize,
+    /// Data version, which clients send back as `ldv`. This is the server time
+    /// in milliseconds, but VRS sends it as a string.
+    #[serde(rename = "lastDv")]
+    pub last_data_version: String,
+    #[serde(rename = "shtTrlSec")]
+    pub short_trail_seconds: i64,
+    /// Server time in milliseconds
+    #[serde(rename = "stm")]
+    pub server_time: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Feed {
+    pub id: i32,
+    pub name: String,
+    #[serde(rename = "polarPlot")]
+    pub polar_plot: bool,
+}
+
+/// An aircraft in the list. Apart from the ID, only values that were updated
+/// since the client's last data version are set.
+#[skip_serializing_none]
+#[derive(Debug, Default, Serialize)]
+pub struct Aircraft {
+    /// The ICAO address as an integer
+    #[serde(rename = "Id")]
+    pub id: i32,
+    /// Seconds the aircraft has been tracked for
+    #[serde(rename = "TSecs")]
+    pub tracked_seconds: Option<i64>,
+    #[serde(rename = "Rcvr")]
+    pub receiver: Option<i32>,
+    /// Only set for aircraft that are new to the client
+    #[serde(rename = "Icao")]
+    pub icao: Option<String>,
+    #[serde(rename = "Reg")]
+    pub registration: Option<String>,
+    /// in ft
+    #[serde(rename = "Alt")]
+    pub altitude: Option<i32>,
+    /// 0: barometric, 1: geometric
+    #[serde(rename = "AltT")]
+    pub altitude_type: Option<u8>,
+    #[serde(rename = "Call")]
+    pub callsign: Option<String>,
+    #[serde(rename = "Lat")]
+    pub latitude: Option<f64>,
+    #[serde(rename = "Long")]
+    pub longitude: Option<f64>,
+    /// Time of the position in milliseconds
+    #[serde(rename = "PosTime")]
+    pub position_time: Option<i64>,
+    #[serde(rename = "Mlat")]
+    pub mlat: Option<bool>,
+    /// in kt
+    #[serde(rename = "Spd")]
+    pub speed: Option<f64>,
+    /// 0: ground speed
+    #[serde(rename = "SpdTyp")]
+    pub speed_type: Option<u8>,
+    /// in degrees
+    #[serde(rename = "Trak")]
+    pub track: Option<f64>,
+    /// Whether `Trak` is the heading, and not the track
+    #[serde(rename = "TrkH")]
+    pub track_is_heading: Option<bool>,
+    /// ICAO type designator
+    #[serde(rename = "Type")]
+    pub model_icao: Option<String>,
+    #[serde(rename = "Mdl")]
+    pub model: Option<String>,
+    #[serde(rename = "Sqk")]
+    pub squawk: Option<Squawk>,
+    /// Whether the squawk is an emergency code
+    #[serde(rename = "Help")]
+    pub emergency: Option<bool>,
+    #[serde(rename = "Gnd")]
+    pub on_ground: Option<bool>,
+    #[serde(rename = "Mil")]
+    pub military: Option<bool>,
+    /// 0: none, 1: light, 2: medium, 3: heavy
+    #[serde(rename = "WTC")]
+    pub wake_turbulence_category: Option<u8>,
+    /// 0: none, 1: land plane, 2: sea plane, 3: amphibian, 4: helicopter, 5:
+    /// gyrocopter, 6: tiltwing
+    #[serde(rename = "Species")]
+    pub species: Option<u8>,
+    /// 0: none, 1: piston, 2: turboprop/turboshaft, 3: jet, 4: electric, 5:
+    /// rocket
+    #[serde(rename = "EngType")]
+    pub engine_type: Option<u8>,
+    #[serde(rename = "Engines")]
+    pub engines: Option<String>,
+    /// Distance from the browser in km
+    #[serde(rename = "Dst")]
+    pub distance: Option<f64>,
+    /// Bearing from the browser in degrees
+    #[serde(rename = "Brng")]
+    pub bearing: Option<f64>,
+    /// Short trail: latitude, longitude, time in milliseconds, and altitude or
+    /// speed if requested
+    #[serde(rename = "Cos")]
+    pub short_trail: Option<Vec<f64>>,
+    /// Full trail: latitude, longitude, track, and altitude or speed if
+    /// requested
+    #[serde(rename = "Cot")]
+    pub full_trail: Option<Vec<f64>>,
+    /// `a` if trails contain altitudes, `s` if they contain speeds
+    #[serde(rename = "TT")]
+    pub trail_type: Option<&'static str>,
+    /// Whether the trail replaces the trail the client has
+    #[serde(rename = "ResetTrail")]
+    pub reset_trail: Option<bool>,
+}
+
+impl Aircraft {
+    /// Sets the values from the aircraft database.
+    pub fn set_info(&mut self, info: &AircraftInfo) {
+        let description = info.model_description.as_deref().unwrap_or_default();
+        let mut description = description.chars();
+
+        self.registration = info.registration.clone();
+        self.model_icao = info.model.clone();
+        self.model = info.model_name.clone();
+        self.military = Some(info.military);
+        self.wake_turbulence_category = info.wtc.map(|wtc| {
+            match wtc {
+                Wtc::Light => 1,
+                Wtc::Medium => 2,
+                Wtc::Heavy | Wtc::Super => 3,
+            }
+        });
+        self.species = description.next().and_then(|species| {
+            match species {
+                'L' => Some(1),
+                'S' => Some(2),
+                'A' => Some(3),
+                'H' => Some(4),
+                'G' => Some(5),
+                'T' => Some(6),
+                _ => None,
+            }
+        });
+        self.engines = description.next().map(|engines| engines.to_string());
+        self.engine_type = description.next().and_then(|engine_type| {
+            match engine_type {
+                'P' => Some(1),
+                'T' => Some(2),
+                'J' => Some(3),
+                'E' => Some(4),
+                'R' => Some(5),
+                _ => None,
+            }
+        });
+    }
+}
+
+pub async fn get_aircraft_list(
+    State(api): State<Api>,
+    Query(query): Query<AircraftListQuery>,
+) -> Result<Json<AircraftList>, ApiError> {
+    let time = Utc::now();
+    let mut list = api
+        .tracker
+        .with_state(move |state| aircraft_list(state, &query, time))
+        .await
+        .ok_or(ApiError::InternalServerError)?;
+
+    // database values only need to be sent for aircraft that are new to the client
+    let new_aircraft = list
+        .aircraft
+        .iter()
+        // non-ICAO addresses are not in the database
+        .filter(|aircraft| {
+            aircraft
+                .icao
+                .as_ref()
+                .is_some_and(|icao| !icao.starts_with('~'))
+        })
+        .map(|aircraft| IcaoAddress::from_u32_unchecked(aircraft.id as u32))
+        .collect::<Vec<_>>();
+    if !new_aircraft.is_empty() {
+        let mut transaction = api.database.transaction().await?;
+        let info = transaction.get_aircraft_info(&new_aircraft).await?;
+        transaction.commit().await?;
+        set_aircraft_info(&mut list, &info);
+    }
+
+    Ok(Json(list))
+}
+
+/// Generates the aircraft list from the tracker's state, without the values
+/// from the aircraft database.
+pub fn aircraft_list(
+    state: &TrackerState,
+    query: &AircraftListQuery,
+    time: DateTime<Utc>,
+) -> AircraftList {
+    let aircraft = state
+        .iter_aircraft()
+        .filter(|aircraft_state| {
+            time.signed_duration_since(aircraft_state.last_seen.last_update) <= DISPLAY_TIMEOUT
+        })
+        .map(|aircraft_state| list_aircraft(aircraft_state, query, time))
+        .collect::<Vec<_>>();
+
+    AircraftList {
+        src: 1,
+        feeds: vec![Feed {
+            id: FEED_ID,
+            name: "adsbee".to_owned(),
+            polar_plot: false,
+        }],
+        source_feed: FEED_ID,
+        show_silhouettes: false,
+        show_flags: false,
+        show_pictures: false,
+        flag_height: 20,
+        flag_width: 85,
+        total_aircraft: aircraft.len(),
+        aircraft,
+        last_data_version: time.timestamp_millis().to_string(),
+        short_trail_seconds: SHORT_TRAIL_SECONDS,
+        server_time: time.timestamp_millis(),
+    }
+}
+
+/// Sets the values from the aircraft database for all aircraft in `info`.
+pub fn set_aircraft_info(list: &mut AircraftList, info: &HashMap<IcaoAddress, AircraftInfo>) {
+    for aircraft in &mut list.aircraft {
+        if let Some(info) = info.get(&IcaoAddress::from_u32_unchecked(aircraft.id as u32)) {
+            aircraft.set_info(info);
+        }
+    }
+}
+
+fn list_aircraft(
+    aircraft_state: &AircraftState,
+    query: &AircraftListQuery,
+    time: DateTime<Utc>,
+) -> Aircraft {
+    let since = query.since();
+    let updated =
+        |last_update: DateTime<Utc>| since.is_none_or(|since| last_update > since - CHANGE_MARGIN);
+    let is_new = updated(aircraft_state.first_seen);
+
+    let mut aircraft = Aircraft {
+        id: u32::from(aircraft_state.icao_address) as i32,
+        tracked_seconds: Some(
+            time.signed_duration_since(aircraft_state.first_seen)
+                .num_seconds(),
+        ),
+        ..Default::default()
+    };
+
+    if is_new {
+        aircraft.receiver = Some(FEED_ID);
+        aircraft.icao = Some(aircraft_state.icao_address.to_string().to_uppercase());
+        aircraft.altitude_type = Some(0);
+        aircraft.speed_type = Some(0);
+        aircraft.track_is_heading = Some(false);
+    }
+
+    if let Some(callsign) = aircraft_state
+        .callsign
+        .as_ref()
+        .filter(|callsign| updated(callsign.last_update))
+    {
+        aircraft.callsign = Some(callsign.value.trimmed().to_owned());
+    }
+
+    if let Some(squawk) = aircraft_state
+        .squawk
+        .as_ref()
+        .filter(|squawk| updated(squawk.last_update))
+    {
+        aircraft.squawk = Some(squawk.value);
+        aircraft.emergency = Some(matches!(
+            squawk.value,
+            Squawk::AIRCRAFT_HIJACKING | Squawk::RADIO_FAILURE | Squawk::EMERGENCY
+        ));
+    }
+
+    if let Some(altitude) = aircraft_state
+        .altitude_barometric
+        .as_ref()
+        .filter(|altitude| updated(altitude.last_update))
+    {
+        aircraft.altitude = Some(altitude.value);
+    }
+
+    if let Some(ground_speed) = aircraft_state
+        .ground_speed
+        .as_ref()
+        .filter(|ground_speed| updated(ground_speed.last_update))
+    {
+        aircraft.speed = Some(ground_speed.value);
+    }
+
+    if let Some(track) = aircraft_state
+        .track
+        .as_ref()
+        .filter(|track| updated(track.last_update))
+    {
+        aircraft.track = Some(track.value.to_degrees().rem_euclid(360.0));
+    }
+
+    if let Some(position) = aircraft_state
+        .position
+        .as_ref()
+        .filter(|position| updated(position.last_update))
+    {
+        aircraft.latitude = Some(position.value.latitude);
+        aircraft.longitude = Some(position.value.longitude);
+        aircraft.position_time = Some(position.last_update.timestamp_millis());
+        aircraft.mlat = Some(position.value.source == PositionSource::Mlat);
+
+        if let (Some(latitude), Some(longitude)) = (query.lat, query.lng) {
+            let browser = LatLon::new(latitude, longitude);
+            let position = position.value.lat_lon();
+            aircraft.distance = Some(browser.haversine_distance(&position) / 1000.0);
+            aircraft.bearing = Some(browser.initial_bearing(&position));
+        }
+    }
+
+    if let Some(vertical_status) = aircraft_state.vertical_status
+        && updated(aircraft_state.last_seen.last_update)
+    {
+        aircraft.on_ground = Some(vertical_status == VerticalStatus::Ground);
+    }
+
+    if let Some(trail_format) = query.trail_format {
+        set_trail(&mut aircraft, aircraft_state, query, trail_format, time);
+    }
+
+    aircraft
+}
+
+fn set_trail(
+    aircraft: &mut Aircraft,
+    aircraft_state: &AircraftState,
+    query: &AircraftListQuery,
+    trail_format: TrailFormat,
+    time: DateTime<Utc>,
+) {
+    let reset_trail = query.since().is_none() || query.refresh_trails == Some(1);
+    let since = if reset_trail { None } else { query.since() };
+    let start = trail_format
+        .is_short()
+        .then(|| time - TimeDelta::seconds(SHORT_TRAIL_SECONDS));
+
+    let points = aircraft_state.trail.iter().filter(|point| {
+        since.is_none_or(|since| point.time > since)
+            && start.is_none_or(|start| point.time >= start)
+    });
+
+    let mut trail = vec![];
+    for point in points {
+        trail.push(point.position.latitude);
+        trail.push(point.position.longitude);
+        if trail_format.is_short() {
+            trail.push(point.time.timestamp_millis() as f64);
+        }
+        else {
+            trail.push(
+                point
+                    .track
+                    .map_or(0.0, |track| track.to_degrees().rem_euclid(360.0)),
+            );
+        }
+        match trail_format.trail_type() {
+            Some("a") => trail.push(trail_altitude(point)),
+            Some(_) => trail.push(point.ground_speed.unwrap_or_default()),
+            None => {}
+        }
+    }
+
+    if trail.is_empty() && !reset_trail {
+        return;
+    }
+
+    if trail_format.is_short() {
+        aircraft.short_trail = Some(trail);
+    }
+    else {
+        aircraft.full_trail = Some(trail);
+    }
+    aircraft.trail_type = trail_format.trail_type();
+    aircraft.reset_trail = Some(reset_trail);
+}
+
+fn trail_altitude(point: &TrailPoint) -> f64 {
+    if point.vertical_status == Some(VerticalStatus::Ground) {
+        0.0
+    }
+    else {
+        point.altitude_barometric.map_or(0.0, f64::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use adsbee_api_types::Wtc;
+    use adsbee_types::IcaoAddress;
+    use chrono::{
+        DateTime,
+        TimeDelta,
+    };
+    use serde_json::json;
+
+    use crate::{
+        api::vrs::{
+            AircraftListQuery,
+            TrailFormat,
+            aircraft_list,
+            set_aircraft_info,
+        },
+        database::AircraftInfo,
+        tracker::state::{
+            State,
+            fixtures,
+        },
+    };
+
+    fn update(state: &mut State, now: i64, latitude: f64, altitude: i32) {
+        fixtures::update(
+            state,
+            DateTime::from_timestamp(now, 0).unwrap(),
+            [fixtures::aircraft(
+                json!({"lat": latitude, "alt_baro": altitude}),
+            )],
+        );
+    }
+
+    #[test]
+    fn it_lists_aircraft() {
+        let mut state = State::default();
+        update(&mut state, 1750000000, 51.47, 37000);
+        update(&mut state, 1750000005, 51.48, 37000);
+        let time = DateTime::from_timestamp(1750000006, 0).unwrap();
+
+        let mut list = aircraft_list(
+            &state,
+            &AircraftListQuery {
+                trail_format: Some(TrailFormat::ShortWithAltitude),
+                ..Default::default()
+            },
+            time,
+        );
+        set_aircraft_info(
+            &mut list,
+            &HashMap::from([(
+                IcaoAddress::from_u32_unchecked(0x4ca7b5),
+                AircraftInfo {
+                    icao_address: IcaoAddress::from_u32_unchecked(0x4ca7b5),
+                    registration: Some("EI-DWA".to_owned()),
+                    model: Some("B738".to_owned()),
+                    model_name: Some("BOEING 737-800".to_owned()),
+                    model_description: Some("L2J".to_owned()),
+                    wtc: Some(Wtc::Medium),
+                    military: false,
+                },
+            )]),
+        );
+
+        let json = serde_json::to_value(&list).unwrap();
+        assert_eq!(json["totalAc"], 1);
+        assert_eq!(json["lastDv"], "1750000006000");
+        let aircraft = &json["acList"][0];
+        assert_eq!(aircraft["Id"], 0x4ca7b5);
+        assert_eq!(aircraft["Icao"], "4CA7B5");
+        assert_eq!(aircraft["Reg"], "EI-DWA");
+        assert_eq!(aircraft["Type"], "B738");
+        assert_eq!(aircraft["WTC"], 2);
+        assert_eq!(aircraft["Species"], 1);
+        assert_eq!(aircraft["EngType"], 3);
+        assert_eq!(aircraft["Engines"], "2");
+        assert_eq!(aircraft["Call"], "RYR4WJ");
+        assert_eq!(aircraft["Sqk"], "1000");
+        assert_eq!(aircraft["Alt"], 37000);
+        assert_eq!(aircraft["Lat"], 51.48);
+        assert_eq!(aircraft["Trak"], 270.0);
+        assert_eq!(aircraft["TT"], "a");
+        assert_eq!(aircraft["ResetTrail"], true);
+        assert_eq!(
+            aircraft["Cos"],
+            json!([
+                51.47,
+                -0.4614,
+                1750000000000.0,
+                37000.0,
+                51.48,
+                -0.4614,
+                1750000005000.0,
+                37000.0
+            ])
+        );
+    }
+
+    #[test]
+    fn it_only_sends_updates() {
+        let mut state = State::default();
+        update(&mut state, 1750000000, 51.47, 37000);
+        let time = DateTime::from_timestamp(1750000030, 0).unwrap();
+        let list = aircraft_list(&state, &AircraftListQuery::default(), time);
+
+        // a minute later only altitude and position are received
+        let time = time + TimeDelta::minutes(1);
+        fixtures::update(
+            &mut state,
+            time,
+            [fixtures::aircraft(json!({
+                "flight": null,
+                "squawk": null,
+                "gs": null,
+                "track": null,
+                "alt_baro": 36000,
+                "lat": 51.47,
+            }))],
+        );
+        let list = aircraft_list(
+            &state,
+            &AircraftListQuery {
+                ldv: Some(list.last_data_version.parse().unwrap()),
+                trail_format: Some(TrailFormat::Short),
+                ..Default::default()
+            },
+            time,
+        );
+
+        let json = serde_json::to_value(&list).unwrap();
+        let aircraft = &json["acList"][0];
+        assert_eq!(aircraft["Id"], 0x4ca7b5);
+        assert_eq!(aircraft["Alt"], 36000);
+        // values that weren't updated, and values that never change, are not sent
+        assert!(aircraft.get("Icao").is_none());
+        assert!(aircraft.get("Call").is_none());
+        assert_eq!(aircraft["ResetTrail"], false);
+        assert_eq!(
+            aircraft["Cos"],
+            json!([51.47, -0.4614, time.timestamp_millis() as f64])
+        );
+    }
+
+    #[test]
+    fn it_removes_aircraft_that_werent_seen() {
+        let mut state = State::default();
+        update(&mut state, 1750000000, 51.47, 37000);
+        fixtures::update(
+            &mut state,
+            DateTime::from_timestamp(1750000020, 0).unwrap(),
+            [fixtures::aircraft(json!({"hex": "400f01"}))],
+        );
+        let time = DateTime::from_timestamp(1750000040, 0).unwrap();
+
+        let list = aircraft_list(&state, &AircraftListQuery::default(), time);
+        assert_eq!(list.total_aircraft, 1);
+        assert_eq!(list.aircraft[0].id, 0x400f01);
+    }
+}
@@ -1,8 +1,16 @@
-use std::ops::{
-    Deref,
-    DerefMut,
+use std::{
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    ops::{
+        Deref,
+        DerefMut,
+    },
 };
 
+use adsbee_api_types::Wtc;
+use adsbee_types::IcaoAddress;
 use serde::{
     Serialize,
     de::DeserializeOwned,
@@ -14,6 +22,8 @@ use sqlx::{
     types::Json,
 };
 
+use crate::metrics::QueryTimer;
+
 #[derive(Debug, thiserror::Error)]
 #[error("database error")]
 pub enum Error {
@@ -76,6 +86,7 @@ impl<'c> Transaction<'c> {
         &mut self,
         key: &str,
     ) -> Result<Option<T>, Error> {
+        let _timer = QueryTimer::start("get_metadata");
         if let Some(row) = sqlx::query!("select value from metadata where key = $1", key)
             .fetch_optional(&mut *self.inner)
             .await?
@@ -87,8 +98,76 @@ impl<'c> Transaction<'c> {
         }
     }
 
+    /// Returns the addresses of all aircraft with `tag` (e.g. `military`).
+    pub async fn get_tagged_aircraft(&mut self, tag: &str) -> Result<HashSet<IcaoAddress>, Error> {
+        let _timer = QueryTimer::start("get_tagged_aircraft");
+        let addresses = sqlx::query_scalar_unchecked!(
+            r#"select icao_address as "icao_address: IcaoAddress" from aircraft_tag where tag = $1"#,
+            tag
+        )
+        .fetch_all(&mut *self.inner)
+        .await?;
+        Ok(addresses.into_iter().collect())
+    }
+
+    /// Returns registration, model and tags for aircraft. Aircraft we know
+    /// nothing about are omitted.
+    pub async fn get_aircraft_info(
+        &mut self,
+        icao_addresses: &[IcaoAddress],
+    ) -> Result<HashMap<IcaoAddress, AircraftInfo>, Error> {
+        let _timer = QueryTimer::start("get_aircraft_info");
+        let rows = sqlx::query_as_unchecked!(
+            AircraftInfo,
+            r#"
+                select
+                    a.icao_address as "icao_address!: IcaoAddress",
+                    r.registration as "registration?",
+                    r.model as "model?",
+                    m.name as "model_name?",
+                    m.description as "model_description?",
+                    m.wtc as "wtc?: Wtc",
+                    exists (
+                        select 1 from aircraft_tag t
+                        where t.icao_address = a.icao_address and t.tag = 'military'
+                    ) as "military!"
+                from unnest($1::int[]) as a (icao_address)
+                left join aircraft_registration r on r.icao_address = a.icao_address
+                left join aircraft_model m on m.icao_code = r.model
+                where r.icao_address is not null or exists (
+                    select 1 from aircraft_tag t where t.icao_address = a.icao_address
+                )
+            "#,
+            icao_addresses
+        )
+        .fetch_all(&mut *self.inner)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|info| (info.icao_address, info))
+            .collect())
+    }
+
     pub async fn set_metadata<T: Serialize>(&mut self, key: &str, value: &T) -> Result<(), Error> {
+        let _timer = QueryTimer::start("set_metadata");
         sqlx::query_unchecked!("insert into metadata (key, value) values ($1, $2) on conflict (key) do update set value = $2", key, Json(value)).execute(&mut *self.inner).await?;
         Ok(())
     }
 }
+
+/// What we know about an aircraft from the aircraft database (see
+/// [`tar1090_db`][crate::source::tar1090_db]).
+#[derive(Clone, Debug)]
+pub struct AircraftInfo {
+    pub icao_address: IcaoAddress,
+    pub registration: Option<String>,
+    /// ICAO type designator, e.g. `B738`
+    pub model: Option<String>,
+    /// e.g. `BOEING 737-800`
+    pub model_name: Option<String>,
+    /// ICAO type description, e.g. `L2J`
+    pub model_description: Option<String>,
+    pub wtc: Option<Wtc>,
+    pub military: bool,
+}
@@ -1,8 +1,11 @@
 #![allow(dead_code)]
 
+pub mod airline;
 pub mod api;
 pub mod country;
 pub mod database;
+pub mod metrics;
+pub mod output;
 pub mod source;
 pub mod spatial;
 pub mod tracker;
@@ -47,8 +50,10 @@ pub enum Error {
     Tar1090AircraftFlags(#[from] crate::source::tar1090_db::AircraftFlagsFromStrError),
     #[error("tar1090-db has no commits")]
     Tar1090NoCommits,
+    Avr(#[from] adsbee_avr::Error),
     Beast(#[from] adsbee_beast::Error),
     Sbs(#[from] adsbee_sbs::Error),
+    Metrics(#[from] metrics_exporter_prometheus::BuildError),
 }
 
 impl From<sqlx::Error> for Error {
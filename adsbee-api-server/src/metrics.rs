@@ -0,0 +1,249 @@
+//! Prometheus metrics
+//!
+//! Metrics are recorded with the [`metrics`] macros where they occur, and are
+//! only collected once a recorder was installed with [`Metrics::install`]. The
+//! API serves them at `/metrics` (see [`Api::with_metrics`][1]).
+//!
+//! [1]: crate::api::Api::with_metrics
+
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use metrics::{
+    Unit,
+    counter,
+    describe_counter,
+    describe_gauge,
+    describe_histogram,
+    gauge,
+    histogram,
+};
+use metrics_exporter_prometheus::{
+    BuildError,
+    Matcher,
+    PrometheusBuilder,
+    PrometheusHandle,
+};
+use uuid::Uuid;
+
+use crate::source::SourceId;
+
+/// Mode-S frames received, by downlink format (label `df`)
+pub const FRAMES: &str = "adsbee_frames_total";
+
+/// Extended squitters with an invalid CRC
+pub const CRC_FAILURES: &str = "adsbee_crc_failures_total";
+
+/// Mode-S frames that couldn't be decoded
+pub const DECODE_ERRORS: &str = "adsbee_decode_errors_total";
+
+/// Messages pushed by sources (labels `source` and `receiver`)
+pub const SOURCE_MESSAGES: &str = "adsbee_source_messages_total";
+
+/// Aircraft in the tracker's state
+pub const AIRCRAFT: &str = "adsbee_aircraft";
+
+/// Connected websocket clients
+pub const WEBSOCKET_CLIENTS: &str = "adsbee_websocket_clients";
+
+/// Duration of database queries, by query (label `query`)
+pub const DATABASE_QUERY_DURATION: &str = "adsbee_database_query_duration_seconds";
+
+/// Histogram buckets for database query durations in seconds.
+const DATABASE_QUERY_DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Interval at which histograms are drained, so they don't grow while
+/// `/metrics` isn't scraped.
+const UPKEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Labels for downlink formats 0 to 24. DF 24 and above are Comm-D.
+const DF_LABELS: [&str; 25] = [
+    "0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12", "13", "14", "15", "16",
+    "17", "18", "19", "20", "21", "22", "23", "24",
+];
+
+#[derive(Clone, Debug)]
+pub struct Metrics {
+    handle: PrometheusHandle,
+}
+
+impl Metrics {
+    /// Installs the global metrics recorder.
+    ///
+    /// This must be called from within a tokio runtime, and at most once.
+    pub fn install() -> Result<Self, BuildError> {
+        let handle = PrometheusBuilder::new()
+            .set_buckets_for_metric(
+                Matcher::Full(DATABASE_QUERY_DURATION.to_owned()),
+                DATABASE_QUERY_DURATION_BUCKETS,
+            )?
+            .install_recorder()?;
+
+        describe();
+
+        tokio::spawn({
+            let handle = handle.clone();
+            async move {
+                let mut interval = tokio::time::interval(UPKEEP_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    handle.run_upkeep();
+                }
+            }
+        });
+
+        Ok(Self { handle })
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        self.handle.render()
+    }
+}
+
+fn describe() {
+    describe_counter!(FRAMES, Unit::Count, "Mode-S frames received");
+    describe_counter!(
+        CRC_FAILURES,
+        Unit::Count,
+        "Extended squitters with an invalid CRC"
+    );
+    describe_counter!(
+        DECODE_ERRORS,
+        Unit::Count,
+        "Mode-S frames that couldn't be decoded"
+    );
+    describe_counter!(SOURCE_MESSAGES, Unit::Count, "Messages pushed by sources");
+    describe_gauge!(AIRCRAFT, Unit::Count, "Aircraft in the tracker's state");
+    describe_gauge!(
+        WEBSOCKET_CLIENTS,
+        Unit::Count,
+        "Connected websocket clients"
+    );
+    describe_histogram!(
+        DATABASE_QUERY_DURATION,
+        Unit::Seconds,
+        "Duration of database queries"
+    );
+}
+
+/// Counts a Mode-S frame, by the downlink format in its first 5 bits.
+pub fn count_frame(data: &[u8]) {
+    if let Some(first) = data.first() {
+        let df = usize::from(first >> 3).min(DF_LABELS.len() - 1);
+        counter!(FRAMES, "df" => DF_LABELS[df]).increment(1);
+    }
+}
+
+pub fn count_crc_failure() {
+    counter!(CRC_FAILURES).increment(1);
+}
+
+pub fn count_decode_error() {
+    counter!(DECODE_ERRORS).increment(1);
+}
+
+pub fn set_aircraft(count: usize) {
+    gauge!(AIRCRAFT).set(count as f64);
+}
+
+/// Counts a connected websocket client while it's alive.
+#[derive(Debug)]
+pub struct WebsocketClientGuard {
+    _private: (),
+}
+
+impl WebsocketClientGuard {
+    pub fn connected() -> Self {
+        gauge!(WEBSOCKET_CLIENTS).increment(1.0);
+        Self { _private: () }
+    }
+}
+
+impl Drop for WebsocketClientGuard {
+    fn drop(&mut self) {
+        gauge!(WEBSOCKET_CLIENTS).decrement(1.0);
+    }
+}
+
+/// Counts a message pushed by a source.
+pub fn count_source_message(source_id: SourceId, receiver_id: Option<Uuid>) {
+    counter!(
+        SOURCE_MESSAGES,
+        "source" => source_id.to_string(),
+        "receiver" => receiver_id.map(|receiver_id| receiver_id.to_string()).unwrap_or_default(),
+    )
+    .increment(1);
+}
+
+/// Records the duration of a database query when dropped.
+#[derive(Debug)]
+pub struct QueryTimer {
+    query: &'static str,
+    start: Instant,
+}
+
+impl QueryTimer {
+    pub fn start(query: &'static str) -> Self {
+        Self {
+            query,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for QueryTimer {
+    fn drop(&mut self) {
+        histogram!(DATABASE_QUERY_DURATION, "query" => self.query)
+            .record(self.start.elapsed().as_secs_f64());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use adsbee_beast::{
+        MlatTimestamp,
+        SignalLevel,
+        output::OutputPacket,
+    };
+    use chrono::Utc;
+
+    use crate::{
+        metrics::Metrics,
+        source::SourceId,
+        tracker::Tracker,
+    };
+
+    fn long(data: &[u8]) -> OutputPacket {
+        OutputPacket::ModeSLong {
+            timestamp: MlatTimestamp::ANY_TIMESTAMP,
+            signal_level: SignalLevel(0),
+            data: data.try_into().unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_renders_tracker_metrics() {
+        let metrics = Metrics::install().unwrap();
+
+        let tracker = Tracker::new();
+        let valid = long(b"\x8d\x48\x40\xd6\x20\x2c\xc3\x71\xc3\x2c\xe0\x57\x60\x98");
+        let invalid = long(b"\x8d\x48\x40\xd6\x20\x2c\xc3\x71\xc3\x2c\xe0\x57\x60\x99");
+        for packet in [valid, invalid] {
+            tracker
+                .push_beast(SourceId::new(7), None, Utc::now(), packet)
+                .await;
+        }
+        // commands are handled in order, so this waits for the frames
+        tracker.with_state(|_| ()).await.unwrap();
+
+        let rendered = metrics.render();
+        assert!(rendered.contains(r#"adsbee_frames_total{df="17"} 2"#));
+        assert!(rendered.contains("adsbee_crc_failures_total 1"));
+        assert!(rendered.contains(r#"adsbee_source_messages_total{source="7",receiver=""} 2"#));
+    }
+}
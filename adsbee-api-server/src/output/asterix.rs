@@ -0,0 +1,362 @@
+//! ASTERIX output
+//!
+//! Sends CAT021 (ADS-B target reports) over UDP, either to a unicast or a
+//! multicast address. Every interval we send a target report for each aircraft
+//! with a recent position.
+
+use std::{
+    net::SocketAddr,
+    time::Duration,
+};
+
+use adsbee_asterix::{
+    self as asterix,
+    cat021,
+};
+use adsbee_mode_s::{
+    VerticalStatus,
+    adsb,
+};
+use chrono::{
+    DateTime,
+    Timelike,
+    Utc,
+};
+use serde::Deserialize;
+use tokio::{
+    net::UdpSocket,
+    time::MissedTickBehavior,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    Error,
+    output::MAX_AGE,
+    tracker::{
+        Tracker,
+        state::{
+            AircraftState,
+            State,
+            fresh,
+            fresh_timestamped,
+        },
+    },
+};
+
+/// Default time-to-live of multicast datagrams.
+pub const DEFAULT_MULTICAST_TTL: u32 = 1;
+
+/// Number of records per data block (and datagram). Target reports are at most
+/// ~70 bytes, so this keeps datagrams below the usual MTU.
+const RECORDS_PER_DATA_BLOCK: usize = 16;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AsterixServerConfig {
+    /// Unicast or multicast address datagrams are sent to, e.g.
+    /// `239.0.0.1:8600`.
+    pub target_address: String,
+
+    /// System area code of this data source
+    pub sac: u8,
+
+    /// System identification code of this data source
+    pub sic: u8,
+
+    /// Interval between updates in seconds
+    #[serde(
+        default = "super::default_interval",
+        deserialize_with = "crate::util::deserialize_positive_seconds"
+    )]
+    pub interval: f64,
+
+    #[serde(default = "default_multicast_ttl")]
+    pub multicast_ttl: u32,
+}
+
+fn default_multicast_ttl() -> u32 {
+    DEFAULT_MULTICAST_TTL
+}
+
+/// Sends target reports to the configured address until `shutdown` is
+/// cancelled.
+pub async fn serve(
+    config: AsterixServerConfig,
+    tracker: Tracker,
+    shutdown: CancellationToken,
+) -> Result<(), Error> {
+    let target_address = tokio::net::lookup_host(&config.target_address)
+        .await?
+        .next()
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("could not resolve {}", config.target_address),
+            )
+        })?;
+
+    let socket = match target_address {
+        SocketAddr::V4(address) => {
+            let socket = UdpSocket::bind("0.0.0.0:0").await?;
+            if address.ip().is_multicast() {
+                socket.set_multicast_ttl_v4(config.multicast_ttl)?;
+            }
+            socket
+        }
+        SocketAddr::V6(_) => UdpSocket::bind("[::]:0").await?,
+    };
+    socket.connect(target_address).await?;
+
+    let data_source = cat021::DataSourceIdentifier {
+        sac: config.sac,
+        sic: config.sic,
+    };
+
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(config.interval));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut buffer = vec![];
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                break;
+            }
+            _ = interval.tick() => {
+                let time = Utc::now();
+                let reports = tracker
+                    .with_state(move |state| target_reports(state, data_source, time))
+                    .await
+                    .unwrap_or_default();
+
+                for reports in reports.chunks(RECORDS_PER_DATA_BLOCK) {
+                    buffer.clear();
+                    asterix::encode_data_block(reports, &mut buffer);
+                    if let Err(error) = socket.send(&buffer).await {
+                        tracing::debug!(?error, "failed to send asterix data block");
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Generates target reports for all aircraft with a recent position.
+pub fn target_reports(
+    state: &State,
+    data_source: cat021::DataSourceIdentifier,
+    time: DateTime<Utc>,
+) -> Vec<cat021::TargetReport> {
+    state
+        .iter_aircraft()
+        .filter_map(|aircraft| target_report(aircraft, data_source, time))
+        .collect()
+}
+
+/// Generates the target report for an aircraft, or `None` if it has no recent
+/// position.
+pub fn target_report(
+    aircraft: &AircraftState,
+    data_source: cat021::DataSourceIdentifier,
+    time: DateTime<Utc>,
+) -> Option<cat021::TargetReport> {
+    let position = fresh_timestamped(&aircraft.position, time, MAX_AGE)?;
+
+    let ground_speed = fresh_timestamped(&aircraft.ground_speed, time, MAX_AGE);
+    let track = fresh(&aircraft.track, time, MAX_AGE);
+    let airborne_ground_vector = ground_speed.zip(track).map(|(ground_speed, track)| {
+        cat021::AirborneGroundVector {
+            ground_speed: ground_speed.value,
+            track_angle: track.to_degrees().rem_euclid(360.0),
+        }
+    });
+
+    let quality = &aircraft.adsb_quality;
+    // todo: NACv and SDA aren't tracked yet.
+    let quality_indicators = quality.mops_version.map(|_| {
+        cat021::QualityIndicators {
+            nac_v: 0,
+            nic: quality.nic.unwrap_or_default(),
+            nic_baro: quality.nic_baro.unwrap_or_default(),
+            sil: quality.sil.map_or(0, |sil| sil.as_u8()),
+            nac_p: quality.nac_p.map_or(0, |nac_p| nac_p.as_u8()),
+            sil_supplement: quality.sil_supplement == Some(adsb::SilSupplement::PerSample),
+            sda: 0,
+            gva: quality.gva.map_or(0, |gva| gva.as_u8()),
+            pic: None,
+        }
+    });
+    let mops_version = quality.mops_version.map(|mops_version| {
+        cat021::MopsVersion {
+            version_not_supported: false,
+            version: mops_version.as_u8(),
+            link_technology: cat021::MopsVersion::LINK_TECHNOLOGY_1090_ES,
+        }
+    });
+
+    let altitude_barometric = fresh(&aircraft.altitude_barometric, time, MAX_AGE);
+
+    // readsb marks addresses that aren't ICAO addresses with a `~`. these are
+    // closest to anonymous addresses.
+    let address_type = if aircraft.icao_address.non_icao() {
+        cat021::TargetReportDescriptor::ADDRESS_TYPE_ANONYMOUS
+    }
+    else {
+        cat021::TargetReportDescriptor::ADDRESS_TYPE_ICAO
+    };
+
+    Some(cat021::TargetReport {
+        data_source,
+        target_report_descriptor: Some(cat021::TargetReportDescriptor {
+            address_type,
+            altitude_reporting_capability: if altitude_barometric.is_some() {
+                cat021::TargetReportDescriptor::ALTITUDE_REPORTING_CAPABILITY_25_FT
+            }
+            else {
+                cat021::TargetReportDescriptor::ALTITUDE_REPORTING_CAPABILITY_UNKNOWN
+            },
+            ground: aircraft.vertical_status == Some(VerticalStatus::Ground),
+            ..Default::default()
+        }),
+        track_number: None,
+        time_of_applicability_for_position: Some(time_of_day(position.last_update)),
+        position: Some(position.value.lat_lon()),
+        time_of_applicability_for_velocity: ground_speed
+            .map(|ground_speed| time_of_day(ground_speed.last_update)),
+        target_address: Some(aircraft.icao_address),
+        time_of_message_reception_of_position: Some(time_of_day(position.last_update)),
+        time_of_message_reception_of_velocity: ground_speed
+            .map(|ground_speed| time_of_day(ground_speed.last_update)),
+        geometric_height: fresh(&aircraft.altitude_gnss, time, MAX_AGE)
+            .map(|altitude| f64::from(*altitude)),
+        quality_indicators,
+        mops_version,
+        mode_3a_code: aircraft.squawk.as_ref().map(|squawk| squawk.value),
+        flight_level: altitude_barometric.map(|altitude| f64::from(*altitude) / 100.0),
+        magnetic_heading: fresh(&aircraft.magnetic_heading, time, MAX_AGE)
+            .map(|heading| heading.to_degrees().rem_euclid(360.0)),
+        airborne_ground_vector,
+        time_of_report_transmission: Some(time_of_day(time)),
+        target_identification: aircraft.callsign.as_ref().map(|callsign| callsign.value),
+    })
+}
+
+fn time_of_day(time: DateTime<Utc>) -> cat021::TimeOfDay {
+    cat021::TimeOfDay::from_seconds(
+        f64::from(time.num_seconds_from_midnight()) + f64::from(time.nanosecond()) * 1e-9,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use adsbee_asterix::{
+        self as asterix,
+        cat021,
+    };
+    use adsbee_types::IcaoAddress;
+    use chrono::Utc;
+    use serde_json::json;
+    use tokio::net::UdpSocket;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::{
+        output::asterix::{
+            AsterixServerConfig,
+            serve,
+            target_reports,
+        },
+        tracker::{
+            Tracker,
+            state::{
+                State,
+                fixtures,
+            },
+        },
+    };
+
+    #[tokio::test]
+    async fn it_sends_target_reports_to_localhost() {
+        let tracker = Tracker::new();
+        tracker
+            .push_aircraft_json(
+                fixtures::source_id(),
+                fixtures::snapshot(Utc::now(), [fixtures::aircraft(json!({}))]),
+            )
+            .await;
+
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let shutdown = CancellationToken::new();
+        tokio::spawn(serve(
+            AsterixServerConfig {
+                target_address: receiver.local_addr().unwrap().to_string(),
+                sac: 25,
+                sic: 201,
+                interval: 1.0,
+                multicast_ttl: 1,
+            },
+            tracker,
+            shutdown.clone(),
+        ));
+
+        let mut buffer = [0; 1500];
+        let length = receiver.recv(&mut buffer).await.unwrap();
+        shutdown.cancel();
+
+        let reports: Vec<cat021::TargetReport> =
+            asterix::decode_data_block(&mut &buffer[..length]).unwrap();
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(
+            report.data_source,
+            cat021::DataSourceIdentifier { sac: 25, sic: 201 }
+        );
+        assert_eq!(
+            report.target_address,
+            Some(IcaoAddress::from_u32_unchecked(0x4ca7b5))
+        );
+        assert_eq!(report.flight_level, Some(370.0));
+        assert_eq!(report.target_identification.unwrap().trimmed(), "RYR4WJ");
+        let vector = report.airborne_ground_vector.unwrap();
+        assert!((vector.ground_speed - 450.0).abs() < 0.5);
+        assert!((vector.track_angle - 270.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn it_marks_non_icao_addresses() {
+        let now = Utc::now();
+        let mut state = State::default();
+        fixtures::update(
+            &mut state,
+            now,
+            [
+                fixtures::aircraft(json!({})),
+                fixtures::aircraft(json!({"hex": "~400f01"})),
+            ],
+        );
+
+        let data_source = cat021::DataSourceIdentifier { sac: 25, sic: 201 };
+        let mut address_types = target_reports(&state, data_source, now)
+            .into_iter()
+            .map(|report| {
+                (
+                    report.target_address.unwrap().to_string(),
+                    report.target_report_descriptor.unwrap().address_type,
+                )
+            })
+            .collect::<Vec<_>>();
+        address_types.sort();
+        assert_eq!(
+            address_types,
+            [
+                (
+                    "4ca7b5".to_owned(),
+                    cat021::TargetReportDescriptor::ADDRESS_TYPE_ICAO
+                ),
+                (
+                    "~400f01".to_owned(),
+                    cat021::TargetReportDescriptor::ADDRESS_TYPE_ANONYMOUS
+                ),
+            ]
+        );
+    }
+}
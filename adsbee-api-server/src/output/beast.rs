@@ -0,0 +1,327 @@
+//! BEAST output server
+//!
+//! Streams the frames from all BEAST sources to any connected TCP client, like
+//! readsb's `--net-bo-port`.
+//!
+//! Clients can change their options by sending BEAST input commands (see
+//! [`beast::input::ToggleDipswitch`]), the same way they would configure a
+//! Beast receiver.
+
+use adsbee_beast::{
+    self as beast,
+    MlatTimestamp,
+    input::{
+        InputPacket,
+        ToggleDipswitch,
+    },
+    output::OutputPacket,
+};
+use futures_util::{
+    SinkExt,
+    StreamExt,
+};
+use serde::Deserialize;
+use tokio::{
+    net::{
+        TcpListener,
+        TcpStream,
+    },
+    sync::broadcast::{
+        self,
+        error::{
+            RecvError,
+            TryRecvError,
+        },
+    },
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    Error,
+    output::reduce::{
+        ReduceConfig,
+        Reducer,
+    },
+    tracker::{
+        BeastFrame,
+        Tracker,
+    },
+};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BeastServerConfig {
+    pub listen_address: String,
+    /// Options clients start with
+    #[serde(default)]
+    pub client_options: ClientOptions,
+    /// Used for clients with [`ClientOptions::reduce`]
+    #[serde(default)]
+    pub reduce: ReduceConfig,
+}
+
+/// Per-client options.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ClientOptions {
+    /// Send MLAT timestamps. Otherwise timestamps are zeroed.
+    pub timestamps: bool,
+    /// Only send DF11 and DF17 frames.
+    pub df_11_17_only: bool,
+    /// Send Mode A/C frames.
+    pub mode_ac: bool,
+    /// Send the receiver ID before each frame from a known receiver.
+    pub receiver_id: bool,
+    /// Only forward as many frames as needed, see
+    /// [`reduce`][crate::output::reduce].
+    pub reduce: bool,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            timestamps: true,
+            df_11_17_only: false,
+            mode_ac: false,
+            receiver_id: false,
+            reduce: false,
+        }
+    }
+}
+
+impl ClientOptions {
+    /// Applies a command sent by the client.
+    ///
+    /// Commands that don't affect the output are ignored.
+    pub fn apply(&mut self, command: &InputPacket) {
+        let InputPacket::DipSwitches(toggle) = command
+        else {
+            return;
+        };
+
+        match *toggle {
+            ToggleDipswitch::TIMESTAMP_INFO_ON => self.timestamps = true,
+            ToggleDipswitch::TIMESTAMP_INFO_OFF => self.timestamps = false,
+            ToggleDipswitch::DF_11_17_ONLY_ON => self.df_11_17_only = true,
+            ToggleDipswitch::DF_11_17_ONLY_OFF => self.df_11_17_only = false,
+            ToggleDipswitch::MODE_AC_ON => self.mode_ac = true,
+            ToggleDipswitch::MODE_AC_OFF => self.mode_ac = false,
+            _ => {}
+        }
+    }
+
+    /// Returns the packets to send to the client for this frame.
+    pub fn packets(&self, frame: &BeastFrame) -> impl Iterator<Item = OutputPacket> {
+        let packet = self.filter(&frame.packet);
+
+        let receiver_id = self
+            .receiver_id
+            .then_some(frame.receiver_id)
+            .flatten()
+            .filter(|_| packet.is_some())
+            .map(|receiver_id| OutputPacket::ReceiverId { receiver_id });
+
+        receiver_id.into_iter().chain(packet)
+    }
+
+    fn filter(&self, packet: &OutputPacket) -> Option<OutputPacket> {
+        let mut packet = packet.clone();
+
+        let (timestamp, downlink_format) = match &mut packet {
+            OutputPacket::ModeAc { timestamp, .. } => {
+                if !self.mode_ac {
+                    return None;
+                }
+                (timestamp, None)
+            }
+            OutputPacket::ModeSShort {
+                timestamp, data, ..
+            } => (timestamp, Some(data[0] >> 3)),
+            OutputPacket::ModeSLong {
+                timestamp, data, ..
+            } => (timestamp, Some(data[0] >> 3)),
+            _ => return None,
+        };
+
+        if self.df_11_17_only
+            && let Some(downlink_format) = downlink_format
+            && downlink_format != 11
+            && downlink_format != 17
+        {
+            return None;
+        }
+
+        if !self.timestamps {
+            *timestamp = MlatTimestamp([0; 6]);
+        }
+
+        Some(packet)
+    }
+}
+
+/// Accepts clients on the configured address until `shutdown` is cancelled.
+///
+/// Each client has its own queue of [`Tracker::beast_frames`]. Clients that
+/// fall behind are disconnected.
+pub async fn serve(
+    config: BeastServerConfig,
+    tracker: Tracker,
+    shutdown: CancellationToken,
+) -> Result<(), Error> {
+    let listener = TcpListener::bind(&config.listen_address).await?;
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                break;
+            }
+            result = listener.accept() => {
+                let (stream, address) = result?;
+                tracing::debug!(%address, "beast client connected");
+
+                let frames = tracker.beast_frames();
+                let options = config.client_options;
+                let reducer = options.reduce.then(|| Reducer::new(config.reduce));
+                let shutdown = shutdown.clone();
+
+                tokio::spawn(async move {
+                    if let Err(error) =
+                        handle_client(stream, frames, options, reducer, shutdown).await
+                    {
+                        tracing::debug!(%address, ?error, "beast client error");
+                    }
+                    tracing::debug!(%address, "beast client disconnected");
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_client(
+    stream: TcpStream,
+    mut frames: broadcast::Receiver<BeastFrame>,
+    mut options: ClientOptions,
+    mut reducer: Option<Reducer>,
+    shutdown: CancellationToken,
+) -> Result<(), Error> {
+    let (reader, writer) = stream.into_split();
+    let mut reader = beast::input::Reader::new(reader);
+    let mut writer = beast::output::Writer::new(writer);
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                break;
+            }
+            command = reader.next() => {
+                match command {
+                    None => break,
+                    Some(command) => options.apply(&command?),
+                }
+            }
+            frame = frames.recv() => {
+                let mut frame = match frame {
+                    Ok(frame) => frame,
+                    Err(RecvError::Lagged(dropped_count)) => {
+                        tracing::info!(dropped_count, "beast client too slow");
+                        break;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+
+                // send everything that is queued, before flushing
+                loop {
+                    let forward = reducer
+                        .as_mut()
+                        .is_none_or(|reducer| reducer.filter(frame.time_received, &frame.packet));
+                    if forward {
+                        for packet in options.packets(&frame) {
+                            writer.feed(packet).await?;
+                        }
+                    }
+
+                    frame = match frames.try_recv() {
+                        Ok(frame) => frame,
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Lagged(dropped_count)) => {
+                            tracing::info!(dropped_count, "beast client too slow");
+                            return Ok(());
+                        }
+                        Err(TryRecvError::Closed) => return Ok(()),
+                    };
+                }
+
+                writer.flush().await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use adsbee_beast::{
+        MlatTimestamp,
+        SignalLevel,
+        input::{
+            InputPacket,
+            ToggleDipswitch,
+        },
+        output::OutputPacket,
+    };
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use crate::{
+        output::beast::ClientOptions,
+        source::SourceId,
+        tracker::BeastFrame,
+    };
+
+    fn frame(data: [u8; 7]) -> BeastFrame {
+        BeastFrame {
+            source_id: SourceId::new(0),
+            receiver_id: Some(Uuid::from_u64_pair(1, 0)),
+            time_received: Utc::now(),
+            packet: OutputPacket::ModeSShort {
+                timestamp: MlatTimestamp([1, 2, 3, 4, 5, 6]),
+                signal_level: SignalLevel(0x80),
+                data,
+            },
+        }
+    }
+
+    #[test]
+    fn it_applies_client_options() {
+        // DF11 and DF4
+        let all_call = frame(*b"\x5d\x3c\x66\x4d\xb2\x9a\x6e");
+        let surveillance = frame(*b"\x20\x00\x17\x18\x00\x00\x00");
+
+        let mut options = ClientOptions::default();
+        assert_eq!(options.packets(&surveillance).count(), 1);
+
+        options.apply(&InputPacket::DipSwitches(ToggleDipswitch::DF_11_17_ONLY_ON));
+        options.apply(&InputPacket::DipSwitches(
+            ToggleDipswitch::TIMESTAMP_INFO_OFF,
+        ));
+        options.receiver_id = true;
+        assert_eq!(options.packets(&surveillance).count(), 0);
+
+        let packets = options.packets(&all_call).collect::<Vec<_>>();
+        assert_eq!(
+            packets,
+            [
+                OutputPacket::ReceiverId {
+                    receiver_id: Uuid::from_u64_pair(1, 0)
+                },
+                OutputPacket::ModeSShort {
+                    timestamp: MlatTimestamp([0; 6]),
+                    signal_level: SignalLevel(0x80),
+                    data: *b"\x5d\x3c\x66\x4d\xb2\x9a\x6e",
+                }
+            ]
+        );
+    }
+}
@@ -0,0 +1,617 @@
+//! Cursor-on-Target (CoT) output
+//!
+//! Generates CoT events for tracked aircraft, which ATAK, WinTAK and TAK
+//! servers can display. Events are either sent as UDP datagrams (usually to
+//! the SA multicast group `239.2.3.1:6969`), or streamed to TCP clients.
+//!
+//! The event type is a MIL-STD-2525 symbol code derived from the emitter
+//! category, e.g. `a-n-A-C-F` for a civil fixed-wing aircraft, or `a-f-A-M-H`
+//! for a military helicopter.
+//!
+//! - [CoT event schema][1]
+//!
+//! [1]: https://www.mitre.org/sites/default/files/pdf/09_4937.pdf
+
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    sync::Arc,
+    time::Duration,
+};
+
+use adsbee_mode_s::adsb::WakeVortexCategory;
+use adsbee_types::{
+    Callsign,
+    IcaoAddress,
+};
+use bytes::Bytes;
+use chrono::{
+    DateTime,
+    SecondsFormat,
+    TimeDelta,
+    Utc,
+};
+use serde::Deserialize;
+use tokio::{
+    io::{
+        AsyncReadExt,
+        AsyncWriteExt,
+    },
+    net::{
+        TcpListener,
+        TcpStream,
+        UdpSocket,
+    },
+    sync::broadcast::{
+        self,
+        error::RecvError,
+    },
+    time::{
+        Interval,
+        MissedTickBehavior,
+    },
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    Error,
+    output::MAX_AGE,
+    tracker::{
+        Tracker,
+        state::{
+            AircraftState,
+            PositionSource,
+            State,
+            fresh,
+            fresh_timestamped,
+        },
+    },
+};
+
+/// Default time after an aircraft was last seen, after which its event is
+/// stale, in seconds.
+pub const DEFAULT_STALE: f64 = 60.0;
+
+/// Value CoT uses for unknown heights and errors.
+const UNKNOWN: f64 = 9999999.0;
+
+/// Number of updates queued for each TCP client.
+const TCP_QUEUE_SIZE: usize = 16;
+
+const METERS_PER_FOOT: f64 = 0.3048;
+const METERS_PER_SECOND_PER_KNOT: f64 = 1852.0 / 3600.0;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CotServerConfig {
+    pub transport: CotTransport,
+
+    /// Interval between updates in seconds
+    #[serde(
+        default = "super::default_interval",
+        deserialize_with = "crate::util::deserialize_positive_seconds"
+    )]
+    pub interval: f64,
+
+    /// Time after an aircraft was last seen, after which its event is stale,
+    /// in seconds
+    #[serde(default = "default_stale")]
+    pub stale: f64,
+}
+
+fn default_stale() -> f64 {
+    DEFAULT_STALE
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum CotTransport {
+    /// Send each event as a UDP datagram. This can be a multicast address.
+    Udp { target_address: String },
+    /// Stream events to any connected TCP client.
+    Tcp { listen_address: String },
+}
+
+/// Sends CoT events until `shutdown` is cancelled.
+///
+/// Aircraft in `military` get military event types.
+pub async fn serve(
+    config: CotServerConfig,
+    tracker: Tracker,
+    military: HashSet<IcaoAddress>,
+    shutdown: CancellationToken,
+) -> Result<(), Error> {
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(config.interval));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let updates = Updates {
+        interval,
+        tracker,
+        military: Arc::new(military),
+        stale: TimeDelta::milliseconds((config.stale * 1000.0) as i64),
+    };
+
+    match config.transport {
+        CotTransport::Udp { target_address } => serve_udp(&target_address, updates, shutdown).await,
+        CotTransport::Tcp { listen_address } => serve_tcp(&listen_address, updates, shutdown).await,
+    }
+}
+
+/// Generates events every interval.
+struct Updates {
+    interval: Interval,
+    tracker: Tracker,
+    military: Arc<HashSet<IcaoAddress>>,
+    stale: TimeDelta,
+}
+
+impl Updates {
+    async fn next(&mut self) -> Vec<Event> {
+        self.interval.tick().await;
+
+        let military = self.military.clone();
+        let stale = self.stale;
+        let time = Utc::now();
+        self.tracker
+            .with_state(move |state| events(state, &military, time, stale))
+            .await
+            .unwrap_or_default()
+    }
+}
+
+async fn serve_udp(
+    target_address: &str,
+    mut updates: Updates,
+    shutdown: CancellationToken,
+) -> Result<(), Error> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(target_address).await?;
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                break;
+            }
+            events = updates.next() => {
+                // one event per datagram
+                for event in events {
+                    if let Err(error) = socket.send(event.to_string().as_bytes()).await {
+                        tracing::debug!(?error, "failed to send cot event");
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn serve_tcp(
+    listen_address: &str,
+    mut updates: Updates,
+    shutdown: CancellationToken,
+) -> Result<(), Error> {
+    let listener = TcpListener::bind(listen_address).await?;
+    let (sender, _) = broadcast::channel(TCP_QUEUE_SIZE);
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                break;
+            }
+            result = listener.accept() => {
+                let (stream, address) = result?;
+                tracing::debug!(%address, "cot client connected");
+
+                let receiver = sender.subscribe();
+                let shutdown = shutdown.clone();
+
+                tokio::spawn(async move {
+                    if let Err(error) = handle_client(stream, receiver, shutdown).await {
+                        tracing::debug!(%address, ?error, "cot client error");
+                    }
+                    tracing::debug!(%address, "cot client disconnected");
+                });
+            }
+            events = updates.next() => {
+                if sender.receiver_count() > 0 {
+                    // events are streamed back to back
+                    let update = events.iter().map(ToString::to_string).collect::<String>();
+                    let _ = sender.send(Bytes::from(update));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_client(
+    stream: TcpStream,
+    mut updates: broadcast::Receiver<Bytes>,
+    shutdown: CancellationToken,
+) -> Result<(), Error> {
+    let (mut reader, mut writer) = stream.into_split();
+
+    // clients might send their own position, which we ignore, but we need to
+    // read to notice when they disconnect.
+    let mut read_buffer = [0; 1024];
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                break;
+            }
+            result = reader.read(&mut read_buffer) => {
+                if result? == 0 {
+                    break;
+                }
+            }
+            update = updates.recv() => {
+                match update {
+                    Ok(update) => writer.write_all(&update).await?,
+                    Err(RecvError::Lagged(dropped_count)) => {
+                        tracing::info!(dropped_count, "cot client too slow");
+                        break;
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Generates events for all aircraft with a recent position.
+pub fn events(
+    state: &State,
+    military: &HashSet<IcaoAddress>,
+    time: DateTime<Utc>,
+    stale: TimeDelta,
+) -> Vec<Event> {
+    state
+        .iter_aircraft()
+        .filter_map(|aircraft| {
+            event(
+                aircraft,
+                military.contains(&aircraft.icao_address),
+                time,
+                stale,
+            )
+        })
+        .collect()
+}
+
+/// Generates the event for an aircraft, or `None` if it has no recent
+/// position.
+pub fn event(
+    aircraft: &AircraftState,
+    military: bool,
+    time: DateTime<Utc>,
+    stale: TimeDelta,
+) -> Option<Event> {
+    let position = fresh_timestamped(&aircraft.position, time, MAX_AGE)?;
+
+    let height = fresh(&aircraft.altitude_gnss, time, MAX_AGE)
+        .or_else(|| fresh(&aircraft.altitude_barometric, time, MAX_AGE))
+        .map(|altitude| f64::from(*altitude) * METERS_PER_FOOT);
+
+    let quality = &aircraft.adsb_quality;
+
+    Some(Event {
+        uid: format!("ICAO-{}", aircraft.icao_address),
+        event_type: event_type(aircraft.wake_vortex_category, military),
+        how: match position.value.source {
+            PositionSource::Mlat => "m-f",
+            _ => "m-g",
+        },
+        time,
+        start: position.last_update,
+        stale: aircraft.last_seen.last_update + stale,
+        latitude: position.value.latitude,
+        longitude: position.value.longitude,
+        height,
+        circular_error: quality
+            .nac_p
+            .and_then(|nac_p| estimated_position_uncertainty(nac_p.as_u8())),
+        linear_error: quality
+            .gva
+            .and_then(|gva| geometric_vertical_accuracy(gva.as_u8())),
+        callsign: aircraft.callsign.as_ref().map(|callsign| callsign.value),
+        course: fresh(&aircraft.track, time, MAX_AGE)
+            .map(|track| track.to_degrees().rem_euclid(360.0)),
+        speed: fresh(&aircraft.ground_speed, time, MAX_AGE)
+            .map(|speed| speed * METERS_PER_SECOND_PER_KNOT),
+    })
+}
+
+/// Derives the 2525 event type from the emitter category.
+///
+/// Military aircraft are assumed to be friendly, others are neutral.
+pub fn event_type(wake_vortex_category: Option<WakeVortexCategory>, military: bool) -> String {
+    let affiliation = if military { 'f' } else { 'n' };
+    let (dimension, function) = match wake_vortex_category {
+        Some(WakeVortexCategory::SurfaceEmergencyVehicle)
+        | Some(WakeVortexCategory::SurfaceServiceVehicle) => ("G-E-V", None),
+        Some(WakeVortexCategory::GroundObstruction { .. }) => ("G", None),
+        Some(WakeVortexCategory::SpaceTransatmospherricVehicle) => ("P", None),
+        Some(WakeVortexCategory::Rotorcraft) => ("A", Some("H")),
+        Some(WakeVortexCategory::LighterThanAir) => ("A", Some("L")),
+        Some(WakeVortexCategory::HighPerformance) if military => ("A", Some("F-F")),
+        Some(WakeVortexCategory::UnmannedAerialVehicle) if military => ("A", Some("F-Q")),
+        Some(
+            WakeVortexCategory::Light
+            | WakeVortexCategory::Medium1
+            | WakeVortexCategory::Medium2
+            | WakeVortexCategory::HighVortexAirrcraft
+            | WakeVortexCategory::Heavy
+            | WakeVortexCategory::HighPerformance
+            | WakeVortexCategory::GliderSailplane
+            | WakeVortexCategory::UltralightHangGliderParaGlider
+            | WakeVortexCategory::UnmannedAerialVehicle,
+        ) => ("A", Some("F")),
+        _ => ("A", None),
+    };
+
+    let mut event_type = format!("a-{affiliation}-{dimension}");
+    if dimension == "A" {
+        event_type.push_str(if military { "-M" } else { "-C" });
+        if let Some(function) = function {
+            event_type.push('-');
+            event_type.push_str(function);
+        }
+    }
+    event_type
+}
+
+/// Upper bound of the estimated position uncertainty for a NACp, in m.
+fn estimated_position_uncertainty(nac_p: u8) -> Option<f64> {
+    match nac_p {
+        1 => Some(18520.0),
+        2 => Some(7408.0),
+        3 => Some(3704.0),
+        4 => Some(1852.0),
+        5 => Some(926.0),
+        6 => Some(555.6),
+        7 => Some(185.2),
+        8 => Some(92.6),
+        9 => Some(30.0),
+        10 => Some(10.0),
+        11 => Some(3.0),
+        _ => None,
+    }
+}
+
+/// Upper bound of the geometric altitude error for a GVA, in m.
+fn geometric_vertical_accuracy(gva: u8) -> Option<f64> {
+    match gva {
+        1 => Some(150.0),
+        2 => Some(45.0),
+        _ => None,
+    }
+}
+
+/// A CoT event
+///
+/// Displaying it gives the XML document.
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub uid: String,
+    pub event_type: String,
+    pub how: &'static str,
+    pub time: DateTime<Utc>,
+    pub start: DateTime<Utc>,
+    pub stale: DateTime<Utc>,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Height above the WGS-84 ellipsoid in m
+    pub height: Option<f64>,
+    /// Circular error in m
+    pub circular_error: Option<f64>,
+    /// Linear (vertical) error in m
+    pub linear_error: Option<f64>,
+    pub callsign: Option<Callsign>,
+    /// in degrees
+    pub course: Option<f64>,
+    /// in m/s
+    pub speed: Option<f64>,
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let timestamp = |time: &DateTime<Utc>| time.to_rfc3339_opts(SecondsFormat::Millis, true);
+
+        write!(
+            f,
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><event version="2.0" uid="{}" type="{}" how="{}" time="{}" start="{}" stale="{}">"#,
+            XmlEscaped(&self.uid),
+            XmlEscaped(&self.event_type),
+            self.how,
+            timestamp(&self.time),
+            timestamp(&self.start),
+            timestamp(&self.stale),
+        )?;
+        write!(
+            f,
+            r#"<point lat="{:.6}" lon="{:.6}" hae="{:.1}" ce="{:.1}" le="{:.1}"/>"#,
+            self.latitude,
+            self.longitude,
+            self.height.unwrap_or(UNKNOWN),
+            self.circular_error.unwrap_or(UNKNOWN),
+            self.linear_error.unwrap_or(UNKNOWN),
+        )?;
+
+        write!(f, "<detail>")?;
+        if let Some(callsign) = &self.callsign {
+            write!(
+                f,
+                r#"<contact callsign="{}"/>"#,
+                XmlEscaped(callsign.trimmed())
+            )?;
+        }
+        if self.course.is_some() || self.speed.is_some() {
+            write!(
+                f,
+                r#"<track course="{:.1}" speed="{:.1}"/>"#,
+                self.course.unwrap_or_default(),
+                self.speed.unwrap_or_default(),
+            )?;
+        }
+        write!(f, "</detail></event>")
+    }
+}
+
+struct XmlEscaped<'a>(&'a str);
+
+impl Display for XmlEscaped<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '<' => write!(f, "&lt;")?,
+                '>' => write!(f, "&gt;")?,
+                '&' => write!(f, "&amp;")?,
+                '"' => write!(f, "&quot;")?,
+                '\'' => write!(f, "&apos;")?,
+                _ => write!(f, "{c}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use adsbee_mode_s::adsb::WakeVortexCategory;
+    use adsbee_types::IcaoAddress;
+    use chrono::{
+        DateTime,
+        TimeDelta,
+        Utc,
+    };
+    use serde_json::{
+        Value,
+        json,
+    };
+    use tokio::{
+        io::AsyncReadExt,
+        net::TcpStream,
+    };
+    use tokio_util::sync::CancellationToken;
+
+    use crate::{
+        output::cot::{
+            CotServerConfig,
+            CotTransport,
+            event_type,
+            events,
+            serve,
+        },
+        tracker::{
+            Tracker,
+            state::{
+                State,
+                fixtures,
+            },
+        },
+    };
+
+    fn aircraft() -> Value {
+        fixtures::aircraft(json!({"seen": 0.5, "seen_pos": 0.5}))
+    }
+
+    #[test]
+    fn it_derives_event_types() {
+        assert_eq!(
+            event_type(Some(WakeVortexCategory::Heavy), false),
+            "a-n-A-C-F"
+        );
+        assert_eq!(
+            event_type(Some(WakeVortexCategory::Rotorcraft), true),
+            "a-f-A-M-H"
+        );
+        assert_eq!(
+            event_type(Some(WakeVortexCategory::HighPerformance), true),
+            "a-f-A-M-F-F"
+        );
+        assert_eq!(event_type(None, true), "a-f-A-M");
+        assert_eq!(
+            event_type(Some(WakeVortexCategory::SurfaceServiceVehicle), false),
+            "a-n-G-E-V"
+        );
+    }
+
+    #[test]
+    fn it_generates_events() {
+        let now = DateTime::from_timestamp(1750000000, 0).unwrap();
+        let mut state = State::default();
+        fixtures::update(&mut state, now, [aircraft()]);
+
+        let military = HashSet::from([IcaoAddress::from_u32_unchecked(0x4ca7b5)]);
+        let events = events(&state, &military, now, TimeDelta::seconds(60));
+        assert_eq!(events.len(), 1);
+
+        let event = &events[0];
+        assert_eq!(event.uid, "ICAO-4ca7b5");
+        assert_eq!(event.event_type, "a-f-A-M");
+        assert_eq!(
+            event.stale.signed_duration_since(now),
+            TimeDelta::milliseconds(59500)
+        );
+
+        let xml = event.to_string();
+        assert!(xml.contains(r#"<point lat="51.477000" lon="-0.461400" hae="11277.6""#));
+        assert!(xml.contains(r#"<contact callsign="RYR4WJ"/>"#));
+        assert!(xml.contains(r#"<track course="270.0" speed="231.5"/>"#));
+        assert!(xml.ends_with("</detail></event>"));
+    }
+
+    #[tokio::test]
+    async fn it_streams_events_over_tcp() {
+        let tracker = Tracker::new();
+        tracker
+            .push_aircraft_json(
+                fixtures::source_id(),
+                fixtures::snapshot(Utc::now(), [aircraft()]),
+            )
+            .await;
+
+        // find a free port
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let listen_address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        let shutdown = CancellationToken::new();
+        tokio::spawn(serve(
+            CotServerConfig {
+                transport: CotTransport::Tcp {
+                    listen_address: listen_address.clone(),
+                },
+                interval: 0.1,
+                stale: 60.0,
+            },
+            tracker,
+            HashSet::new(),
+            shutdown.clone(),
+        ));
+
+        let mut stream = loop {
+            match TcpStream::connect(&listen_address).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+            }
+        };
+
+        let mut received = String::new();
+        let mut buffer = [0; 1024];
+        while !received.contains("</event>") {
+            let n = stream.read(&mut buffer).await.unwrap();
+            assert!(n > 0, "connection closed");
+            received.push_str(std::str::from_utf8(&buffer[..n]).unwrap());
+        }
+        shutdown.cancel();
+
+        assert!(received.contains(r#"type="a-n-A-C""#));
+        assert!(received.contains(r#"uid="ICAO-4ca7b5""#));
+    }
+}
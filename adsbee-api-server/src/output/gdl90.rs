@@ -0,0 +1,402 @@
+//! GDL90 output
+//!
+//! Broadcasts traffic as GDL90 over UDP, which most EFB apps (ForeFlight,
+//! SkyDemon, ...) can display. Once per second we send a heartbeat, the
+//! configured geometric altitude and a traffic report for each aircraft in
+//! range.
+//!
+//! There is no ownship, since we're not on an aircraft. EFB apps usually show
+//! the traffic relative to the device's own GPS position.
+
+use std::time::Duration;
+
+use adsbee_gdl90 as gdl90;
+use adsbee_mode_s::{
+    VerticalStatus,
+    adsb::{
+        EmergencyPriorityStatus,
+        WakeVortexCategory,
+    },
+};
+use adsbee_types::geo::LatLon;
+use chrono::{
+    DateTime,
+    Timelike,
+    Utc,
+};
+use serde::Deserialize;
+use tokio::{
+    net::UdpSocket,
+    time::MissedTickBehavior,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    Error,
+    output::MAX_AGE,
+    tracker::{
+        Tracker,
+        state::{
+            AircraftState,
+            State,
+            fresh,
+        },
+    },
+};
+
+/// Default maximum distance of traffic from the configured position, in nm.
+pub const DEFAULT_MAX_RANGE: f64 = 30.0;
+
+const METERS_PER_NAUTICAL_MILE: f64 = 1852.0;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Gdl90ServerConfig {
+    /// Address datagrams are sent to, e.g. `255.255.255.255:4000` or the
+    /// address of a tablet.
+    pub target_address: String,
+
+    /// Position traffic is filtered around.
+    pub position: LatLon,
+
+    /// Geometric altitude of the position in ft. This is sent as ownship
+    /// geometric altitude, and used to filter traffic by altitude.
+    pub altitude: Option<i32>,
+
+    /// Maximum distance of traffic, in nm.
+    #[serde(default = "default_max_range")]
+    pub max_range: f64,
+
+    /// Maximum altitude difference of traffic, in ft.
+    pub max_altitude_difference: Option<i32>,
+}
+
+fn default_max_range() -> f64 {
+    DEFAULT_MAX_RANGE
+}
+
+/// Sends GDL90 messages to the configured address until `shutdown` is
+/// cancelled.
+pub async fn serve(
+    config: Gdl90ServerConfig,
+    tracker: Tracker,
+    shutdown: CancellationToken,
+) -> Result<(), Error> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.set_broadcast(true)?;
+    socket.connect(&config.target_address).await?;
+
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut buffer = vec![];
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                break;
+            }
+            _ = interval.tick() => {
+                let time = Utc::now();
+                let messages = tracker
+                    .with_state({
+                        let config = config.clone();
+                        move |state| messages(&config, state, time)
+                    })
+                    .await
+                    .unwrap_or_default();
+
+                // one message per datagram
+                for message in messages {
+                    buffer.clear();
+                    message.encode(&mut buffer);
+                    if let Err(error) = socket.send(&buffer).await {
+                        tracing::debug!(?error, "failed to send gdl90 message");
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Generates the messages sent at `time`.
+pub fn messages(
+    config: &Gdl90ServerConfig,
+    state: &State,
+    time: DateTime<Utc>,
+) -> Vec<gdl90::Message> {
+    let mut messages = vec![gdl90::Message::Heartbeat(gdl90::Heartbeat {
+        utc_ok: true,
+        timestamp: time.num_seconds_from_midnight(),
+        ..Default::default()
+    })];
+
+    if let Some(altitude) = config.altitude {
+        messages.push(gdl90::Message::OwnshipGeometricAltitude(
+            gdl90::OwnshipGeometricAltitude {
+                altitude,
+                vertical_warning: false,
+                vertical_figure_of_merit: None,
+            },
+        ));
+    }
+
+    messages.extend(
+        state
+            .iter_aircraft()
+            .filter_map(|aircraft| traffic_report(aircraft, time))
+            .filter(|report| in_range(config, report))
+            .map(gdl90::Message::TrafficReport),
+    );
+
+    messages
+}
+
+/// Generates the traffic report for an aircraft, or `None` if it has no
+/// recent position.
+pub fn traffic_report(
+    aircraft: &AircraftState,
+    time: DateTime<Utc>,
+) -> Option<gdl90::TrafficReport> {
+    let position = fresh(&aircraft.position, time, MAX_AGE)?;
+
+    let (track_type, track) = if let Some(track) = fresh(&aircraft.track, time, MAX_AGE) {
+        (gdl90::TrackType::TrueTrack, *track)
+    }
+    else if let Some(heading) = fresh(&aircraft.true_heading, time, MAX_AGE) {
+        (gdl90::TrackType::TrueHeading, *heading)
+    }
+    else if let Some(heading) = fresh(&aircraft.magnetic_heading, time, MAX_AGE) {
+        (gdl90::TrackType::MagneticHeading, *heading)
+    }
+    else {
+        (gdl90::TrackType::Invalid, 0.0)
+    };
+
+    let emergency_priority_status = aircraft
+        .squawk
+        .as_ref()
+        .and_then(|squawk| EmergencyPriorityStatus::from_squawk(squawk.value))
+        .unwrap_or(EmergencyPriorityStatus::NO_EMERGENCY);
+
+    // readsb marks addresses that aren't ICAO addresses with a `~`. these are
+    // closest to self-assigned addresses.
+    let address_type = if aircraft.icao_address.non_icao() {
+        gdl90::AddressType::AdsbSelfAssigned
+    }
+    else {
+        gdl90::AddressType::AdsbIcao
+    };
+
+    Some(gdl90::TrafficReport {
+        traffic_alert: false,
+        address_type,
+        address: aircraft.icao_address.into(),
+        latitude: position.latitude,
+        longitude: position.longitude,
+        pressure_altitude: fresh(&aircraft.altitude_barometric, time, MAX_AGE).copied(),
+        airborne: aircraft.vertical_status != Some(VerticalStatus::Ground),
+        extrapolated: false,
+        track_type,
+        track: track.to_degrees(),
+        nic: aircraft.adsb_quality.nic.unwrap_or_default(),
+        nac_p: aircraft.adsb_quality.nac_p.map_or(0, |nac_p| nac_p.as_u8()),
+        horizontal_velocity: fresh(&aircraft.ground_speed, time, MAX_AGE).copied(),
+        vertical_velocity: fresh(&aircraft.vertical_rate, time, MAX_AGE).copied(),
+        emitter_category: emitter_category(aircraft.wake_vortex_category),
+        callsign: aircraft.callsign.as_ref().map(|callsign| callsign.value),
+        emergency_priority_status,
+    })
+}
+
+/// Maps the ADS-B emitter category to the GDL90 one.
+///
+/// GDL90 uses the same categories as ADS-B, numbered through sets A to C.
+pub fn emitter_category(wake_vortex_category: Option<WakeVortexCategory>) -> u8 {
+    match wake_vortex_category {
+        Some(WakeVortexCategory::Light) => 1,
+        Some(WakeVortexCategory::Medium1) => 2,
+        Some(WakeVortexCategory::Medium2) => 3,
+        Some(WakeVortexCategory::HighVortexAirrcraft) => 4,
+        Some(WakeVortexCategory::Heavy) => 5,
+        Some(WakeVortexCategory::HighPerformance) => 6,
+        Some(WakeVortexCategory::Rotorcraft) => 7,
+        Some(WakeVortexCategory::GliderSailplane) => 9,
+        Some(WakeVortexCategory::LighterThanAir) => 10,
+        Some(WakeVortexCategory::ParachutistSkydiver) => 11,
+        Some(WakeVortexCategory::UltralightHangGliderParaGlider) => 12,
+        Some(WakeVortexCategory::UnmannedAerialVehicle) => 14,
+        Some(WakeVortexCategory::SpaceTransatmospherricVehicle) => 15,
+        Some(WakeVortexCategory::SurfaceEmergencyVehicle) => 17,
+        Some(WakeVortexCategory::SurfaceServiceVehicle) => 18,
+        // we don't know which kind of obstacle it is, so report a point obstacle.
+        Some(WakeVortexCategory::GroundObstruction { .. }) => 19,
+        Some(
+            WakeVortexCategory::NoCategoryInformation { .. } | WakeVortexCategory::Reserved { .. },
+        )
+        | None => 0,
+    }
+}
+
+fn in_range(config: &Gdl90ServerConfig, report: &gdl90::TrafficReport) -> bool {
+    let distance = config
+        .position
+        .haversine_distance(&LatLon::new(report.latitude, report.longitude))
+        / METERS_PER_NAUTICAL_MILE;
+    if distance > config.max_range {
+        return false;
+    }
+
+    match (config.max_altitude_difference, report.pressure_altitude) {
+        (Some(max_altitude_difference), Some(altitude)) => {
+            (altitude - config.altitude.unwrap_or_default()).abs() <= max_altitude_difference
+        }
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use adsbee_gdl90 as gdl90;
+    use adsbee_mode_s::adsb::WakeVortexCategory;
+    use adsbee_types::geo::LatLon;
+    use chrono::Utc;
+    use serde_json::json;
+    use tokio::net::UdpSocket;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::{
+        output::gdl90::{
+            Gdl90ServerConfig,
+            emitter_category,
+            messages,
+            serve,
+        },
+        tracker::{
+            Tracker,
+            state::{
+                State,
+                fixtures,
+            },
+        },
+    };
+
+    fn config(target_address: String) -> Gdl90ServerConfig {
+        Gdl90ServerConfig {
+            target_address,
+            position: LatLon::new(51.477, -0.4614),
+            altitude: Some(80),
+            max_range: 30.0,
+            max_altitude_difference: Some(20000),
+        }
+    }
+
+    #[test]
+    fn it_filters_traffic() {
+        let now = Utc::now();
+        let mut state = State::default();
+        fixtures::update(
+            &mut state,
+            now,
+            [
+                fixtures::aircraft(
+                    json!({"alt_baro": 5000, "baro_rate": -640, "lat": 51.5, "lon": -0.5, "seen_pos": 0.5}),
+                ),
+                // too high
+                fixtures::aircraft(
+                    json!({"hex": "3c6444", "lat": 51.5, "lon": -0.5, "seen_pos": 0.5}),
+                ),
+                // too far
+                fixtures::aircraft(
+                    json!({"hex": "400f01", "alt_baro": 5000, "lat": 53.35, "lon": -2.27, "seen_pos": 0.5}),
+                ),
+                // no recent position
+                fixtures::aircraft(
+                    json!({"hex": "400f02", "alt_baro": 5000, "lat": 51.5, "lon": -0.5, "seen_pos": 60.0}),
+                ),
+            ],
+        );
+
+        let messages = messages(&config(String::new()), &state, now);
+        assert_eq!(messages.len(), 3);
+        assert!(matches!(messages[0], gdl90::Message::Heartbeat(_)));
+        assert!(matches!(
+            messages[1],
+            gdl90::Message::OwnshipGeometricAltitude(_)
+        ));
+        let gdl90::Message::TrafficReport(report) = &messages[2]
+        else {
+            panic!("not a traffic report: {:?}", messages[2]);
+        };
+        assert_eq!(report.address, 0x4ca7b5);
+        assert_eq!(report.address_type, gdl90::AddressType::AdsbIcao);
+        assert_eq!(report.pressure_altitude, Some(5000));
+        assert_eq!(report.vertical_velocity, Some(-640));
+        assert_eq!(report.track_type, gdl90::TrackType::TrueTrack);
+        assert!((report.track - 270.0).abs() < 1e-6);
+        assert_eq!(report.callsign.unwrap().trimmed(), "RYR4WJ");
+    }
+
+    #[test]
+    fn it_marks_non_icao_addresses() {
+        let now = Utc::now();
+        let mut state = State::default();
+        fixtures::update(
+            &mut state,
+            now,
+            [fixtures::aircraft(
+                json!({"hex": "~4ca7b5", "alt_baro": 5000}),
+            )],
+        );
+
+        let messages = messages(&config(String::new()), &state, now);
+        let gdl90::Message::TrafficReport(report) = &messages[2]
+        else {
+            panic!("not a traffic report: {:?}", messages[2]);
+        };
+        assert_eq!(report.address, 0x4ca7b5);
+        assert_eq!(report.address_type, gdl90::AddressType::AdsbSelfAssigned);
+    }
+
+    #[test]
+    fn it_maps_emitter_categories() {
+        assert_eq!(emitter_category(None), 0);
+        assert_eq!(
+            emitter_category(Some(WakeVortexCategory::NoCategoryInformation {
+                type_code: 4
+            })),
+            0
+        );
+        assert_eq!(emitter_category(Some(WakeVortexCategory::Light)), 1);
+        assert_eq!(emitter_category(Some(WakeVortexCategory::Heavy)), 5);
+        assert_eq!(emitter_category(Some(WakeVortexCategory::Rotorcraft)), 7);
+        assert_eq!(
+            emitter_category(Some(WakeVortexCategory::GliderSailplane)),
+            9
+        );
+        assert_eq!(
+            emitter_category(Some(WakeVortexCategory::SurfaceEmergencyVehicle)),
+            17
+        );
+    }
+
+    #[tokio::test]
+    async fn it_sends_to_localhost() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let shutdown = CancellationToken::new();
+
+        tokio::spawn(serve(
+            config(receiver.local_addr().unwrap().to_string()),
+            Tracker::new(),
+            shutdown.clone(),
+        ));
+
+        let mut buffer = [0; 64];
+        let length = receiver.recv(&mut buffer).await.unwrap();
+        shutdown.cancel();
+
+        // heartbeat
+        assert_eq!(buffer[..2], [0x7e, 0x00]);
+        assert_eq!(buffer[length - 1], 0x7e);
+    }
+}
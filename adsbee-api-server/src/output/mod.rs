@@ -0,0 +1,22 @@
+use chrono::TimeDelta;
+
+pub mod asterix;
+pub mod beast;
+pub mod cot;
+pub mod gdl90;
+pub mod mqtt;
+pub mod reduce;
+pub mod sbs;
+pub mod webhook;
+
+/// Values older than this are not sent by the outputs that periodically send
+/// the whole state.
+pub const MAX_AGE: TimeDelta = TimeDelta::seconds(15);
+
+/// Default interval between updates of the outputs that periodically send the
+/// whole state, in seconds.
+pub const DEFAULT_INTERVAL: f64 = 1.0;
+
+fn default_interval() -> f64 {
+    DEFAULT_INTERVAL
+}
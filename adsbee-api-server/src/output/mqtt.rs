@@ -0,0 +1,623 @@
+//! MQTT output
+//!
+//! Publishes the state of each aircraft to a retained topic (by default
+//! `adsb/aircraft/<icao>`) as JSON, whenever it changes. When an aircraft
+//! hasn't been seen for a while, its retained message is cleared.
+//!
+//! Additionally events are published when an aircraft is seen for the first
+//! time, and when it starts squawking an emergency code.
+
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    time::Duration,
+};
+
+use adsbee_mode_s::{
+    VerticalStatus,
+    adsb::EmergencyPriorityStatus,
+};
+use adsbee_types::{
+    Callsign,
+    IcaoAddress,
+    Squawk,
+};
+use chrono::{
+    DateTime,
+    TimeDelta,
+    Utc,
+};
+use rumqttc::{
+    AsyncClient,
+    Event,
+    EventLoop,
+    MqttOptions,
+    Packet,
+    QoS,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    Error,
+    tracker::{
+        Tracker,
+        state::{
+            AircraftState,
+            State,
+        },
+    },
+};
+
+pub const DEFAULT_PORT: u16 = 1883;
+
+pub const DEFAULT_CLIENT_ID: &str = "adsbee";
+
+/// Default time after which aircraft that weren't seen are removed, in
+/// seconds.
+pub const DEFAULT_EXPIRE: f64 = 300.0;
+
+pub const DEFAULT_AIRCRAFT_TOPIC: &str = "adsb/aircraft/{icao}";
+
+pub const DEFAULT_EMERGENCY_TOPIC: &str = "adsb/events/emergency";
+
+pub const DEFAULT_NEW_AIRCRAFT_TOPIC: &str = "adsb/events/new_aircraft";
+
+/// Placeholder for the ICAO address in topics.
+const ICAO_PLACEHOLDER: &str = "{icao}";
+
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+/// Number of messages queued while we're not connected to the broker.
+const REQUEST_QUEUE_SIZE: usize = 1024;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MqttConfig {
+    /// Host name or address of the broker
+    pub host: String,
+
+    #[serde(default = "default_port")]
+    pub port: u16,
+
+    #[serde(default = "default_client_id")]
+    pub client_id: String,
+
+    pub username: Option<String>,
+    pub password: Option<String>,
+
+    #[serde(default)]
+    pub topics: MqttTopics,
+
+    #[serde(default)]
+    pub qos: MqttQos,
+
+    /// Interval between updates in seconds
+    #[serde(
+        default = "super::default_interval",
+        deserialize_with = "crate::util::deserialize_positive_seconds"
+    )]
+    pub interval: f64,
+
+    /// Time after which aircraft that weren't seen are removed, in seconds
+    #[serde(default = "default_expire")]
+    pub expire: f64,
+}
+
+fn default_port() -> u16 {
+    DEFAULT_PORT
+}
+
+fn default_client_id() -> String {
+    DEFAULT_CLIENT_ID.to_owned()
+}
+
+fn default_expire() -> f64 {
+    DEFAULT_EXPIRE
+}
+
+/// Topics to publish to. `{icao}` is replaced with the aircraft's ICAO
+/// address.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct MqttTopics {
+    /// Retained aircraft state
+    pub aircraft: String,
+    /// Aircraft that start squawking an emergency code
+    pub emergency: String,
+    /// Aircraft that are seen for the first time
+    pub new_aircraft: String,
+}
+
+impl Default for MqttTopics {
+    fn default() -> Self {
+        Self {
+            aircraft: DEFAULT_AIRCRAFT_TOPIC.to_owned(),
+            emergency: DEFAULT_EMERGENCY_TOPIC.to_owned(),
+            new_aircraft: DEFAULT_NEW_AIRCRAFT_TOPIC.to_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MqttQos {
+    #[default]
+    AtMostOnce,
+    AtLeastOnce,
+    ExactlyOnce,
+}
+
+/// Parses the QoS by name (e.g. `at_least_once`) or level (e.g. `1`).
+impl FromStr for MqttQos {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" | "at_most_once" => Ok(Self::AtMostOnce),
+            "1" | "at_least_once" => Ok(Self::AtLeastOnce),
+            "2" | "exactly_once" => Ok(Self::ExactlyOnce),
+            _ => Err(format!("invalid QoS: {s}")),
+        }
+    }
+}
+
+impl From<MqttQos> for QoS {
+    fn from(value: MqttQos) -> Self {
+        match value {
+            MqttQos::AtMostOnce => QoS::AtMostOnce,
+            MqttQos::AtLeastOnce => QoS::AtLeastOnce,
+            MqttQos::ExactlyOnce => QoS::ExactlyOnce,
+        }
+    }
+}
+
+/// Publishes to the configured broker until `shutdown` is cancelled.
+///
+/// If the connection to the broker fails, we reconnect with exponential
+/// backoff. Messages are queued while disconnected, and dropped if the queue
+/// is full.
+pub async fn serve(
+    config: MqttConfig,
+    tracker: Tracker,
+    shutdown: CancellationToken,
+) -> Result<(), Error> {
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(KEEP_ALIVE);
+    if let Some(username) = &config.username {
+        options.set_credentials(username, config.password.clone().unwrap_or_default());
+    }
+
+    let (client, event_loop) = AsyncClient::new(options, REQUEST_QUEUE_SIZE);
+    tokio::spawn(run_event_loop(event_loop, shutdown.clone()));
+
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(config.interval));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let expire = TimeDelta::milliseconds((config.expire * 1000.0) as i64);
+    let qos = QoS::from(config.qos);
+    let mut publisher = Publisher::new(config.topics);
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                let _ = client.try_disconnect();
+                break;
+            }
+            _ = interval.tick() => {
+                let time = Utc::now();
+                let Some(aircraft) = tracker
+                    .with_state(move |state| aircraft_messages(state, time, expire))
+                    .await
+                else {
+                    continue;
+                };
+
+                for message in publisher.update(aircraft) {
+                    if let Err(error) =
+                        client.try_publish(message.topic, qos, message.retain, message.payload)
+                    {
+                        tracing::debug!(%error, "dropping mqtt message");
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Drives the connection to the broker, and reconnects with exponential
+/// backoff.
+async fn run_event_loop(mut event_loop: EventLoop, shutdown: CancellationToken) {
+    let mut backoff = MIN_BACKOFF;
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                break;
+            }
+            event = event_loop.poll() => {
+                match event {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        tracing::debug!("connected to mqtt broker");
+                        backoff = MIN_BACKOFF;
+                    }
+                    Ok(_) => {}
+                    Err(error) => {
+                        tracing::warn!(%error, ?backoff, "mqtt connection failed");
+                        tokio::select! {
+                            _ = shutdown.cancelled() => break,
+                            _ = tokio::time::sleep(backoff) => {}
+                        }
+                        backoff = (backoff * 2).min(MAX_BACKOFF);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Generates the messages for all aircraft seen in the last `expire`.
+pub fn aircraft_messages(
+    state: &State,
+    time: DateTime<Utc>,
+    expire: TimeDelta,
+) -> Vec<AircraftMessage> {
+    state
+        .iter_aircraft()
+        .filter(|aircraft| time.signed_duration_since(aircraft.last_seen.last_update) <= expire)
+        .map(AircraftMessage::from_state)
+        .collect()
+}
+
+/// JSON payload for an aircraft
+#[derive(Clone, Debug, Serialize)]
+pub struct AircraftMessage {
+    pub icao: IcaoAddress,
+    pub callsign: Option<Callsign>,
+    pub squawk: Option<Squawk>,
+    /// Emergency indicated by the squawk, e.g. `general` or
+    /// `unlawful_interference`
+    pub emergency: Option<&'static str>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// in ft
+    pub altitude_barometric: Option<i32>,
+    /// in ft, height above ellipsoid
+    pub altitude_gnss: Option<i32>,
+    /// in kt
+    pub ground_speed: Option<f64>,
+    /// in degrees
+    pub track: Option<f64>,
+    pub on_ground: Option<bool>,
+    pub last_seen: DateTime<Utc>,
+}
+
+impl AircraftMessage {
+    pub fn from_state(aircraft: &AircraftState) -> Self {
+        let squawk = aircraft.squawk.as_ref().map(|squawk| squawk.value);
+        let position = aircraft.position.as_ref().map(|position| &position.value);
+
+        Self {
+            icao: aircraft.icao_address,
+            callsign: aircraft.callsign.as_ref().map(|callsign| callsign.value),
+            squawk,
+            emergency: squawk
+                .and_then(EmergencyPriorityStatus::from_squawk)
+                .map(emergency_name),
+            latitude: position.map(|position| position.latitude),
+            longitude: position.map(|position| position.longitude),
+            altitude_barometric: aircraft
+                .altitude_barometric
+                .as_ref()
+                .map(|altitude| altitude.value),
+            altitude_gnss: aircraft
+                .altitude_gnss
+                .as_ref()
+                .map(|altitude| altitude.value),
+            ground_speed: aircraft.ground_speed.as_ref().map(|speed| speed.value),
+            track: aircraft
+                .track
+                .as_ref()
+                .map(|track| track.value.to_degrees().rem_euclid(360.0)),
+            on_ground: aircraft
+                .vertical_status
+                .map(|vertical_status| vertical_status == VerticalStatus::Ground),
+            last_seen: aircraft.last_seen.last_update,
+        }
+    }
+}
+
+fn emergency_name(status: EmergencyPriorityStatus) -> &'static str {
+    match status {
+        EmergencyPriorityStatus::GENERAL_EMERGENCY => "general",
+        EmergencyPriorityStatus::LIFEGUARD_MEDICAL_EMERGENCY => "lifeguard",
+        EmergencyPriorityStatus::MINIMAL_FUEL => "minimum_fuel",
+        EmergencyPriorityStatus::NO_COMMUNICATIONS => "no_communications",
+        EmergencyPriorityStatus::UNLAWFUL_INTERFERENCE => "unlawful_interference",
+        EmergencyPriorityStatus::DOWNED_AIRCRAFT => "downed_aircraft",
+        _ => "unknown",
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MqttMessage {
+    pub topic: String,
+    pub retain: bool,
+    pub payload: Vec<u8>,
+}
+
+/// Decides what to publish, by remembering what was published before.
+#[derive(Debug)]
+pub struct Publisher {
+    topics: MqttTopics,
+    published: HashMap<IcaoAddress, Published>,
+}
+
+#[derive(Debug)]
+struct Published {
+    last_seen: DateTime<Utc>,
+    emergency: bool,
+}
+
+impl Publisher {
+    pub fn new(topics: MqttTopics) -> Self {
+        Self {
+            topics,
+            published: HashMap::new(),
+        }
+    }
+
+    /// Returns the messages to publish for a snapshot of all aircraft.
+    ///
+    /// Aircraft that were published before, but aren't in the snapshot
+    /// anymore, are removed.
+    pub fn update(&mut self, aircraft: Vec<AircraftMessage>) -> Vec<MqttMessage> {
+        let mut messages = vec![];
+        let mut published = HashMap::with_capacity(aircraft.len());
+
+        for aircraft in aircraft {
+            let previous = self.published.remove(&aircraft.icao);
+            let emergency = aircraft.emergency.is_some();
+            let payload = serde_json::to_vec(&aircraft).expect("aircraft message serialization");
+
+            if previous.is_none() {
+                messages.push(MqttMessage {
+                    topic: topic(&self.topics.new_aircraft, aircraft.icao),
+                    retain: false,
+                    payload: payload.clone(),
+                });
+            }
+
+            if emergency && !previous.as_ref().is_some_and(|previous| previous.emergency) {
+                messages.push(MqttMessage {
+                    topic: topic(&self.topics.emergency, aircraft.icao),
+                    retain: false,
+                    payload: payload.clone(),
+                });
+            }
+
+            if previous
+                .as_ref()
+                .is_none_or(|previous| previous.last_seen < aircraft.last_seen)
+            {
+                messages.push(MqttMessage {
+                    topic: topic(&self.topics.aircraft, aircraft.icao),
+                    retain: true,
+                    payload,
+                });
+            }
+
+            published.insert(
+                aircraft.icao,
+                Published {
+                    last_seen: aircraft.last_seen,
+                    emergency,
+                },
+            );
+        }
+
+        // an empty retained message removes the retained message
+        for icao in self.published.keys() {
+            messages.push(MqttMessage {
+                topic: topic(&self.topics.aircraft, *icao),
+                retain: true,
+                payload: vec![],
+            });
+        }
+
+        self.published = published;
+        messages
+    }
+}
+
+fn topic(template: &str, icao: IcaoAddress) -> String {
+    template.replace(ICAO_PLACEHOLDER, &icao.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use chrono::{
+        DateTime,
+        TimeDelta,
+        Utc,
+    };
+    use rumqttc::{
+        ConnAck,
+        ConnectReturnCode,
+        Packet,
+    };
+    use serde_json::json;
+    use tokio::{
+        io::{
+            AsyncReadExt,
+            AsyncWriteExt,
+        },
+        net::{
+            TcpListener,
+            TcpStream,
+        },
+    };
+    use tokio_util::sync::CancellationToken;
+
+    use crate::{
+        output::mqtt::{
+            AircraftMessage,
+            MqttConfig,
+            MqttMessage,
+            MqttQos,
+            Publisher,
+            aircraft_messages,
+            serve,
+        },
+        tracker::{
+            Tracker,
+            state::{
+                State,
+                fixtures,
+            },
+        },
+    };
+
+    async fn next_packet(stream: &mut TcpStream, buffer: &mut BytesMut) -> Packet {
+        loop {
+            match Packet::read(buffer, 1 << 16) {
+                Ok(packet) => return packet,
+                Err(rumqttc::Error::InsufficientBytes(_)) => {}
+                Err(error) => panic!("{error:?}"),
+            }
+            assert!(stream.read_buf(buffer).await.unwrap() > 0);
+        }
+    }
+
+    /// Messages for the fixture aircraft, last seen `seen` seconds after the
+    /// start of the test.
+    fn aircraft(seen: i64, squawk: &str) -> Vec<AircraftMessage> {
+        let now =
+            "2025-06-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap() + TimeDelta::seconds(seen);
+        let mut state = State::default();
+        fixtures::update(
+            &mut state,
+            now,
+            [fixtures::aircraft(json!({"squawk": squawk}))],
+        );
+        aircraft_messages(&state, now, TimeDelta::seconds(300))
+    }
+
+    fn topics(messages: &[MqttMessage]) -> Vec<&str> {
+        messages
+            .iter()
+            .map(|message| message.topic.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn it_parses_qos() {
+        assert_eq!("0".parse(), Ok(MqttQos::AtMostOnce));
+        assert_eq!("at_least_once".parse(), Ok(MqttQos::AtLeastOnce));
+        assert_eq!("2".parse(), Ok(MqttQos::ExactlyOnce));
+        assert!("3".parse::<MqttQos>().is_err());
+    }
+
+    #[test]
+    fn it_publishes_changes_and_events() {
+        let mut publisher = Publisher::new(Default::default());
+
+        let messages = publisher.update(aircraft(0, "1000"));
+        assert_eq!(
+            topics(&messages),
+            ["adsb/events/new_aircraft", "adsb/aircraft/4ca7b5"]
+        );
+        assert!(messages[1].retain);
+        let payload: serde_json::Value = serde_json::from_slice(&messages[1].payload).unwrap();
+        assert_eq!(payload["icao"], "4ca7b5");
+        assert_eq!(payload["callsign"], "RYR4WJ");
+        assert_eq!(payload["altitude_barometric"], 37000);
+
+        // not seen since
+        let messages = publisher.update(aircraft(0, "1000"));
+        assert!(messages.is_empty());
+
+        let messages = publisher.update(aircraft(1, "7700"));
+        assert_eq!(
+            topics(&messages),
+            ["adsb/events/emergency", "adsb/aircraft/4ca7b5"]
+        );
+
+        // still squawking 7700, no new event
+        let messages = publisher.update(aircraft(2, "7700"));
+        assert_eq!(topics(&messages), ["adsb/aircraft/4ca7b5"]);
+
+        // expired
+        let messages = publisher.update(vec![]);
+        assert_eq!(topics(&messages), ["adsb/aircraft/4ca7b5"]);
+        assert!(messages[0].retain);
+        assert!(messages[0].payload.is_empty());
+    }
+
+    #[tokio::test]
+    async fn it_publishes_to_a_broker() {
+        let tracker = Tracker::new();
+        tracker
+            .push_aircraft_json(
+                fixtures::source_id(),
+                fixtures::snapshot(Utc::now(), [fixtures::aircraft(json!({"seen": 0.5}))]),
+            )
+            .await;
+
+        // a minimal broker, that accepts a connection and reads publishes
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let shutdown = CancellationToken::new();
+        tokio::spawn(serve(
+            MqttConfig {
+                host: "127.0.0.1".to_owned(),
+                port,
+                client_id: "adsbee-test".to_owned(),
+                username: None,
+                password: None,
+                topics: Default::default(),
+                qos: Default::default(),
+                interval: 0.1,
+                expire: 300.0,
+            },
+            tracker,
+            shutdown.clone(),
+        ));
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buffer = BytesMut::new();
+
+        let Packet::Connect(connect) = next_packet(&mut stream, &mut buffer).await
+        else {
+            panic!("expected connect");
+        };
+        assert_eq!(connect.client_id, "adsbee-test");
+
+        let mut connack = BytesMut::new();
+        ConnAck::new(ConnectReturnCode::Success, false)
+            .write(&mut connack)
+            .unwrap();
+        stream.write_all(&connack).await.unwrap();
+
+        let mut publishes = vec![];
+        while publishes.len() < 2 {
+            if let Packet::Publish(publish) = next_packet(&mut stream, &mut buffer).await {
+                publishes.push(publish);
+            }
+        }
+        shutdown.cancel();
+
+        assert_eq!(publishes[0].topic, "adsb/events/new_aircraft");
+        assert_eq!(publishes[1].topic, "adsb/aircraft/4ca7b5");
+        assert!(publishes[1].retain);
+    }
+}
@@ -0,0 +1,319 @@
+//! BEAST reduce mode
+//!
+//! Forwards only as many frames per aircraft as a map needs, similar to
+//! readsb's `--net-beast-reduce-out-port`. Frames are decoded just enough to
+//! find the aircraft address and what kind of information they carry. For
+//! each aircraft and kind of frame, a frame is forwarded at most once per
+//! [`ReduceConfig::interval`]. Frames that don't carry positions or velocities
+//! are only forwarded if their content changed, or after
+//! [`ReduceConfig::keepalive_interval`].
+
+use std::{
+    collections::{
+        HashMap,
+        hash_map::DefaultHasher,
+    },
+    hash::{
+        Hash,
+        Hasher,
+    },
+};
+
+use adsbee_beast::output::OutputPacket;
+use adsbee_mode_s as mode_s;
+use adsbee_types::IcaoAddress;
+use chrono::{
+    DateTime,
+    TimeDelta,
+    Utc,
+};
+use serde::Deserialize;
+
+/// Aircraft that haven't announced their address for this long are
+/// forgotten.
+const AIRCRAFT_TIMEOUT: TimeDelta = TimeDelta::minutes(5);
+
+/// How often we look for aircraft to forget.
+const PRUNE_INTERVAL: TimeDelta = TimeDelta::seconds(60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ReduceConfig {
+    /// Minimum interval between frames of the same kind from an aircraft, in
+    /// seconds.
+    pub interval: f64,
+    /// Interval in seconds after which frames are forwarded even if their
+    /// content didn't change.
+    pub keepalive_interval: f64,
+}
+
+impl Default for ReduceConfig {
+    fn default() -> Self {
+        Self {
+            interval: 1.0,
+            keepalive_interval: 10.0,
+        }
+    }
+}
+
+/// What a frame is about. Frames of the same kind from the same aircraft
+/// supersede each other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum FrameKind {
+    AllCall,
+    Identification,
+    /// CPR needs both formats to decode positions, so we track them
+    /// separately.
+    Position {
+        odd: bool,
+    },
+    Velocity,
+    /// Other ADS-B messages, by type code
+    AdsbOther {
+        type_code: u8,
+    },
+    Altitude,
+    Identity,
+    CommB {
+        downlink_format: u8,
+    },
+    Acas {
+        downlink_format: u8,
+    },
+}
+
+impl FrameKind {
+    /// Whether these frames carry continuously changing data, and should be
+    /// forwarded regardless of whether they changed.
+    fn is_continuous(&self) -> bool {
+        matches!(self, Self::Position { .. } | Self::Velocity)
+    }
+}
+
+#[derive(Debug)]
+struct LastForwarded {
+    time: DateTime<Utc>,
+    content_hash: u64,
+}
+
+#[derive(Debug)]
+struct Aircraft {
+    last_announced: DateTime<Utc>,
+    last_forwarded: HashMap<FrameKind, LastForwarded>,
+}
+
+/// Decides which frames to forward.
+#[derive(Debug)]
+pub struct Reducer {
+    interval: TimeDelta,
+    keepalive_interval: TimeDelta,
+    aircraft: HashMap<IcaoAddress, Aircraft>,
+    last_pruned: Option<DateTime<Utc>>,
+}
+
+impl Reducer {
+    pub fn new(config: ReduceConfig) -> Self {
+        Self {
+            interval: TimeDelta::milliseconds((config.interval * 1000.0) as i64),
+            keepalive_interval: TimeDelta::milliseconds(
+                (config.keepalive_interval * 1000.0) as i64,
+            ),
+            aircraft: HashMap::new(),
+            last_pruned: None,
+        }
+    }
+
+    /// Returns whether the packet received at `time` should be forwarded.
+    ///
+    /// Mode A/C frames, and frames we can't decode or attribute to an aircraft
+    /// are dropped. Packets that aren't frames (e.g. receiver IDs) are always
+    /// forwarded.
+    pub fn filter(&mut self, time: DateTime<Utc>, packet: &OutputPacket) -> bool {
+        let data = match packet {
+            OutputPacket::ModeSShort { data, .. } => &data[..],
+            OutputPacket::ModeSLong { data, .. } => &data[..],
+            OutputPacket::ModeAc { .. } => return false,
+            _ => return true,
+        };
+
+        self.prune(time);
+
+        let Some((address, kind, announced)) = classify(data)
+        else {
+            return false;
+        };
+
+        let aircraft = if announced {
+            let aircraft = self.aircraft.entry(address).or_insert_with(|| {
+                Aircraft {
+                    last_announced: time,
+                    last_forwarded: HashMap::new(),
+                }
+            });
+            aircraft.last_announced = time;
+            aircraft
+        }
+        else if let Some(aircraft) = self.aircraft.get_mut(&address) {
+            aircraft
+        }
+        else {
+            // the address was recovered from the parity, and we haven't seen the
+            // aircraft announce it. this is most likely a corrupted frame.
+            return false;
+        };
+
+        // the last 3 bytes are parity
+        let content_hash = {
+            let mut hasher = DefaultHasher::new();
+            data[..data.len() - 3].hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let forward = aircraft
+            .last_forwarded
+            .get(&kind)
+            .is_none_or(|last_forwarded| {
+                let elapsed = time - last_forwarded.time;
+                elapsed >= self.interval
+                    && (kind.is_continuous()
+                        || last_forwarded.content_hash != content_hash
+                        || elapsed >= self.keepalive_interval)
+            });
+
+        if forward {
+            aircraft
+                .last_forwarded
+                .insert(kind, LastForwarded { time, content_hash });
+        }
+
+        forward
+    }
+
+    fn prune(&mut self, time: DateTime<Utc>) {
+        if self
+            .last_pruned
+            .is_some_and(|last_pruned| time - last_pruned < PRUNE_INTERVAL)
+        {
+            return;
+        }
+
+        self.aircraft
+            .retain(|_, aircraft| time - aircraft.last_announced < AIRCRAFT_TIMEOUT);
+        self.last_pruned = Some(time);
+    }
+}
+
+/// Returns the address, the kind of frame, and whether the address was
+/// announced (as opposed to recovered from the parity).
+fn classify(data: &[u8]) -> Option<(IcaoAddress, FrameKind, bool)> {
+    let frame = mode_s::Frame::decode_and_calculate_checksum(&mut &data[..]).ok()?;
+    if frame.check() == Some(false) {
+        return None;
+    }
+    let address = frame.address()?;
+    let downlink_format = data[0] >> 3;
+
+    let (kind, announced) = match downlink_format {
+        11 => (FrameKind::AllCall, true),
+        17 | 18 => {
+            // type code is the first 5 bits of the ADS-B message
+            let type_code = data[4] >> 3;
+            let kind = match type_code {
+                1..=4 => FrameKind::Identification,
+                5..=18 | 20..=22 => {
+                    FrameKind::Position {
+                        odd: data[6] & 0x04 != 0,
+                    }
+                }
+                19 => FrameKind::Velocity,
+                _ => FrameKind::AdsbOther { type_code },
+            };
+            (kind, true)
+        }
+        4 => (FrameKind::Altitude, false),
+        5 => (FrameKind::Identity, false),
+        20 | 21 => (FrameKind::CommB { downlink_format }, false),
+        0 | 16 => (FrameKind::Acas { downlink_format }, false),
+        _ => return None,
+    };
+
+    Some((address, kind, announced))
+}
+
+#[cfg(test)]
+mod tests {
+    use adsbee_beast::{
+        MlatTimestamp,
+        SignalLevel,
+        output::OutputPacket,
+    };
+    use chrono::{
+        DateTime,
+        TimeDelta,
+        Utc,
+    };
+
+    use crate::output::reduce::{
+        ReduceConfig,
+        Reducer,
+    };
+
+    fn short(data: &[u8]) -> OutputPacket {
+        OutputPacket::ModeSShort {
+            timestamp: MlatTimestamp::ANY_TIMESTAMP,
+            signal_level: SignalLevel(0),
+            data: data.try_into().unwrap(),
+        }
+    }
+
+    fn long(data: &[u8]) -> OutputPacket {
+        OutputPacket::ModeSLong {
+            timestamp: MlatTimestamp::ANY_TIMESTAMP,
+            signal_level: SignalLevel(0),
+            data: data.try_into().unwrap(),
+        }
+    }
+
+    fn at(millis: i64) -> DateTime<Utc> {
+        DateTime::UNIX_EPOCH + TimeDelta::milliseconds(millis)
+    }
+
+    #[test]
+    fn it_limits_positions_per_cpr_format() {
+        let even = long(b"\x8d\x40\x62\x1d\x58\xc3\x82\xd6\x90\xc8\xac\x28\x63\xa7");
+        let odd = long(b"\x8d\x40\x62\x1d\x58\xc3\x86\x43\x5c\xc4\x12\x69\x2a\xd6");
+
+        let mut reducer = Reducer::new(ReduceConfig::default());
+        assert!(reducer.filter(at(0), &even));
+        assert!(reducer.filter(at(100), &odd));
+        assert!(!reducer.filter(at(500), &even));
+        assert!(!reducer.filter(at(600), &odd));
+        assert!(reducer.filter(at(1000), &even));
+    }
+
+    #[test]
+    fn it_forwards_on_change_or_keepalive() {
+        let identification = long(b"\x8d\x48\x40\xd6\x20\x2c\xc3\x71\xc3\x2c\xe0\x57\x60\x98");
+        // DF4 from the same aircraft, with different altitudes
+        let altitude_1 = short(b"\x20\x00\x17\x18\x02\x4e\xbd");
+        let altitude_2 = short(b"\x20\x00\x17\x30\x03\xff\x61");
+
+        let mut reducer = Reducer::new(ReduceConfig::default());
+        assert!(reducer.filter(at(0), &identification));
+        assert!(!reducer.filter(at(2000), &identification));
+        assert!(reducer.filter(at(10000), &identification));
+
+        assert!(reducer.filter(at(0), &altitude_1));
+        assert!(!reducer.filter(at(2000), &altitude_1));
+        assert!(reducer.filter(at(3000), &altitude_2));
+    }
+
+    #[test]
+    fn it_drops_frames_from_unknown_aircraft() {
+        // DF4 from 123456, which never announced itself
+        let altitude = short(b"\x20\x00\x17\x18\x58\x3a\x3d");
+        let mut reducer = Reducer::new(ReduceConfig::default());
+        assert!(!reducer.filter(at(0), &altitude));
+        assert!(reducer.filter(at(0), &OutputPacket::DipSwitches(0)));
+    }
+}
@@ -0,0 +1,475 @@
+//! SBS output server
+//!
+//! Sends SBS (BaseStation) `MSG` lines to any connected TCP client, like
+//! readsb's `--net-sbs-port` (usually port 30003).
+//!
+//! The messages are generated from the Mode-S frames the tracker receives.
+//! Values that need state, like positions decoded from CPR, are taken from the
+//! tracker's state after it was updated with the frame.
+
+use adsbee_mode_s::{
+    self as mode_s,
+    VerticalStatus,
+    adsb,
+};
+use adsbee_sbs as sbs;
+use chrono::{
+    DateTime,
+    Utc,
+};
+use futures_util::SinkExt;
+use serde::Deserialize;
+use tokio::{
+    io::AsyncReadExt,
+    net::{
+        TcpListener,
+        TcpStream,
+    },
+    sync::broadcast::{
+        self,
+        error::{
+            RecvError,
+            TryRecvError,
+        },
+    },
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    Error,
+    tracker::{
+        Tracker,
+        altitude,
+        state::{
+            PositionSource,
+            State,
+            Timestamped,
+        },
+    },
+};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SbsServerConfig {
+    pub listen_address: String,
+}
+
+/// Accepts clients on the configured address until `shutdown` is cancelled.
+///
+/// Each client has its own queue of [`Tracker::sbs_messages`]. Clients that
+/// fall behind are disconnected.
+pub async fn serve(
+    config: SbsServerConfig,
+    tracker: Tracker,
+    shutdown: CancellationToken,
+) -> Result<(), Error> {
+    let listener = TcpListener::bind(&config.listen_address).await?;
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                break;
+            }
+            result = listener.accept() => {
+                let (stream, address) = result?;
+                tracing::debug!(%address, "sbs client connected");
+
+                let messages = tracker.sbs_messages();
+                let shutdown = shutdown.clone();
+
+                tokio::spawn(async move {
+                    if let Err(error) = handle_client(stream, messages, shutdown).await {
+                        tracing::debug!(%address, ?error, "sbs client error");
+                    }
+                    tracing::debug!(%address, "sbs client disconnected");
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_client(
+    stream: TcpStream,
+    mut messages: broadcast::Receiver<sbs::Message>,
+    shutdown: CancellationToken,
+) -> Result<(), Error> {
+    let (mut reader, writer) = stream.into_split();
+    let mut writer = sbs::Writer::new(writer);
+
+    // clients don't send anything, but we need to read to notice when they
+    // disconnect.
+    let mut read_buffer = [0; 64];
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                break;
+            }
+            result = reader.read(&mut read_buffer) => {
+                if result? == 0 {
+                    break;
+                }
+            }
+            message = messages.recv() => {
+                let mut message = match message {
+                    Ok(message) => message,
+                    Err(RecvError::Lagged(dropped_count)) => {
+                        tracing::info!(dropped_count, "sbs client too slow");
+                        break;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+
+                // send everything that is queued, before flushing
+                loop {
+                    writer.feed(message).await?;
+
+                    message = match messages.try_recv() {
+                        Ok(message) => message,
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Lagged(dropped_count)) => {
+                            tracing::info!(dropped_count, "sbs client too slow");
+                            return Ok(());
+                        }
+                        Err(TryRecvError::Closed) => return Ok(()),
+                    };
+                }
+
+                writer.flush().await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Generates the `MSG` line readsb would send for a frame received at `time`.
+///
+/// This must be called after `state` was updated with the frame. Frames from
+/// aircraft that aren't tracked, and frames that have no SBS equivalent return
+/// `None`.
+pub fn message(
+    state: &State,
+    time: DateTime<Utc>,
+    frame: &mode_s::FrameWithChecksum,
+) -> Option<sbs::Message> {
+    let hex_ident = frame.address()?;
+    let aircraft = state.get_aircraft(&hex_ident)?;
+
+    let transmission = match &frame.frame {
+        mode_s::Frame::AllCallReply(reply) => {
+            sbs::Transmission::AllCallReply {
+                is_on_ground: capability_on_ground(reply.capability),
+            }
+        }
+        mode_s::Frame::SurveillanceAltitudeReply(mode_s::SurveillanceAltitudeReply {
+            flight_status,
+            altitude_code,
+            ..
+        })
+        | mode_s::Frame::CommBAltitudeReply(mode_s::CommBAltitudeReply {
+            flight_status,
+            altitude_code,
+            ..
+        }) => {
+            sbs::Transmission::SurveillanceAltMessage {
+                altitude: altitude_code.decode().map(altitude::altitude_to_ft),
+                alert: Some(sbs::Alert::from_flight_status(*flight_status)),
+                spi: Some(sbs::Spi::from_flight_status(*flight_status)),
+                is_on_ground: flight_status_on_ground(*flight_status),
+            }
+        }
+        mode_s::Frame::SurveillanceIdentityReply(mode_s::SurveillanceIdentityReply {
+            flight_status,
+            identity_code,
+            ..
+        })
+        | mode_s::Frame::CommBIdentityReply(mode_s::CommBIdentityReply {
+            flight_status,
+            identity_code,
+            ..
+        }) => {
+            let squawk = identity_code.squawk();
+            sbs::Transmission::SurveillanceIdMessage {
+                altitude: None,
+                squawk,
+                alert: Some(sbs::Alert::from_flight_status(*flight_status)),
+                emergency: Some(sbs::Emergency::from_squawk(squawk)),
+                spi: Some(sbs::Spi::from_flight_status(*flight_status)),
+                is_on_ground: flight_status_on_ground(*flight_status),
+            }
+        }
+        mode_s::Frame::ShortAirAirSurveillance(mode_s::ShortAirAirSurveillance {
+            vertical_status,
+            altitude_code,
+            ..
+        })
+        | mode_s::Frame::LongAirAirSurveillance(mode_s::LongAirAirSurveillance {
+            vertical_status,
+            altitude_code,
+            ..
+        }) => {
+            sbs::Transmission::AirToAirMessage {
+                altitude: altitude_code.decode().map(altitude::altitude_to_ft),
+                is_on_ground: Some(*vertical_status == VerticalStatus::Ground),
+            }
+        }
+        mode_s::Frame::ExtendedSquitter(mode_s::ExtendedSquitter { adsb_message, .. })
+        | mode_s::Frame::ExtendedSquitterNonTransponder(
+            mode_s::ExtendedSquitterNonTransponder::AdsbWithIcaoAddress { adsb_message, .. },
+        ) => {
+            // positions are only sent if the frame could be decoded
+            let position = updated(&aircraft.position, time)
+                .filter(|position| position.source == PositionSource::Gnss);
+            let latitude = position.map(|position| position.latitude);
+            let longitude = position.map(|position| position.longitude);
+            let ground_speed = updated(&aircraft.ground_speed, time);
+            let track =
+                updated(&aircraft.track, time).map(|track| track.to_degrees().rem_euclid(360.0));
+
+            match adsb_message {
+                adsb::Message::AircraftIdentification(identification) => {
+                    sbs::Transmission::EsIdentificationAndCategory {
+                        callsign: identification.callsign.decode().ok()?,
+                    }
+                }
+                adsb::Message::SurfacePosition(_) => {
+                    sbs::Transmission::EsSurfacePosition {
+                        altitude: None,
+                        ground_speed,
+                        track,
+                        latitude,
+                        longitude,
+                        is_on_ground: Some(true),
+                    }
+                }
+                adsb::Message::AirbornePosition(airborne_position) => {
+                    let altitude = match airborne_position.altitude() {
+                        Some(adsb::Altitude::Barometric(altitude)) => Some(altitude),
+                        _ => None,
+                    };
+                    sbs::Transmission::EsAirbornePosition {
+                        altitude,
+                        latitude,
+                        longitude,
+                        alert: None,
+                        emergency: None,
+                        spi: None,
+                        is_on_ground: Some(false),
+                    }
+                }
+                adsb::Message::AirborneVelocity(velocity) => {
+                    sbs::Transmission::EsAirborneVelocity {
+                        ground_speed,
+                        track,
+                        vertical_rate: velocity.vertical_rate.as_ft_per_min().map(Into::into),
+                    }
+                }
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+
+    // readsb doesn't keep track of sessions, aircraft and flights, and always
+    // sends 1.
+    Some(sbs::Message::Transmission {
+        session_id: 1,
+        aircraft_id: 1,
+        hex_ident,
+        flight_id: 1,
+        time_generated: time,
+        time_logged: Utc::now(),
+        transmission,
+    })
+}
+
+/// Returns the value if it was updated at `time`, i.e. by the current frame.
+fn updated<T: Copy>(value: &Option<Timestamped<T>>, time: DateTime<Utc>) -> Option<T> {
+    value
+        .as_ref()
+        .filter(|value| value.last_update == time)
+        .map(|value| value.value)
+}
+
+fn flight_status_on_ground(flight_status: mode_s::FlightStatus) -> Option<bool> {
+    match flight_status {
+        mode_s::FlightStatus::NO_ALERT_NO_SPI_AIRBORNE
+        | mode_s::FlightStatus::ALERT_NO_SPI_AIRBORNE => Some(false),
+        mode_s::FlightStatus::NO_ALERT_NO_SPI_GROUND
+        | mode_s::FlightStatus::ALERT_NO_SPI_GROUND => Some(true),
+        _ => None,
+    }
+}
+
+fn capability_on_ground(capability: mode_s::Capability) -> Option<bool> {
+    match capability {
+        mode_s::Capability::LEVEL2_GROUND => Some(true),
+        mode_s::Capability::LEVEL2_AIRBORNE => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use adsbee_mode_s as mode_s;
+    use adsbee_sbs as sbs;
+    use chrono::{
+        DateTime,
+        TimeDelta,
+        Utc,
+    };
+
+    use crate::{
+        output::sbs::message,
+        tracker::state::State,
+    };
+
+    /// Updates the state with a frame received `seconds` after the start of the
+    /// test, like the tracker does, and returns the `MSG` line for it.
+    fn line(state: &mut State, seconds: i64, data: &[u8]) -> Option<String> {
+        let time =
+            DateTime::<Utc>::from_timestamp(1750000000, 0).unwrap() + TimeDelta::seconds(seconds);
+        let frame = mode_s::Frame::decode_and_calculate_checksum(&mut &data[..]).unwrap();
+        state.update_with_mode_s(time, &frame.frame);
+        state.update_with_altitude_reply(time, &frame);
+        state.update_with_comm_b(time, &frame);
+
+        let mut message = message(state, time, &frame)?;
+        // the time logged is the current time
+        if let sbs::Message::Transmission { time_logged, .. } = &mut message {
+            *time_logged = time;
+        }
+        Some(message.to_string())
+    }
+
+    #[test]
+    fn it_generates_surveillance_messages() {
+        let mut state = State::default();
+
+        // DF4 from 4840d6, which isn't tracked yet
+        assert_eq!(line(&mut state, 0, b"\x20\x00\x18\x38\x59\xc3\x8d"), None);
+
+        // DF11
+        assert_eq!(
+            line(&mut state, 0, b"\x5d\x48\x40\xd6\xf8\x74\x0f").as_deref(),
+            Some("MSG,8,1,1,4840D6,1,2025/06/15,15:06:40.000,2025/06/15,15:06:40.000,,,,,,,,,,,,0")
+        );
+
+        // DF4, airborne without alert and SPI
+        assert_eq!(
+            line(&mut state, 1, b"\x20\x00\x18\x38\x59\xc3\x8d").as_deref(),
+            Some(
+                "MSG,5,1,1,4840D6,1,2025/06/15,15:06:41.000,2025/06/15,15:06:41.000,,38000,,,,,,,0,,0,0"
+            )
+        );
+
+        // DF5 squawking 7700, with alert and SPI
+        assert_eq!(
+            line(&mut state, 2, b"\x2c\x00\x0a\xaa\xad\x11\x53").as_deref(),
+            Some(
+                "MSG,6,1,1,4840D6,1,2025/06/15,15:06:42.000,2025/06/15,15:06:42.000,,,,,,,,7700,-1,-1,-1,"
+            )
+        );
+
+        // DF20, on the ground
+        assert_eq!(
+            line(
+                &mut state,
+                3,
+                b"\xa1\x00\x01\x30\x00\x00\x00\x00\x00\x00\x00\x36\xf1\x7f"
+            )
+            .as_deref(),
+            Some(
+                "MSG,5,1,1,4840D6,1,2025/06/15,15:06:43.000,2025/06/15,15:06:43.000,,1000,,,,,,,0,,0,-1"
+            )
+        );
+
+        // DF21 squawking 1000, with SPI
+        assert_eq!(
+            line(
+                &mut state,
+                4,
+                b"\xad\x00\x08\x00\x00\x00\x00\x00\x00\x00\x00\x43\x93\x6d"
+            )
+            .as_deref(),
+            Some(
+                "MSG,6,1,1,4840D6,1,2025/06/15,15:06:44.000,2025/06/15,15:06:44.000,,,,,,,,1000,0,0,-1,"
+            )
+        );
+
+        // DF0
+        assert_eq!(
+            line(&mut state, 5, b"\x00\x00\x18\x38\xd9\xa5\xd2").as_deref(),
+            Some(
+                "MSG,7,1,1,4840D6,1,2025/06/15,15:06:45.000,2025/06/15,15:06:45.000,,38000,,,,,,,,,,0"
+            )
+        );
+    }
+
+    #[test]
+    fn it_generates_extended_squitter_messages() {
+        let mut state = State::default();
+
+        assert_eq!(
+            line(
+                &mut state,
+                0,
+                b"\x8d\x48\x40\xd6\x20\x2c\xc3\x71\xc3\x2c\xe0\x57\x60\x98"
+            )
+            .as_deref(),
+            Some(
+                "MSG,1,1,1,4840D6,1,2025/06/15,15:06:40.000,2025/06/15,15:06:40.000,KLM1023 ,,,,,,,,,,,"
+            )
+        );
+
+        // the position is only sent once both CPR formats were received
+        assert_eq!(
+            line(
+                &mut state,
+                0,
+                b"\x8d\x40\x62\x1d\x58\xc3\x82\xd6\x90\xc8\xac\x28\x63\xa7"
+            )
+            .as_deref(),
+            Some(
+                "MSG,3,1,1,40621D,1,2025/06/15,15:06:40.000,2025/06/15,15:06:40.000,,38000,,,,,,,,,,0"
+            )
+        );
+        assert_eq!(
+            line(
+                &mut state,
+                1,
+                b"\x8d\x40\x62\x1d\x58\xc3\x86\x43\x5c\xc4\x12\x69\x2a\xd6"
+            )
+            .as_deref(),
+            Some(
+                "MSG,3,1,1,40621D,1,2025/06/15,15:06:41.000,2025/06/15,15:06:41.000,,38000,,,52.26578,3.93891,,,,,,0"
+            )
+        );
+
+        assert_eq!(
+            line(
+                &mut state,
+                2,
+                b"\x8d\x48\x50\x20\x99\x44\x09\x94\x08\x38\x17\x5b\x28\x4f"
+            )
+            .as_deref(),
+            Some(
+                "MSG,4,1,1,485020,1,2025/06/15,15:06:42.000,2025/06/15,15:06:42.000,,,159,183,,,-832,,,,,"
+            )
+        );
+
+        assert_eq!(
+            line(
+                &mut state,
+                3,
+                b"\x8c\x48\x41\x75\x3a\xab\x23\x87\x33\xc8\xcd\x40\x20\xb1"
+            )
+            .as_deref(),
+            Some(
+                "MSG,2,1,1,484175,1,2025/06/15,15:06:43.000,2025/06/15,15:06:43.000,,,18,141,,,,,,,,-1"
+            )
+        );
+    }
+}
@@ -0,0 +1,254 @@
+//! Webhook output
+//!
+//! Delivers alerts (see [`alerts`][crate::tracker::alerts]) as JSON via HTTP
+//! POST. Failed deliveries are retried with exponential backoff.
+
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    Error,
+    tracker::{
+        Tracker,
+        alerts::Alert,
+    },
+    util::http_client,
+};
+
+/// Default number of retries of a failed delivery.
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+
+/// Default delay before the first retry in seconds.
+pub const DEFAULT_RETRY_DELAY: f64 = 1.0;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+
+    /// Names of the rules to deliver alerts for. If empty, alerts of all rules
+    /// are delivered.
+    #[serde(default)]
+    pub rules: Vec<String>,
+
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+
+    /// Delay before the first retry in seconds. It is doubled for each
+    /// further retry.
+    #[serde(
+        default = "default_retry_delay",
+        deserialize_with = "crate::util::deserialize_positive_seconds"
+    )]
+    pub retry_delay: f64,
+}
+
+fn default_max_retries() -> u32 {
+    DEFAULT_MAX_RETRIES
+}
+
+fn default_retry_delay() -> f64 {
+    DEFAULT_RETRY_DELAY
+}
+
+/// Delivers alerts to the configured URL until `shutdown` is cancelled.
+///
+/// Each alert is delivered in its own task, so that retries don't hold up
+/// other alerts.
+pub async fn serve(
+    config: WebhookConfig,
+    tracker: Tracker,
+    shutdown: CancellationToken,
+) -> Result<(), Error> {
+    let mut alerts = tracker.alerts();
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                break;
+            }
+            alert = alerts.recv() => {
+                match alert {
+                    Ok(alert) => {
+                        if config.rules.is_empty() || config.rules.contains(&alert.rule) {
+                            tokio::spawn(deliver(config.clone(), alert, shutdown.clone()));
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(dropped_count)) => {
+                        tracing::warn!(dropped_count, "webhook output lagging behind");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn deliver(config: WebhookConfig, alert: Alert, shutdown: CancellationToken) {
+    let mut retry_delay = Duration::from_secs_f64(config.retry_delay);
+
+    for attempt in 0..=config.max_retries {
+        if attempt > 0 {
+            tokio::select! {
+                _ = shutdown.cancelled() => return,
+                _ = tokio::time::sleep(retry_delay) => {}
+            }
+            retry_delay *= 2;
+        }
+
+        match post(&config.url, &alert).await {
+            Ok(()) => return,
+            Err(error) => {
+                tracing::warn!(
+                    url = config.url,
+                    rule = alert.rule,
+                    attempt,
+                    ?error,
+                    "webhook delivery failed"
+                );
+
+                // other client errors won't go away by retrying
+                if let Some(status) = error.status()
+                    && status.is_client_error()
+                    && status != StatusCode::REQUEST_TIMEOUT
+                    && status != StatusCode::TOO_MANY_REQUESTS
+                {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+async fn post(url: &str, alert: &Alert) -> Result<(), reqwest::Error> {
+    http_client()
+        .post(url)
+        .timeout(REQUEST_TIMEOUT)
+        .json(alert)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{
+            Arc,
+            atomic::{
+                AtomicUsize,
+                Ordering,
+            },
+        },
+    };
+
+    use axum::{
+        Json,
+        Router,
+        extract::State,
+        http::StatusCode,
+        routing::post,
+    };
+    use chrono::Utc;
+    use serde_json::json;
+    use tokio::{
+        net::TcpListener,
+        sync::mpsc,
+    };
+    use tokio_util::sync::CancellationToken;
+
+    use crate::{
+        output::webhook::{
+            WebhookConfig,
+            serve,
+        },
+        tracker::{
+            Tracker,
+            alerts::{
+                AlertRule,
+                Condition,
+            },
+            state::fixtures,
+        },
+    };
+
+    #[derive(Clone)]
+    struct Hook {
+        requests: Arc<AtomicUsize>,
+        alert_sender: mpsc::Sender<serde_json::Value>,
+    }
+
+    /// Fails the first request, and forwards the alerts of all further
+    /// requests.
+    async fn hook(State(hook): State<Hook>, Json(alert): Json<serde_json::Value>) -> StatusCode {
+        if hook.requests.fetch_add(1, Ordering::SeqCst) == 0 {
+            return StatusCode::SERVICE_UNAVAILABLE;
+        }
+        hook.alert_sender.send(alert).await.unwrap();
+        StatusCode::NO_CONTENT
+    }
+
+    #[tokio::test]
+    async fn it_delivers_alerts_with_retries() {
+        let (alert_sender, mut alert_receiver) = mpsc::channel(4);
+        let requests = Arc::new(AtomicUsize::new(0));
+        let router = Router::new().route("/hook", post(hook)).with_state(Hook {
+            requests: requests.clone(),
+            alert_sender,
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let tracker = Tracker::new();
+        tracker
+            .set_alert_rules(
+                vec![AlertRule {
+                    name: "emergency".to_owned(),
+                    condition: Condition::Emergency,
+                    cooldown: 600.0,
+                }],
+                HashMap::new(),
+            )
+            .await;
+
+        let shutdown = CancellationToken::new();
+        tokio::spawn(serve(
+            WebhookConfig {
+                url: format!("http://{address}/hook"),
+                rules: vec![],
+                max_retries: 3,
+                retry_delay: 0.1,
+            },
+            tracker.clone(),
+            shutdown.clone(),
+        ));
+
+        tracker
+            .push_aircraft_json(
+                fixtures::source_id(),
+                fixtures::snapshot(
+                    Utc::now(),
+                    [fixtures::aircraft(json!({"squawk": "7700", "seen": 0.5}))],
+                ),
+            )
+            .await;
+
+        let alert = alert_receiver.recv().await.unwrap();
+        shutdown.cancel();
+
+        assert_eq!(alert["rule"], "emergency");
+        assert_eq!(alert["icao"], "4ca7b5");
+        assert_eq!(alert["squawk"], "7700");
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,291 @@
+//! readsb/dump1090 `aircraft.json` source
+//!
+//! Polls `aircraft.json` from an HTTP endpoint or a local file. Aircraft for
+//! which nothing was received since the previous snapshot are skipped, the
+//! others are pushed to the tracker.
+//!
+//! - [readsb documentation][1]
+//!
+//! [1]: https://github.com/wiedehopf/readsb/blob/75decb53c0e66f4c12cf24127578a3fe7d919219/README-json.md
+
+use std::{
+    collections::HashMap,
+    time::Duration,
+};
+
+use adsbee_mode_s::adsb::WakeVortexCategory;
+use adsbee_types::{
+    IcaoAddress,
+    Squawk,
+};
+use chrono::{
+    DateTime,
+    TimeDelta,
+    Utc,
+};
+use serde::Deserialize;
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    Error,
+    source::{
+        SourceId,
+        history::Altitude,
+    },
+    tracker::Tracker,
+    util::{
+        http_client,
+        json::json_decode,
+    },
+};
+
+/// Default polling interval in seconds. readsb updates `aircraft.json` once
+/// per second.
+pub const DEFAULT_INTERVAL: f64 = 1.0;
+
+/// Polls `url_or_path` every `interval` seconds until `shutdown` is cancelled.
+///
+/// Failing to fetch or parse a snapshot is logged, and we try again on the
+/// next tick.
+pub async fn poll(
+    url_or_path: &str,
+    interval: f64,
+    source_id: SourceId,
+    shutdown: CancellationToken,
+    tracker: Tracker,
+) -> Result<(), Error> {
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(interval));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut snapshots = Snapshots::default();
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                break;
+            }
+            _ = interval.tick() => {
+                match fetch(url_or_path).await {
+                    Ok(mut snapshot) => {
+                        snapshots.retain_changed(&mut snapshot);
+                        if !snapshot.aircraft.is_empty() {
+                            tracker.push_aircraft_json(source_id, snapshot).await;
+                        }
+                    }
+                    Err(error) => {
+                        tracing::warn!(url_or_path, ?error, "failed to fetch aircraft.json");
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn fetch(url_or_path: &str) -> Result<AircraftJson, Error> {
+    let json = if url_or_path.starts_with("http://") || url_or_path.starts_with("https://") {
+        http_client()
+            .get(url_or_path)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?
+            .to_vec()
+    }
+    else {
+        tokio::fs::read(url_or_path).await?
+    };
+
+    Ok(json_decode(json)?)
+}
+
+/// Remembers when each aircraft was last seen, to skip aircraft that didn't
+/// change between snapshots.
+#[derive(Debug, Default)]
+struct Snapshots {
+    last_seen: HashMap<IcaoAddress, DateTime<Utc>>,
+}
+
+impl Snapshots {
+    /// Removes aircraft from `snapshot` that didn't change since the previous
+    /// snapshot.
+    fn retain_changed(&mut self, snapshot: &mut AircraftJson) {
+        let now = snapshot.now();
+        let mut last_seen = HashMap::with_capacity(snapshot.aircraft.len());
+
+        snapshot.aircraft.retain(|aircraft| {
+            let seen = aircraft.last_seen(now);
+            last_seen.insert(aircraft.hex, seen);
+            self.last_seen
+                .get(&aircraft.hex)
+                .is_none_or(|previous| *previous < seen)
+        });
+
+        // aircraft that aren't in the snapshot anymore are forgotten
+        self.last_seen = last_seen;
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AircraftJson {
+    /// Time the file was generated, in seconds since the Unix epoch
+    pub now: f64,
+    #[serde(default)]
+    pub aircraft: Vec<Aircraft>,
+}
+
+impl AircraftJson {
+    pub fn now(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp_micros((self.now * 1e6) as i64).unwrap_or_default()
+    }
+}
+
+/// An aircraft in `aircraft.json`.
+///
+/// Only the fields we use are decoded.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Aircraft {
+    pub hex: IcaoAddress,
+    /// Callsign, padded with spaces
+    pub flight: Option<String>,
+    /// Barometric altitude in ft, or `"ground"`
+    pub alt_baro: Option<Altitude>,
+    /// Geometric (GNSS) altitude in ft
+    pub alt_geom: Option<f64>,
+    /// Ground speed in kt
+    pub gs: Option<f64>,
+    /// Indicated airspeed in kt
+    pub ias: Option<f64>,
+    /// True airspeed in kt
+    pub tas: Option<f64>,
+    /// Barometric vertical rate in ft/min
+    pub baro_rate: Option<f64>,
+    /// True track over ground in degrees
+    pub track: Option<f64>,
+    pub squawk: Option<Squawk>,
+    /// Emitter category, e.g. `A3`
+    pub category: Option<String>,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+    /// Seconds since the position was last updated
+    pub seen_pos: Option<f64>,
+    /// Seconds since any message was last received
+    #[serde(default)]
+    pub seen: f64,
+}
+
+impl Aircraft {
+    /// Time a message was last received from the aircraft
+    pub fn last_seen(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        seconds_before(now, self.seen)
+    }
+
+    /// Emitter category, if it's valid
+    pub fn wake_vortex_category(&self) -> Option<WakeVortexCategory> {
+        let mut chars = self.category.as_ref()?.chars();
+        // sets A to D are type codes 4 to 1
+        let type_code = match chars.next()? {
+            'A' => 4,
+            'B' => 3,
+            'C' => 2,
+            'D' => 1,
+            _ => return None,
+        };
+        let category = chars.next()?.to_digit(8)? as u8;
+        chars
+            .next()
+            .is_none()
+            .then(|| WakeVortexCategory::from_type_code_and_category_unchecked(type_code, category))
+    }
+
+    /// Time the position was last updated
+    pub fn position_updated(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.seen_pos.map(|seen_pos| seconds_before(now, seen_pos))
+    }
+}
+
+fn seconds_before(time: DateTime<Utc>, seconds: f64) -> DateTime<Utc> {
+    time - TimeDelta::microseconds((seconds * 1e6) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use adsbee_types::IcaoAddress;
+
+    use crate::{
+        source::aircraft_json::{
+            AircraftJson,
+            Snapshots,
+            fetch,
+        },
+        util::json::json_decode,
+    };
+
+    fn snapshot(now: f64, seen: f64) -> AircraftJson {
+        json_decode(format!(
+            r#"{{
+                "now": {now},
+                "messages": 1234,
+                "aircraft": [
+                    {{
+                        "hex": "4ca7b5",
+                        "type": "adsb_icao",
+                        "flight": "RYR4WJ  ",
+                        "alt_baro": 37000,
+                        "gs": 452.1,
+                        "track": 270.5,
+                        "squawk": "1000",
+                        "lat": 51.477,
+                        "lon": -0.4614,
+                        "seen_pos": {seen},
+                        "seen": {seen}
+                    }},
+                    {{
+                        "hex": "~3c6444",
+                        "alt_baro": "ground",
+                        "seen": 10.0
+                    }}
+                ]
+            }}"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn it_skips_unchanged_aircraft() {
+        let mut snapshots = Snapshots::default();
+
+        let mut first = snapshot(1714564800.0, 0.5);
+        snapshots.retain_changed(&mut first);
+        assert_eq!(first.aircraft.len(), 2);
+
+        // 1 second later, only the first aircraft sent something
+        let mut second = snapshot(1714564801.0, 0.2);
+        second.aircraft[1].seen = 11.0;
+        snapshots.retain_changed(&mut second);
+        assert_eq!(second.aircraft.len(), 1);
+        assert_eq!(
+            second.aircraft[0].hex,
+            IcaoAddress::from_u32_unchecked(0x4ca7b5)
+        );
+    }
+
+    #[tokio::test]
+    async fn it_reads_a_local_file() {
+        let path = std::env::temp_dir().join(format!("aircraft-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"now": 1714564800.0, "aircraft": [{"hex": "4ca7b5"}]}"#,
+        )
+        .unwrap();
+
+        let snapshot = fetch(path.to_str().unwrap()).await;
+        std::fs::remove_file(&path).unwrap();
+
+        let snapshot = snapshot.unwrap();
+        assert_eq!(snapshot.now().timestamp(), 1714564800);
+        assert_eq!(snapshot.aircraft.len(), 1);
+    }
+}
@@ -20,12 +20,14 @@ use chrono::{
 use libflate::gzip;
 use serde::{
     Deserialize,
+    Serialize,
     de::Visitor,
 };
 
 use crate::{
     Error,
     database::Database,
+    metrics::QueryTimer,
     util::json::json_decode,
 };
 
@@ -73,7 +75,7 @@ pub async fn index_trace(database: &Database, trace: &TraceFile) -> Result<(), E
         let is_first = i == 0;
         let is_last = i == n - 1;
 
-        let time = datetime_from_timestamp(trace.timestamp + tp.dt);
+        let time = datetime_from_timestamp(trace.timestamp + f64::from(tp.dt));
         let mut callsign_changed = false;
         let mut squawk_changed = false;
 
@@ -97,6 +99,7 @@ pub async fn index_trace(database: &Database, trace: &TraceFile) -> Result<(), E
         if is_first || is_last || callsign_changed || squawk_changed {
             tracing::debug!(icao = %icao_address, %time, callsign = ?current_callsign, squawk = ?current_squawk);
 
+            let _timer = QueryTimer::start("insert_trace_info");
             sqlx::query_unchecked!(
                 "insert into trace_info (time, icao_address, callsign, squawk) values ($1, $2, $3, $4)",
                 time,
@@ -112,28 +115,35 @@ pub async fn index_trace(database: &Database, trace: &TraceFile) -> Result<(), E
     Ok(())
 }
 
-fn datetime_from_timestamp(timestamp: f32) -> DateTime<Utc> {
+fn datetime_from_timestamp(timestamp: f64) -> DateTime<Utc> {
     DateTime::from_timestamp_millis((timestamp * 1000.0) as i64).expect("invalid timestamp")
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TraceFile {
     pub icao: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub r: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub t: Option<String>,
     #[serde(default)]
     pub db_flags: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub desc: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub own_op: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub year: Option<String>,
-    pub version: String,
-    pub timestamp: f32,
+    /// Only set in archived traces
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    pub timestamp: f64,
     pub trace: Vec<TracePoint>,
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(try_from = "TraceTupleV2")]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "TraceTupleV2", into = "TraceTupleV2")]
 pub struct TracePoint {
     pub dt: f32,
     pub lat: f32,
@@ -229,6 +239,27 @@ impl From<TraceTupleV2> for TracePoint {
     }
 }
 
+impl From<TracePoint> for TraceTupleV2 {
+    fn from(value: TracePoint) -> Self {
+        (
+            value.dt,
+            value.lat,
+            value.long,
+            value.altitude,
+            value.ground_speed,
+            value.track,
+            value.db_flags,
+            value.vertical_rate,
+            value.aircraft,
+            value.source,
+            value.geo_alt,
+            value.geo_vr,
+            value.ind_airspeed,
+            value.roll_angle,
+        )
+    }
+}
+
 impl From<TraceTuple> for TracePoint {
     fn from(value: TraceTuple) -> Self {
         match value {
@@ -238,12 +269,25 @@ impl From<TraceTuple> for TracePoint {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum Altitude {
     Ground,
     Value(f32),
 }
 
+impl Serialize for Altitude {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            Self::Ground => serializer.serialize_str("ground"),
+            // readsb writes altitudes in whole feet
+            Self::Value(altitude) => serializer.serialize_i32(altitude.round() as i32),
+        }
+    }
+}
+
 impl<'de> Deserialize<'de> for Altitude {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -354,17 +398,20 @@ impl<'de> Visitor<'de> for AltitudeVisitor {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct Aircraft {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub squawk: Option<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub nav_modes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub emergency: Option<Emergency>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub flight: Option<String>,
     // todo
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Emergency {
     None,
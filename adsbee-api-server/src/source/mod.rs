@@ -1,3 +1,4 @@
+pub mod aircraft_json;
 pub mod history;
 pub mod tar1090_db;
 
@@ -22,10 +23,42 @@ use crate::{
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct SourceId(usize);
 
+impl SourceId {
+    pub fn new(id: usize) -> Self {
+        Self(id)
+    }
+}
+
+impl std::fmt::Display for SourceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub enum SourceConfig {
-    Beast { address: String },
-    SbsMlat { address: String },
+    AircraftJson {
+        /// HTTP(S) URL or path to a local file
+        url_or_path: String,
+        /// Polling interval in seconds
+        #[serde(
+            default = "default_aircraft_json_interval",
+            deserialize_with = "crate::util::deserialize_positive_seconds"
+        )]
+        interval: f64,
+    },
+    Avr {
+        address: String,
+    },
+    Beast {
+        address: String,
+    },
+    Sbs {
+        address: String,
+    },
+    SbsMlat {
+        address: String,
+    },
 }
 
 impl SourceConfig {
@@ -36,6 +69,24 @@ impl SourceConfig {
         tracker: Tracker,
     ) -> Result<(), Error> {
         match self {
+            SourceConfig::AircraftJson {
+                url_or_path,
+                interval,
+            } => {
+                aircraft_json::poll(url_or_path, *interval, source_id, shutdown, tracker).await?;
+            }
+            SourceConfig::Avr { address } => {
+                connect_client(address, shutdown, adsbee_avr::Reader::new, async |frame| {
+                    // AVR frames are handled like BEAST frames, which also puts them on the
+                    // BEAST output.
+                    let time_received = Utc::now();
+
+                    tracker
+                        .push_beast(source_id, None, time_received, frame.into())
+                        .await
+                })
+                .await?;
+            }
             SourceConfig::Beast { address } => {
                 connect_client(
                     address,
@@ -53,6 +104,15 @@ impl SourceConfig {
                 )
                 .await?;
             }
+            SourceConfig::Sbs { address } => {
+                connect_client(
+                    address,
+                    shutdown,
+                    adsbee_sbs::Reader::new,
+                    async |message| tracker.push_sbs(source_id, false, message).await,
+                )
+                .await?;
+            }
             SourceConfig::SbsMlat { address } => {
                 connect_client(
                     address,
@@ -68,6 +128,10 @@ impl SourceConfig {
     }
 }
 
+fn default_aircraft_json_interval() -> f64 {
+    aircraft_json::DEFAULT_INTERVAL
+}
+
 async fn connect_client<T, F, R, E, P>(
     address: &str,
     shutdown: CancellationToken,
@@ -0,0 +1,418 @@
+//! Alert rules
+//!
+//! Rules are loaded from a JSON file (see [`AlertsConfig`]) and evaluated
+//! periodically by the tracker reactor against all aircraft. A rule fires when
+//! its condition becomes true for an aircraft, e.g. when the aircraft enters an
+//! area, and not again until the condition was false in between and the rule's
+//! cooldown has passed.
+//!
+//! Alerts are broadcast to all receivers of [`Tracker::alerts`][1], e.g. the
+//! webhook output.
+//!
+//! [1]: crate::tracker::Tracker::alerts
+
+use std::{
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    path::Path,
+};
+
+use adsbee_types::{
+    Callsign,
+    IcaoAddress,
+    Squawk,
+    geo::Polygon,
+};
+use chrono::{
+    DateTime,
+    TimeDelta,
+    Utc,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::{
+    Error,
+    database::{
+        self,
+        Transaction,
+    },
+    output::webhook::WebhookConfig,
+    tracker::state::{
+        AircraftState,
+        State,
+    },
+    util::json::json_decode,
+};
+
+/// Default cooldown of rules in seconds.
+pub const DEFAULT_COOLDOWN: f64 = 600.0;
+
+/// Alert rules and the webhooks their alerts are delivered to.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct AlertsConfig {
+    #[serde(default)]
+    pub rules: Vec<AlertRule>,
+
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+}
+
+impl AlertsConfig {
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        tracing::info!(path = %path.display(), "loading alert rules");
+        Ok(json_decode(std::fs::read(path)?)?)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AlertRule {
+    /// Name of the rule, included in alerts
+    pub name: String,
+
+    pub condition: Condition,
+
+    /// Minimum time between alerts of this rule for the same aircraft, in
+    /// seconds
+    #[serde(default = "default_cooldown")]
+    pub cooldown: f64,
+}
+
+fn default_cooldown() -> f64 {
+    DEFAULT_COOLDOWN
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Condition {
+    /// Aircraft squawks 7500, 7600 or 7700
+    Emergency,
+    /// Aircraft squawks any of these codes
+    Squawk { squawks: Vec<Squawk> },
+    /// Aircraft has any of these addresses
+    IcaoAddress { icao_addresses: Vec<IcaoAddress> },
+    /// Aircraft has a tag in the `aircraft_tag` table, e.g. `military`
+    Tagged { tag: String },
+    /// Aircraft's position is inside the polygon
+    Area { polygon: Polygon },
+}
+
+impl Condition {
+    fn matches(
+        &self,
+        aircraft: &AircraftState,
+        tagged: &HashMap<String, HashSet<IcaoAddress>>,
+    ) -> bool {
+        match self {
+            Self::Emergency => {
+                aircraft.squawk.as_ref().is_some_and(|squawk| {
+                    matches!(
+                        squawk.value,
+                        Squawk::AIRCRAFT_HIJACKING | Squawk::RADIO_FAILURE | Squawk::EMERGENCY
+                    )
+                })
+            }
+            Self::Squawk { squawks } => {
+                aircraft
+                    .squawk
+                    .as_ref()
+                    .is_some_and(|squawk| squawks.contains(&squawk.value))
+            }
+            Self::IcaoAddress { icao_addresses } => icao_addresses.contains(&aircraft.icao_address),
+            Self::Tagged { tag } => {
+                tagged
+                    .get(tag)
+                    .is_some_and(|addresses| addresses.contains(&aircraft.icao_address))
+            }
+            Self::Area { polygon } => {
+                aircraft
+                    .position
+                    .as_ref()
+                    .is_some_and(|position| polygon.contains(&position.value.lat_lon()))
+            }
+        }
+    }
+}
+
+/// Loads the aircraft addresses for all tags used in `rules`.
+pub async fn tagged_aircraft(
+    rules: &[AlertRule],
+    transaction: &mut Transaction<'_>,
+) -> Result<HashMap<String, HashSet<IcaoAddress>>, database::Error> {
+    let mut tagged = HashMap::new();
+
+    for rule in rules {
+        if let Condition::Tagged { tag } = &rule.condition
+            && !tagged.contains_key(tag)
+        {
+            let addresses = transaction.get_tagged_aircraft(tag).await?;
+            tagged.insert(tag.clone(), addresses);
+        }
+    }
+
+    Ok(tagged)
+}
+
+/// An alert, sent when a rule fires for an aircraft.
+#[derive(Clone, Debug, Serialize)]
+pub struct Alert {
+    pub rule: String,
+    pub time: DateTime<Utc>,
+    pub icao: IcaoAddress,
+    pub callsign: Option<Callsign>,
+    pub squawk: Option<Squawk>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// in ft
+    pub altitude_barometric: Option<i32>,
+}
+
+impl Alert {
+    fn new(rule: &AlertRule, aircraft: &AircraftState, time: DateTime<Utc>) -> Self {
+        let position = aircraft.position.as_ref().map(|position| &position.value);
+
+        Self {
+            rule: rule.name.clone(),
+            time,
+            icao: aircraft.icao_address,
+            callsign: aircraft.callsign.as_ref().map(|callsign| callsign.value),
+            squawk: aircraft.squawk.as_ref().map(|squawk| squawk.value),
+            latitude: position.map(|position| position.latitude),
+            longitude: position.map(|position| position.longitude),
+            altitude_barometric: aircraft
+                .altitude_barometric
+                .as_ref()
+                .map(|altitude| altitude.value),
+        }
+    }
+}
+
+/// Alert rules and the state needed to evaluate them.
+#[derive(Debug, Default)]
+pub struct Alerts {
+    rules: Vec<AlertRule>,
+
+    /// Aircraft addresses by tag, for [`Condition::Tagged`]
+    tagged: HashMap<String, HashSet<IcaoAddress>>,
+
+    /// Per rule, the aircraft that match it or are in cooldown
+    rule_states: Vec<HashMap<IcaoAddress, RuleState>>,
+}
+
+#[derive(Debug)]
+struct RuleState {
+    matching: bool,
+    last_alert: DateTime<Utc>,
+}
+
+impl Alerts {
+    pub fn new(rules: Vec<AlertRule>, tagged: HashMap<String, HashSet<IcaoAddress>>) -> Self {
+        let rule_states = rules.iter().map(|_| HashMap::new()).collect();
+        Self {
+            rules,
+            tagged,
+            rule_states,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Evaluates all rules against all aircraft and returns the alerts that
+    /// fired.
+    pub fn evaluate(&mut self, state: &State, time: DateTime<Utc>) -> Vec<Alert> {
+        let mut alerts = vec![];
+
+        for (rule, rule_states) in self.rules.iter().zip(&mut self.rule_states) {
+            let cooldown = TimeDelta::milliseconds((rule.cooldown * 1000.0) as i64);
+            let in_cooldown = |rule_state: &RuleState| {
+                time.signed_duration_since(rule_state.last_alert) < cooldown
+            };
+            let mut next_rule_states = HashMap::with_capacity(rule_states.len());
+
+            for aircraft in state.iter_aircraft() {
+                let previous = rule_states.remove(&aircraft.icao_address);
+
+                if !rule.condition.matches(aircraft, &self.tagged) {
+                    // keep the cooldown, in case it matches again
+                    if let Some(mut previous) = previous.filter(in_cooldown) {
+                        previous.matching = false;
+                        next_rule_states.insert(aircraft.icao_address, previous);
+                    }
+                    continue;
+                }
+
+                let last_alert = match previous {
+                    Some(previous) if previous.matching || in_cooldown(&previous) => {
+                        previous.last_alert
+                    }
+                    _ => {
+                        alerts.push(Alert::new(rule, aircraft, time));
+                        time
+                    }
+                };
+
+                next_rule_states.insert(
+                    aircraft.icao_address,
+                    RuleState {
+                        matching: true,
+                        last_alert,
+                    },
+                );
+            }
+
+            // aircraft that were removed from the state
+            for (icao_address, mut previous) in rule_states.drain() {
+                if in_cooldown(&previous) {
+                    previous.matching = false;
+                    next_rule_states.insert(icao_address, previous);
+                }
+            }
+
+            *rule_states = next_rule_states;
+        }
+
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use adsbee_types::{
+        IcaoAddress,
+        Squawk,
+        geo::{
+            LatLon,
+            Polygon,
+        },
+    };
+    use chrono::{
+        DateTime,
+        TimeDelta,
+        Utc,
+    };
+    use serde_json::json;
+
+    use crate::{
+        tracker::{
+            alerts::{
+                AlertRule,
+                Alerts,
+                AlertsConfig,
+                Condition,
+                DEFAULT_COOLDOWN,
+            },
+            state::{
+                State,
+                fixtures,
+            },
+        },
+        util::json::json_decode,
+    };
+
+    fn update(state: &mut State, now: DateTime<Utc>, squawk: &str, latitude: f64) {
+        fixtures::update(
+            state,
+            now,
+            [fixtures::aircraft(
+                json!({"squawk": squawk, "lat": latitude, "lon": 13.5}),
+            )],
+        );
+    }
+
+    #[test]
+    fn it_decodes_the_config() {
+        let config: AlertsConfig = json_decode(
+            r#"{
+                "rules": [
+                    {"name": "emergency", "condition": {"type": "emergency"}},
+                    {"name": "military", "condition": {"type": "tagged", "tag": "military"}, "cooldown": 60}
+                ],
+                "webhooks": [{"url": "http://localhost:8000/hook", "rules": ["emergency"]}]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.rules.len(), 2);
+        assert_eq!(config.rules[0].cooldown, DEFAULT_COOLDOWN);
+        assert!(matches!(
+            &config.rules[1].condition,
+            Condition::Tagged { tag } if tag == "military"
+        ));
+        assert_eq!(config.webhooks[0].rules, ["emergency"]);
+
+        assert!(
+            json_decode::<AlertsConfig>(
+                r#"{"webhooks": [{"url": "http://localhost:8000/hook", "retry_delay": 0}]}"#
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn it_fires_when_conditions_become_true() {
+        let mut alerts = Alerts::new(
+            vec![
+                AlertRule {
+                    name: "emergency".to_owned(),
+                    condition: Condition::Emergency,
+                    cooldown: 60.0,
+                },
+                AlertRule {
+                    name: "berlin".to_owned(),
+                    condition: Condition::Area {
+                        polygon: Polygon {
+                            vertices: vec![
+                                LatLon::new(52.0, 12.5),
+                                LatLon::new(53.0, 12.5),
+                                LatLon::new(53.0, 14.5),
+                                LatLon::new(52.0, 14.5),
+                            ],
+                        },
+                    },
+                    cooldown: 60.0,
+                },
+            ],
+            HashMap::new(),
+        );
+        let mut state = State::default();
+        let start = DateTime::from_timestamp(1750000000, 0).unwrap();
+        let at = |seconds| start + TimeDelta::seconds(seconds);
+
+        update(&mut state, at(0), "1000", 51.5);
+        assert!(alerts.evaluate(&state, at(0)).is_empty());
+
+        update(&mut state, at(1), "7700", 52.5);
+        let fired = alerts.evaluate(&state, at(1));
+        let rules: Vec<_> = fired.iter().map(|alert| alert.rule.as_str()).collect();
+        assert_eq!(rules, ["emergency", "berlin"]);
+        assert_eq!(fired[0].icao, IcaoAddress::from_u32_unchecked(0x4ca7b5));
+        assert_eq!(fired[0].squawk, Some(Squawk::EMERGENCY));
+
+        // still matching
+        update(&mut state, at(2), "7700", 52.5);
+        assert!(alerts.evaluate(&state, at(2)).is_empty());
+
+        // matches again, but still in cooldown
+        update(&mut state, at(3), "1000", 52.5);
+        assert!(alerts.evaluate(&state, at(3)).is_empty());
+        update(&mut state, at(4), "7700", 52.5);
+        assert!(alerts.evaluate(&state, at(4)).is_empty());
+
+        // matches again after the cooldown
+        update(&mut state, at(70), "1000", 52.5);
+        assert!(alerts.evaluate(&state, at(70)).is_empty());
+        update(&mut state, at(71), "7700", 52.5);
+        let fired = alerts.evaluate(&state, at(71));
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].rule, "emergency");
+    }
+}
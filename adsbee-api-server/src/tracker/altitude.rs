@@ -0,0 +1,333 @@
+//! Altitude model
+//!
+//! Transponders report pressure altitude, i.e. the altitude in the standard
+//! atmosphere (1013.25 hPa at sea level) for the static pressure the aircraft
+//! measures. ADS-B aircraft additionally report their GNSS height, either
+//! directly in airborne position messages or as a difference to the pressure
+//! altitude in airborne velocity messages.
+//!
+//! From this we estimate:
+//!
+//! - GNSS altitude: reported by the aircraft, or pressure altitude plus the
+//!   reported difference. For aircraft that don't report it (e.g. Mode-S only),
+//!   the difference is estimated from nearby ADS-B aircraft at similar
+//!   altitudes.
+//! - QNH altitude: the altitude an altimeter set to the local QNH would show.
+//!   This is only meaningful for traffic near the station the QNH is from.
+//!
+//! Each estimate comes with a standard deviation, so users can decide whether
+//! it's good enough.
+
+use adsbee_mode_s as mode_s;
+use chrono::{
+    DateTime,
+    TimeDelta,
+    Utc,
+};
+
+use crate::tracker::{
+    meteo,
+    state::{
+        AircraftState,
+        fresh,
+    },
+};
+
+/// Altitudes must be at least this recent to be used.
+const MAX_ALTITUDE_AGE: TimeDelta = TimeDelta::seconds(10);
+
+/// The baro-GNSS difference changes slowly, so we keep it for longer.
+const MAX_DIFFERENCE_AGE: TimeDelta = TimeDelta::seconds(60);
+
+/// Position of the aircraft must be at least this recent to look for
+/// neighbours.
+const MAX_POSITION_AGE: TimeDelta = TimeDelta::seconds(60);
+
+/// Neighbours further away than this (in km) are not used. If the position of
+/// the aircraft is unknown, the neighbours are assumed to be this far away.
+const MAX_NEIGHBOUR_DISTANCE: f64 = 300.0;
+
+/// Neighbours with a pressure altitude that differs more than this (in ft) are
+/// not used.
+const MAX_NEIGHBOUR_ALTITUDE_DIFFERENCE: f64 = 10000.0;
+
+/// Standard deviation of the pressure altitude in ft.
+///
+/// Mostly due to 25 ft (or 100 ft for Gillham code) resolution.
+const BAROMETRIC_SIGMA: f64 = 25.0;
+
+/// Standard deviation of the reported GNSS altitude in ft.
+const GNSS_SIGMA: f64 = 50.0;
+
+/// Standard deviation of the reported baro-GNSS difference in ft.
+const DIFFERENCE_SIGMA: f64 = 25.0;
+
+/// How much the baro-GNSS difference varies with horizontal distance (ft per
+/// km).
+///
+/// About 1 hPa per 100 km, which is roughly 27 ft.
+const DIFFERENCE_SIGMA_PER_DISTANCE: f64 = 0.3;
+
+/// How much the baro-GNSS difference varies with altitude (ft per ft).
+///
+/// The difference scales with the temperature deviation from ISA. 10 K
+/// deviation gives about 3.5%.
+const DIFFERENCE_SIGMA_PER_ALTITUDE: f64 = 0.035;
+
+/// Altitude estimates for an aircraft.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AircraftAltitude {
+    /// Pressure altitude in ft, as reported
+    pub barometric: Option<i32>,
+    pub gnss: Option<AltitudeEstimate>,
+    pub qnh: Option<AltitudeEstimate>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AltitudeEstimate {
+    /// Altitude in ft
+    pub altitude: f64,
+    /// Standard deviation in ft
+    pub sigma: f64,
+    pub source: AltitudeSource,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AltitudeSource {
+    /// Reported by the aircraft
+    Reported,
+    /// Pressure altitude and the baro-GNSS difference reported by the aircraft
+    ReportedDifference,
+    /// Pressure altitude and baro-GNSS difference estimated from other
+    /// aircraft
+    Neighbours { count: usize },
+    /// Pressure altitude corrected with local QNH
+    Qnh,
+}
+
+/// Estimates the altitudes of `aircraft`.
+///
+/// `neighbours` are used to estimate the baro-GNSS difference if the aircraft
+/// doesn't report it. It may include `aircraft` itself. `qnh` is in hPa.
+pub fn estimate<'a>(
+    aircraft: &AircraftState,
+    neighbours: impl IntoIterator<Item = &'a AircraftState>,
+    qnh: Option<f64>,
+    time: DateTime<Utc>,
+) -> AircraftAltitude {
+    let barometric = fresh(&aircraft.altitude_barometric, time, MAX_ALTITUDE_AGE).copied();
+
+    let gnss = fresh(&aircraft.altitude_gnss, time, MAX_ALTITUDE_AGE)
+        .map(|altitude| {
+            AltitudeEstimate {
+                altitude: (*altitude).into(),
+                sigma: GNSS_SIGMA,
+                source: AltitudeSource::Reported,
+            }
+        })
+        .or_else(|| {
+            let barometric = f64::from(barometric?);
+            let difference = fresh(&aircraft.altitude_difference, time, MAX_DIFFERENCE_AGE)?;
+            Some(AltitudeEstimate {
+                altitude: barometric + f64::from(*difference),
+                sigma: BAROMETRIC_SIGMA.hypot(DIFFERENCE_SIGMA),
+                source: AltitudeSource::ReportedDifference,
+            })
+        })
+        .or_else(|| {
+            let barometric = f64::from(barometric?);
+            let (difference, sigma, count) =
+                estimate_difference(aircraft, barometric, neighbours, time)?;
+            Some(AltitudeEstimate {
+                altitude: barometric + difference,
+                sigma: BAROMETRIC_SIGMA.hypot(sigma),
+                source: AltitudeSource::Neighbours { count },
+            })
+        });
+
+    let qnh = barometric.zip(qnh).map(|(barometric, qnh)| {
+        AltitudeEstimate {
+            altitude: qnh_altitude(barometric.into(), qnh),
+            sigma: BAROMETRIC_SIGMA,
+            source: AltitudeSource::Qnh,
+        }
+    });
+
+    AircraftAltitude {
+        barometric,
+        gnss,
+        qnh,
+    }
+}
+
+/// Estimates the baro-GNSS difference at the aircraft from its neighbours.
+///
+/// This is a weighted mean, where the weights are the inverse variance of each
+/// neighbour's difference at the aircraft's location. Returns the difference,
+/// its standard deviation and the number of neighbours used.
+fn estimate_difference<'a>(
+    aircraft: &AircraftState,
+    barometric: f64,
+    neighbours: impl IntoIterator<Item = &'a AircraftState>,
+    time: DateTime<Utc>,
+) -> Option<(f64, f64, usize)> {
+    let position = fresh(&aircraft.position, time, MAX_POSITION_AGE);
+
+    let mut count = 0;
+    let mut sum_weights = 0.0;
+    let mut sum_weighted = 0.0;
+    let mut sum_weighted_squared = 0.0;
+
+    for neighbour in neighbours {
+        if neighbour.icao_address == aircraft.icao_address {
+            continue;
+        }
+
+        let Some(neighbour_barometric) =
+            fresh(&neighbour.altitude_barometric, time, MAX_ALTITUDE_AGE)
+        else {
+            continue;
+        };
+        let neighbour_barometric = f64::from(*neighbour_barometric);
+
+        let Some(difference) = difference(neighbour, time)
+        else {
+            continue;
+        };
+
+        let Some(neighbour_position) = fresh(&neighbour.position, time, MAX_POSITION_AGE)
+        else {
+            continue;
+        };
+        let distance = position.map_or(MAX_NEIGHBOUR_DISTANCE, |position| {
+            position
+                .lat_lon()
+                .haversine_distance(&neighbour_position.lat_lon())
+                / 1000.0
+        });
+        let altitude_difference = (barometric - neighbour_barometric).abs();
+        if distance > MAX_NEIGHBOUR_DISTANCE
+            || altitude_difference > MAX_NEIGHBOUR_ALTITUDE_DIFFERENCE
+        {
+            continue;
+        }
+
+        let variance = DIFFERENCE_SIGMA.powi(2)
+            + (DIFFERENCE_SIGMA_PER_DISTANCE * distance).powi(2)
+            + (DIFFERENCE_SIGMA_PER_ALTITUDE * altitude_difference).powi(2);
+        let weight = 1.0 / variance;
+
+        count += 1;
+        sum_weights += weight;
+        sum_weighted += weight * difference;
+        sum_weighted_squared += weight * difference * difference;
+    }
+
+    if count == 0 {
+        return None;
+    }
+
+    let mean = sum_weighted / sum_weights;
+    // uncertainty of the mean plus the scatter of the neighbours
+    let scatter = (sum_weighted_squared / sum_weights - mean * mean).max(0.0);
+    let sigma = (1.0 / sum_weights + scatter).sqrt();
+
+    Some((mean, sigma, count))
+}
+
+/// Baro-GNSS difference of an aircraft in ft, as reported, or calculated from
+/// both altitudes.
+fn difference(aircraft: &AircraftState, time: DateTime<Utc>) -> Option<f64> {
+    fresh(&aircraft.altitude_difference, time, MAX_DIFFERENCE_AGE)
+        .map(|difference| f64::from(*difference))
+        .or_else(|| {
+            let barometric = fresh(&aircraft.altitude_barometric, time, MAX_ALTITUDE_AGE)?;
+            let gnss = fresh(&aircraft.altitude_gnss, time, MAX_ALTITUDE_AGE)?;
+            Some(f64::from(*gnss - *barometric))
+        })
+}
+
+/// Altitude in ft an altimeter set to `qnh` (hPa) shows at a pressure altitude
+/// (ft).
+pub fn qnh_altitude(pressure_altitude: f64, qnh: f64) -> f64 {
+    let pressure = meteo::isa_pressure(pressure_altitude);
+    145366.45 * (1.0 - (pressure / qnh).powf(0.190263))
+}
+
+/// Converts an altitude decoded from a Mode-S altitude code to ft.
+pub fn altitude_to_ft(altitude: mode_s::Altitude) -> i32 {
+    match altitude.unit {
+        mode_s::AltitudeUnit::Feet => altitude.altitude,
+        mode_s::AltitudeUnit::Meter => (f64::from(altitude.altitude) / 0.3048).round() as i32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use adsbee_types::IcaoAddress;
+    use chrono::DateTime;
+
+    use crate::tracker::{
+        altitude::{
+            AltitudeSource,
+            estimate,
+            qnh_altitude,
+        },
+        state::{
+            AircraftState,
+            Position,
+            PositionSource,
+            Timestamped,
+        },
+    };
+
+    #[test]
+    fn it_corrects_for_qnh() {
+        assert!((qnh_altitude(1000.0, 1013.25) - 1000.0).abs() < 1.0);
+        // about 27 ft per hPa near the ground
+        let altitude = qnh_altitude(1000.0, 1023.25);
+        assert!((altitude - 1275.0).abs() < 10.0, "{altitude}");
+    }
+
+    #[test]
+    fn it_estimates_gnss_altitude_from_neighbours() {
+        let time = DateTime::UNIX_EPOCH;
+        fn timestamped<T>(value: T) -> Option<Timestamped<T>> {
+            Some(Timestamped {
+                last_update: DateTime::UNIX_EPOCH,
+                value,
+            })
+        }
+        let position = |latitude, longitude| {
+            timestamped(Position {
+                latitude,
+                longitude,
+                source: PositionSource::Gnss,
+            })
+        };
+
+        let mut mode_s_only = AircraftState::new(IcaoAddress::from_u32_unchecked(1), time);
+        mode_s_only.altitude_barometric = timestamped(35000);
+        mode_s_only.position = position(52.0, 13.0);
+
+        let mut near = AircraftState::new(IcaoAddress::from_u32_unchecked(2), time);
+        near.altitude_barometric = timestamped(36000);
+        near.altitude_difference = timestamped(-500);
+        near.position = position(52.1, 13.1);
+
+        let mut far = AircraftState::new(IcaoAddress::from_u32_unchecked(3), time);
+        far.altitude_barometric = timestamped(5000);
+        far.altitude_difference = timestamped(200);
+        far.position = position(53.0, 13.0);
+
+        let altitude = estimate(&mode_s_only, [&near, &far], None, time);
+        let gnss = altitude.gnss.unwrap();
+        assert_eq!(gnss.source, AltitudeSource::Neighbours { count: 1 });
+        assert!((gnss.altitude - 34500.0).abs() < 1.0, "{gnss:?}");
+        assert!(gnss.sigma > 25.0 && gnss.sigma < 100.0, "{gnss:?}");
+
+        near.altitude_difference = None;
+        let altitude = estimate(&near, [&mode_s_only, &far], None, time);
+        assert!(altitude.gnss.is_none());
+    }
+}
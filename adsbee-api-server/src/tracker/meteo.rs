@@ -0,0 +1,241 @@
+//! Wind and temperature observations from Mode-S EHS.
+//!
+//! Aircraft that reply to Comm-B interrogations for BDS 5,0 (track and turn)
+//! and BDS 6,0 (heading and speed) tell us their air vector (true airspeed and
+//! heading) and Mach number. Together with the ground vector from ADS-B this
+//! gives us:
+//!
+//! - wind: ground vector minus air vector
+//! - static air temperature: from the speed of sound, which is TAS / Mach
+//!
+//! Aircraft that report BDS 4,4 (MRAR) tell us wind and temperature directly.
+//!
+//! <https://mode-s.org/1090mhz/content/mode-s/6-mrar.html>
+
+use std::f64::consts::TAU;
+
+use adsbee_mode_s::commb::Register;
+use adsbee_types::IcaoAddress;
+use chrono::{
+    DateTime,
+    TimeDelta,
+    Utc,
+};
+
+use crate::tracker::state::{
+    AircraftState,
+    fresh,
+};
+
+/// Inputs that are older than this are not combined into an observation.
+const MAX_INPUT_AGE: TimeDelta = TimeDelta::seconds(5);
+
+/// Position and altitude must be at least this recent.
+const MAX_POSITION_AGE: TimeDelta = TimeDelta::seconds(10);
+
+/// Below this Mach number the quantization of TAS and Mach make the
+/// temperature too inaccurate.
+const MIN_MACH: f64 = 0.4;
+
+/// Speed of sound at sea level (ISA) in kt
+pub const SPEED_OF_SOUND_SEA_LEVEL: f64 = 661.4788;
+
+/// Temperature at sea level (ISA) in K
+pub const TEMPERATURE_SEA_LEVEL: f64 = 288.15;
+
+/// Pressure at sea level (ISA) in hPa
+pub const PRESSURE_SEA_LEVEL: f64 = 1013.25;
+
+const KELVIN: f64 = 273.15;
+
+#[derive(Clone, Copy, Debug)]
+pub struct MeteoObservation {
+    pub icao_address: IcaoAddress,
+    pub time: DateTime<Utc>,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Pressure altitude in ft
+    pub altitude_barometric: i32,
+    pub wind: Option<Wind>,
+    /// Static air temperature in °C
+    pub static_air_temperature: Option<f64>,
+    pub source: MeteoSource,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MeteoSource {
+    /// Derived from BDS 5,0, 6,0 and ADS-B velocity
+    Derived,
+    /// Reported by the aircraft in BDS 4,4
+    Mrar,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Wind {
+    /// Wind speed in kt
+    pub speed: f64,
+    /// Direction the wind is coming from, in radians clockwise from true north
+    pub direction: f64,
+}
+
+impl Wind {
+    /// Creates the wind from the components (in kt) of the direction the air is
+    /// moving to.
+    pub fn from_components(east: f64, north: f64) -> Self {
+        Self {
+            speed: east.hypot(north),
+            direction: (-east).atan2(-north).rem_euclid(TAU),
+        }
+    }
+
+    /// Components in kt (east, north) of the direction the air is moving to.
+    pub fn components(&self) -> [f64; 2] {
+        [
+            -self.speed * self.direction.sin(),
+            -self.speed * self.direction.cos(),
+        ]
+    }
+}
+
+/// Produces an observation after `register` was received from the aircraft.
+pub fn estimate(
+    aircraft: &AircraftState,
+    register: &Register,
+    time: DateTime<Utc>,
+) -> Option<MeteoObservation> {
+    let position = fresh(&aircraft.position, time, MAX_POSITION_AGE)?;
+    let altitude_barometric = *fresh(&aircraft.altitude_barometric, time, MAX_POSITION_AGE)?;
+
+    let (wind, static_air_temperature, source) = match register {
+        Register::MeteorologicalRoutineAirReport(mrar) => {
+            let wind = mrar.wind.map(|(speed, direction)| {
+                Wind {
+                    speed,
+                    direction: direction.to_radians(),
+                }
+            });
+            (wind, Some(mrar.static_air_temperature), MeteoSource::Mrar)
+        }
+        Register::TrackAndTurnReport(_) | Register::HeadingAndSpeedReport(_) => {
+            (
+                derive_wind(aircraft, time),
+                derive_static_air_temperature(aircraft, time),
+                MeteoSource::Derived,
+            )
+        }
+    };
+
+    if wind.is_none() && static_air_temperature.is_none() {
+        return None;
+    }
+
+    Some(MeteoObservation {
+        icao_address: aircraft.icao_address,
+        time,
+        latitude: position.latitude,
+        longitude: position.longitude,
+        altitude_barometric,
+        wind,
+        static_air_temperature,
+        source,
+    })
+}
+
+fn derive_wind(aircraft: &AircraftState, time: DateTime<Utc>) -> Option<Wind> {
+    let track_and_turn = fresh(&aircraft.comm_b.track_and_turn, time, MAX_INPUT_AGE);
+    let heading_and_speed = fresh(&aircraft.comm_b.heading_and_speed, time, MAX_INPUT_AGE);
+
+    let true_airspeed = track_and_turn?.true_airspeed?;
+
+    // true heading, prefer BDS 6,0 but fall back to ADS-B
+    let heading = heading_and_speed
+        .and_then(|report| report.magnetic_heading)
+        .zip(aircraft.declination(time))
+        .map(|(heading, declination)| heading.to_radians() + declination)
+        .or_else(|| fresh(&aircraft.true_heading, time, MAX_INPUT_AGE).copied())?;
+
+    // prefer ADS-B ground velocity, but fall back to BDS 5,0
+    let (ground_speed, track) = fresh(&aircraft.ground_speed, time, MAX_INPUT_AGE)
+        .zip(fresh(&aircraft.track, time, MAX_INPUT_AGE))
+        .map(|(ground_speed, track)| (*ground_speed, *track))
+        .or_else(|| {
+            let report = track_and_turn?;
+            Some((report.ground_speed?, report.true_track_angle?.to_radians()))
+        })?;
+
+    Some(Wind::from_components(
+        ground_speed * track.sin() - true_airspeed * heading.sin(),
+        ground_speed * track.cos() - true_airspeed * heading.cos(),
+    ))
+}
+
+fn derive_static_air_temperature(aircraft: &AircraftState, time: DateTime<Utc>) -> Option<f64> {
+    let true_airspeed =
+        fresh(&aircraft.comm_b.track_and_turn, time, MAX_INPUT_AGE)?.true_airspeed?;
+    let mach = fresh(&aircraft.comm_b.heading_and_speed, time, MAX_INPUT_AGE)?.mach?;
+
+    (mach >= MIN_MACH).then(|| static_air_temperature(true_airspeed, mach))
+}
+
+/// Static air temperature in °C from true airspeed (kt) and Mach number.
+pub fn static_air_temperature(true_airspeed: f64, mach: f64) -> f64 {
+    let speed_of_sound = true_airspeed / mach;
+    TEMPERATURE_SEA_LEVEL * (speed_of_sound / SPEED_OF_SOUND_SEA_LEVEL).powi(2) - KELVIN
+}
+
+/// ISA static pressure in hPa at a pressure altitude in ft.
+pub fn isa_pressure(altitude: f64) -> f64 {
+    let altitude = altitude * 0.3048;
+    if altitude <= 11000.0 {
+        PRESSURE_SEA_LEVEL * (1.0 - 2.25577e-5 * altitude).powf(5.25588)
+    }
+    else {
+        226.32 * (-(altitude - 11000.0) / 6341.62).exp()
+    }
+}
+
+/// Calibrated airspeed in kt for a Mach number at a static pressure in hPa.
+pub fn mach_to_calibrated_airspeed(mach: f64, pressure: f64) -> f64 {
+    let impact_pressure = pressure * ((1.0 + 0.2 * mach * mach).powf(3.5) - 1.0);
+    SPEED_OF_SOUND_SEA_LEVEL
+        * (5.0 * ((impact_pressure / PRESSURE_SEA_LEVEL + 1.0).powf(2.0 / 7.0) - 1.0)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::FRAC_PI_2;
+
+    use crate::tracker::meteo::{
+        Wind,
+        isa_pressure,
+        mach_to_calibrated_airspeed,
+        static_air_temperature,
+    };
+
+    #[test]
+    fn wind_direction_is_where_it_comes_from() {
+        // air moving east, i.e. wind from the west
+        let wind = Wind::from_components(10.0, 0.0);
+        assert!((wind.speed - 10.0).abs() < 1e-9);
+        assert!((wind.direction - 3.0 * FRAC_PI_2).abs() < 1e-9);
+
+        let [east, north] = wind.components();
+        assert!((east - 10.0).abs() < 1e-9);
+        assert!(north.abs() < 1e-9);
+    }
+
+    #[test]
+    fn it_derives_isa_temperature() {
+        // at FL350 ISA temperature is -54.3 °C, speed of sound 576 kt
+        let temperature = static_air_temperature(0.78 * 576.0, 0.78);
+        assert!((temperature + 54.3).abs() < 0.5, "{temperature}");
+    }
+
+    #[test]
+    fn it_converts_mach_to_cas() {
+        // M0.78 at FL350 is about 264 kt CAS
+        let cas = mach_to_calibrated_airspeed(0.78, isa_pressure(35000.0));
+        assert!((cas - 264.0).abs() < 2.0, "{cas}");
+        assert!((isa_pressure(0.0) - 1013.25).abs() < 1e-6);
+    }
+}
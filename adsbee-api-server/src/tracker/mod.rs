@@ -1,6 +1,17 @@
+pub mod alerts;
+pub mod altitude;
+pub mod meteo;
 pub mod state;
 pub mod subscriptions;
 
+use std::{
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    time::Duration,
+};
+
 use adsbee_api_types::live::{
     ServerToClientMessage,
     SubscriptionFilter,
@@ -11,17 +22,42 @@ use adsbee_beast::{
 };
 use adsbee_mode_s as mode_s;
 use adsbee_sbs as sbs;
+use adsbee_types::IcaoAddress;
 use chrono::{
     DateTime,
+    TimeDelta,
     Utc,
 };
-use tokio::sync::mpsc;
+use futures_util::{
+    Stream,
+    stream,
+};
+use tokio::{
+    sync::{
+        broadcast,
+        mpsc,
+        oneshot,
+    },
+    time::MissedTickBehavior,
+};
 use uuid::Uuid;
 
 use crate::{
     api::live::ClientId,
-    source::SourceId,
+    metrics,
+    output,
+    source::{
+        SourceId,
+        aircraft_json,
+    },
     tracker::{
+        alerts::{
+            Alert,
+            AlertRule,
+            Alerts,
+        },
+        altitude::AircraftAltitude,
+        meteo::MeteoObservation,
         state::{
             Position,
             PositionSource,
@@ -32,6 +68,16 @@ use crate::{
 };
 
 const COMMAND_QUEUE_SIZE: usize = 32;
+const METEO_QUEUE_SIZE: usize = 256;
+const BEAST_QUEUE_SIZE: usize = 4096;
+const SBS_QUEUE_SIZE: usize = 4096;
+const ALERT_QUEUE_SIZE: usize = 256;
+
+/// Interval at which alert rules are evaluated and metrics are updated.
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// GPS timestamps further away from the time a frame was received are ignored.
+const MAX_TIMESTAMP_OFFSET: TimeDelta = TimeDelta::seconds(10);
 
 #[derive(Debug, thiserror::Error)]
 #[error("broker error")]
@@ -54,24 +100,98 @@ pub enum Error {
 #[derive(Clone, Debug)]
 pub struct Tracker {
     command_sender: mpsc::Sender<Command>,
+    meteo_sender: broadcast::Sender<MeteoObservation>,
+    beast_sender: broadcast::Sender<BeastFrame>,
+    sbs_sender: broadcast::Sender<sbs::Message>,
+    alert_sender: broadcast::Sender<Alert>,
 }
 
 impl Tracker {
     pub fn new() -> Self {
         let (command_sender, command_receiver) = mpsc::channel(COMMAND_QUEUE_SIZE);
+        let (meteo_sender, _) = broadcast::channel(METEO_QUEUE_SIZE);
+        let (beast_sender, _) = broadcast::channel(BEAST_QUEUE_SIZE);
+        let (sbs_sender, _) = broadcast::channel(SBS_QUEUE_SIZE);
+        let (alert_sender, _) = broadcast::channel(ALERT_QUEUE_SIZE);
 
         tokio::spawn({
+            let meteo_sender = meteo_sender.clone();
+            let sbs_sender = sbs_sender.clone();
+            let alert_sender = alert_sender.clone();
             async move {
                 let reactor = Reactor {
                     command_receiver,
                     subscriptions: Default::default(),
                     state: Default::default(),
+                    alerts: Default::default(),
+                    meteo_sender,
+                    sbs_sender,
+                    alert_sender,
                 };
                 reactor.run().await.expect("broker reactor error");
             }
         });
 
-        Self { command_sender }
+        Self {
+            command_sender,
+            meteo_sender,
+            beast_sender,
+            sbs_sender,
+            alert_sender,
+        }
+    }
+
+    /// Returns a receiver for all Mode A/C and Mode-S frames pushed from BEAST
+    /// sources.
+    ///
+    /// The receiver buffers up to [`BEAST_QUEUE_SIZE`] frames. If it falls
+    /// behind further, it will return [`RecvError::Lagged`][1].
+    ///
+    /// [1]: broadcast::error::RecvError::Lagged
+    pub fn beast_frames(&self) -> broadcast::Receiver<BeastFrame> {
+        self.beast_sender.subscribe()
+    }
+
+    /// Returns a receiver for SBS messages generated from the Mode-S frames the
+    /// tracker receives (see [`output::sbs::message`]).
+    ///
+    /// The receiver buffers up to [`SBS_QUEUE_SIZE`] messages. If it falls
+    /// behind further, it will return [`RecvError::Lagged`][1].
+    ///
+    /// [1]: broadcast::error::RecvError::Lagged
+    pub fn sbs_messages(&self) -> broadcast::Receiver<sbs::Message> {
+        self.sbs_sender.subscribe()
+    }
+
+    /// Returns a receiver for alerts fired by the rules set with
+    /// [`set_alert_rules`][Self::set_alert_rules].
+    ///
+    /// The receiver buffers up to [`ALERT_QUEUE_SIZE`] alerts. If it falls
+    /// behind further, it will return [`RecvError::Lagged`][1].
+    ///
+    /// [1]: broadcast::error::RecvError::Lagged
+    pub fn alerts(&self) -> broadcast::Receiver<Alert> {
+        self.alert_sender.subscribe()
+    }
+
+    /// Returns a stream of wind and temperature observations.
+    ///
+    /// Observations are derived from Mode-S EHS replies (see [`meteo`]). If the
+    /// stream isn't polled fast enough, observations are dropped.
+    pub fn meteo_observations(&self) -> impl Stream<Item = MeteoObservation> + Send + 'static {
+        stream::unfold(self.meteo_sender.subscribe(), |mut receiver| {
+            async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(observation) => return Some((observation, receiver)),
+                        Err(broadcast::error::RecvError::Lagged(dropped_count)) => {
+                            tracing::warn!(dropped_count, "meteo observations lagged");
+                        }
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    }
+                }
+            }
+        })
     }
 
     async fn send_command(&self, command: Command) {
@@ -120,6 +240,21 @@ impl Tracker {
         time_received: DateTime<Utc>,
         packet: beast::output::OutputPacket,
     ) {
+        if matches!(
+            packet,
+            beast::output::OutputPacket::ModeAc { .. }
+                | beast::output::OutputPacket::ModeSShort { .. }
+                | beast::output::OutputPacket::ModeSLong { .. }
+        ) {
+            // only fails if nobody is listening
+            let _ = self.beast_sender.send(BeastFrame {
+                source_id,
+                receiver_id,
+                time_received,
+                packet: packet.clone(),
+            });
+        }
+
         self.send_command(Command::PushBeast {
             source_id,
             receiver_id,
@@ -129,6 +264,65 @@ impl Tracker {
         .await;
     }
 
+    /// Sets the local QNH in hPa, used to correct pressure altitudes.
+    pub async fn set_qnh(&self, qnh: Option<f64>) {
+        self.send_command(Command::SetQnh { qnh }).await;
+    }
+
+    /// Returns the altitude estimates for an aircraft, or `None` if it isn't
+    /// tracked.
+    pub async fn get_altitude(&self, icao_address: IcaoAddress) -> Option<AircraftAltitude> {
+        let (result_sender, result_receiver) = oneshot::channel();
+        self.send_command(Command::GetAltitude {
+            icao_address,
+            result_sender,
+        })
+        .await;
+        result_receiver.await.ok().flatten()
+    }
+
+    /// Runs `f` with the tracker's state, and returns its result.
+    ///
+    /// This blocks the tracker while `f` runs, so it should be quick.
+    pub async fn with_state<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&State) -> R + Send + 'static,
+    ) -> Option<R> {
+        let (result_sender, result_receiver) = oneshot::channel();
+        self.send_command(Command::WithState {
+            f: StateFn(Box::new(move |state| {
+                let _ = result_sender.send(f(state));
+            })),
+        })
+        .await;
+        result_receiver.await.ok()
+    }
+
+    /// Replaces the alert rules (see [`alerts`]).
+    ///
+    /// `tagged` contains the aircraft addresses for each tag used in
+    /// [`Condition::Tagged`][alerts::Condition::Tagged] rules.
+    pub async fn set_alert_rules(
+        &self,
+        rules: Vec<AlertRule>,
+        tagged: HashMap<String, HashSet<IcaoAddress>>,
+    ) {
+        self.send_command(Command::SetAlertRules { rules, tagged })
+            .await;
+    }
+
+    pub async fn push_aircraft_json(
+        &self,
+        source_id: SourceId,
+        snapshot: aircraft_json::AircraftJson,
+    ) {
+        self.send_command(Command::PushAircraftJson {
+            source_id,
+            snapshot,
+        })
+        .await;
+    }
+
     pub async fn push_sbs(&self, source_id: SourceId, mlat: bool, message: sbs::Message) {
         self.send_command(Command::PushSbs {
             source_id,
@@ -139,22 +333,62 @@ impl Tracker {
     }
 }
 
+/// A frame received from a BEAST source.
+#[derive(Clone, Debug)]
+pub struct BeastFrame {
+    pub source_id: SourceId,
+    pub receiver_id: Option<Uuid>,
+    pub time_received: DateTime<Utc>,
+    pub packet: beast::output::OutputPacket,
+}
+
 #[derive(Debug)]
 struct Reactor {
     subscriptions: Subscriptions,
     state: State,
     command_receiver: mpsc::Receiver<Command>,
+    meteo_sender: broadcast::Sender<MeteoObservation>,
+    sbs_sender: broadcast::Sender<sbs::Message>,
+    alerts: Alerts,
+    alert_sender: broadcast::Sender<Alert>,
 }
 
 impl Reactor {
     async fn run(mut self) -> Result<(), Error> {
-        while let Some(command) = self.command_receiver.recv().await {
-            self.handle_command(command).await?;
+        let mut interval = tokio::time::interval(TICK_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                command = self.command_receiver.recv() => {
+                    let Some(command) = command
+                    else {
+                        break;
+                    };
+                    self.handle_command(command).await?;
+                }
+                _ = interval.tick() => {
+                    self.evaluate_alerts();
+                    metrics::set_aircraft(self.state.num_aircraft());
+                }
+            }
         }
 
         Ok(())
     }
 
+    fn evaluate_alerts(&mut self) {
+        if self.alerts.is_empty() {
+            return;
+        }
+
+        for alert in self.alerts.evaluate(&self.state, Utc::now()) {
+            tracing::debug!(rule = %alert.rule, icao = %alert.icao, "alert");
+            // only fails if nobody is listening
+            let _ = self.alert_sender.send(alert);
+        }
+    }
+
     async fn handle_command(&mut self, command: Command) -> Result<(), Error> {
         match command {
             Command::Subscribe {
@@ -196,6 +430,7 @@ impl Reactor {
                 time_received,
                 packet,
             } => {
+                metrics::count_source_message(source_id, receiver_id);
                 self.handle_beast_packet(source_id, receiver_id, time_received, packet)
                     .await?
             }
@@ -204,14 +439,40 @@ impl Reactor {
                 mlat,
                 message,
             } => {
+                metrics::count_source_message(source_id, None);
                 if mlat {
                     self.handle_sbs_mlat_message(source_id, message).await?;
                 }
                 else {
-                    //self.handle_sbs_message(source_id, message).await?;
-                    todo!("handle non-mlat sbs message");
+                    self.handle_sbs_message(source_id, message).await?;
                 }
             }
+            Command::PushAircraftJson {
+                source_id,
+                snapshot,
+            } => {
+                let now = snapshot.now();
+                for aircraft in &snapshot.aircraft {
+                    metrics::count_source_message(source_id, None);
+                    self.state
+                        .update_with_aircraft_json(now, source_id, aircraft);
+                }
+            }
+            Command::SetQnh { qnh } => {
+                self.state.set_qnh(qnh);
+            }
+            Command::GetAltitude {
+                icao_address,
+                result_sender,
+            } => {
+                let _ = result_sender.send(self.state.get_altitude(&icao_address, Utc::now()));
+            }
+            Command::WithState { f } => {
+                (f.0)(&self.state);
+            }
+            Command::SetAlertRules { rules, tagged } => {
+                self.alerts = Alerts::new(rules, tagged);
+            }
         }
 
         Ok(())
@@ -270,18 +531,41 @@ impl Reactor {
         _signal_level: beast::SignalLevel,
         data: &[u8],
     ) -> Result<(), Error> {
-        let time = if mlat_timestamp.is_synthetic() {
-            time_received
-        }
-        else {
-            todo!("parse beast::MlatTimestamp");
-        };
+        // we don't know which timestamp format the receiver uses. a 12 MHz counter
+        // interpreted as GPS time-of-day is very unlikely to be close to the time we
+        // received the frame.
+        let time = mlat_timestamp
+            .decode(beast::TimestampFormat::GpsTimeOfDay)
+            .to_date_time(time_received)
+            .filter(|time| (*time - time_received).abs() <= MAX_TIMESTAMP_OFFSET)
+            .unwrap_or(time_received);
+
+        metrics::count_frame(data);
 
-        match mode_s::Frame::decode(&mut &data[..]) {
+        match mode_s::Frame::decode_and_calculate_checksum(&mut &data[..]) {
             Ok(frame) => {
-                self.state.update_with_mode_s(time, &frame);
+                if frame.check() == Some(false) {
+                    metrics::count_crc_failure();
+                }
+
+                self.state.update_with_mode_s(time, &frame.frame);
+                self.state.update_with_altitude_reply(time, &frame);
+
+                if let Some((aircraft, register)) = self.state.update_with_comm_b(time, &frame)
+                    && let Some(observation) = meteo::estimate(aircraft, &register, time)
+                {
+                    // only fails if nobody is listening
+                    let _ = self.meteo_sender.send(observation);
+                }
+
+                if self.sbs_sender.receiver_count() > 0
+                    && let Some(message) = output::sbs::message(&self.state, time, &frame)
+                {
+                    let _ = self.sbs_sender.send(message);
+                }
             }
             Err(error) => {
+                metrics::count_decode_error();
                 tracing::error!(?error);
             }
         }
@@ -289,9 +573,38 @@ impl Reactor {
         Ok(())
     }
 
+    async fn handle_sbs_message(
+        &mut self,
+        source_id: SourceId,
+        message: sbs::Message,
+    ) -> Result<(), Error> {
+        if let sbs::Message::Transmission {
+            hex_ident,
+            time_generated,
+            transmission,
+            ..
+        } = message
+        {
+            // SBS timestamps might be in local time, so we only trust them if they're close
+            // to the time we received the message.
+            let time_received = Utc::now();
+            let time = if (time_generated - time_received).abs() <= MAX_TIMESTAMP_OFFSET {
+                time_generated
+            }
+            else {
+                time_received
+            };
+
+            self.state
+                .update_with_sbs(time, source_id, hex_ident, &transmission);
+        }
+
+        Ok(())
+    }
+
     async fn handle_sbs_mlat_message(
         &mut self,
-        _source_id: SourceId,
+        source_id: SourceId,
         message: sbs::Message,
     ) -> Result<(), Error> {
         match message {
@@ -306,15 +619,18 @@ impl Reactor {
                     },
                 ..
             } => {
-                self.state.update_mlat_position(
-                    hex_ident,
-                    time_generated,
-                    Position {
-                        latitude,
-                        longitude,
-                        source: PositionSource::Mlat,
-                    },
-                );
+                if let (Some(latitude), Some(longitude)) = (latitude, longitude) {
+                    self.state.update_mlat_position(
+                        hex_ident,
+                        time_generated,
+                        source_id,
+                        Position {
+                            latitude,
+                            longitude,
+                            source: PositionSource::Mlat,
+                        },
+                    );
+                }
             }
             _ => {}
         }
@@ -348,4 +664,30 @@ enum Command {
         mlat: bool,
         message: sbs::Message,
     },
+    PushAircraftJson {
+        source_id: SourceId,
+        snapshot: aircraft_json::AircraftJson,
+    },
+    SetQnh {
+        qnh: Option<f64>,
+    },
+    GetAltitude {
+        icao_address: IcaoAddress,
+        result_sender: oneshot::Sender<Option<AircraftAltitude>>,
+    },
+    WithState {
+        f: StateFn,
+    },
+    SetAlertRules {
+        rules: Vec<AlertRule>,
+        tagged: HashMap<String, HashSet<IcaoAddress>>,
+    },
+}
+
+struct StateFn(Box<dyn FnOnce(&State) + Send>);
+
+impl std::fmt::Debug for StateFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StateFn").finish_non_exhaustive()
+    }
 }
@@ -1,6 +1,11 @@
-use std::collections::{
-    HashMap,
-    hash_map,
+use std::{
+    collections::{
+        HashMap,
+        HashSet,
+        VecDeque,
+        hash_map,
+    },
+    f64::consts::TAU,
 };
 
 use adsbee_mode_s::{
@@ -14,10 +19,20 @@ use adsbee_mode_s::{
             Decoder,
         },
     },
+    commb::{
+        self,
+        HeadingAndSpeedReport,
+        MeteorologicalRoutineAirReport,
+        TrackAndTurnReport,
+    },
 };
+use adsbee_sbs as sbs;
 use adsbee_types::{
+    AirlineDesignator,
     IcaoAddress,
     Squawk,
+    geo::LatLon,
+    wmm,
 };
 use chrono::{
     DateTime,
@@ -25,12 +40,40 @@ use chrono::{
     Utc,
 };
 
-use crate::util::sparse_list::SparseList;
+use crate::{
+    source::{
+        SourceId,
+        aircraft_json,
+        history,
+    },
+    tracker::{
+        altitude::{
+            self,
+            AircraftAltitude,
+        },
+        meteo,
+    },
+    util::sparse_list::SparseList,
+};
+
+/// ADS-B velocity must be at least this recent to be used to check Comm-B
+/// replies.
+const MAX_VELOCITY_AGE: TimeDelta = TimeDelta::seconds(10);
+
+/// Positions older than this are removed from trails.
+pub const TRAIL_MAX_AGE: TimeDelta = TimeDelta::minutes(5);
+
+/// Minimum time between positions in a trail. Positions received more often are
+/// not added to the trail.
+const TRAIL_MIN_INTERVAL: TimeDelta = TimeDelta::seconds(2);
 
 #[derive(Debug, Default)]
 pub struct State {
     aircraft: SparseList<AircraftState>,
     indices: AircraftIndices,
+
+    /// Local QNH in hPa
+    qnh: Option<f64>,
 }
 
 impl State {
@@ -63,18 +106,51 @@ impl State {
         }
     }
 
+    pub fn num_aircraft(&self) -> usize {
+        self.indices.by_icao_address.len()
+    }
+
     pub fn iter_aircraft(&self) -> impl Iterator<Item = &AircraftState> {
         self.aircraft.iter()
     }
 
+    pub fn get_aircraft(&self, icao_address: &IcaoAddress) -> Option<&AircraftState> {
+        self.indices
+            .by_icao_address
+            .get(icao_address)
+            .map(|index| &self.aircraft[*index])
+    }
+
+    pub fn get_aircraft_by_callsign(&self, callsign: &Callsign) -> Option<&AircraftState> {
+        self.indices
+            .by_callsign
+            .get(callsign)
+            .map(|index| &self.aircraft[*index])
+    }
+
+    /// Iterates over all aircraft whose callsign belongs to the airline.
+    pub fn iter_aircraft_by_airline(
+        &self,
+        airline: &AirlineDesignator,
+    ) -> impl Iterator<Item = &AircraftState> {
+        self.indices
+            .by_airline
+            .get(airline)
+            .into_iter()
+            .flatten()
+            .map(|index| &self.aircraft[*index])
+    }
+
     pub fn update_mlat_position(
         &mut self,
         icao_address: IcaoAddress,
         time: DateTime<Utc>,
+        source_id: SourceId,
         position: Position,
     ) {
-        let aircraft = self.update_aircraft(icao_address, time);
-        aircraft.state.position.update(time, position);
+        let mut aircraft = self.update_aircraft(icao_address, time);
+        aircraft.received_from(source_id);
+        aircraft.state.record_position(time, position);
     }
 
     pub fn update_with_mode_s(&mut self, time: DateTime<Utc>, frame: &mode_s::Frame) {
@@ -112,6 +188,130 @@ impl State {
         }
     }
 
+    pub fn set_qnh(&mut self, qnh: Option<f64>) {
+        self.qnh = qnh;
+    }
+
+    /// Estimates the altitudes of an aircraft. See [`altitude`].
+    pub fn get_altitude(
+        &self,
+        icao_address: &IcaoAddress,
+        time: DateTime<Utc>,
+    ) -> Option<AircraftAltitude> {
+        let aircraft = self.get_aircraft(icao_address)?;
+        Some(altitude::estimate(
+            aircraft,
+            self.aircraft.iter(),
+            self.qnh,
+            time,
+        ))
+    }
+
+    /// Updates the pressure altitude of an aircraft from a Mode-S reply.
+    ///
+    /// Like Comm-B replies, this only updates aircraft that are already
+    /// tracked.
+    pub fn update_with_altitude_reply(
+        &mut self,
+        time: DateTime<Utc>,
+        frame: &mode_s::FrameWithChecksum,
+    ) {
+        let altitude_code = match &frame.frame {
+            mode_s::Frame::ShortAirAirSurveillance(reply) => reply.altitude_code,
+            mode_s::Frame::SurveillanceAltitudeReply(reply) => reply.altitude_code,
+            mode_s::Frame::LongAirAirSurveillance(reply) => reply.altitude_code,
+            mode_s::Frame::CommBAltitudeReply(reply) => reply.altitude_code,
+            _ => return,
+        };
+
+        let Some(altitude) = altitude_code.decode()
+        else {
+            return;
+        };
+        let Some(index) = frame
+            .address()
+            .and_then(|address| self.indices.by_icao_address.get(&address))
+        else {
+            return;
+        };
+
+        let aircraft = &mut self.aircraft[*index];
+        aircraft.last_seen.update(time, ());
+        aircraft
+            .altitude_barometric
+            .update(time, altitude::altitude_to_ft(altitude));
+    }
+
+    /// Updates an aircraft with a Comm-B reply.
+    ///
+    /// Since the address of Comm-B replies can't be verified, this only updates
+    /// aircraft that are already tracked. The message is decoded into the
+    /// register that is plausible given the aircraft's state. If it was
+    /// decoded, this returns the aircraft and the register.
+    pub fn update_with_comm_b(
+        &mut self,
+        time: DateTime<Utc>,
+        frame: &mode_s::FrameWithChecksum,
+    ) -> Option<(&AircraftState, commb::Register)> {
+        let message = match &frame.frame {
+            mode_s::Frame::CommBAltitudeReply(reply) => &reply.message,
+            mode_s::Frame::CommBIdentityReply(reply) => &reply.message,
+            _ => return None,
+        };
+
+        let index = *self.indices.by_icao_address.get(&frame.address()?)?;
+        let aircraft = &mut self.aircraft[index];
+
+        let mut candidates = commb::Register::candidates(message)
+            .filter(|register| aircraft.is_plausible_comm_b(register, time));
+        let register = candidates.next()?;
+        if candidates.next().is_some() {
+            // ambiguous
+            return None;
+        }
+
+        aircraft.last_seen.update(time, ());
+        match register {
+            commb::Register::MeteorologicalRoutineAirReport(report) => {
+                aircraft.comm_b.mrar.update(time, report);
+            }
+            commb::Register::TrackAndTurnReport(report) => {
+                aircraft.comm_b.track_and_turn.update(time, report);
+            }
+            commb::Register::HeadingAndSpeedReport(report) => {
+                aircraft.comm_b.heading_and_speed.update(time, report);
+            }
+        }
+
+        Some((aircraft, register))
+    }
+
+    /// Updates an aircraft with its entry in an `aircraft.json` snapshot
+    /// generated at `now`.
+    pub fn update_with_aircraft_json(
+        &mut self,
+        now: DateTime<Utc>,
+        source_id: SourceId,
+        aircraft: &aircraft_json::Aircraft,
+    ) {
+        let mut state = self.update_aircraft(aircraft.hex, aircraft.last_seen(now));
+        state.received_from(source_id);
+        state.update_aircraft_json(now, aircraft);
+    }
+
+    /// Updates an aircraft with an SBS `MSG` message from a non-MLAT source.
+    pub fn update_with_sbs(
+        &mut self,
+        time: DateTime<Utc>,
+        source_id: SourceId,
+        icao_address: IcaoAddress,
+        transmission: &sbs::Transmission,
+    ) {
+        let mut aircraft = self.update_aircraft(icao_address, time);
+        aircraft.received_from(source_id);
+        aircraft.update_sbs_transmission(transmission);
+    }
+
     pub fn update_with_adsb(
         &mut self,
         time: DateTime<Utc>,
@@ -136,6 +336,9 @@ impl State {
             adsb::Message::AircraftStatus(aircraft_status) => {
                 aircraft.update_aircraft_status(aircraft_status)
             }
+            adsb::Message::AircraftOperationalStatus(operational_status) => {
+                aircraft.update_operational_status(operational_status)
+            }
             _ => {}
         }
     }
@@ -145,6 +348,7 @@ impl State {
 struct AircraftIndices {
     by_icao_address: HashMap<IcaoAddress, usize>,
     by_callsign: HashMap<Callsign, usize>,
+    by_airline: HashMap<AirlineDesignator, HashSet<usize>>,
     by_squawk: HashMap<Squawk, usize>,
 }
 
@@ -152,30 +356,57 @@ struct AircraftIndices {
 pub struct AircraftState {
     pub icao_address: IcaoAddress,
 
+    pub first_seen: DateTime<Utc>,
     pub last_seen: Timestamped<()>,
 
+    /// Sources the aircraft was received from, and when each last sent
+    /// something. Only SBS and `aircraft.json` sources are recorded.
+    pub sources: HashMap<SourceId, DateTime<Utc>>,
+
     pub callsign: Option<Timestamped<Callsign>>,
     pub squawk: Option<Timestamped<Squawk>>,
 
+    /// Emitter category, from the aircraft identification
+    pub wake_vortex_category: Option<adsb::WakeVortexCategory>,
+
     // latitude and longitude
     pub position: Option<Timestamped<Position>>,
 
     // in ft
     pub altitude_barometric: Option<Timestamped<i32>>,
 
-    // in m
+    // in ft, height above ellipsoid
     pub altitude_gnss: Option<Timestamped<i32>>,
 
+    // GNSS minus barometric altitude, in ft
+    pub altitude_difference: Option<Timestamped<i32>>,
+
     // in kt
     pub ground_speed: Option<Timestamped<f64>>,
-    pub airspeed: Option<Timestamped<f64>>,
+    pub indicated_airspeed: Option<Timestamped<f64>>,
+    pub true_airspeed: Option<Timestamped<f64>>,
+
+    // barometric, in ft/min, positive is up
+    pub vertical_rate: Option<Timestamped<i32>>,
 
     // in radians, clockwise
     pub track: Option<Timestamped<f64>>,
     pub magnetic_heading: Option<Timestamped<f64>>,
+    pub true_heading: Option<Timestamped<f64>>,
+
+    /// Reference for the heading the aircraft reports, from the operational
+    /// status. If unknown we assume magnetic north.
+    pub horizontal_reference_direction: Option<adsb::HorizontalReferenceDirection>,
 
     pub vertical_status: Option<VerticalStatus>,
 
+    /// Recent positions, oldest first (see [`TRAIL_MAX_AGE`])
+    pub trail: VecDeque<TrailPoint>,
+
+    pub comm_b: CommBState,
+
+    pub adsb_quality: AdsbQualityState,
+
     pub cpr_decoder: Decoder<DateTime<Utc>>,
 }
 
@@ -183,23 +414,188 @@ impl AircraftState {
     pub fn new(icao_address: IcaoAddress, time: DateTime<Utc>) -> Self {
         Self {
             icao_address,
+            first_seen: time,
             last_seen: Timestamped {
                 last_update: time,
                 value: (),
             },
+            sources: HashMap::new(),
             callsign: None,
             squawk: None,
+            wake_vortex_category: None,
             position: None,
             altitude_barometric: None,
             altitude_gnss: None,
+            altitude_difference: None,
             ground_speed: None,
-            airspeed: None,
+            indicated_airspeed: None,
+            true_airspeed: None,
+            vertical_rate: None,
             track: None,
             magnetic_heading: None,
+            true_heading: None,
+            horizontal_reference_direction: None,
             vertical_status: None,
+            trail: VecDeque::new(),
+            comm_b: Default::default(),
+            adsb_quality: Default::default(),
             cpr_decoder: Default::default(),
         }
     }
+
+    /// Updates the position, and adds it to the trail.
+    fn record_position(&mut self, time: DateTime<Utc>, position: Position) {
+        if !self.position.update(time, position) {
+            return;
+        }
+
+        if self
+            .trail
+            .back()
+            .is_some_and(|last| time.signed_duration_since(last.time) < TRAIL_MIN_INTERVAL)
+        {
+            return;
+        }
+
+        while self
+            .trail
+            .front()
+            .is_some_and(|first| time.signed_duration_since(first.time) > TRAIL_MAX_AGE)
+        {
+            self.trail.pop_front();
+        }
+
+        self.trail.push_back(TrailPoint {
+            time,
+            position,
+            altitude_barometric: self
+                .altitude_barometric
+                .as_ref()
+                .map(|altitude| altitude.value),
+            altitude_gnss: self.altitude_gnss.as_ref().map(|altitude| altitude.value),
+            vertical_rate: self
+                .vertical_rate
+                .as_ref()
+                .map(|vertical_rate| vertical_rate.value),
+            ground_speed: self.ground_speed.as_ref().map(|speed| speed.value),
+            track: self.track.as_ref().map(|track| track.value),
+            vertical_status: self.vertical_status,
+        });
+    }
+
+    /// Magnetic declination (radians, positive east) at the aircraft's last
+    /// known position.
+    ///
+    /// The declination changes slowly with position, so we don't care how old
+    /// the position is.
+    pub fn declination(&self, time: DateTime<Utc>) -> Option<f64> {
+        let position = &self.position.as_ref()?.value;
+        let altitude = self
+            .altitude_barometric
+            .as_ref()
+            .map_or(0.0, |altitude| f64::from(altitude.value) * 0.3048);
+        Some(
+            wmm::declination(
+                position.latitude,
+                position.longitude,
+                altitude,
+                wmm::decimal_year(&time),
+            )
+            .to_radians(),
+        )
+    }
+
+    /// Checks a Comm-B register decoded for this aircraft against what we know
+    /// from ADS-B.
+    fn is_plausible_comm_b(&self, register: &commb::Register, time: DateTime<Utc>) -> bool {
+        let fresh = |value| fresh(value, time, MAX_VELOCITY_AGE).copied();
+
+        match register {
+            commb::Register::MeteorologicalRoutineAirReport(_) => true,
+            commb::Register::TrackAndTurnReport(report) => {
+                let ground_speed_matches = fresh(&self.ground_speed)
+                    .zip(report.ground_speed)
+                    .is_none_or(|(expected, ground_speed)| (expected - ground_speed).abs() <= 20.0);
+                let track_matches = fresh(&self.track).zip(report.true_track_angle).is_none_or(
+                    |(expected, track)| {
+                        angle_difference(expected, track.to_radians()) <= 10f64.to_radians()
+                    },
+                );
+                ground_speed_matches && track_matches
+            }
+            commb::Register::HeadingAndSpeedReport(report) => {
+                // we allow for crab angle, and if we don't know the position, for
+                // declination.
+                let declination = self.declination(time).unwrap_or_default();
+                let heading_matches = fresh(&self.track).zip(report.magnetic_heading).is_none_or(
+                    |(track, heading)| {
+                        angle_difference(track, heading.to_radians() + declination)
+                            <= 45f64.to_radians()
+                    },
+                );
+                let airspeed_matches = self
+                    .altitude_barometric
+                    .as_ref()
+                    .zip(report.mach.zip(report.indicated_airspeed))
+                    .is_none_or(|(altitude, (mach, indicated_airspeed))| {
+                        let pressure = meteo::isa_pressure(altitude.value.into());
+                        (meteo::mach_to_calibrated_airspeed(mach, pressure) - indicated_airspeed)
+                            .abs()
+                            <= 20.0
+                    });
+                heading_matches && airspeed_matches
+            }
+        }
+    }
+}
+
+/// The latest Comm-B registers we decoded for an aircraft.
+#[derive(Debug, Default)]
+pub struct CommBState {
+    /// BDS 4,4
+    pub mrar: Option<Timestamped<MeteorologicalRoutineAirReport>>,
+    /// BDS 5,0
+    pub track_and_turn: Option<Timestamped<TrackAndTurnReport>>,
+    /// BDS 6,0
+    pub heading_and_speed: Option<Timestamped<HeadingAndSpeedReport>>,
+}
+
+/// ADS-B version and quality indicators. These are mostly sent in the aircraft
+/// operational status.
+#[derive(Debug, Default)]
+pub struct AdsbQualityState {
+    pub mops_version: Option<adsb::MopsVersion>,
+    /// Navigation integrity category, from the last airborne position
+    pub nic: Option<u8>,
+    pub nic_supplement_a: bool,
+    pub nac_p: Option<adsb::NacP>,
+    pub sil: Option<adsb::Sil>,
+    pub sil_supplement: Option<adsb::SilSupplement>,
+    pub nic_baro: Option<bool>,
+    pub gva: Option<adsb::Gva>,
+}
+
+impl AdsbQualityState {
+    fn update_operational_status(
+        &mut self,
+        mops_version: adsb::MopsVersion,
+        nic_supplement_a: bool,
+        nac_p: adsb::NacP,
+        sil: adsb::Sil,
+        sil_supplement: adsb::SilSupplement,
+    ) {
+        self.mops_version = Some(mops_version);
+        self.nic_supplement_a = nic_supplement_a;
+        self.nac_p = Some(nac_p);
+        self.sil = Some(sil);
+        self.sil_supplement = Some(sil_supplement);
+    }
+}
+
+/// Absolute difference between two angles in radians.
+fn angle_difference(a: f64, b: f64) -> f64 {
+    let difference = (a - b).rem_euclid(TAU);
+    difference.min(TAU - difference)
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -209,10 +605,40 @@ pub struct Position {
     pub source: PositionSource,
 }
 
+impl Position {
+    pub fn lat_lon(&self) -> LatLon {
+        LatLon::new(self.latitude, self.longitude)
+    }
+}
+
+/// A position in an aircraft's trail, with the values the aircraft reported
+/// at that time.
+#[derive(Clone, Copy, Debug)]
+pub struct TrailPoint {
+    pub time: DateTime<Utc>,
+    pub position: Position,
+    /// in ft
+    pub altitude_barometric: Option<i32>,
+    /// in ft, height above ellipsoid
+    pub altitude_gnss: Option<i32>,
+    /// barometric, in ft/min, positive is up
+    pub vertical_rate: Option<i32>,
+    /// in kt
+    pub ground_speed: Option<f64>,
+    /// in radians, clockwise
+    pub track: Option<f64>,
+    pub vertical_status: Option<VerticalStatus>,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum PositionSource {
     Gnss,
     Mlat,
+    /// Received from an SBS source. These are usually decoded from ADS-B, but
+    /// SBS doesn't tell.
+    Sbs,
+    /// Taken from a readsb/dump1090 `aircraft.json`.
+    AircraftJson,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -221,6 +647,26 @@ pub struct Timestamped<T> {
     pub value: T,
 }
 
+/// Returns the value if it was updated at most `max_age` before `time`.
+pub fn fresh<T>(
+    value: &Option<Timestamped<T>>,
+    time: DateTime<Utc>,
+    max_age: TimeDelta,
+) -> Option<&T> {
+    fresh_timestamped(value, time, max_age).map(|value| &value.value)
+}
+
+/// Like [`fresh`], but keeps the timestamp.
+pub fn fresh_timestamped<T>(
+    value: &Option<Timestamped<T>>,
+    time: DateTime<Utc>,
+    max_age: TimeDelta,
+) -> Option<&Timestamped<T>> {
+    value
+        .as_ref()
+        .filter(|value| time.signed_duration_since(value.last_update) <= max_age)
+}
+
 trait UpdateTimestamped<T> {
     fn update_with(&mut self, time: DateTime<Utc>, value: impl FnOnce() -> T) -> bool;
 
@@ -233,6 +679,7 @@ impl<T> UpdateTimestamped<T> for Timestamped<T> {
     fn update_with(&mut self, time: DateTime<Utc>, value: impl FnOnce() -> T) -> bool {
         if self.last_update < time {
             self.value = value();
+            self.last_update = time;
             true
         }
         else {
@@ -265,6 +712,12 @@ pub struct UpdateAircraftState<'a> {
 }
 
 impl<'a> UpdateAircraftState<'a> {
+    /// Records that the aircraft was received from this source.
+    pub fn received_from(&mut self, source_id: SourceId) {
+        let last_received = self.state.sources.entry(source_id).or_insert(self.time);
+        *last_received = (*last_received).max(self.time);
+    }
+
     pub fn update_surface_position(&mut self, surface_position: &adsb::SurfacePosition) {
         self.update_position(&surface_position.cpr, VerticalStatus::Ground);
 
@@ -285,7 +738,7 @@ impl<'a> UpdateAircraftState<'a> {
     pub fn update_airborne_position(&mut self, airborne_position: &adsb::AirbornePosition) {
         // update position
         if let Some(cpr) = &airborne_position.cpr {
-            self.update_position(cpr, VerticalStatus::Ground);
+            self.update_position(cpr, VerticalStatus::Airborne);
         }
 
         // update altitude
@@ -300,6 +753,8 @@ impl<'a> UpdateAircraftState<'a> {
             }
         }
 
+        self.state.adsb_quality.nic =
+            Some(airborne_position.nic(self.state.adsb_quality.nic_supplement_a));
         self.state.vertical_status = Some(VerticalStatus::Airborne);
     }
 
@@ -316,20 +771,36 @@ impl<'a> UpdateAircraftState<'a> {
                 }
             }
             adsb::VelocityType::Airspeed(airspeed) => {
-                if let Some(magnetic_heading) = &airspeed.magnetic_heading {
-                    self.state
-                        .magnetic_heading
-                        .update(self.time, magnetic_heading.as_radians());
+                if let Some(heading) = &airspeed.magnetic_heading {
+                    self.update_heading(heading.as_radians());
 
-                    if let Some(airspeed) = &airspeed.airspeed_value {
-                        self.state
-                            .airspeed
-                            .update(self.time, airspeed.as_knots(velocity.supersonic) as f64);
+                    if let Some(value) = &airspeed.airspeed_value {
+                        let value = value.as_knots(velocity.supersonic) as f64;
+                        match airspeed.airspeed_type {
+                            adsb::AirspeedType::Indicated => {
+                                self.state.indicated_airspeed.update(self.time, value)
+                            }
+                            adsb::AirspeedType::True => {
+                                self.state.true_airspeed.update(self.time, value)
+                            }
+                        };
                     }
                 }
             }
         }
 
+        if let Some(difference) = velocity.altitude_difference.as_ft() {
+            self.state.altitude_difference.update(self.time, difference);
+        }
+
+        if velocity.vertical_rate.source == adsb::VerticalRateSource::Barometric
+            && let Some(vertical_rate) = velocity.vertical_rate.as_ft_per_min()
+        {
+            self.state
+                .vertical_rate
+                .update(self.time, vertical_rate.into());
+        }
+
         self.state.vertical_status = Some(VerticalStatus::Airborne);
     }
 
@@ -337,7 +808,14 @@ impl<'a> UpdateAircraftState<'a> {
         &mut self,
         identification: &adsb::AircraftIdentification,
     ) {
-        self.update_callsign(identification.callsign.decode_permissive());
+        self.state.wake_vortex_category = Some(identification.wake_vortex_category);
+
+        match identification.callsign.decode() {
+            Ok(callsign) => self.update_callsign(callsign),
+            Err(error) => {
+                tracing::trace!(icao_address = %self.state.icao_address, %error, "ignoring invalid callsign");
+            }
+        }
     }
 
     pub fn update_position(&mut self, cpr: &cpr::Cpr, vertical_status: VerticalStatus) {
@@ -360,7 +838,7 @@ impl<'a> UpdateAircraftState<'a> {
                 .cpr_decoder
                 .push(*cpr, vertical_status, self.time, reference)
         {
-            self.state.position.update(
+            self.state.record_position(
                 self.time,
                 Position {
                     latitude: position.latitude,
@@ -371,6 +849,175 @@ impl<'a> UpdateAircraftState<'a> {
         }
     }
 
+    pub fn update_sbs_transmission(&mut self, transmission: &sbs::Transmission) {
+        if let sbs::Transmission::EsAirborneVelocity {
+            vertical_rate: Some(vertical_rate),
+            ..
+        } = *transmission
+        {
+            self.state.vertical_rate.update(self.time, vertical_rate);
+        }
+
+        let (altitude, ground_speed, track, latitude, longitude, squawk, is_on_ground) =
+            match *transmission {
+                sbs::Transmission::EsIdentificationAndCategory { callsign } => {
+                    self.update_callsign(callsign);
+                    return;
+                }
+                sbs::Transmission::EsSurfacePosition {
+                    altitude,
+                    ground_speed,
+                    track,
+                    latitude,
+                    longitude,
+                    is_on_ground,
+                } => {
+                    (
+                        altitude,
+                        ground_speed,
+                        track,
+                        latitude,
+                        longitude,
+                        None,
+                        // surface positions are only sent for aircraft on the ground
+                        is_on_ground.or(Some(true)),
+                    )
+                }
+                sbs::Transmission::EsAirbornePosition {
+                    altitude,
+                    latitude,
+                    longitude,
+                    is_on_ground,
+                    ..
+                } => {
+                    (
+                        altitude,
+                        None,
+                        None,
+                        latitude,
+                        longitude,
+                        None,
+                        is_on_ground,
+                    )
+                }
+                sbs::Transmission::EsAirborneVelocity {
+                    ground_speed,
+                    track,
+                    ..
+                } => (None, ground_speed, track, None, None, None, None),
+                sbs::Transmission::SurveillanceAltMessage {
+                    altitude,
+                    is_on_ground,
+                    ..
+                } => (altitude, None, None, None, None, None, is_on_ground),
+                sbs::Transmission::SurveillanceIdMessage {
+                    altitude,
+                    squawk,
+                    is_on_ground,
+                    ..
+                } => (altitude, None, None, None, None, Some(squawk), is_on_ground),
+                sbs::Transmission::AirToAirMessage {
+                    altitude,
+                    is_on_ground,
+                } => (altitude, None, None, None, None, None, is_on_ground),
+                sbs::Transmission::AllCallReply { is_on_ground } => {
+                    (None, None, None, None, None, None, is_on_ground)
+                }
+            };
+
+        if let Some(altitude) = altitude {
+            self.state.altitude_barometric.update(self.time, altitude);
+        }
+        if let Some(ground_speed) = ground_speed {
+            self.state.ground_speed.update(self.time, ground_speed);
+        }
+        if let Some(track) = track {
+            self.state.track.update(self.time, track.to_radians());
+        }
+        if let (Some(latitude), Some(longitude)) = (latitude, longitude) {
+            self.state.record_position(
+                self.time,
+                Position {
+                    latitude,
+                    longitude,
+                    source: PositionSource::Sbs,
+                },
+            );
+        }
+        if let Some(squawk) = squawk {
+            self.update_squawk(squawk);
+        }
+        if let Some(is_on_ground) = is_on_ground {
+            self.state.vertical_status = Some(if is_on_ground {
+                VerticalStatus::Ground
+            }
+            else {
+                VerticalStatus::Airborne
+            });
+        }
+    }
+
+    pub fn update_aircraft_json(&mut self, now: DateTime<Utc>, aircraft: &aircraft_json::Aircraft) {
+        if let Some(flight) = &aircraft.flight {
+            match flight.trim_end().parse() {
+                Ok(callsign) => self.update_callsign(callsign),
+                Err(error) => tracing::debug!(?flight, ?error, "invalid callsign"),
+            }
+        }
+        if let Some(squawk) = aircraft.squawk {
+            self.update_squawk(squawk);
+        }
+        if let Some(wake_vortex_category) = aircraft.wake_vortex_category() {
+            self.state.wake_vortex_category = Some(wake_vortex_category);
+        }
+        match aircraft.alt_baro {
+            Some(history::Altitude::Ground) => {
+                self.state.vertical_status = Some(VerticalStatus::Ground);
+            }
+            Some(history::Altitude::Value(altitude)) => {
+                self.state
+                    .altitude_barometric
+                    .update(self.time, altitude.round() as i32);
+                self.state.vertical_status = Some(VerticalStatus::Airborne);
+            }
+            None => {}
+        }
+        if let Some(altitude) = aircraft.alt_geom {
+            self.state
+                .altitude_gnss
+                .update(self.time, altitude.round() as i32);
+        }
+        if let Some(ground_speed) = aircraft.gs {
+            self.state.ground_speed.update(self.time, ground_speed);
+        }
+        if let Some(airspeed) = aircraft.ias {
+            self.state.indicated_airspeed.update(self.time, airspeed);
+        }
+        if let Some(airspeed) = aircraft.tas {
+            self.state.true_airspeed.update(self.time, airspeed);
+        }
+        if let Some(vertical_rate) = aircraft.baro_rate {
+            self.state
+                .vertical_rate
+                .update(self.time, vertical_rate.round() as i32);
+        }
+        if let Some(track) = aircraft.track {
+            self.state.track.update(self.time, track.to_radians());
+        }
+        if let (Some(latitude), Some(longitude), Some(time)) =
+            (aircraft.lat, aircraft.lon, aircraft.position_updated(now))
+        {
+            self.state.record_position(
+                time,
+                Position {
+                    latitude,
+                    longitude,
+                    source: PositionSource::AircraftJson,
+                },
+            );
+        }
+    }
+
     pub fn update_callsign(&mut self, callsign: Callsign) {
         update_timestamped_option_with_index_update::<Callsign, Callsign>(
             &mut self.state.callsign,
@@ -379,14 +1026,107 @@ impl<'a> UpdateAircraftState<'a> {
             |old_callsign, new_callsign| {
                 if let Some(old_callsign) = old_callsign {
                     self.indices.by_callsign.remove(old_callsign);
+
+                    if let Some(flight) = old_callsign.flight_designator()
+                        && let hash_map::Entry::Occupied(mut occupied) =
+                            self.indices.by_airline.entry(flight.airline)
+                    {
+                        occupied.get_mut().remove(&self.index);
+                        if occupied.get().is_empty() {
+                            occupied.remove();
+                        }
+                    }
+                }
+
+                self.indices.by_callsign.insert(*new_callsign, self.index);
+
+                if let Some(flight) = new_callsign.flight_designator() {
                     self.indices
-                        .by_callsign
-                        .insert(new_callsign.to_owned(), self.index);
+                        .by_airline
+                        .entry(flight.airline)
+                        .or_default()
+                        .insert(self.index);
                 }
             },
         );
     }
 
+    /// Updates magnetic and true heading from a heading that is referenced as
+    /// announced by the aircraft.
+    pub fn update_heading(&mut self, heading: f64) {
+        let declination = self.state.declination(self.time);
+
+        match self
+            .state
+            .horizontal_reference_direction
+            .unwrap_or(adsb::HorizontalReferenceDirection::MagneticNorth)
+        {
+            adsb::HorizontalReferenceDirection::MagneticNorth => {
+                self.state.magnetic_heading.update(self.time, heading);
+                if let Some(declination) = declination {
+                    self.state
+                        .true_heading
+                        .update(self.time, (heading + declination).rem_euclid(TAU));
+                }
+            }
+            adsb::HorizontalReferenceDirection::TrueNorth => {
+                self.state.true_heading.update(self.time, heading);
+                if let Some(declination) = declination {
+                    self.state
+                        .magnetic_heading
+                        .update(self.time, (heading - declination).rem_euclid(TAU));
+                }
+            }
+        }
+    }
+
+    pub fn update_operational_status(&mut self, status: &adsb::AircraftOperationalStatus) {
+        if let Some(hrd) = status.hrd() {
+            self.state.horizontal_reference_direction = Some(hrd);
+        }
+
+        let quality = &mut self.state.adsb_quality;
+        match status {
+            adsb::AircraftOperationalStatus::Airborne {
+                mops_version,
+                nic_supp_a,
+                nac_p,
+                gva,
+                sil,
+                nic_baro,
+                sil_supplement,
+                ..
+            } => {
+                quality.gva = Some(*gva);
+                quality.nic_baro = Some(*nic_baro);
+                quality.update_operational_status(
+                    *mops_version,
+                    *nic_supp_a,
+                    *nac_p,
+                    *sil,
+                    *sil_supplement,
+                );
+            }
+            adsb::AircraftOperationalStatus::Surface {
+                mops_version,
+                nic_supp_a,
+                nac_p,
+                sil,
+                sil_supplement,
+                ..
+            } => {
+                quality.update_operational_status(
+                    *mops_version,
+                    *nic_supp_a,
+                    *nac_p,
+                    *sil,
+                    *sil_supplement,
+                );
+            }
+            adsb::AircraftOperationalStatus::Reserved { .. } => {}
+        }
+    }
+
     pub fn update_aircraft_status(&mut self, status: &adsb::AircraftStatus) {
         match status {
             adsb::AircraftStatus::EmergencyPriorityStatusAndModeACode(
@@ -441,3 +1181,195 @@ fn update_timestamped_option_with_index_update<T, U>(
         });
     }
 }
+
+/// `aircraft.json` fixtures for tests that need some aircraft in the state.
+#[cfg(test)]
+pub mod fixtures {
+    use chrono::{
+        DateTime,
+        Utc,
+    };
+    use serde_json::{
+        Value,
+        json,
+    };
+
+    use crate::{
+        source::{
+            SourceId,
+            aircraft_json::AircraftJson,
+        },
+        tracker::state::State,
+    };
+
+    /// Source the fixtures are pushed from.
+    pub fn source_id() -> SourceId {
+        SourceId::new(1)
+    }
+
+    /// An `aircraft.json` entry for 4ca7b5 (RYR4WJ) flying west at FL370
+    /// near Heathrow, with a fresh position.
+    ///
+    /// `fields` are merged into the entry. Fields set to `null` are removed.
+    pub fn aircraft(fields: Value) -> Value {
+        let mut aircraft = json!({
+            "hex": "4ca7b5",
+            "flight": "RYR4WJ  ",
+            "squawk": "1000",
+            "alt_baro": 37000,
+            "gs": 450.0,
+            "track": 270.0,
+            "lat": 51.477,
+            "lon": -0.4614,
+            "seen": 0.0,
+            "seen_pos": 0.0,
+        });
+        let object = aircraft.as_object_mut().unwrap();
+        for (key, value) in fields.as_object().expect("fields must be an object") {
+            if value.is_null() {
+                object.remove(key);
+            }
+            else {
+                object.insert(key.clone(), value.clone());
+            }
+        }
+        aircraft
+    }
+
+    /// A snapshot generated at `now`.
+    pub fn snapshot(now: DateTime<Utc>, aircraft: impl IntoIterator<Item = Value>) -> AircraftJson {
+        serde_json::from_value(json!({
+            "now": now.timestamp_micros() as f64 / 1e6,
+            "aircraft": aircraft.into_iter().collect::<Vec<_>>(),
+        }))
+        .unwrap()
+    }
+
+    /// Updates `state` with a snapshot generated at `now`.
+    pub fn update(
+        state: &mut State,
+        now: DateTime<Utc>,
+        aircraft: impl IntoIterator<Item = Value>,
+    ) {
+        let snapshot = snapshot(now, aircraft);
+        for aircraft in &snapshot.aircraft {
+            state.update_with_aircraft_json(snapshot.now(), source_id(), aircraft);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use adsbee_mode_s::VerticalStatus;
+    use adsbee_sbs as sbs;
+    use adsbee_types::IcaoAddress;
+    use chrono::{
+        DateTime,
+        TimeDelta,
+    };
+    use serde_json::json;
+
+    use crate::{
+        source::SourceId,
+        tracker::state::{
+            PositionSource,
+            State,
+            fixtures,
+        },
+    };
+
+    #[test]
+    fn it_updates_with_sbs_transmissions() {
+        let lines = [
+            "MSG,1,1,1,4CA7B5,1,2024/05/01,12:00:00.000,2024/05/01,12:00:00.000,RYR4WJ  ,,,,,,,,,,,0",
+            "MSG,3,1,1,4CA7B5,1,2024/05/01,12:00:01.000,2024/05/01,12:00:01.000,,37000,,,51.47700,-0.46140,,,0,,0,0",
+            "MSG,4,1,1,4CA7B5,1,2024/05/01,12:00:02.000,2024/05/01,12:00:02.000,,,452,270,,,-64,,,,,0",
+            "MSG,6,1,1,4CA7B5,1,2024/05/01,12:00:03.000,2024/05/01,12:00:03.000,,,,,,,,1000,0,0,0,0",
+        ];
+
+        let mut state = State::default();
+        for line in lines {
+            let sbs::Message::Transmission {
+                hex_ident,
+                time_generated,
+                transmission,
+                ..
+            } = line.parse::<sbs::Message>().unwrap()
+            else {
+                panic!("not a transmission: {line}");
+            };
+            state.update_with_sbs(time_generated, SourceId::new(1), hex_ident, &transmission);
+        }
+
+        let aircraft = state
+            .get_aircraft(&IcaoAddress::from_u32_unchecked(0x4ca7b5))
+            .unwrap();
+        assert_eq!(
+            aircraft.callsign.as_ref().unwrap().value.trimmed(),
+            "RYR4WJ"
+        );
+        assert_eq!(aircraft.altitude_barometric.unwrap().value, 37000);
+        let position = aircraft.position.unwrap().value;
+        assert_eq!(position.latitude, 51.477);
+        assert_eq!(position.longitude, -0.4614);
+        assert_eq!(position.source, PositionSource::Sbs);
+        assert_eq!(aircraft.ground_speed.unwrap().value, 452.0);
+        assert!((aircraft.track.unwrap().value - 270f64.to_radians()).abs() < 1e-9);
+        assert_eq!(aircraft.squawk.unwrap().value.to_string(), "1000");
+        assert_eq!(aircraft.vertical_status, Some(VerticalStatus::Airborne));
+        assert_eq!(
+            aircraft
+                .sources
+                .get(&SourceId::new(1))
+                .map(|time| time.to_rfc3339()),
+            Some("2024-05-01T12:00:03+00:00".to_owned())
+        );
+    }
+
+    #[test]
+    fn it_updates_with_aircraft_json() {
+        let now = DateTime::from_timestamp(1714564800, 0).unwrap();
+        let mut state = State::default();
+        fixtures::update(
+            &mut state,
+            now,
+            [fixtures::aircraft(json!({
+                "alt_geom": 37450,
+                "ias": 270,
+                "tas": 460,
+                "baro_rate": -64,
+                "seen": 0.5,
+                "seen_pos": 2.0,
+            }))],
+        );
+
+        let aircraft = state
+            .get_aircraft(&IcaoAddress::from_u32_unchecked(0x4ca7b5))
+            .unwrap();
+        assert_eq!(
+            aircraft.callsign.as_ref().unwrap().value.trimmed(),
+            "RYR4WJ"
+        );
+        assert_eq!(
+            aircraft.last_seen.last_update,
+            now - TimeDelta::milliseconds(500)
+        );
+        assert_eq!(aircraft.altitude_barometric.unwrap().value, 37000);
+        assert_eq!(aircraft.altitude_gnss.unwrap().value, 37450);
+        assert_eq!(aircraft.indicated_airspeed.unwrap().value, 270.0);
+        assert_eq!(aircraft.true_airspeed.unwrap().value, 460.0);
+        assert_eq!(aircraft.vertical_rate.unwrap().value, -64);
+        assert_eq!(aircraft.vertical_status, Some(VerticalStatus::Airborne));
+
+        // the position is timestamped with `seen_pos`, not `seen`
+        let position = aircraft.position.unwrap();
+        assert_eq!(position.last_update, now - TimeDelta::seconds(2));
+        assert_eq!(position.value.source, PositionSource::AircraftJson);
+        assert_eq!(aircraft.trail.len(), 1);
+
+        assert_eq!(
+            aircraft.sources.get(&fixtures::source_id()),
+            Some(&(now - TimeDelta::milliseconds(500)))
+        );
+    }
+}
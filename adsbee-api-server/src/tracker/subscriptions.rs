@@ -6,6 +6,8 @@ use adsbee_api_types::live::{
     SubscriptionFilter,
 };
 use adsbee_types::{
+    AirlineDesignator,
+    Callsign,
     IcaoAddress,
     Squawk,
 };
@@ -26,7 +28,8 @@ pub struct Subscriptions {
     by_subscriber_id: HashMap<(ClientId, Uuid), usize>,
 
     by_icao_address: HashMap<IcaoAddress, SparseList<usize>>,
-    by_callsign: HashMap<String, SparseList<usize>>,
+    by_callsign: HashMap<Callsign, SparseList<usize>>,
+    by_airline: HashMap<AirlineDesignator, SparseList<usize>>,
     by_squawk: HashMap<Squawk, SparseList<usize>>,
     // todo: by location -> r*tree
 }
@@ -50,6 +53,7 @@ impl Subscriptions {
             message_sender,
             by_icao_address: Vec::with_capacity(filter.aircraft.icao.len()),
             by_callsign: Vec::with_capacity(filter.aircraft.callsign.len()),
+            by_airline: Vec::with_capacity(filter.aircraft.airline.len()),
             by_squawk: Vec::with_capacity(filter.aircraft.squawk.len()),
         });
 
@@ -65,13 +69,13 @@ impl Subscriptions {
                     .push((icao_address, filter_index));
             }
             for callsign in filter.aircraft.callsign {
-                let filter_index = self
-                    .by_callsign
-                    .entry(callsign.clone())
-                    .or_default()
-                    .insert(index);
+                let filter_index = self.by_callsign.entry(callsign).or_default().insert(index);
                 subscription.by_callsign.push((callsign, filter_index));
             }
+            for airline in filter.aircraft.airline {
+                let filter_index = self.by_airline.entry(airline).or_default().insert(index);
+                subscription.by_airline.push((airline, filter_index));
+            }
             for squawk in filter.aircraft.squawk {
                 let filter_index = self.by_squawk.entry(squawk).or_default().insert(index);
                 subscription.by_squawk.push((squawk, filter_index))
@@ -107,6 +111,12 @@ impl Subscriptions {
                 .expect("invalid backref")
                 .remove(filter_index);
         }
+        for (airline, filter_index) in subscription.by_airline {
+            self.by_airline
+                .get_mut(&airline)
+                .expect("invalid backref")
+                .remove(filter_index);
+        }
         for (squawk, filter_index) in subscription.by_squawk {
             self.by_squawk
                 .get_mut(&squawk)
@@ -124,7 +134,8 @@ struct Subscription {
     id: Uuid,
     message_sender: mpsc::Sender<ServerToClientMessage>,
     by_icao_address: Vec<(IcaoAddress, usize)>,
-    by_callsign: Vec<(String, usize)>,
+    by_callsign: Vec<(Callsign, usize)>,
+    by_airline: Vec<(AirlineDesignator, usize)>,
     by_squawk: Vec<(Squawk, usize)>,
     // todo: secondary filter
 }
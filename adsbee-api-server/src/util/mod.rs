@@ -13,6 +13,11 @@ use std::{
 };
 
 use bytes::Buf;
+use serde::{
+    Deserialize,
+    Deserializer,
+    de::Error as _,
+};
 
 pub fn http_client() -> reqwest::Client {
     static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
@@ -27,6 +32,25 @@ pub fn http_client() -> reqwest::Client {
         .clone()
 }
 
+/// Deserializes a positive number of seconds, e.g. an interval.
+///
+/// Zero, negative and non-finite values are rejected, because
+/// [`Duration`][std::time::Duration]s and [`tokio::time::interval`]s can't be
+/// created from them.
+pub fn deserialize_positive_seconds<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<f64, D::Error> {
+    let seconds = f64::deserialize(deserializer)?;
+    if seconds > 0.0 && seconds.is_finite() {
+        Ok(seconds)
+    }
+    else {
+        Err(D::Error::custom(format!(
+            "expected a positive number of seconds, got {seconds}"
+        )))
+    }
+}
+
 #[derive(Debug)]
 pub struct AtomicIdGenerator {
     next: AtomicUsize,
@@ -1,4 +1,7 @@
 use adsbee_types::{
+    AirlineDesignator,
+    Callsign,
+    FlightDesignator,
     IcaoAddress,
     Squawk,
 };
@@ -36,7 +39,10 @@ pub struct AircraftQuery {
     pub icao: Vec<IcaoAddress>,
 
     #[serde(default)]
-    pub callsign: Vec<String>,
+    pub callsign: Vec<Callsign>,
+
+    #[serde(default)]
+    pub airline: Vec<AirlineDesignator>,
 
     #[serde(default)]
     pub squawk: Vec<Squawk>,
@@ -59,5 +65,7 @@ pub struct SearchResult {
     pub time: DateTime<Utc>,
     pub icao: IcaoAddress,
     pub callsign: Option<String>,
+    pub flight: Option<FlightDesignator>,
+    pub airline_name: Option<String>,
     pub squawk: Option<Squawk>,
 }
@@ -6,6 +6,10 @@ use std::{
     str::FromStr,
 };
 
+use adsbee_types::geo::{
+    BoundingBox,
+    LatLon,
+};
 use serde::{
     Deserialize,
     Serialize,
@@ -108,3 +112,22 @@ pub struct Bbox {
     pub east_of: f32,
     pub west_of: f32,
 }
+
+impl Bbox {
+    /// Checks if a position is in the box. If `east_of` is greater than
+    /// `west_of`, the box wraps around the antimeridian.
+    pub fn contains(&self, position: &LatLon) -> bool {
+        BoundingBox::from(*self).contains(position)
+    }
+}
+
+impl From<Bbox> for BoundingBox {
+    fn from(value: Bbox) -> Self {
+        BoundingBox {
+            south: value.north_of.into(),
+            west: value.east_of.into(),
+            north: value.south_of.into(),
+            east: value.west_of.into(),
+        }
+    }
+}
@@ -0,0 +1,849 @@
+//! CAT021: ADS-B target reports
+//!
+//! Only the data items we can fill from the tracker's state are encoded and
+//! decoded. Other data items are skipped when decoding, except compound ones.
+//!
+//! - [Part 12: Category 021, edition 2.6][1]
+//!
+//! [1]: https://www.eurocontrol.int/publication/cat021-eurocontrol-specification-surveillance-data-exchange-asterix-part-12-category-21
+
+use adsbee_types::{
+    Callsign,
+    IcaoAddress,
+    Squawk,
+    geo::LatLon,
+};
+use bytes::{
+    Buf,
+    BufMut,
+};
+
+use crate::{
+    DecodeError,
+    Fspec,
+    ItemFormat,
+    Record,
+    ensure_remaining,
+};
+
+/// Field reference numbers of the data items in the user application profile.
+mod frn {
+    pub const DATA_SOURCE_IDENTIFICATION: u8 = 1;
+    pub const TARGET_REPORT_DESCRIPTOR: u8 = 2;
+    pub const TRACK_NUMBER: u8 = 3;
+    pub const TIME_OF_APPLICABILITY_FOR_POSITION: u8 = 5;
+    pub const POSITION: u8 = 6;
+    pub const HIGH_RESOLUTION_POSITION: u8 = 7;
+    pub const TIME_OF_APPLICABILITY_FOR_VELOCITY: u8 = 8;
+    pub const TARGET_ADDRESS: u8 = 11;
+    pub const TIME_OF_MESSAGE_RECEPTION_OF_POSITION: u8 = 12;
+    pub const TIME_OF_MESSAGE_RECEPTION_OF_VELOCITY: u8 = 14;
+    pub const GEOMETRIC_HEIGHT: u8 = 16;
+    pub const QUALITY_INDICATORS: u8 = 17;
+    pub const MOPS_VERSION: u8 = 18;
+    pub const MODE_3A_CODE: u8 = 19;
+    pub const FLIGHT_LEVEL: u8 = 21;
+    pub const MAGNETIC_HEADING: u8 = 22;
+    pub const AIRBORNE_GROUND_VECTOR: u8 = 26;
+    pub const TIME_OF_REPORT_TRANSMISSION: u8 = 28;
+    pub const TARGET_IDENTIFICATION: u8 = 29;
+}
+
+/// Formats of the data items in the user application profile, indexed by
+/// FRN - 1.
+const UAP: [Option<ItemFormat>; 49] = {
+    use ItemFormat::*;
+    [
+        Some(Fixed(2)),      // 010 Data Source Identification
+        Some(Extended),      // 040 Target Report Descriptor
+        Some(Fixed(2)),      // 161 Track Number
+        Some(Fixed(1)),      // 015 Service Identification
+        Some(Fixed(3)),      // 071 Time of Applicability for Position
+        Some(Fixed(6)),      // 130 Position in WGS-84 co-ordinates
+        Some(Fixed(8)),      // 131 High-Resolution Position in WGS-84 co-ordinates
+        Some(Fixed(3)),      // 072 Time of Applicability for Velocity
+        Some(Fixed(2)),      // 150 Air Speed
+        Some(Fixed(2)),      // 151 True Air Speed
+        Some(Fixed(3)),      // 080 Target Address
+        Some(Fixed(3)),      // 073 Time of Message Reception of Position
+        Some(Fixed(4)),      // 074 Time of Message Reception of Position-High Precision
+        Some(Fixed(3)),      // 075 Time of Message Reception of Velocity
+        Some(Fixed(4)),      // 076 Time of Message Reception of Velocity-High Precision
+        Some(Fixed(2)),      // 140 Geometric Height
+        Some(Extended),      // 090 Quality Indicators
+        Some(Fixed(1)),      // 210 MOPS Version
+        Some(Fixed(2)),      // 070 Mode 3/A Code
+        Some(Fixed(2)),      // 230 Roll Angle
+        Some(Fixed(2)),      // 145 Flight Level
+        Some(Fixed(2)),      // 152 Magnetic Heading
+        Some(Fixed(1)),      // 200 Target Status
+        Some(Fixed(2)),      // 155 Barometric Vertical Rate
+        Some(Fixed(2)),      // 157 Geometric Vertical Rate
+        Some(Fixed(4)),      // 160 Airborne Ground Vector
+        Some(Fixed(2)),      // 165 Track Angle Rate
+        Some(Fixed(3)),      // 077 Time of Report Transmission
+        Some(Fixed(6)),      // 170 Target Identification
+        Some(Fixed(1)),      // 020 Emitter Category
+        Some(Compound),      // 220 Met Information
+        Some(Fixed(2)),      // 146 Selected Altitude
+        Some(Fixed(2)),      // 148 Final State Selected Altitude
+        Some(Compound),      // 110 Trajectory Intent
+        Some(Fixed(1)),      // 016 Service Management
+        Some(Fixed(1)),      // 008 Aircraft Operational Status
+        Some(Extended),      // 271 Surface Capabilities and Characteristics
+        Some(Fixed(1)),      // 132 Message Amplitude
+        Some(Repetitive(8)), // 250 Mode S MB Data
+        Some(Fixed(7)),      // 260 ACAS Resolution Advisory Report
+        Some(Fixed(1)),      // 400 Receiver ID
+        Some(Compound),      // 295 Data Ages
+        None,
+        None,
+        None,
+        None,
+        None,
+        Some(Explicit), // RE Reserved Expansion Field
+        Some(Explicit), // SP Special Purpose Field
+    ]
+};
+
+/// LSB of high-resolution latitude and longitude in degrees
+const HIGH_RESOLUTION_ANGLE_LSB: f64 = 180.0 / (1u32 << 30) as f64;
+
+/// LSB of latitude and longitude in degrees
+const ANGLE_LSB: f64 = 180.0 / (1u32 << 23) as f64;
+
+/// LSB of headings and track angles in degrees
+const HEADING_LSB: f64 = 360.0 / (1u32 << 16) as f64;
+
+/// LSB of ground speed in kt (2^-14 NM/s)
+const GROUND_SPEED_LSB: f64 = 3600.0 / (1u32 << 14) as f64;
+
+/// LSB of geometric height in ft
+const GEOMETRIC_HEIGHT_LSB: f64 = 6.25;
+
+/// LSB of flight level in FL
+const FLIGHT_LEVEL_LSB: f64 = 0.25;
+
+/// ADS-B target report
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TargetReport {
+    /// I021/010
+    pub data_source: DataSourceIdentifier,
+    /// I021/040
+    pub target_report_descriptor: Option<TargetReportDescriptor>,
+    /// I021/161, 12 bits
+    pub track_number: Option<u16>,
+    /// I021/071
+    pub time_of_applicability_for_position: Option<TimeOfDay>,
+    /// I021/131 (or I021/130 when decoding)
+    pub position: Option<LatLon>,
+    /// I021/072
+    pub time_of_applicability_for_velocity: Option<TimeOfDay>,
+    /// I021/080
+    pub target_address: Option<IcaoAddress>,
+    /// I021/073
+    pub time_of_message_reception_of_position: Option<TimeOfDay>,
+    /// I021/075
+    pub time_of_message_reception_of_velocity: Option<TimeOfDay>,
+    /// I021/140, height above the WGS-84 ellipsoid in ft
+    pub geometric_height: Option<f64>,
+    /// I021/090
+    pub quality_indicators: Option<QualityIndicators>,
+    /// I021/210
+    pub mops_version: Option<MopsVersion>,
+    /// I021/070
+    pub mode_3a_code: Option<Squawk>,
+    /// I021/145, in FL
+    pub flight_level: Option<f64>,
+    /// I021/152, in degrees
+    pub magnetic_heading: Option<f64>,
+    /// I021/160
+    pub airborne_ground_vector: Option<AirborneGroundVector>,
+    /// I021/077
+    pub time_of_report_transmission: Option<TimeOfDay>,
+    /// I021/170
+    pub target_identification: Option<Callsign>,
+}
+
+impl Record for TargetReport {
+    const CATEGORY: u8 = 21;
+
+    fn encode<B: BufMut>(&self, buffer: &mut B) {
+        let mut fspec = Fspec::default();
+        let mut insert = |frn, present: bool| {
+            if present {
+                fspec.insert(frn);
+            }
+        };
+        insert(frn::DATA_SOURCE_IDENTIFICATION, true);
+        insert(
+            frn::TARGET_REPORT_DESCRIPTOR,
+            self.target_report_descriptor.is_some(),
+        );
+        insert(frn::TRACK_NUMBER, self.track_number.is_some());
+        insert(
+            frn::TIME_OF_APPLICABILITY_FOR_POSITION,
+            self.time_of_applicability_for_position.is_some(),
+        );
+        insert(frn::HIGH_RESOLUTION_POSITION, self.position.is_some());
+        insert(
+            frn::TIME_OF_APPLICABILITY_FOR_VELOCITY,
+            self.time_of_applicability_for_velocity.is_some(),
+        );
+        insert(frn::TARGET_ADDRESS, self.target_address.is_some());
+        insert(
+            frn::TIME_OF_MESSAGE_RECEPTION_OF_POSITION,
+            self.time_of_message_reception_of_position.is_some(),
+        );
+        insert(
+            frn::TIME_OF_MESSAGE_RECEPTION_OF_VELOCITY,
+            self.time_of_message_reception_of_velocity.is_some(),
+        );
+        insert(frn::GEOMETRIC_HEIGHT, self.geometric_height.is_some());
+        insert(frn::QUALITY_INDICATORS, self.quality_indicators.is_some());
+        insert(frn::MOPS_VERSION, self.mops_version.is_some());
+        insert(frn::MODE_3A_CODE, self.mode_3a_code.is_some());
+        insert(frn::FLIGHT_LEVEL, self.flight_level.is_some());
+        insert(frn::MAGNETIC_HEADING, self.magnetic_heading.is_some());
+        insert(
+            frn::AIRBORNE_GROUND_VECTOR,
+            self.airborne_ground_vector.is_some(),
+        );
+        insert(
+            frn::TIME_OF_REPORT_TRANSMISSION,
+            self.time_of_report_transmission.is_some(),
+        );
+        insert(
+            frn::TARGET_IDENTIFICATION,
+            self.target_identification.is_some(),
+        );
+        fspec.encode(buffer);
+
+        // data items must be in the order of the UAP
+        self.data_source.encode(buffer);
+        if let Some(descriptor) = &self.target_report_descriptor {
+            descriptor.encode(buffer);
+        }
+        if let Some(track_number) = self.track_number {
+            buffer.put_u16(track_number & 0x0fff);
+        }
+        if let Some(time) = self.time_of_applicability_for_position {
+            time.encode(buffer);
+        }
+        if let Some(position) = &self.position {
+            buffer.put_i32((position.latitude / HIGH_RESOLUTION_ANGLE_LSB).round() as i32);
+            buffer.put_i32((position.longitude / HIGH_RESOLUTION_ANGLE_LSB).round() as i32);
+        }
+        if let Some(time) = self.time_of_applicability_for_velocity {
+            time.encode(buffer);
+        }
+        if let Some(address) = self.target_address {
+            buffer.put_slice(&address.as_bytes());
+        }
+        if let Some(time) = self.time_of_message_reception_of_position {
+            time.encode(buffer);
+        }
+        if let Some(time) = self.time_of_message_reception_of_velocity {
+            time.encode(buffer);
+        }
+        if let Some(height) = self.geometric_height {
+            buffer.put_i16((height / GEOMETRIC_HEIGHT_LSB).round() as i16);
+        }
+        if let Some(quality_indicators) = &self.quality_indicators {
+            quality_indicators.encode(buffer);
+        }
+        if let Some(mops_version) = &self.mops_version {
+            mops_version.encode(buffer);
+        }
+        if let Some(squawk) = self.mode_3a_code {
+            buffer.put_u16(squawk.as_u16() & 0x0fff);
+        }
+        if let Some(flight_level) = self.flight_level {
+            buffer.put_i16((flight_level / FLIGHT_LEVEL_LSB).round() as i16);
+        }
+        if let Some(heading) = self.magnetic_heading {
+            buffer.put_u16(encode_heading(heading));
+        }
+        if let Some(vector) = &self.airborne_ground_vector {
+            vector.encode(buffer);
+        }
+        if let Some(time) = self.time_of_report_transmission {
+            time.encode(buffer);
+        }
+        if let Some(callsign) = &self.target_identification {
+            encode_callsign(callsign, buffer);
+        }
+    }
+
+    fn decode<B: Buf>(buffer: &mut B) -> Result<Self, DecodeError> {
+        let fspec = Fspec::decode(buffer)?;
+        if !fspec.contains(frn::DATA_SOURCE_IDENTIFICATION) {
+            // I021/010 is mandatory
+            return Err(DecodeError::UnsupportedItem {
+                frn: frn::DATA_SOURCE_IDENTIFICATION,
+            });
+        }
+
+        let mut report = Self::default();
+
+        for frn in fspec.iter() {
+            let format = UAP
+                .get(usize::from(frn - 1))
+                .copied()
+                .flatten()
+                .ok_or(DecodeError::UnsupportedItem { frn })?;
+
+            if let ItemFormat::Fixed(length) = format {
+                ensure_remaining(buffer, length)?;
+            }
+
+            match frn {
+                frn::DATA_SOURCE_IDENTIFICATION => {
+                    report.data_source = DataSourceIdentifier::decode(buffer);
+                }
+                frn::TARGET_REPORT_DESCRIPTOR => {
+                    report.target_report_descriptor = Some(TargetReportDescriptor::decode(buffer)?);
+                }
+                frn::TRACK_NUMBER => {
+                    report.track_number = Some(buffer.get_u16() & 0x0fff);
+                }
+                frn::TIME_OF_APPLICABILITY_FOR_POSITION => {
+                    report.time_of_applicability_for_position = Some(TimeOfDay::decode(buffer));
+                }
+                frn::POSITION => {
+                    let latitude = get_i24(buffer);
+                    let longitude = get_i24(buffer);
+                    // prefer the high-resolution position, if both are present
+                    report.position.get_or_insert(LatLon::new(
+                        f64::from(latitude) * ANGLE_LSB,
+                        f64::from(longitude) * ANGLE_LSB,
+                    ));
+                }
+                frn::HIGH_RESOLUTION_POSITION => {
+                    let latitude = buffer.get_i32();
+                    let longitude = buffer.get_i32();
+                    report.position = Some(LatLon::new(
+                        f64::from(latitude) * HIGH_RESOLUTION_ANGLE_LSB,
+                        f64::from(longitude) * HIGH_RESOLUTION_ANGLE_LSB,
+                    ));
+                }
+                frn::TIME_OF_APPLICABILITY_FOR_VELOCITY => {
+                    report.time_of_applicability_for_velocity = Some(TimeOfDay::decode(buffer));
+                }
+                frn::TARGET_ADDRESS => {
+                    let mut address = [0; 3];
+                    buffer.copy_to_slice(&mut address);
+                    report.target_address = Some(IcaoAddress::from_bytes(address));
+                }
+                frn::TIME_OF_MESSAGE_RECEPTION_OF_POSITION => {
+                    report.time_of_message_reception_of_position = Some(TimeOfDay::decode(buffer));
+                }
+                frn::TIME_OF_MESSAGE_RECEPTION_OF_VELOCITY => {
+                    report.time_of_message_reception_of_velocity = Some(TimeOfDay::decode(buffer));
+                }
+                frn::GEOMETRIC_HEIGHT => {
+                    report.geometric_height =
+                        Some(f64::from(buffer.get_i16()) * GEOMETRIC_HEIGHT_LSB);
+                }
+                frn::QUALITY_INDICATORS => {
+                    report.quality_indicators = Some(QualityIndicators::decode(buffer)?);
+                }
+                frn::MOPS_VERSION => {
+                    report.mops_version = Some(MopsVersion::decode(buffer));
+                }
+                frn::MODE_3A_CODE => {
+                    report.mode_3a_code =
+                        Some(Squawk::from_u16_unchecked(buffer.get_u16() & 0x0fff));
+                }
+                frn::FLIGHT_LEVEL => {
+                    report.flight_level = Some(f64::from(buffer.get_i16()) * FLIGHT_LEVEL_LSB);
+                }
+                frn::MAGNETIC_HEADING => {
+                    report.magnetic_heading = Some(f64::from(buffer.get_u16()) * HEADING_LSB);
+                }
+                frn::AIRBORNE_GROUND_VECTOR => {
+                    report.airborne_ground_vector = Some(AirborneGroundVector::decode(buffer));
+                }
+                frn::TIME_OF_REPORT_TRANSMISSION => {
+                    report.time_of_report_transmission = Some(TimeOfDay::decode(buffer));
+                }
+                frn::TARGET_IDENTIFICATION => {
+                    report.target_identification = Some(decode_callsign(buffer));
+                }
+                _ => format.skip(buffer, frn)?,
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// I021/010 Data Source Identification
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DataSourceIdentifier {
+    /// System area code
+    pub sac: u8,
+    /// System identification code
+    pub sic: u8,
+}
+
+impl DataSourceIdentifier {
+    fn encode<B: BufMut>(&self, buffer: &mut B) {
+        buffer.put_u8(self.sac);
+        buffer.put_u8(self.sic);
+    }
+
+    fn decode<B: Buf>(buffer: &mut B) -> Self {
+        Self {
+            sac: buffer.get_u8(),
+            sic: buffer.get_u8(),
+        }
+    }
+}
+
+/// I021/040 Target Report Descriptor
+///
+/// Only the primary subfield and the first extension are encoded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TargetReportDescriptor {
+    /// Address type (ATP): 0 = 24-bit ICAO address, 1 = duplicate address, 2 =
+    /// surface vehicle address, 3 = anonymous address
+    pub address_type: u8,
+    /// Altitude reporting capability (ARC): 0 = 25 ft, 1 = 100 ft, 2 =
+    /// unknown, 3 = invalid
+    pub altitude_reporting_capability: u8,
+    /// Range check (RC)
+    pub range_check: bool,
+    /// Report from field monitor (RAB)
+    pub field_monitor: bool,
+    /// Differential correction (DCR)
+    pub differential_correction: bool,
+    /// Ground bit (GBS)
+    pub ground: bool,
+    /// Simulated target (SIM)
+    pub simulated: bool,
+    /// Test target (TST)
+    pub test: bool,
+    /// Selected altitude available (SAA)
+    pub selected_altitude_available: bool,
+    /// Confidence level (CL): 0 = valid, 1 = suspect, 2 = no information, 3
+    /// = reserved
+    pub confidence_level: u8,
+}
+
+impl TargetReportDescriptor {
+    pub const ADDRESS_TYPE_ICAO: u8 = 0;
+    pub const ADDRESS_TYPE_DUPLICATE: u8 = 1;
+    pub const ADDRESS_TYPE_SURFACE_VEHICLE: u8 = 2;
+    pub const ADDRESS_TYPE_ANONYMOUS: u8 = 3;
+    pub const ALTITUDE_REPORTING_CAPABILITY_25_FT: u8 = 0;
+    pub const ALTITUDE_REPORTING_CAPABILITY_100_FT: u8 = 1;
+    pub const ALTITUDE_REPORTING_CAPABILITY_UNKNOWN: u8 = 2;
+
+    fn encode<B: BufMut>(&self, buffer: &mut B) {
+        buffer.put_u8(
+            ((self.address_type & 0b111) << 5)
+                | ((self.altitude_reporting_capability & 0b11) << 3)
+                | (u8::from(self.range_check) << 2)
+                | (u8::from(self.field_monitor) << 1)
+                | 1,
+        );
+        buffer.put_u8(
+            (u8::from(self.differential_correction) << 7)
+                | (u8::from(self.ground) << 6)
+                | (u8::from(self.simulated) << 5)
+                | (u8::from(self.test) << 4)
+                | (u8::from(self.selected_altitude_available) << 3)
+                | ((self.confidence_level & 0b11) << 1),
+        );
+    }
+
+    fn decode<B: Buf>(buffer: &mut B) -> Result<Self, DecodeError> {
+        let octets = get_extended::<B, 2>(buffer)?;
+        Ok(Self {
+            address_type: octets[0] >> 5,
+            altitude_reporting_capability: (octets[0] >> 3) & 0b11,
+            range_check: octets[0] & 0b100 != 0,
+            field_monitor: octets[0] & 0b10 != 0,
+            differential_correction: octets[1] & 0x80 != 0,
+            ground: octets[1] & 0x40 != 0,
+            simulated: octets[1] & 0x20 != 0,
+            test: octets[1] & 0x10 != 0,
+            selected_altitude_available: octets[1] & 0x08 != 0,
+            confidence_level: (octets[1] >> 1) & 0b11,
+        })
+    }
+}
+
+/// I021/090 Quality Indicators
+///
+/// The values are the same as in the ADS-B operational status and position
+/// messages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QualityIndicators {
+    /// Navigation accuracy category for velocity (NACv)
+    pub nac_v: u8,
+    /// Navigation integrity category (NIC)
+    pub nic: u8,
+    /// Barometric altitude integrity (NICbaro)
+    pub nic_baro: bool,
+    /// Source integrity level (SIL)
+    pub sil: u8,
+    /// Navigation accuracy category for position (NACp)
+    pub nac_p: u8,
+    /// Whether SIL is per sample (instead of per hour)
+    pub sil_supplement: bool,
+    /// System design assurance (SDA)
+    pub sda: u8,
+    /// Geometric altitude accuracy (GVA)
+    pub gva: u8,
+    /// Position integrity category (PIC)
+    pub pic: Option<u8>,
+}
+
+impl QualityIndicators {
+    fn encode<B: BufMut>(&self, buffer: &mut B) {
+        let has_pic = self.pic.is_some();
+        buffer.put_u8(((self.nac_v & 0b111) << 5) | ((self.nic & 0xf) << 1) | 1);
+        buffer.put_u8(
+            (u8::from(self.nic_baro) << 7)
+                | ((self.sil & 0b11) << 5)
+                | ((self.nac_p & 0xf) << 1)
+                | 1,
+        );
+        buffer.put_u8(
+            (u8::from(self.sil_supplement) << 5)
+                | ((self.sda & 0b11) << 3)
+                | ((self.gva & 0b11) << 1)
+                | u8::from(has_pic),
+        );
+        if let Some(pic) = self.pic {
+            buffer.put_u8((pic & 0xf) << 4);
+        }
+    }
+
+    fn decode<B: Buf>(buffer: &mut B) -> Result<Self, DecodeError> {
+        let octets = get_extended::<B, 4>(buffer)?;
+        Ok(Self {
+            nac_v: octets[0] >> 5,
+            nic: (octets[0] >> 1) & 0xf,
+            nic_baro: octets[1] & 0x80 != 0,
+            sil: (octets[1] >> 5) & 0b11,
+            nac_p: (octets[1] >> 1) & 0xf,
+            sil_supplement: octets[2] & 0x20 != 0,
+            sda: (octets[2] >> 3) & 0b11,
+            gva: (octets[2] >> 1) & 0b11,
+            pic: (octets[2] & 1 != 0).then_some(octets[3] >> 4),
+        })
+    }
+}
+
+/// I021/210 MOPS Version
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MopsVersion {
+    /// Version not supported (VNS)
+    pub version_not_supported: bool,
+    /// Version number (VN): 0 = DO-260, 1 = DO-260A, 2 = DO-260B
+    pub version: u8,
+    /// Link technology type (LTT)
+    pub link_technology: u8,
+}
+
+impl MopsVersion {
+    pub const LINK_TECHNOLOGY_OTHER: u8 = 0;
+    pub const LINK_TECHNOLOGY_UAT: u8 = 1;
+    pub const LINK_TECHNOLOGY_1090_ES: u8 = 2;
+    pub const LINK_TECHNOLOGY_VDL_4: u8 = 3;
+
+    fn encode<B: BufMut>(&self, buffer: &mut B) {
+        buffer.put_u8(
+            (u8::from(self.version_not_supported) << 6)
+                | ((self.version & 0b111) << 3)
+                | (self.link_technology & 0b111),
+        );
+    }
+
+    fn decode<B: Buf>(buffer: &mut B) -> Self {
+        let octet = buffer.get_u8();
+        Self {
+            version_not_supported: octet & 0x40 != 0,
+            version: (octet >> 3) & 0b111,
+            link_technology: octet & 0b111,
+        }
+    }
+}
+
+/// I021/160 Airborne Ground Vector
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AirborneGroundVector {
+    /// in kt
+    pub ground_speed: f64,
+    /// True track angle in degrees
+    pub track_angle: f64,
+}
+
+impl AirborneGroundVector {
+    fn encode<B: BufMut>(&self, buffer: &mut B) {
+        let ground_speed = (self.ground_speed / GROUND_SPEED_LSB).round() as u16;
+        if ground_speed > 0x7fff {
+            // range exceeded (RE)
+            buffer.put_u16(0xffff);
+        }
+        else {
+            buffer.put_u16(ground_speed);
+        }
+        buffer.put_u16(encode_heading(self.track_angle));
+    }
+
+    fn decode<B: Buf>(buffer: &mut B) -> Self {
+        let ground_speed = buffer.get_u16() & 0x7fff;
+        let track_angle = buffer.get_u16();
+        Self {
+            ground_speed: f64::from(ground_speed) * GROUND_SPEED_LSB,
+            track_angle: f64::from(track_angle) * HEADING_LSB,
+        }
+    }
+}
+
+/// Time of day in 1/128 s since midnight UTC
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TimeOfDay(u32);
+
+impl TimeOfDay {
+    const TICKS_PER_SECOND: f64 = 128.0;
+    const TICKS_PER_DAY: u32 = 86400 * 128;
+
+    pub fn from_seconds(seconds: f64) -> Self {
+        Self(((seconds * Self::TICKS_PER_SECOND).round() as u32) % Self::TICKS_PER_DAY)
+    }
+
+    pub fn as_seconds(&self) -> f64 {
+        f64::from(self.0) / Self::TICKS_PER_SECOND
+    }
+
+    fn encode<B: BufMut>(&self, buffer: &mut B) {
+        buffer.put_slice(&self.0.to_be_bytes()[1..]);
+    }
+
+    fn decode<B: Buf>(buffer: &mut B) -> Self {
+        let mut bytes = [0; 4];
+        buffer.copy_to_slice(&mut bytes[1..]);
+        Self(u32::from_be_bytes(bytes))
+    }
+}
+
+/// Characters of the 6-bit ICAO alphabet, see I021/170. Invalid characters
+/// are mapped to `?`.
+const CALLSIGN_ALPHABET: &[u8; 64] =
+    b"?ABCDEFGHIJKLMNOPQRSTUVWXYZ????? ???????????????0123456789??????";
+
+fn encode_callsign<B: BufMut>(callsign: &Callsign, buffer: &mut B) {
+    let mut bits = 0u64;
+    for character in callsign.as_str().bytes() {
+        let code = CALLSIGN_ALPHABET
+            .iter()
+            .position(|c| *c == character && character != b'?')
+            .unwrap_or(0b100000) as u64;
+        bits = (bits << 6) | code;
+    }
+    buffer.put_slice(&bits.to_be_bytes()[2..]);
+}
+
+fn decode_callsign<B: Buf>(buffer: &mut B) -> Callsign {
+    let mut bytes = [0; 8];
+    buffer.copy_to_slice(&mut bytes[2..]);
+    let bits = u64::from_be_bytes(bytes);
+
+    let mut characters = [b' '; Callsign::LENGTH];
+    for (i, character) in characters.iter_mut().enumerate() {
+        let code = (bits >> (6 * (Callsign::LENGTH - 1 - i))) & 0b111111;
+        *character = CALLSIGN_ALPHABET[code as usize];
+    }
+    Callsign::from_bytes_unchecked(characters)
+}
+
+/// Reads an extensible data item with up to `N` octets. Further octets are
+/// skipped, missing ones are zero.
+fn get_extended<B: Buf, const N: usize>(buffer: &mut B) -> Result<[u8; N], DecodeError> {
+    let mut octets = [0; N];
+    let mut i = 0;
+    loop {
+        ensure_remaining(buffer, 1)?;
+        let octet = buffer.get_u8();
+        if let Some(slot) = octets.get_mut(i) {
+            *slot = octet;
+        }
+        i += 1;
+        if octet & 1 == 0 {
+            break;
+        }
+    }
+    Ok(octets)
+}
+
+fn get_i24<B: Buf>(buffer: &mut B) -> i32 {
+    let mut bytes = [0; 4];
+    buffer.copy_to_slice(&mut bytes[..3]);
+    // sign-extend
+    i32::from_be_bytes(bytes) >> 8
+}
+
+fn encode_heading(degrees: f64) -> u16 {
+    ((degrees.rem_euclid(360.0) / HEADING_LSB).round() as u32 & 0xffff) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use adsbee_types::{
+        IcaoAddress,
+        geo::LatLon,
+    };
+
+    use crate::{
+        DecodeError,
+        Record,
+        cat021::{
+            AirborneGroundVector,
+            DataSourceIdentifier,
+            MopsVersion,
+            QualityIndicators,
+            TargetReport,
+            TargetReportDescriptor,
+            TimeOfDay,
+        },
+        decode_data_block,
+        encode_data_block,
+    };
+
+    fn target_report() -> TargetReport {
+        TargetReport {
+            data_source: DataSourceIdentifier { sac: 25, sic: 201 },
+            target_report_descriptor: Some(TargetReportDescriptor {
+                altitude_reporting_capability:
+                    TargetReportDescriptor::ALTITUDE_REPORTING_CAPABILITY_25_FT,
+                ..Default::default()
+            }),
+            track_number: Some(42),
+            time_of_applicability_for_position: Some(TimeOfDay::from_seconds(43200.5)),
+            position: Some(LatLon::new(51.477, -0.4614)),
+            time_of_applicability_for_velocity: Some(TimeOfDay::from_seconds(43200.25)),
+            target_address: Some(IcaoAddress::from_u32_unchecked(0x4ca7b5)),
+            time_of_message_reception_of_position: Some(TimeOfDay::from_seconds(43200.5)),
+            time_of_message_reception_of_velocity: Some(TimeOfDay::from_seconds(43200.25)),
+            geometric_height: Some(37125.0),
+            quality_indicators: Some(QualityIndicators {
+                nac_v: 1,
+                nic: 8,
+                nic_baro: true,
+                sil: 3,
+                nac_p: 9,
+                sil_supplement: false,
+                sda: 2,
+                gva: 2,
+                pic: None,
+            }),
+            mops_version: Some(MopsVersion {
+                version_not_supported: false,
+                version: 2,
+                link_technology: MopsVersion::LINK_TECHNOLOGY_1090_ES,
+            }),
+            mode_3a_code: Some("7700".parse().unwrap()),
+            flight_level: Some(370.0),
+            magnetic_heading: Some(90.0),
+            airborne_ground_vector: Some(AirborneGroundVector {
+                ground_speed: 450.0,
+                track_angle: 270.0,
+            }),
+            time_of_report_transmission: Some(TimeOfDay::from_seconds(43201.0)),
+            target_identification: Some("RYR4WJ".parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn it_encodes_the_data_source() {
+        let report = TargetReport {
+            data_source: DataSourceIdentifier { sac: 25, sic: 201 },
+            ..Default::default()
+        };
+
+        let mut buffer = vec![];
+        encode_data_block([&report], &mut buffer);
+        assert_eq!(buffer, [21, 0, 6, 0x80, 25, 201]);
+    }
+
+    #[test]
+    fn it_round_trips_target_reports() {
+        let report = target_report();
+
+        let mut buffer = vec![];
+        report.encode(&mut buffer);
+        let decoded = TargetReport::decode(&mut &buffer[..]).unwrap();
+
+        let position = decoded.position.unwrap();
+        assert!((position.latitude - 51.477).abs() < 1e-6);
+        assert!((position.longitude + 0.4614).abs() < 1e-6);
+        assert_eq!(decoded.target_address, report.target_address);
+        assert_eq!(decoded.quality_indicators, report.quality_indicators);
+        assert_eq!(decoded.mops_version, report.mops_version);
+        assert_eq!(decoded.mode_3a_code, report.mode_3a_code);
+        assert_eq!(decoded.flight_level, report.flight_level);
+        assert_eq!(decoded.geometric_height, report.geometric_height);
+        assert_eq!(decoded.magnetic_heading, report.magnetic_heading);
+        assert_eq!(
+            decoded.airborne_ground_vector,
+            report.airborne_ground_vector
+        );
+        assert_eq!(decoded.target_identification.unwrap().trimmed(), "RYR4WJ");
+        assert_eq!(
+            decoded
+                .time_of_applicability_for_position
+                .unwrap()
+                .as_seconds(),
+            43200.5
+        );
+
+        // encoding again must give the same bytes
+        let mut encoded_again = vec![];
+        decoded.encode(&mut encoded_again);
+        assert_eq!(encoded_again, buffer);
+    }
+
+    #[test]
+    fn it_round_trips_data_blocks() {
+        let reports = [target_report(), TargetReport::default()];
+
+        let mut buffer = vec![];
+        encode_data_block(&reports, &mut buffer);
+        assert_eq!(
+            usize::from(u16::from_be_bytes([buffer[1], buffer[2]])),
+            buffer.len()
+        );
+
+        let decoded: Vec<TargetReport> = decode_data_block(&mut &buffer[..]).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].target_address, reports[0].target_address);
+        assert_eq!(decoded[1], reports[1]);
+    }
+
+    #[test]
+    fn it_skips_unknown_items() {
+        // I021/010, I021/015 (service identification), I021/080
+        let record = [0b1001_0001, 0b0001_0000, 25, 201, 7, 0x4c, 0xa7, 0xb5];
+        let report = TargetReport::decode(&mut &record[..]).unwrap();
+        assert_eq!(
+            report.target_address,
+            Some(IcaoAddress::from_u32_unchecked(0x4ca7b5))
+        );
+    }
+
+    #[test]
+    fn it_fails_on_truncated_records() {
+        let mut buffer = vec![];
+        target_report().encode(&mut buffer);
+        buffer.pop();
+        assert!(matches!(
+            TargetReport::decode(&mut &buffer[..]),
+            Err(DecodeError::Truncated)
+        ));
+    }
+}
@@ -0,0 +1,243 @@
+//! EUROCONTROL ASTERIX
+//!
+//! ASTERIX data is sent in data blocks. A data block starts with the category
+//! and its length, followed by one or more records of that category. Each
+//! record starts with a field specification (FSPEC), which says which data
+//! items are present.
+//!
+//! Only CAT021 (ADS-B target reports) is implemented.
+//!
+//! - [Part 1: General Principles][1]
+//!
+//! [1]: https://www.eurocontrol.int/publication/eurocontrol-specification-surveillance-data-exchange-part-i
+
+pub mod cat021;
+
+use bytes::{
+    Buf,
+    BufMut,
+};
+
+/// Length of the data block header: category (1 byte) and length (2 bytes).
+const DATA_BLOCK_HEADER_LENGTH: usize = 3;
+
+/// Maximum number of field reference numbers (FRN) in a field specification.
+const MAX_FRN: u8 = 63;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DecodeError {
+    #[error("data truncated")]
+    Truncated,
+    #[error("invalid data block length: {length}")]
+    InvalidLength { length: usize },
+    #[error("unexpected category: expected {expected}, got {got}")]
+    UnexpectedCategory { expected: u8, got: u8 },
+    #[error("field specification too long")]
+    FspecTooLong,
+    #[error("unsupported data item: FRN {frn}")]
+    UnsupportedItem { frn: u8 },
+}
+
+/// A record of an ASTERIX category.
+pub trait Record: Sized {
+    const CATEGORY: u8;
+
+    /// Encodes the record, including its field specification.
+    fn encode<B: BufMut>(&self, buffer: &mut B);
+
+    /// Decodes a record, including its field specification.
+    fn decode<B: Buf>(buffer: &mut B) -> Result<Self, DecodeError>;
+}
+
+/// Encodes `records` as a data block.
+///
+/// Data blocks can be at most 65535 bytes long, so the caller should limit the
+/// number of records.
+pub fn encode_data_block<'a, R: Record + 'a>(
+    records: impl IntoIterator<Item = &'a R>,
+    buffer: &mut Vec<u8>,
+) {
+    let start = buffer.len();
+    buffer.put_u8(R::CATEGORY);
+    // the length is filled in after the records are encoded
+    buffer.put_u16(0);
+
+    for record in records {
+        record.encode(buffer);
+    }
+
+    let length = u16::try_from(buffer.len() - start).expect("data block too long");
+    buffer[start + 1..][..2].copy_from_slice(&length.to_be_bytes());
+}
+
+/// Decodes a data block with records of category `R`.
+pub fn decode_data_block<R: Record, B: Buf>(buffer: &mut B) -> Result<Vec<R>, DecodeError> {
+    ensure_remaining(buffer, DATA_BLOCK_HEADER_LENGTH)?;
+    let category = buffer.get_u8();
+    let length = usize::from(buffer.get_u16());
+
+    if category != R::CATEGORY {
+        return Err(DecodeError::UnexpectedCategory {
+            expected: R::CATEGORY,
+            got: category,
+        });
+    }
+    if length < DATA_BLOCK_HEADER_LENGTH {
+        return Err(DecodeError::InvalidLength { length });
+    }
+    ensure_remaining(buffer, length - DATA_BLOCK_HEADER_LENGTH)?;
+
+    let mut data = buffer.take(length - DATA_BLOCK_HEADER_LENGTH);
+    let mut records = vec![];
+    while data.has_remaining() {
+        records.push(R::decode(&mut data)?);
+    }
+
+    Ok(records)
+}
+
+/// Field specification: which data items are present in a record.
+///
+/// Data items are identified by their field reference number (FRN), which is
+/// their position in the category's user application profile (UAP), starting
+/// at 1.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Fspec {
+    bits: u64,
+}
+
+impl Fspec {
+    /// Number of FRNs per octet. The last bit is the field extension (FX).
+    const FRNS_PER_OCTET: u8 = 7;
+
+    pub fn insert(&mut self, frn: u8) {
+        assert!((1..=MAX_FRN).contains(&frn), "invalid FRN: {frn}");
+        self.bits |= 1 << (frn - 1);
+    }
+
+    pub fn contains(&self, frn: u8) -> bool {
+        (1..=MAX_FRN).contains(&frn) && self.bits & (1 << (frn - 1)) != 0
+    }
+
+    /// Returns the FRNs that are present, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (1..=MAX_FRN).filter(|frn| self.contains(*frn))
+    }
+
+    pub fn encode<B: BufMut>(&self, buffer: &mut B) {
+        let max_frn = 64 - self.bits.leading_zeros() as u8;
+        let num_octets = max_frn.div_ceil(Self::FRNS_PER_OCTET).max(1);
+
+        for i in 0..num_octets {
+            let bits = (self.bits >> (i * Self::FRNS_PER_OCTET)) as u8 & 0x7f;
+            let mut octet = bits.reverse_bits();
+            if i + 1 < num_octets {
+                octet |= 1;
+            }
+            buffer.put_u8(octet);
+        }
+    }
+
+    pub fn decode<B: Buf>(buffer: &mut B) -> Result<Self, DecodeError> {
+        let mut bits = 0;
+        for i in 0.. {
+            if i * Self::FRNS_PER_OCTET >= MAX_FRN {
+                return Err(DecodeError::FspecTooLong);
+            }
+
+            ensure_remaining(buffer, 1)?;
+            let octet = buffer.get_u8();
+            bits |= u64::from((octet & 0xfe).reverse_bits()) << (i * Self::FRNS_PER_OCTET);
+
+            if octet & 1 == 0 {
+                break;
+            }
+        }
+        Ok(Self { bits })
+    }
+}
+
+/// How the length of a data item is determined, see part 1, 4.3.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ItemFormat {
+    Fixed(usize),
+    /// Octets are added while the last bit (FX) is set.
+    Extended,
+    /// Starts with a repetition factor, followed by that many fixed-length
+    /// sub-items.
+    Repetitive(usize),
+    /// Starts with a length octet, including itself.
+    Explicit,
+    /// Starts with its own field specification for sub-items.
+    Compound,
+}
+
+impl ItemFormat {
+    /// Skips a data item we don't decode.
+    ///
+    /// Compound data items can't be skipped without knowing their sub-items.
+    pub fn skip<B: Buf>(&self, buffer: &mut B, frn: u8) -> Result<(), DecodeError> {
+        let length = match self {
+            Self::Fixed(length) => *length,
+            Self::Extended => {
+                loop {
+                    ensure_remaining(buffer, 1)?;
+                    if buffer.get_u8() & 1 == 0 {
+                        break;
+                    }
+                }
+                0
+            }
+            Self::Repetitive(length) => {
+                ensure_remaining(buffer, 1)?;
+                usize::from(buffer.get_u8()) * length
+            }
+            Self::Explicit => {
+                ensure_remaining(buffer, 1)?;
+                usize::from(buffer.get_u8()).saturating_sub(1)
+            }
+            Self::Compound => return Err(DecodeError::UnsupportedItem { frn }),
+        };
+
+        ensure_remaining(buffer, length)?;
+        buffer.advance(length);
+        Ok(())
+    }
+}
+
+fn ensure_remaining<B: Buf>(buffer: &B, length: usize) -> Result<(), DecodeError> {
+    if buffer.remaining() < length {
+        Err(DecodeError::Truncated)
+    }
+    else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Fspec;
+
+    #[test]
+    fn it_encodes_fspecs() {
+        let mut fspec = Fspec::default();
+        fspec.insert(1);
+        fspec.insert(7);
+        fspec.insert(11);
+
+        let mut buffer = vec![];
+        fspec.encode(&mut buffer);
+        assert_eq!(buffer, [0b1000_0011, 0b0001_0000]);
+
+        let decoded = Fspec::decode(&mut &buffer[..]).unwrap();
+        assert_eq!(decoded, fspec);
+        assert_eq!(decoded.iter().collect::<Vec<_>>(), [1, 7, 11]);
+    }
+
+    #[test]
+    fn it_encodes_empty_fspecs() {
+        let mut buffer = vec![];
+        Fspec::default().encode(&mut buffer);
+        assert_eq!(buffer, [0]);
+    }
+}
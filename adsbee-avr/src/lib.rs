@@ -0,0 +1,412 @@
+//! AVR format
+//!
+//! AVR (also called "raw") is a line based format, where each line contains
+//! one hex-encoded Mode A/C or Mode S frame. It's what readsb sends on its raw
+//! output port (usually port 30002), what `rtl_adsb` prints, and what many
+//! hobbyist decoders produce.
+//!
+//! There are a few variants, which differ in the first character of the line:
+//!
+//! - `*8D4840D6202CC371C32CE0576098;`: Only the frame.
+//! - `@0123456789AB8D4840D6202CC371C32CE0576098;`: Prefixed with a 48 bit MLAT
+//!   timestamp.
+//! - `<0123456789AB3F8D4840D6202CC371C32CE0576098;`: Prefixed with a 48 bit
+//!   MLAT timestamp and an 8 bit signal level.
+//!
+//! The timestamp and signal level are encoded like they are in BEAST, so this
+//! uses [`MlatTimestamp`] and [`SignalLevel`] from the BEAST crate.
+//!
+//! - [readsb][1] (see `decodeHexMessage` and `modesSendRawOutput`)
+//!
+//! [1]: https://github.com/wiedehopf/readsb/blob/75decb53c0e66f4c12cf24127578a3fe7d919219/net_io.c
+
+use std::{
+    fmt::{
+        self,
+        Display,
+    },
+    str::FromStr,
+};
+
+use adsbee_beast::{
+    MlatTimestamp,
+    SignalLevel,
+    output::OutputPacket,
+};
+use adsbee_line as line;
+
+/// Lines longer than this are skipped. The longest valid line is 44 bytes, but
+/// we allow for some whitespace.
+const MAX_LINE_LENGTH: usize = 128;
+
+#[derive(Debug, thiserror::Error)]
+#[error("avr decode error")]
+pub enum Error {
+    Line(#[from] line::Error),
+    InvalidFrame(#[from] FrameFromStrError),
+}
+
+/// Incremental decoder for AVR frames. See [`line::Decoder`].
+pub type Decoder = line::Decoder<Frame>;
+
+/// Reads AVR frames from a tokio `AsyncRead`.
+pub type Reader<R> = line::Reader<R, Frame>;
+
+/// Reads AVR frames from a `futures_io::AsyncRead`.
+#[cfg(feature = "futures-io")]
+pub type FuturesReader<R> = line::FuturesReader<R, Frame>;
+
+/// Reads AVR frames from a blocking [`std::io::Read`].
+pub type BlockingReader<R> = line::BlockingReader<R, Frame>;
+
+/// Writes AVR frames to a tokio `AsyncWrite`.
+///
+/// Each frame is terminated with `\n`, like readsb does.
+pub type Writer<W> = line::Writer<W, Frame>;
+
+impl line::Line for Frame {
+    type Error = Error;
+
+    const MAX_LENGTH: usize = MAX_LINE_LENGTH;
+    const LINE_ENDING: &'static str = "\n";
+
+    fn parse_line(line: &[u8]) -> Result<Self, Self::Error> {
+        Ok(Self::parse(line.trim_ascii())?)
+    }
+}
+
+/// A frame, optionally with MLAT timestamp and signal level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub timestamp: Option<MlatTimestamp>,
+    /// Only the `<` variant has a signal level.
+    pub signal_level: Option<SignalLevel>,
+    pub data: FrameData,
+}
+
+impl Frame {
+    /// Converts a BEAST Mode A/C or Mode S packet.
+    ///
+    /// [`MlatTimestamp::ANY_TIMESTAMP`] is treated as no timestamp. Returns
+    /// `None` for other packets.
+    pub fn from_beast(packet: &OutputPacket) -> Option<Self> {
+        let (timestamp, signal_level, data) = match *packet {
+            OutputPacket::ModeAc {
+                timestamp,
+                signal_level,
+                data,
+            } => (timestamp, signal_level, FrameData::ModeAc(data)),
+            OutputPacket::ModeSShort {
+                timestamp,
+                signal_level,
+                data,
+            } => (timestamp, signal_level, FrameData::ModeSShort(data)),
+            OutputPacket::ModeSLong {
+                timestamp,
+                signal_level,
+                data,
+            } => (timestamp, signal_level, FrameData::ModeSLong(data)),
+            _ => return None,
+        };
+
+        Some(Self {
+            timestamp: (timestamp != MlatTimestamp::ANY_TIMESTAMP).then_some(timestamp),
+            signal_level: Some(signal_level),
+            data,
+        })
+    }
+
+    fn parse(line: &[u8]) -> Result<Self, FrameFromStrError> {
+        let (prefix, rest) = line.split_first().ok_or(FrameFromStrError::Empty)?;
+        let rest = rest
+            .strip_suffix(b";")
+            .ok_or(FrameFromStrError::MissingTerminator)?;
+
+        let (timestamp, signal_level, data) = match prefix {
+            b'*' => (None, None, rest),
+            b'@' => {
+                let (timestamp, data) = rest
+                    .split_at_checked(12)
+                    .ok_or(FrameFromStrError::Truncated)?;
+                (Some(parse_timestamp(timestamp)?), None, data)
+            }
+            b'<' => {
+                let (timestamp, rest) = rest
+                    .split_at_checked(12)
+                    .ok_or(FrameFromStrError::Truncated)?;
+                let (signal_level, data) = rest
+                    .split_at_checked(2)
+                    .ok_or(FrameFromStrError::Truncated)?;
+                let mut signal_level_byte = [0];
+                hex::decode_to_slice(signal_level, &mut signal_level_byte)?;
+                (
+                    Some(parse_timestamp(timestamp)?),
+                    Some(SignalLevel(signal_level_byte[0])),
+                    data,
+                )
+            }
+            _ => {
+                return Err(FrameFromStrError::InvalidPrefix {
+                    prefix: char::from(*prefix),
+                });
+            }
+        };
+
+        let data = match data.len() {
+            4 => {
+                let mut bytes = [0; 2];
+                hex::decode_to_slice(data, &mut bytes)?;
+                FrameData::ModeAc(bytes)
+            }
+            14 => {
+                let mut bytes = [0; 7];
+                hex::decode_to_slice(data, &mut bytes)?;
+                FrameData::ModeSShort(bytes)
+            }
+            28 => {
+                let mut bytes = [0; 14];
+                hex::decode_to_slice(data, &mut bytes)?;
+                FrameData::ModeSLong(bytes)
+            }
+            length => return Err(FrameFromStrError::InvalidLength { length }),
+        };
+
+        Ok(Self {
+            timestamp,
+            signal_level,
+            data,
+        })
+    }
+}
+
+fn parse_timestamp(s: &[u8]) -> Result<MlatTimestamp, FrameFromStrError> {
+    let mut timestamp = [0; 6];
+    hex::decode_to_slice(s, &mut timestamp)?;
+    Ok(MlatTimestamp(timestamp))
+}
+
+impl FromStr for Frame {
+    type Err = FrameFromStrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s.trim_ascii().as_bytes())
+    }
+}
+
+impl Display for Frame {
+    /// Formats the frame as a line, without the line ending.
+    ///
+    /// Frames with signal level use the `<` variant. If they don't have a
+    /// timestamp, [`MlatTimestamp::ANY_TIMESTAMP`] is used.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.timestamp, self.signal_level) {
+            (timestamp, Some(signal_level)) => {
+                write!(f, "<")?;
+                write_hex(f, &timestamp.unwrap_or(MlatTimestamp::ANY_TIMESTAMP).0)?;
+                write_hex(f, &[signal_level.0])?;
+            }
+            (Some(timestamp), None) => {
+                write!(f, "@")?;
+                write_hex(f, &timestamp.0)?;
+            }
+            (None, None) => write!(f, "*")?,
+        }
+        write_hex(f, self.data.as_bytes())?;
+        write!(f, ";")
+    }
+}
+
+/// readsb writes upper case hex.
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    for byte in bytes {
+        write!(f, "{byte:02X}")?;
+    }
+    Ok(())
+}
+
+impl From<Frame> for OutputPacket {
+    /// Converts the frame to a BEAST packet.
+    ///
+    /// A missing timestamp is replaced with [`MlatTimestamp::ANY_TIMESTAMP`],
+    /// and a missing signal level with 0.
+    fn from(value: Frame) -> Self {
+        let timestamp = value.timestamp.unwrap_or(MlatTimestamp::ANY_TIMESTAMP);
+        let signal_level = value.signal_level.unwrap_or(SignalLevel(0));
+        match value.data {
+            FrameData::ModeAc(data) => {
+                OutputPacket::ModeAc {
+                    timestamp,
+                    signal_level,
+                    data,
+                }
+            }
+            FrameData::ModeSShort(data) => {
+                OutputPacket::ModeSShort {
+                    timestamp,
+                    signal_level,
+                    data,
+                }
+            }
+            FrameData::ModeSLong(data) => {
+                OutputPacket::ModeSLong {
+                    timestamp,
+                    signal_level,
+                    data,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameData {
+    ModeAc([u8; 2]),
+    ModeSShort([u8; 7]),
+    ModeSLong([u8; 14]),
+}
+
+impl FrameData {
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::ModeAc(data) => data,
+            Self::ModeSShort(data) => data,
+            Self::ModeSLong(data) => data,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FrameFromStrError {
+    #[error("empty frame")]
+    Empty,
+    #[error("invalid prefix: {prefix:?}")]
+    InvalidPrefix { prefix: char },
+    #[error("frame is not terminated with `;`")]
+    MissingTerminator,
+    #[error("frame is truncated")]
+    Truncated,
+    #[error("invalid hex")]
+    InvalidHex(#[from] hex::FromHexError),
+    /// Length of the hex-encoded frame data
+    #[error("invalid frame length: {length}")]
+    InvalidLength { length: usize },
+}
+
+#[cfg(test)]
+mod tests {
+    use adsbee_beast::{
+        MlatTimestamp,
+        SignalLevel,
+        output::OutputPacket,
+    };
+
+    use crate::{
+        Frame,
+        FrameData,
+        FrameFromStrError,
+    };
+
+    const EXAMPLE: &str = r#"*8D4840D6202CC371C32CE0576098;
+*5D4840D6A1B2C3;
+@0123456789AB8D4840D6202CC371C32CE0576098;
+<0123456789AB3F8D4840D6202CC371C32CE0576098;
+*0F12;
+"#;
+
+    const LONG_FRAME: [u8; 14] = [
+        0x8d, 0x48, 0x40, 0xd6, 0x20, 0x2c, 0xc3, 0x71, 0xc3, 0x2c, 0xe0, 0x57, 0x60, 0x98,
+    ];
+
+    #[test]
+    fn it_parses_all_variants() {
+        let frames = EXAMPLE
+            .lines()
+            .map(|line| line.parse::<Frame>().unwrap())
+            .collect::<Vec<_>>();
+        let timestamp = MlatTimestamp([0x01, 0x23, 0x45, 0x67, 0x89, 0xab]);
+
+        assert_eq!(
+            frames,
+            [
+                Frame {
+                    timestamp: None,
+                    signal_level: None,
+                    data: FrameData::ModeSLong(LONG_FRAME),
+                },
+                Frame {
+                    timestamp: None,
+                    signal_level: None,
+                    data: FrameData::ModeSShort([0x5d, 0x48, 0x40, 0xd6, 0xa1, 0xb2, 0xc3]),
+                },
+                Frame {
+                    timestamp: Some(timestamp),
+                    signal_level: None,
+                    data: FrameData::ModeSLong(LONG_FRAME),
+                },
+                Frame {
+                    timestamp: Some(timestamp),
+                    signal_level: Some(SignalLevel(0x3f)),
+                    data: FrameData::ModeSLong(LONG_FRAME),
+                },
+                Frame {
+                    timestamp: None,
+                    signal_level: None,
+                    data: FrameData::ModeAc([0x0f, 0x12]),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_rejects_invalid_frames() {
+        assert!(matches!(
+            "#8D4840D6202CC371C32CE0576098;".parse::<Frame>(),
+            Err(FrameFromStrError::InvalidPrefix { prefix: '#' })
+        ));
+        assert!(matches!(
+            "*8D4840D6202CC371C32CE0576098".parse::<Frame>(),
+            Err(FrameFromStrError::MissingTerminator)
+        ));
+        assert!(matches!(
+            "*8D4840D6202C;".parse::<Frame>(),
+            Err(FrameFromStrError::InvalidLength { length: 12 })
+        ));
+        assert!(matches!(
+            "*8D4840D6202CC371C32CE05760XX;".parse::<Frame>(),
+            Err(FrameFromStrError::InvalidHex(_))
+        ));
+        assert!(matches!(
+            "@0123;".parse::<Frame>(),
+            Err(FrameFromStrError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn it_formats_frames() {
+        for line in EXAMPLE.lines() {
+            let frame: Frame = line.parse().unwrap();
+            assert_eq!(frame.to_string(), line);
+        }
+    }
+
+    #[test]
+    fn it_converts_to_and_from_beast() {
+        let frame: Frame = "*8D4840D6202CC371C32CE0576098;".parse().unwrap();
+        let packet = OutputPacket::from(frame);
+        assert_eq!(
+            packet,
+            OutputPacket::ModeSLong {
+                timestamp: MlatTimestamp::ANY_TIMESTAMP,
+                signal_level: SignalLevel(0),
+                data: LONG_FRAME,
+            }
+        );
+        assert_eq!(
+            Frame::from_beast(&packet).unwrap(),
+            Frame {
+                signal_level: Some(SignalLevel(0)),
+                ..frame
+            }
+        );
+    }
+}
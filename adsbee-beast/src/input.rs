@@ -1,37 +1,218 @@
-#![allow(dead_code)]
+//! BEAST input format decoder and encoder
 
-//! BEAST input format encoder
+use std::{
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+    },
+};
 
+use bytes::{
+    Buf,
+    BufMut,
+    BytesMut,
+};
+use futures_util::{
+    Sink,
+    SinkExt,
+    Stream,
+};
 use pin_project_lite::pin_project;
+use tokio::io::{
+    AsyncRead,
+    AsyncWrite,
+};
 
-use crate::PacketType;
+use crate::{
+    Error,
+    PacketDecode,
+    PacketEncode,
+    PacketType,
+    util::{
+        BufReadBytesExt,
+        ReadState,
+        encode_escaped,
+        poll_read_tokio,
+        poll_write_buffer,
+    },
+};
+
+/// commands are tiny, so this is plenty.
+const WRITE_BUFFER_SIZE: usize = 32;
+
+/// Decodes BEAST input packets without doing any I/O.
+pub type Decoder = crate::Decoder<InputPacketType>;
 
 pin_project! {
+    /// Reads BEAST input packets (commands), e.g. from clients connected to a
+    /// BEAST output server.
+    #[derive(Debug)]
+    pub struct Reader<R> {
+        #[pin]
+        reader: R,
+        state: ReadState<InputPacketType>,
+    }
+}
+
+impl<R> Reader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            state: Default::default(),
+        }
+    }
+}
+
+impl<R: AsyncRead> Stream for Reader<R> {
+    type Item = Result<InputPacket, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let mut reader = this.reader;
+        this.state
+            .poll_next_packet(|buffer| poll_read_tokio(reader.as_mut(), cx, buffer))
+    }
+}
+
+pin_project! {
+    /// Writes BEAST input packets (commands).
+    ///
+    /// Packets are buffered, so make sure to flush the sink.
     #[derive(Debug)]
     pub struct Writer<W> {
         #[pin]
         writer: W,
-        write_buffer: WriteBuffer,
+        write_buffer: BytesMut,
     }
 }
 
-// this only needs to be able to hold any packet we construct
-const WRITE_BUFFER_SIZE: usize = 32;
-const PACKET_BUFFER_SIZE: usize = 32;
+impl<W> Writer<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            write_buffer: BytesMut::with_capacity(WRITE_BUFFER_SIZE),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: AsyncWrite + Unpin> Writer<W> {
+    /// Sends the commands for `configuration` and flushes them.
+    ///
+    /// Neither a Beast nor readsb acknowledge the commands. A Beast reports
+    /// its DIP switch state with [`OutputPacket::DipSwitches`][1], which can
+    /// be used to verify the settings.
+    ///
+    /// [1]: crate::output::OutputPacket::DipSwitches
+    pub async fn configure(&mut self, configuration: &Configuration) -> Result<(), Error> {
+        for packet in configuration.packets() {
+            self.feed(packet).await?;
+        }
+        self.flush().await
+    }
+}
+
+impl<W: AsyncWrite> Sink<InputPacket> for Writer<W> {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
+
+        if this.write_buffer.len() >= WRITE_BUFFER_SIZE {
+            poll_write_buffer(this.writer, this.write_buffer, cx).map_err(Into::into)
+        }
+        else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: InputPacket) -> Result<(), Self::Error> {
+        let this = self.project();
+        encode_escaped(&item.packet_type(), &item, this.write_buffer);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+
+        match poll_write_buffer(this.writer.as_mut(), this.write_buffer, cx) {
+            Poll::Ready(Ok(())) => this.writer.poll_flush(cx).map_err(Into::into),
+            Poll::Ready(Err(error)) => Poll::Ready(Err(error.into())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.as_mut().poll_flush(cx) {
+            Poll::Ready(Ok(())) => self.project().writer.poll_shutdown(cx).map_err(Into::into),
+            poll => poll,
+        }
+    }
+}
 
-#[derive(Debug)]
-struct WriteBuffer {
-    buffer: [u8; WRITE_BUFFER_SIZE],
-    write_pos: usize,
-    read_pos: usize,
+/// Receiver settings that can be set with [`Writer::configure`].
+///
+/// The binary format is always selected, since that's the only one we can
+/// read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Configuration {
+    /// Send MLAT timestamps with each frame.
+    pub mlat_timestamps: bool,
+    /// Only forward frames with a valid CRC.
+    pub crc_check: bool,
+    /// Forward Mode A/C frames.
+    pub mode_ac: bool,
+    /// Only forward DF11 and DF17 frames.
+    pub df_11_17_only: bool,
 }
 
-impl WriteBuffer {}
+impl Default for Configuration {
+    fn default() -> Self {
+        Self {
+            mlat_timestamps: true,
+            crc_check: true,
+            mode_ac: false,
+            df_11_17_only: false,
+        }
+    }
+}
+
+impl Configuration {
+    /// The commands that set this configuration.
+    pub fn packets(&self) -> impl Iterator<Item = InputPacket> {
+        fn toggle(on: bool, on_value: ToggleDipswitch, off_value: ToggleDipswitch) -> InputPacket {
+            InputPacket::DipSwitches(if on { on_value } else { off_value })
+        }
 
-#[derive(Debug)]
-struct PacketEncoder {
-    buffer: [u8; PACKET_BUFFER_SIZE],
-    packet_type: Option<InputPacketType>,
+        [
+            InputPacket::DipSwitches(ToggleDipswitch::FORMAT_BINARY),
+            toggle(
+                self.mlat_timestamps,
+                ToggleDipswitch::TIMESTAMP_INFO_ON,
+                ToggleDipswitch::TIMESTAMP_INFO_OFF,
+            ),
+            toggle(
+                self.crc_check,
+                ToggleDipswitch::CRC_CHECK_ON,
+                ToggleDipswitch::CRC_CHECK_OFF,
+            ),
+            toggle(
+                self.mode_ac,
+                ToggleDipswitch::MODE_AC_ON,
+                ToggleDipswitch::MODE_AC_OFF,
+            ),
+            toggle(
+                self.df_11_17_only,
+                ToggleDipswitch::DF_11_17_ONLY_ON,
+                ToggleDipswitch::DF_11_17_ONLY_OFF,
+            ),
+        ]
+        .into_iter()
+    }
 }
 
 /// BEAST out packet type
@@ -61,6 +242,15 @@ impl PacketType for InputPacketType {
         }
     }
 
+    fn to_byte(&self) -> u8 {
+        match self {
+            Self::DipSwitches => b'1',
+            Self::Ping => b'P',
+            Self::ReadsbConfig => b'W',
+            Self::Unknown(byte) => *byte,
+        }
+    }
+
     fn expected_length(&self) -> Option<usize> {
         match self {
             InputPacketType::DipSwitches => Some(1),
@@ -78,13 +268,49 @@ impl PacketType for InputPacketType {
     }
 }
 
-#[derive(Clone, Debug)]
+impl PacketDecode for InputPacketType {
+    fn decode<B: Buf>(&self, buffer: &mut B) -> Option<InputPacket> {
+        match self {
+            Self::DipSwitches => Some(InputPacket::DipSwitches(ToggleDipswitch(buffer.get_u8()))),
+            Self::Ping => Some(InputPacket::Ping(buffer.get_bytes())),
+            Self::ReadsbConfig => Some(InputPacket::ReadsbConfig(buffer.get_u8())),
+            Self::Unknown(byte) => {
+                tracing::trace!("beast: unknown input packet type: 0x{byte:02x}");
+                None
+            }
+        }
+    }
+}
+
+impl PacketEncode for InputPacketType {
+    fn encode<B: BufMut>(&self, packet: &InputPacket, buffer: &mut B) {
+        debug_assert_eq!(*self, packet.packet_type());
+
+        match packet {
+            InputPacket::DipSwitches(toggle) => buffer.put_u8(toggle.0),
+            InputPacket::Ping(data) => buffer.put_slice(data),
+            InputPacket::ReadsbConfig(value) => buffer.put_u8(*value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum InputPacket {
     DipSwitches(ToggleDipswitch),
     Ping([u8; 3]),
     ReadsbConfig(u8),
 }
 
+impl InputPacket {
+    pub fn packet_type(&self) -> InputPacketType {
+        match self {
+            Self::DipSwitches(_) => InputPacketType::DipSwitches,
+            Self::Ping(_) => InputPacketType::Ping,
+            Self::ReadsbConfig(_) => InputPacketType::ReadsbConfig,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct ToggleDipswitch(pub u8);
 
@@ -106,3 +332,68 @@ impl ToggleDipswitch {
     pub const MODE_AC_OFF: Self = Self(b'j');
     pub const MODE_AC_ON: Self = Self(b'J');
 }
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{
+        SinkExt,
+        TryStreamExt,
+    };
+
+    use crate::input::{
+        Configuration,
+        InputPacket,
+        Reader,
+        ToggleDipswitch,
+        Writer,
+    };
+
+    #[tokio::test]
+    async fn it_encodes_commands() {
+        let mut writer = Writer::new(vec![]);
+        writer
+            .send(InputPacket::DipSwitches(ToggleDipswitch::MODE_AC_ON))
+            .await
+            .unwrap();
+        writer
+            .send(InputPacket::Ping(*b"\x00\x1a\x01"))
+            .await
+            .unwrap();
+        assert_eq!(writer.into_inner(), b"\x1a1J\x1aP\x00\x1a\x1a\x01");
+    }
+
+    #[tokio::test]
+    async fn it_round_trips() {
+        let packets = vec![
+            InputPacket::DipSwitches(ToggleDipswitch::TIMESTAMP_INFO_OFF),
+            InputPacket::Ping([0x1a, 2, 3]),
+            InputPacket::ReadsbConfig(b'O'),
+        ];
+
+        let mut writer = Writer::new(vec![]);
+        for packet in &packets {
+            writer.feed(packet.clone()).await.unwrap();
+        }
+        writer.flush().await.unwrap();
+        let encoded = writer.into_inner();
+
+        let decoded = Reader::new(&encoded[..])
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(decoded, packets);
+    }
+
+    #[tokio::test]
+    async fn it_configures() {
+        let mut writer = Writer::new(vec![]);
+        writer
+            .configure(&Configuration {
+                mode_ac: true,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(writer.into_inner(), b"\x1a1C\x1a1E\x1a1f\x1a1J\x1a1d");
+    }
+}
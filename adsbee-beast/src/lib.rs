@@ -22,8 +22,8 @@
 //! # TODO
 //!
 //! - make this a separate crate
-//! - make reader/writer work with both futures and tokio AsyncRead/AsyncWrite
-//! - make reader/writer work both as Stream/Sink and with plain methods
+//! - make writer work with futures AsyncWrite
+//! - make writer work both as Sink and with plain methods
 //!
 //! [1]: https://wiki.jetvision.de/wiki/Mode-S_Beast:Data_Output_Formats
 //! [2]: https://github.com/wiedehopf/readsb/blob/75decb53c0e66f4c12cf24127578a3fe7d919219/net_io.c#L1754
@@ -38,6 +38,13 @@ use bytes::{
     Buf,
     BufMut,
 };
+use chrono::{
+    DateTime,
+    NaiveTime,
+    TimeDelta,
+    Utc,
+};
+pub use util::Decoder;
 
 /// the "escape" byte.
 const ESCAPE: u8 = 0x1a;
@@ -50,11 +57,12 @@ pub enum Error {
 
 /// Timestamp used for multilateration.
 ///
-/// # TODO
-///
-/// decode this into a DateTime? it's big-endian
+/// It's a 48 bit big-endian integer
 /// <https://github.com/wiedehopf/readsb/blob/75decb53c0e66f4c12cf24127578a3fe7d919219/net_io.c#L1701>
 ///
+/// Whether it's a 12 MHz counter or GPS time-of-day can't be told from the
+/// timestamp itself, so [`MlatTimestamp::decode`] needs to be told the format.
+///
 /// some values have special meaning:
 /// <https://github.com/wiedehopf/readsb/blob/75decb53c0e66f4c12cf24127578a3fe7d919219/readsb.h#L341>
 ///
@@ -75,6 +83,9 @@ pub enum Error {
 /// > HULC Status Message. Both timestamp formats are compatible with the
 /// > respective timestamp formats used in the Beast BinaryProtocol.
 ///
+/// [`HulcStatus::timestamp_format`][output::HulcStatus::timestamp_format]
+/// does this.
+///
 /// [1]: https://wiki.jetvision.de/wiki/Mode-S_Beast:Data_Input_Formats
 /// [2]: https://static.avionix-tech.com/statics/cms/2023-11-21/GNS5894T_ADSB_Module_datasheet_V1.1.pdf
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -98,6 +109,106 @@ impl MlatTimestamp {
     pub fn is_synthetic(&self) -> bool {
         &self.0[0..5] == b"\xFF\x00\x4D\x4C\x41" || self == &Self::ANY_TIMESTAMP
     }
+
+    /// The timestamp as 48 bit integer
+    pub fn as_u64(&self) -> u64 {
+        self.0
+            .iter()
+            .fold(0, |value, byte| (value << 8) | u64::from(*byte))
+    }
+
+    pub fn decode(&self, format: TimestampFormat) -> Timestamp {
+        match *self {
+            Self::SYNTHETIC_MLAT => Timestamp::SyntheticMlat,
+            Self::SYNTHETIC_UAT => Timestamp::SyntheticUat,
+            Self::NO_FORWARD => Timestamp::NoForward,
+            Self::ANY_TIMESTAMP => Timestamp::Any,
+            _ if self.is_synthetic() => Timestamp::Synthetic(*self),
+            _ => {
+                let value = self.as_u64();
+                match format {
+                    TimestampFormat::Counter12Mhz => Timestamp::Counter12Mhz(value),
+                    TimestampFormat::GpsTimeOfDay => {
+                        Timestamp::GpsTimeOfDay {
+                            seconds: (value >> 30) as u32,
+                            nanoseconds: (value & 0x3fff_ffff) as u32,
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Which format the receiver uses for [`MlatTimestamp`]s.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TimestampFormat {
+    /// Free-running 12 MHz counter. This is what readsb and a Beast without
+    /// GPS use.
+    #[default]
+    Counter12Mhz,
+    /// GPS time-of-day, used by Radarcape and HULC with a GPS fix.
+    GpsTimeOfDay,
+}
+
+/// A decoded [`MlatTimestamp`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Timestamp {
+    /// Ticks of a free-running 12 MHz counter. This has no relation to wall
+    /// clock time, but can be used to calculate time differences between
+    /// frames from the same receiver.
+    Counter12Mhz(u64),
+    /// Time since last midnight UTC
+    GpsTimeOfDay { seconds: u32, nanoseconds: u32 },
+    /// See [`MlatTimestamp::SYNTHETIC_MLAT`]
+    SyntheticMlat,
+    /// See [`MlatTimestamp::SYNTHETIC_UAT`]
+    SyntheticUat,
+    /// See [`MlatTimestamp::NO_FORWARD`]
+    NoForward,
+    /// See [`MlatTimestamp::ANY_TIMESTAMP`]
+    Any,
+    /// Other synthetic timestamps
+    Synthetic(MlatTimestamp),
+}
+
+impl Timestamp {
+    /// Frequency of [`Timestamp::Counter12Mhz`] in Hz
+    pub const COUNTER_FREQUENCY: u64 = 12_000_000;
+
+    /// Converts the timestamp to an absolute time.
+    ///
+    /// Only [`Timestamp::GpsTimeOfDay`] can be converted. Since it only
+    /// contains the time of day, the date is taken from `reference`, which
+    /// should be the approximate time the frame was received. If the
+    /// timestamp is more than 12 hours away from `reference`, we assume that
+    /// midnight lies in between, and use the previous or next day instead.
+    ///
+    /// Returns `None` for invalid times of day.
+    pub fn to_date_time(&self, reference: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let Self::GpsTimeOfDay {
+            seconds,
+            nanoseconds,
+        } = *self
+        else {
+            return None;
+        };
+
+        // this also handles leap seconds, which show up as nanoseconds >= 1e9
+        let time_of_day = NaiveTime::from_num_seconds_from_midnight_opt(seconds, nanoseconds)?;
+        let time = reference.date_naive().and_time(time_of_day).and_utc();
+
+        let half_day = TimeDelta::hours(12);
+        if time - reference > half_day {
+            Some(time - TimeDelta::days(1))
+        }
+        else if reference - time > half_day {
+            Some(time + TimeDelta::days(1))
+        }
+        else {
+            Some(time)
+        }
+    }
 }
 
 /// RSSI encoded as one byte.
@@ -133,6 +244,7 @@ pub trait PacketType {
     type Packet;
 
     fn from_byte(byte: u8) -> Self;
+    fn to_byte(&self) -> u8;
     fn expected_length(&self) -> Option<usize>;
     fn is_known(&self) -> bool;
 }
@@ -142,5 +254,79 @@ pub trait PacketDecode: PacketType {
 }
 
 pub trait PacketEncode: PacketType {
+    /// Encodes the payload of the packet, without escaping.
     fn encode<B: BufMut>(&self, packet: &Self::Packet, buffer: &mut B);
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::{
+        DateTime,
+        TimeDelta,
+        Utc,
+    };
+
+    use crate::{
+        MlatTimestamp,
+        Timestamp,
+        TimestampFormat,
+    };
+
+    fn gps_timestamp(seconds: u64, nanoseconds: u64) -> MlatTimestamp {
+        let value = (seconds << 30) | nanoseconds;
+        MlatTimestamp(value.to_be_bytes()[2..].try_into().unwrap())
+    }
+
+    #[test]
+    fn it_decodes_counters() {
+        let timestamp = MlatTimestamp([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        assert_eq!(
+            timestamp.decode(TimestampFormat::Counter12Mhz),
+            Timestamp::Counter12Mhz(0x0102030405)
+        );
+        assert_eq!(
+            MlatTimestamp::SYNTHETIC_MLAT.decode(TimestampFormat::Counter12Mhz),
+            Timestamp::SyntheticMlat
+        );
+    }
+
+    #[test]
+    fn it_decodes_gps_time_of_day() {
+        let timestamp = gps_timestamp(3600, 500_000_000);
+        let decoded = timestamp.decode(TimestampFormat::GpsTimeOfDay);
+        assert_eq!(
+            decoded,
+            Timestamp::GpsTimeOfDay {
+                seconds: 3600,
+                nanoseconds: 500_000_000
+            }
+        );
+
+        let reference: DateTime<Utc> = "2025-07-01T01:00:01Z".parse().unwrap();
+        assert_eq!(
+            decoded.to_date_time(reference).unwrap(),
+            "2025-07-01T01:00:00.5Z".parse::<DateTime<Utc>>().unwrap()
+        );
+
+        assert_eq!(Timestamp::Counter12Mhz(1234).to_date_time(reference), None);
+    }
+
+    #[test]
+    fn it_handles_midnight_rollover() {
+        // received just after midnight, timestamped just before
+        let reference: DateTime<Utc> = "2025-07-02T00:00:00.2Z".parse().unwrap();
+        let time = gps_timestamp(86399, 900_000_000)
+            .decode(TimestampFormat::GpsTimeOfDay)
+            .to_date_time(reference)
+            .unwrap();
+        assert_eq!(time, reference - TimeDelta::milliseconds(300));
+
+        // receiver clock slightly ahead of ours
+        let reference: DateTime<Utc> = "2025-07-01T23:59:59.9Z".parse().unwrap();
+        let time = gps_timestamp(0, 100_000_000)
+            .decode(TimestampFormat::GpsTimeOfDay)
+            .to_date_time(reference)
+            .unwrap();
+        assert_eq!(time, reference + TimeDelta::milliseconds(200));
+    }
+}
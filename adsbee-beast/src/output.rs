@@ -1,4 +1,4 @@
-//! BEAST output format decoder
+//! BEAST output format decoder and encoder
 
 use std::{
     pin::Pin,
@@ -8,31 +8,43 @@ use std::{
     },
 };
 
-use bytes::Buf;
-use futures_util::Stream;
+use bytes::{
+    Buf,
+    BufMut,
+    Bytes,
+    BytesMut,
+};
+use futures_util::{
+    Sink,
+    Stream,
+};
 use pin_project_lite::pin_project;
 use tokio::io::{
     AsyncRead,
-    ReadBuf,
+    AsyncWrite,
 };
 use uuid::Uuid;
 
 use crate::{
-    ESCAPE,
     Error,
     MlatTimestamp,
     PacketDecode,
+    PacketEncode,
     PacketType,
     SignalLevel,
-    util::BufReadBytesExt,
+    TimestampFormat,
+    util::{
+        BufReadBytesExt,
+        ReadState,
+        encode_escaped,
+        poll_read_tokio,
+        poll_write_buffer,
+    },
 };
 
-/// this can be larger for more efficient reads, although the underlying reader
-/// is probably buffered.
-const RECEIVE_BUFFER_SIZE: usize = 512;
-
-/// this only needs to be able to hold any packet we decode
-const PACKET_BUFFER_SIZE: usize = 64;
+/// if the write buffer holds more than this, the writer flushes before
+/// accepting new packets.
+const WRITE_BUFFER_SIZE: usize = 512;
 
 /// Standard are 1, 2, 3, 4 (see [doc](https://wiki.jetvision.de/wiki/Mode-S_Beast:Data_Output_Formats))
 ///
@@ -44,13 +56,14 @@ const PACKET_BUFFER_SIZE: usize = 64;
 ///
 /// 4: 1 byte - [DIP switches](https://wiki.jetvision.de/wiki/Mode-S_Beast:Data_Input_Formats)
 ///
-/// 5: unknown
+/// 5: Radarcape receiver position. Same length as a long Mode-S frame, but
+///  contains latitude, longitude and altitude as little-endian floats at
+///  offsets 4, 8 and 12.
 ///   <https://github.com/wiedehopf/readsb/blob/75decb53c0e66f4c12cf24127578a3fe7d919219/net_io.c#L5043>
 ///
 /// H: HULC extension
 ///  <https://static.avionix-tech.com/statics/cms/2023-11-21/GNS5894T_ADSB_Module_datasheet_V1.1.pdf> (page 17)
 ///  0x1A : 0x48 : ID : LEN : DATA
-///  is this worth implementing?
 ///
 /// P: ping. code comments reference a 'p as well, but can't find actual code.
 ///  <https://github.com/wiedehopf/readsb/blob/75decb53c0e66f4c12cf24127578a3fe7d919219/net_io.c#L5050>
@@ -65,19 +78,28 @@ const PACKET_BUFFER_SIZE: usize = 64;
 /// 0xe3: <https://github.com/wiedehopf/readsb/blob/75decb53c0e66f4c12cf24127578a3fe7d919219/net_io.c#L4982>
 ///
 /// 0xe4:
-///  this seems to be a string encoded uuid. this is how a feeder sends its uuid
+///  string encoded uuid. this is how a feeder sends its uuid
 ///  <https://github.com/wiedehopf/readsb/blob/75decb53c0e66f4c12cf24127578a3fe7d919219/net_io.c#L433>
 ///  <https://github.com/wiedehopf/readsb/blob/75decb53c0e66f4c12cf24127578a3fe7d919219/net_io.c#L5045>
 ///
 /// 0xe8: <https://github.com/wiedehopf/readsb/blob/75decb53c0e66f4c12cf24127578a3fe7d919219/net_io.c#L4920>
+///  readsb only uses this internally and never sends it to BEAST outputs. It
+///  is neither documented, nor does readsb's decoder give it a fixed length,
+///  so we can't tell where its payload ends. It's skipped like any unknown
+///  packet type.
+///
+/// Packet types without a fixed length end where the next packet starts.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum OutputPacketType {
     ModeAc,
     ModeSShort,
     ModeSLong,
     DipSwitches,
+    RadarcapePosition,
     Ping,
+    Hulc,
     ReceiverId,
+    ReceiverUuid,
     Unknown(u8),
 }
 
@@ -90,12 +112,27 @@ impl PacketType for OutputPacketType {
             b'2' => Self::ModeSShort,
             b'3' => Self::ModeSLong,
             b'4' => Self::DipSwitches,
-            //b'P' => Self::Ping, // todo: uppercase P is an input command
-            //0xe3 => Self::ReceiverId,
-            _ => {
-                todo!("unknown packet type: {:02x}", byte);
-                //Self::Unknown(byte)
-            }
+            b'5' => Self::RadarcapePosition,
+            b'P' => Self::Ping,
+            b'H' => Self::Hulc,
+            0xe3 => Self::ReceiverId,
+            0xe4 => Self::ReceiverUuid,
+            _ => Self::Unknown(byte),
+        }
+    }
+
+    fn to_byte(&self) -> u8 {
+        match self {
+            Self::ModeAc => b'1',
+            Self::ModeSShort => b'2',
+            Self::ModeSLong => b'3',
+            Self::DipSwitches => b'4',
+            Self::RadarcapePosition => b'5',
+            Self::Ping => b'P',
+            Self::Hulc => b'H',
+            Self::ReceiverId => 0xe3,
+            Self::ReceiverUuid => 0xe4,
+            Self::Unknown(byte) => *byte,
         }
     }
 
@@ -105,6 +142,7 @@ impl PacketType for OutputPacketType {
             OutputPacketType::ModeSShort => Some(14),
             OutputPacketType::ModeSLong => Some(21),
             OutputPacketType::DipSwitches => Some(1),
+            OutputPacketType::RadarcapePosition => Some(21),
             OutputPacketType::Ping => Some(3),
             OutputPacketType::ReceiverId => Some(8),
             _ => None,
@@ -144,26 +182,147 @@ impl PacketDecode for OutputPacketType {
                 })
             }
             Self::DipSwitches => Some(OutputPacket::DipSwitches(buffer.get_u8())),
+            Self::RadarcapePosition => {
+                buffer.advance(4);
+                Some(OutputPacket::RadarcapePosition {
+                    latitude: buffer.get_f32_le(),
+                    longitude: buffer.get_f32_le(),
+                    altitude: buffer.get_f32_le(),
+                })
+            }
             Self::Ping => {
                 Some(OutputPacket::Ping {
                     data: buffer.get_bytes(),
                 })
             }
+            Self::Hulc => {
+                if buffer.remaining() < 2 {
+                    tracing::debug!("beast: HULC message too short");
+                    return None;
+                }
+                let message_id = buffer.get_u8();
+                let length = usize::from(buffer.get_u8());
+                if buffer.remaining() != length {
+                    tracing::debug!(
+                        length,
+                        remaining = buffer.remaining(),
+                        "beast: HULC message with invalid length"
+                    );
+                    return None;
+                }
+                let message = if message_id == HulcStatus::MESSAGE_ID {
+                    if length != HulcStatus::LENGTH {
+                        tracing::debug!(length, "beast: HULC status message with invalid length");
+                        return None;
+                    }
+                    HulcMessage::Status(HulcStatus::decode(buffer))
+                }
+                else {
+                    HulcMessage::Other {
+                        message_id,
+                        data: buffer.copy_to_bytes(length),
+                    }
+                };
+                Some(OutputPacket::Hulc(message))
+            }
             Self::ReceiverId => {
                 Some(OutputPacket::ReceiverId {
-                    receiver_id: Uuid::from_bytes(buffer.get_bytes()),
+                    receiver_id: Uuid::from_u64_pair(buffer.get_u64(), 0),
                 })
             }
+            Self::ReceiverUuid => {
+                let data = buffer.copy_to_bytes(buffer.remaining());
+                // readsb ignores anything after the uuid
+                let uuid = data.get(..36).unwrap_or(&data);
+                match Uuid::try_parse_ascii(uuid) {
+                    Ok(receiver_id) => Some(OutputPacket::ReceiverUuid { receiver_id }),
+                    Err(error) => {
+                        tracing::debug!(?error, "beast: invalid receiver uuid");
+                        None
+                    }
+                }
+            }
             Self::Unknown(byte) => {
-                // todo: during development we want to know all packet types we receive.
-                // normally this should return None
-                todo!("beast: unknown packet type: 0x{byte:02x}");
+                tracing::trace!("beast: unknown packet type: 0x{byte:02x}");
+                None
             }
         }
     }
 }
 
-#[derive(Clone, Debug)]
+impl PacketEncode for OutputPacketType {
+    fn encode<B: BufMut>(&self, packet: &OutputPacket, buffer: &mut B) {
+        debug_assert_eq!(*self, packet.packet_type());
+
+        match packet {
+            OutputPacket::ModeAc {
+                timestamp,
+                signal_level,
+                data,
+            } => {
+                buffer.put_slice(&timestamp.0);
+                buffer.put_u8(signal_level.0);
+                buffer.put_slice(data);
+            }
+            OutputPacket::ModeSShort {
+                timestamp,
+                signal_level,
+                data,
+            } => {
+                buffer.put_slice(&timestamp.0);
+                buffer.put_u8(signal_level.0);
+                buffer.put_slice(data);
+            }
+            OutputPacket::ModeSLong {
+                timestamp,
+                signal_level,
+                data,
+            } => {
+                buffer.put_slice(&timestamp.0);
+                buffer.put_u8(signal_level.0);
+                buffer.put_slice(data);
+            }
+            OutputPacket::DipSwitches(dip_switches) => buffer.put_u8(*dip_switches),
+            OutputPacket::RadarcapePosition {
+                latitude,
+                longitude,
+                altitude,
+            } => {
+                buffer.put_bytes(0, 4);
+                buffer.put_f32_le(*latitude);
+                buffer.put_f32_le(*longitude);
+                buffer.put_f32_le(*altitude);
+                buffer.put_bytes(0, 5);
+            }
+            OutputPacket::Ping { data } => buffer.put_slice(data),
+            OutputPacket::Hulc(HulcMessage::Status(status)) => {
+                buffer.put_u8(HulcStatus::MESSAGE_ID);
+                buffer.put_u8(HulcStatus::LENGTH as u8);
+                status.encode(buffer);
+            }
+            OutputPacket::Hulc(HulcMessage::Other { message_id, data }) => {
+                // the length is a single byte
+                let data = &data[..data.len().min(0xff)];
+                buffer.put_u8(*message_id);
+                buffer.put_u8(data.len() as u8);
+                buffer.put_slice(data);
+            }
+            OutputPacket::ReceiverId { receiver_id } => {
+                buffer.put_u64(receiver_id.as_u64_pair().0);
+            }
+            OutputPacket::ReceiverUuid { receiver_id } => {
+                buffer.put_slice(
+                    receiver_id
+                        .hyphenated()
+                        .encode_lower(&mut Uuid::encode_buffer())
+                        .as_bytes(),
+                );
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum OutputPacket {
     ModeAc {
         timestamp: MlatTimestamp,
@@ -181,22 +340,140 @@ pub enum OutputPacket {
         data: [u8; 14],
     },
     DipSwitches(u8),
+    /// Position of a Radarcape receiver
+    RadarcapePosition {
+        latitude: f32,
+        longitude: f32,
+        /// todo: which unit is this? readsb doesn't use it.
+        altitude: f32,
+    },
     Ping {
         // todo: is this a timestamp?
         data: [u8; 3],
     },
+    Hulc(HulcMessage),
+    /// readsb sends the receiver ID as 8 bytes. These are the upper 64 bits
+    /// of the UUID, the lower bits are not transmitted.
     ReceiverId {
         receiver_id: Uuid,
     },
+    /// Receiver UUID sent as string, usually by feeders.
+    ReceiverUuid {
+        receiver_id: Uuid,
+    },
 }
 
+impl OutputPacket {
+    pub fn packet_type(&self) -> OutputPacketType {
+        match self {
+            Self::ModeAc { .. } => OutputPacketType::ModeAc,
+            Self::ModeSShort { .. } => OutputPacketType::ModeSShort,
+            Self::ModeSLong { .. } => OutputPacketType::ModeSLong,
+            Self::DipSwitches(_) => OutputPacketType::DipSwitches,
+            Self::RadarcapePosition { .. } => OutputPacketType::RadarcapePosition,
+            Self::Ping { .. } => OutputPacketType::Ping,
+            Self::Hulc(_) => OutputPacketType::Hulc,
+            Self::ReceiverId { .. } => OutputPacketType::ReceiverId,
+            Self::ReceiverUuid { .. } => OutputPacketType::ReceiverUuid,
+        }
+    }
+}
+
+/// Message from an Avionix HULC receiver.
+///
+/// [HULC datasheet][1] (page 17)
+///
+/// [1]: https://static.avionix-tech.com/statics/cms/2023-11-21/GNS5894T_ADSB_Module_datasheet_V1.1.pdf
+#[derive(Clone, Debug, PartialEq)]
+pub enum HulcMessage {
+    Status(HulcStatus),
+    /// Any other message: `message_id` and up to 255 bytes of data.
+    Other {
+        message_id: u8,
+        data: Bytes,
+    },
+}
+
+/// HULC status message. All fields are big-endian.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HulcStatus {
+    pub serial_number: u32,
+    /// Status flags, see e.g. [`HulcStatus::FLAG_GPS_DETECTED`]
+    pub flags: u16,
+    /// Seconds since the Unix epoch
+    pub epoch: u32,
+    /// todo: which unit is this? readsb doesn't use it.
+    pub latitude: i32,
+    /// todo: which unit is this? readsb doesn't use it.
+    pub longitude: i32,
+    /// todo: which unit is this? readsb doesn't use it.
+    pub altitude: i16,
+    /// Number of GPS satellites in use
+    pub satellites: u8,
+    pub hdop: u8,
+}
+
+impl HulcStatus {
+    pub const MESSAGE_ID: u8 = 0x01;
+    pub const LENGTH: usize = 24;
+
+    /// A GPS receiver is attached, so timestamps are GPS time-of-day.
+    pub const FLAG_GPS_DETECTED: u16 = 1 << 15;
+
+    pub fn gps_detected(&self) -> bool {
+        self.flags & Self::FLAG_GPS_DETECTED != 0
+    }
+
+    /// Format of the timestamps this receiver sends.
+    pub fn timestamp_format(&self) -> TimestampFormat {
+        if self.gps_detected() {
+            TimestampFormat::GpsTimeOfDay
+        }
+        else {
+            TimestampFormat::Counter12Mhz
+        }
+    }
+
+    fn decode<B: Buf>(buffer: &mut B) -> Self {
+        let serial_number = buffer.get_u32();
+        let flags = buffer.get_u16();
+        // reserved
+        buffer.advance(2);
+        Self {
+            serial_number,
+            flags,
+            epoch: buffer.get_u32(),
+            latitude: buffer.get_i32(),
+            longitude: buffer.get_i32(),
+            altitude: buffer.get_i16(),
+            satellites: buffer.get_u8(),
+            hdop: buffer.get_u8(),
+        }
+    }
+
+    fn encode<B: BufMut>(&self, buffer: &mut B) {
+        buffer.put_u32(self.serial_number);
+        buffer.put_u16(self.flags);
+        buffer.put_u16(0);
+        buffer.put_u32(self.epoch);
+        buffer.put_i32(self.latitude);
+        buffer.put_i32(self.longitude);
+        buffer.put_i16(self.altitude);
+        buffer.put_u8(self.satellites);
+        buffer.put_u8(self.hdop);
+    }
+}
+
+/// Decodes BEAST output packets without doing any I/O.
+pub type Decoder = crate::Decoder<OutputPacketType>;
+
 pin_project! {
+    /// Reads BEAST output packets from a tokio [`AsyncRead`].
     #[derive(Debug)]
     pub struct Reader<R> {
         #[pin]
         reader: R,
-        receive_buffer: ReceiveBuffer,
-        decoder: PacketDecoder,
+        state: ReadState<OutputPacketType>,
     }
 }
 
@@ -204,8 +481,7 @@ impl<R> Reader<R> {
     pub fn new(reader: R) -> Self {
         Self {
             reader,
-            receive_buffer: Default::default(),
-            decoder: Default::default(),
+            state: Default::default(),
         }
     }
 }
@@ -213,233 +489,130 @@ impl<R> Reader<R> {
 impl<R: AsyncRead> Stream for Reader<R> {
     type Item = Result<OutputPacket, Error>;
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        loop {
-            let mut this = self.as_mut().project();
-
-            if this.receive_buffer.has_data() {
-                if let Some(packet) = this.decoder.decode_next(&mut this.receive_buffer)? {
-                    return Poll::Ready(Some(Ok(packet)));
-                }
-            }
-            else {
-                // if there is no data in the receiver buffer, we need to receive some
-                this.receive_buffer.reset();
-
-                let mut read_buf = ReadBuf::new(&mut this.receive_buffer.buffer);
-                match this.reader.poll_read(cx, &mut read_buf) {
-                    Poll::Pending => return Poll::Pending,
-                    Poll::Ready(Err(error)) => return Poll::Ready(Some(Err(error.into()))),
-                    Poll::Ready(Ok(())) => {
-                        let num_bytes_read = read_buf.filled().len();
-
-                        // if no data was received, the underlying reader reached EOF
-                        if num_bytes_read == 0 {
-                            return Poll::Ready(None);
-                        }
-
-                        this.receive_buffer.write_pos = num_bytes_read;
-                    }
-                }
-            }
-        }
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let mut reader = this.reader;
+        this.state
+            .poll_next_packet(|buffer| poll_read_tokio(reader.as_mut(), cx, buffer))
     }
 }
 
-#[derive(Debug)]
-struct ReceiveBuffer {
-    buffer: [u8; RECEIVE_BUFFER_SIZE],
-    read_pos: usize,
-    write_pos: usize,
+#[cfg(feature = "futures-io")]
+pin_project! {
+    /// Reads BEAST output packets from a [`futures_io::AsyncRead`].
+    #[derive(Debug)]
+    pub struct FuturesReader<R> {
+        #[pin]
+        reader: R,
+        state: ReadState<OutputPacketType>,
+    }
 }
 
-impl Default for ReceiveBuffer {
-    fn default() -> Self {
+#[cfg(feature = "futures-io")]
+impl<R> FuturesReader<R> {
+    pub fn new(reader: R) -> Self {
         Self {
-            buffer: [0; RECEIVE_BUFFER_SIZE],
-            read_pos: 0,
-            write_pos: 0,
+            reader,
+            state: Default::default(),
         }
     }
 }
 
-impl ReceiveBuffer {
-    #[inline(always)]
-    fn has_data(&self) -> bool {
-        self.read_pos < self.write_pos
-    }
-
-    #[inline(always)]
-    fn reset(&mut self) {
-        self.read_pos = 0;
-        self.write_pos = 0;
-    }
+#[cfg(feature = "futures-io")]
+impl<R: futures_io::AsyncRead> Stream for FuturesReader<R> {
+    type Item = Result<OutputPacket, Error>;
 
-    #[inline(always)]
-    fn next_byte(&mut self) -> Option<u8> {
-        self.has_data().then(|| {
-            let byte = self.buffer[self.read_pos];
-            self.read_pos += 1;
-            byte
-        })
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let mut reader = this.reader;
+        this.state
+            .poll_next_packet(|buffer| reader.as_mut().poll_read(cx, buffer))
     }
 }
 
+/// Reads BEAST output packets from a blocking [`std::io::Read`].
 #[derive(Debug)]
-struct PacketDecoder {
-    leading_escape_read: bool,
-    packet_type: Option<OutputPacketType>,
-    buffer: [u8; PACKET_BUFFER_SIZE],
-    buffer_write_pos: usize,
-    read_incomplete_escape: bool,
+pub struct BlockingReader<R> {
+    reader: R,
+    state: ReadState<OutputPacketType>,
 }
 
-impl PacketDecoder {
-    /// Reads from the receiver_buffer as much as needed to decode one packet.
-    /// if there isn't enough data in the buffer, returns None.
-    ///
-    /// This will read as much as possible. So if None is returned all data has
-    /// been read and the receive_buffer can be cleared.
-    ///
-    /// The decoder keeps track of any partially decoded packets and the next
-    /// invocation of this method will resume decoding.
-    fn decode_next(
-        &mut self,
-        receive_buffer: &mut ReceiveBuffer,
-    ) -> Result<Option<OutputPacket>, Error> {
-        while let Some(mut byte) = receive_buffer.next_byte() {
-            if self.leading_escape_read {
-                // we already read the packet escape
-
-                if let Some(packet_type) = self.packet_type {
-                    // we already read the packet type
-
-                    if self.read_incomplete_escape {
-                        // we read an escape before, but we don't know what follows yet.
-                        // note: this whole block only handles the case that we read an escape at
-                        // the end of the buffer earlier.
-                        self.read_incomplete_escape = false;
-
-                        if byte == ESCAPE {
-                            // double escape
-                            if packet_type.is_known() {
-                                self.push_byte(ESCAPE);
-                            }
-                        }
-                        else {
-                            // the escape we read was the start of a new packet
-                            self.leading_escape_read = true;
-
-                            if let Some(packet) =
-                                self.emit_packet(Some(OutputPacketType::from_byte(byte)))?
-                            {
-                                return Ok(Some(packet));
-                            }
-                        }
-                    }
-                    else {
-                        if byte == ESCAPE {
-                            if let Some(next_byte) = receive_buffer.next_byte() {
-                                byte = next_byte;
-                            }
-                            else {
-                                // we read an escape, but the buffer is drained, so we need to
-                                // remember this
-                                self.read_incomplete_escape = true;
-                                break;
-                            }
-                        }
-
-                        // payload byte
-                        if packet_type.is_known() {
-                            self.push_byte(byte);
-                        }
-                    }
-
-                    if let Some(expected_length) = packet_type.expected_length() {
-                        assert!(self.buffer_write_pos <= expected_length);
-
-                        if self.buffer_write_pos == expected_length {
-                            if let Some(packet) = self.emit_packet(None)? {
-                                return Ok(Some(packet));
-                            }
-                        }
-                    }
-                }
-                else {
-                    // if we read an escape here, this is a double escape, meaning we're reading
-                    // garbage
-                    if byte == ESCAPE {
-                        todo!("expected packet type, but read escape -> garbage");
-                    }
-
-                    // we didn't read the packet type yet, so this byte is it.
-                    self.packet_type = Some(OutputPacketType::from_byte(byte));
-                }
-            }
-            else if byte == ESCAPE {
-                // we didn't receive a packet escape yet, but the current byte is one.
-                self.leading_escape_read = true;
-                self.packet_type = None;
-                self.buffer_write_pos = 0;
-            }
-            else {
-                // we didn't receive a packet escape yet, and the current byte isn't one.
-                // this is a protocol error.
-                todo!("garbage");
-                // todo: we might want to return a specific (recoverable) error
-            }
+impl<R> BlockingReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            state: Default::default(),
         }
+    }
+}
 
-        Ok(None)
+impl<R: std::io::Read> Iterator for BlockingReader<R> {
+    type Item = Result<OutputPacket, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.state.read_next_packet(&mut self.reader)
     }
+}
 
-    #[inline(always)]
-    fn push_byte(&mut self, byte: u8) {
-        self.buffer[self.buffer_write_pos] = byte;
-        self.buffer_write_pos += 1;
+pin_project! {
+    /// Writes BEAST output packets.
+    ///
+    /// Packets are buffered, so make sure to flush the sink.
+    #[derive(Debug)]
+    pub struct Writer<W> {
+        #[pin]
+        writer: W,
+        write_buffer: BytesMut,
     }
+}
 
-    #[inline(always)]
-    fn emit_packet(
-        &mut self,
-        next_packet_type: Option<OutputPacketType>,
-    ) -> Result<Option<OutputPacket>, Error> {
-        assert!(!self.read_incomplete_escape);
-        assert!(self.leading_escape_read);
+impl<W> Writer<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            write_buffer: BytesMut::with_capacity(WRITE_BUFFER_SIZE),
+        }
+    }
 
-        if let Some(packet_type) = self.packet_type {
-            if let Some(expected_length) = packet_type.expected_length() {
-                if self.buffer_write_pos != expected_length {
-                    todo!("error, invalid length");
-                }
-            }
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
 
-            let mut buffer = &self.buffer[..self.buffer_write_pos];
-            tracing::trace!(?buffer, len = buffer.len(), "decode packet");
-            let packet = packet_type.decode(&mut buffer);
+impl<W: AsyncWrite> Sink<OutputPacket> for Writer<W> {
+    type Error = Error;
 
-            // reset decoder state
-            self.leading_escape_read = false;
-            self.packet_type = next_packet_type;
-            self.buffer_write_pos = 0;
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
 
-            Ok(packet)
+        if this.write_buffer.len() >= WRITE_BUFFER_SIZE {
+            poll_write_buffer(this.writer, this.write_buffer, cx).map_err(Into::into)
         }
         else {
-            Ok(None)
+            Poll::Ready(Ok(()))
         }
     }
-}
 
-impl Default for PacketDecoder {
-    fn default() -> Self {
-        Self {
-            leading_escape_read: false,
-            packet_type: None,
-            buffer: [0; PACKET_BUFFER_SIZE],
-            buffer_write_pos: 0,
-            read_incomplete_escape: false,
+    fn start_send(self: Pin<&mut Self>, item: OutputPacket) -> Result<(), Self::Error> {
+        let this = self.project();
+        encode_escaped(&item.packet_type(), &item, this.write_buffer);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+
+        match poll_write_buffer(this.writer.as_mut(), this.write_buffer, cx) {
+            Poll::Ready(Ok(())) => this.writer.poll_flush(cx).map_err(Into::into),
+            Poll::Ready(Err(error)) => Poll::Ready(Err(error.into())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.as_mut().poll_flush(cx) {
+            Poll::Ready(Ok(())) => self.project().writer.poll_shutdown(cx).map_err(Into::into),
+            poll => poll,
         }
     }
 }
@@ -447,7 +620,8 @@ impl Default for PacketDecoder {
 pin_project! {
     /// Keeps track of the receiver ID.
     ///
-    /// This will consume any [`OutputPacket::ReceiverId`] packets from the
+    /// This will consume any [`OutputPacket::ReceiverId`] and
+    /// [`OutputPacket::ReceiverUuid`] packets from the
     /// underlying stream and yield all other packets together with the last
     /// received receiver ID.
     #[derive(Debug)]
@@ -477,7 +651,10 @@ impl<R: AsyncRead> Stream for ReaderWithReceiverId<R> {
                 Poll::Pending => return Poll::Pending,
                 Poll::Ready(Some(Err(error))) => return Poll::Ready(Some(Err(error))),
                 Poll::Ready(None) => return Poll::Ready(None),
-                Poll::Ready(Some(Ok(OutputPacket::ReceiverId { receiver_id: id }))) => {
+                Poll::Ready(Some(Ok(
+                    OutputPacket::ReceiverId { receiver_id: id }
+                    | OutputPacket::ReceiverUuid { receiver_id: id },
+                ))) => {
                     *this.receiver_id = Some(id);
                 }
                 Poll::Ready(Some(Ok(packet))) => {
@@ -487,3 +664,213 @@ impl<R: AsyncRead> Stream for ReaderWithReceiverId<R> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{
+        SinkExt,
+        TryStreamExt,
+    };
+    use uuid::Uuid;
+
+    use crate::{
+        MlatTimestamp,
+        SignalLevel,
+        TimestampFormat,
+        output::{
+            BlockingReader,
+            Decoder,
+            HulcMessage,
+            HulcStatus,
+            OutputPacket,
+            Reader,
+            ReaderWithReceiverId,
+            Writer,
+        },
+    };
+
+    async fn round_trip(packets: Vec<OutputPacket>) -> (Vec<u8>, Vec<OutputPacket>) {
+        let mut writer = Writer::new(vec![]);
+        for packet in &packets {
+            writer.feed(packet.clone()).await.unwrap();
+        }
+        writer.close().await.unwrap();
+        let encoded = writer.into_inner();
+
+        let decoded = Reader::new(&encoded[..])
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        (encoded, decoded)
+    }
+
+    #[tokio::test]
+    async fn it_round_trips() {
+        let packets = vec![
+            OutputPacket::ModeAc {
+                timestamp: MlatTimestamp([0, 1, 2, 3, 4, 5]),
+                signal_level: SignalLevel(0x80),
+                data: [0x12, 0x34],
+            },
+            OutputPacket::ModeSShort {
+                timestamp: MlatTimestamp::SYNTHETIC_MLAT,
+                signal_level: SignalLevel(0xff),
+                data: *b"\x5d\x3c\x66\x4d\xb2\x9a\x6e",
+            },
+            OutputPacket::ModeSLong {
+                timestamp: MlatTimestamp([0x1a; 6]),
+                signal_level: SignalLevel(0x1a),
+                data: *b"\x8d\xa3\xd4\x25\x99\x25\x01\x29\x78\x04\x84\x71\x2c\x1a",
+            },
+            OutputPacket::DipSwitches(0x1a),
+            OutputPacket::ReceiverId {
+                receiver_id: Uuid::from_u64_pair(0x1a2b3c4d5e6f1a1a, 0),
+            },
+        ];
+
+        let (_, decoded) = round_trip(packets.clone()).await;
+        assert_eq!(decoded, packets);
+    }
+
+    #[tokio::test]
+    async fn it_escapes_escape_bytes() {
+        let (encoded, _) = round_trip(vec![OutputPacket::DipSwitches(0x1a)]).await;
+        assert_eq!(encoded, b"\x1a\x34\x1a\x1a");
+    }
+
+    #[tokio::test]
+    async fn it_round_trips_extensions() {
+        let packets = vec![
+            OutputPacket::ReceiverUuid {
+                receiver_id: "0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0".parse().unwrap(),
+            },
+            OutputPacket::RadarcapePosition {
+                latitude: 52.5,
+                longitude: 13.25,
+                altitude: 34.0,
+            },
+            OutputPacket::Ping { data: [1, 0x1a, 3] },
+            OutputPacket::Hulc(HulcMessage::Other {
+                message_id: 0x1a,
+                data: b"\x00\x1a\x1a\x01"[..].into(),
+            }),
+            OutputPacket::ReceiverUuid {
+                receiver_id: "00000000-0000-0000-0000-00000000001a".parse().unwrap(),
+            },
+        ];
+
+        let (_, decoded) = round_trip(packets.clone()).await;
+        assert_eq!(decoded, packets);
+    }
+
+    #[tokio::test]
+    async fn it_decodes_hulc_status() {
+        // serial 0x0001e240, GPS detected, 2025-06-15 12:00:00, 9 satellites,
+        // HDOP 12. The 0x1a in the latitude is escaped.
+        let data = b"\x1aH\x01\x18\x00\x01\xe2\x40\x80\x01\x00\x00\x68\x4e\xb5\xc0\x1f\x4a\x3c\x1a\x1a\x07\xe8\x9d\x80\x00\x22\x09\x0c";
+        let decoded = Reader::new(&data[..])
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        let [OutputPacket::Hulc(HulcMessage::Status(status))] = &decoded[..]
+        else {
+            panic!("unexpected packets: {decoded:?}");
+        };
+        assert_eq!(status.serial_number, 123456);
+        assert!(status.gps_detected());
+        assert_eq!(status.timestamp_format(), TimestampFormat::GpsTimeOfDay);
+        assert_eq!(status.epoch, 1749988800);
+        assert_eq!(status.latitude, 524958746);
+        assert_eq!(status.longitude, 132685184);
+        assert_eq!(status.altitude, 34);
+        assert_eq!(status.satellites, 9);
+        assert_eq!(status.hdop, 12);
+
+        let (encoded, _) = round_trip(decoded.clone()).await;
+        assert_eq!(encoded, data);
+
+        let without_gps = HulcStatus {
+            flags: 0,
+            ..*status
+        };
+        assert_eq!(
+            without_gps.timestamp_format(),
+            TimestampFormat::Counter12Mhz
+        );
+    }
+
+    #[tokio::test]
+    async fn it_skips_unknown_packets_and_garbage() {
+        let data = b"\x00\x01\x1a\xe8\x01\x1a\x1a\x02\x1a\x34\x05\x1a\x1a\x1a\x34\x06";
+        let decoded = Reader::new(&data[..])
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(
+            decoded,
+            [OutputPacket::DipSwitches(5), OutputPacket::DipSwitches(6)]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_tracks_receiver_uuids() {
+        let receiver_id: Uuid = "0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0".parse().unwrap();
+        let (encoded, _) = round_trip(vec![
+            OutputPacket::DipSwitches(1),
+            OutputPacket::ReceiverUuid { receiver_id },
+            OutputPacket::DipSwitches(2),
+        ])
+        .await;
+
+        let decoded = ReaderWithReceiverId::new(&encoded[..])
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(
+            decoded,
+            [
+                (None, OutputPacket::DipSwitches(1)),
+                (Some(receiver_id), OutputPacket::DipSwitches(2))
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_decodes_in_chunks() {
+        let packets = vec![
+            OutputPacket::DipSwitches(0x1a),
+            OutputPacket::ModeSShort {
+                timestamp: MlatTimestamp([0x1a; 6]),
+                signal_level: SignalLevel(0x1a),
+                data: *b"\x5d\x3c\x66\x4d\xb2\x9a\x6e",
+            },
+            OutputPacket::ReceiverUuid {
+                receiver_id: "0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0".parse().unwrap(),
+            },
+        ];
+        let (encoded, _) = round_trip(packets.clone()).await;
+
+        let mut decoder = Decoder::default();
+        let mut decoded = vec![];
+        for chunk in encoded.chunks(1) {
+            decoded.extend(decoder.decode_all(chunk));
+        }
+        decoded.extend(decoder.finish());
+        assert_eq!(decoded, packets);
+    }
+
+    #[tokio::test]
+    async fn it_reads_blocking() {
+        let packets = vec![
+            OutputPacket::DipSwitches(1),
+            OutputPacket::Ping { data: [1, 0x1a, 3] },
+        ];
+        let (encoded, _) = round_trip(packets.clone()).await;
+
+        let decoded = BlockingReader::new(&encoded[..])
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(decoded, packets);
+    }
+}
@@ -1,4 +1,37 @@
-use bytes::Buf;
+use std::{
+    fmt::Debug,
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+    },
+};
+
+use bytes::{
+    Buf,
+    BufMut,
+    BytesMut,
+};
+use tokio::io::{
+    AsyncRead,
+    AsyncWrite,
+    ReadBuf,
+};
+
+use crate::{
+    ESCAPE,
+    Error,
+    PacketDecode,
+    PacketEncode,
+};
+
+/// this can be larger for more efficient reads, although the underlying reader
+/// is probably buffered.
+const RECEIVE_BUFFER_SIZE: usize = 512;
+
+/// this only needs to be able to hold any packet we decode. the largest are
+/// HULC messages with up to 255 bytes of data.
+const PACKET_BUFFER_SIZE: usize = 260;
 
 pub trait BufReadBytesExt {
     fn get_bytes<const N: usize>(&mut self) -> [u8; N];
@@ -11,3 +44,346 @@ impl<B: Buf> BufReadBytesExt for B {
         data
     }
 }
+
+/// Encodes a packet with leading escape and packet type, and escapes any
+/// [`ESCAPE`] bytes in the payload.
+pub fn encode_escaped<T: PacketEncode>(packet_type: &T, packet: &T::Packet, buffer: &mut BytesMut) {
+    let mut payload = BytesMut::new();
+    packet_type.encode(packet, &mut payload);
+
+    buffer.reserve(2 + 2 * payload.len());
+    buffer.put_u8(ESCAPE);
+    buffer.put_u8(packet_type.to_byte());
+    for byte in payload {
+        if byte == ESCAPE {
+            buffer.put_u8(ESCAPE);
+        }
+        buffer.put_u8(byte);
+    }
+}
+
+/// Writes the buffer to the writer until it's empty.
+pub fn poll_write_buffer<W: AsyncWrite>(
+    mut writer: Pin<&mut W>,
+    buffer: &mut BytesMut,
+    cx: &mut Context<'_>,
+) -> Poll<Result<(), std::io::Error>> {
+    while !buffer.is_empty() {
+        match writer.as_mut().poll_write(cx, buffer) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(Err(error)) => return Poll::Ready(Err(error)),
+            Poll::Ready(Ok(0)) => return Poll::Ready(Err(std::io::ErrorKind::WriteZero.into())),
+            Poll::Ready(Ok(num_bytes_written)) => buffer.advance(num_bytes_written),
+        }
+    }
+
+    Poll::Ready(Ok(()))
+}
+
+/// Receive buffer and decoder shared by the readers.
+///
+/// The readers only need to provide a function that reads into the buffer,
+/// which makes it easy to support different I/O traits.
+#[derive(Debug)]
+pub struct ReadState<T> {
+    buffer: [u8; RECEIVE_BUFFER_SIZE],
+    read_pos: usize,
+    write_pos: usize,
+    decoder: Decoder<T>,
+}
+
+impl<T> Default for ReadState<T> {
+    fn default() -> Self {
+        Self {
+            buffer: [0; RECEIVE_BUFFER_SIZE],
+            read_pos: 0,
+            write_pos: 0,
+            decoder: Default::default(),
+        }
+    }
+}
+
+impl<T: PacketDecode + Copy + Debug> ReadState<T> {
+    /// Reads using `read` until a packet is decoded.
+    ///
+    /// `read` reads into the buffer it's given and returns the number of bytes
+    /// read. 0 bytes read means the underlying reader reached EOF.
+    pub fn poll_next_packet(
+        &mut self,
+        mut read: impl FnMut(&mut [u8]) -> Poll<Result<usize, std::io::Error>>,
+    ) -> Poll<Option<Result<T::Packet, Error>>> {
+        loop {
+            if self.read_pos < self.write_pos {
+                let mut data = &self.buffer[self.read_pos..self.write_pos];
+                let packet = self.decoder.decode(&mut data);
+                self.read_pos = self.write_pos - data.len();
+
+                if let Some(packet) = packet {
+                    return Poll::Ready(Some(Ok(packet)));
+                }
+            }
+            else {
+                // if there is no data in the receiver buffer, we need to receive some
+                match read(&mut self.buffer) {
+                    Poll::Pending => return Poll::Pending,
+                    Poll::Ready(Err(error)) => return Poll::Ready(Some(Err(error.into()))),
+                    Poll::Ready(Ok(0)) => {
+                        // the underlying reader reached EOF. a packet without fixed length
+                        // might still be pending.
+                        return Poll::Ready(self.decoder.finish().map(Ok));
+                    }
+                    Poll::Ready(Ok(num_bytes_read)) => {
+                        self.read_pos = 0;
+                        self.write_pos = num_bytes_read;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads from a blocking reader until a packet is decoded.
+    pub fn read_next_packet<R: std::io::Read>(
+        &mut self,
+        reader: &mut R,
+    ) -> Option<Result<T::Packet, Error>> {
+        let poll = self.poll_next_packet(|buffer| {
+            loop {
+                match reader.read(buffer) {
+                    Err(error) if error.kind() == std::io::ErrorKind::Interrupted => {}
+                    result => return Poll::Ready(result),
+                }
+            }
+        });
+
+        match poll {
+            Poll::Ready(result) => result,
+            Poll::Pending => unreachable!("blocking reads are never pending"),
+        }
+    }
+}
+
+/// Reads from a tokio reader into `buffer`.
+pub fn poll_read_tokio<R: AsyncRead>(
+    reader: Pin<&mut R>,
+    cx: &mut Context<'_>,
+    buffer: &mut [u8],
+) -> Poll<Result<usize, std::io::Error>> {
+    let mut read_buf = ReadBuf::new(buffer);
+    reader
+        .poll_read(cx, &mut read_buf)
+        .map_ok(|()| read_buf.filled().len())
+}
+
+/// Incremental decoder that doesn't do any I/O.
+///
+/// Data can be passed in chunks of any size. Partially received packets are
+/// kept until the rest of the packet is passed in.
+#[derive(Debug)]
+pub struct Decoder<T> {
+    leading_escape_read: bool,
+    packet_type: Option<T>,
+    buffer: [u8; PACKET_BUFFER_SIZE],
+    buffer_write_pos: usize,
+    read_incomplete_escape: bool,
+    /// the packet didn't fit into the buffer
+    overflow: bool,
+}
+
+impl<T: PacketDecode + Copy + Debug> Decoder<T> {
+    /// Decodes the next packet from `data`, and advances it past the consumed
+    /// bytes.
+    ///
+    /// Returns `None` once all of `data` has been consumed without completing
+    /// a packet. The next invocation of this method will resume decoding.
+    pub fn decode(&mut self, data: &mut &[u8]) -> Option<T::Packet> {
+        while let Some(byte) = next_byte(data) {
+            if self.leading_escape_read {
+                // we already read the packet escape
+
+                if let Some(packet_type) = self.packet_type {
+                    // we already read the packet type
+
+                    if self.read_incomplete_escape {
+                        // we read an escape before, but we don't know what follows yet.
+                        // note: this whole block only handles the case that we read an escape at
+                        // the end of the buffer earlier.
+                        self.read_incomplete_escape = false;
+
+                        if byte == ESCAPE {
+                            // double escape
+                            if packet_type.is_known() {
+                                self.push_byte(ESCAPE);
+                            }
+                        }
+                        else {
+                            // the escape we read was the start of a new packet
+                            self.leading_escape_read = true;
+
+                            if let Some(packet) = self.emit_packet(Some(T::from_byte(byte))) {
+                                return Some(packet);
+                            }
+                        }
+                    }
+                    else {
+                        if byte == ESCAPE {
+                            let Some(next_byte) = next_byte(data)
+                            else {
+                                // we read an escape, but the buffer is drained, so we need to
+                                // remember this
+                                self.read_incomplete_escape = true;
+                                break;
+                            };
+
+                            if next_byte != ESCAPE {
+                                // the escape is the start of a new packet. this is how packets
+                                // without a fixed length end.
+                                if let Some(packet) =
+                                    self.emit_packet(Some(T::from_byte(next_byte)))
+                                {
+                                    return Some(packet);
+                                }
+                                continue;
+                            }
+                        }
+
+                        // payload byte
+                        if packet_type.is_known() {
+                            self.push_byte(byte);
+                        }
+                    }
+
+                    if let Some(expected_length) = packet_type.expected_length() {
+                        assert!(self.buffer_write_pos <= expected_length);
+
+                        if self.buffer_write_pos == expected_length {
+                            if let Some(packet) = self.emit_packet(None) {
+                                return Some(packet);
+                            }
+                        }
+                    }
+                }
+                else {
+                    // if we read an escape here, this is a double escape, meaning we're reading
+                    // garbage. skip until the next packet escape.
+                    if byte == ESCAPE {
+                        tracing::debug!("beast: expected packet type, but read escape");
+                        self.leading_escape_read = false;
+                        continue;
+                    }
+                    // we didn't read the packet type yet, so this byte is it.
+                    self.packet_type = Some(T::from_byte(byte));
+                    self.overflow = false;
+                }
+            }
+            else if byte == ESCAPE {
+                // we didn't receive a packet escape yet, but the current byte is one.
+                self.leading_escape_read = true;
+                self.packet_type = None;
+                self.buffer_write_pos = 0;
+            }
+            else {
+                // we didn't receive a packet escape yet, and the current byte isn't one.
+                // this is a protocol error, but we can just skip it.
+                // todo: we might want to return a specific (recoverable) error
+                tracing::trace!("beast: garbage byte: 0x{byte:02x}");
+            }
+        }
+
+        None
+    }
+
+    /// Decodes all packets in `data`.
+    pub fn decode_all<'a>(
+        &'a mut self,
+        mut data: &'a [u8],
+    ) -> impl Iterator<Item = T::Packet> + 'a {
+        std::iter::from_fn(move || self.decode(&mut data))
+    }
+
+    #[inline(always)]
+    fn push_byte(&mut self, byte: u8) {
+        if self.buffer_write_pos < PACKET_BUFFER_SIZE {
+            self.buffer[self.buffer_write_pos] = byte;
+            self.buffer_write_pos += 1;
+        }
+        else {
+            self.overflow = true;
+        }
+    }
+
+    /// Emits a pending packet without fixed length at the end of the stream.
+    ///
+    /// Packets without fixed length end with the start of the next packet, so
+    /// the last one can only be emitted once it's known that no more data
+    /// follows.
+    pub fn finish(&mut self) -> Option<T::Packet> {
+        let packet_type = self.packet_type?;
+        if !self.leading_escape_read
+            || self.read_incomplete_escape
+            || packet_type.expected_length().is_some()
+        {
+            return None;
+        }
+        self.emit_packet(None)
+    }
+
+    #[inline(always)]
+    fn emit_packet(&mut self, next_packet_type: Option<T>) -> Option<T::Packet> {
+        assert!(!self.read_incomplete_escape);
+        assert!(self.leading_escape_read);
+
+        if let Some(packet_type) = self.packet_type {
+            let valid_length = packet_type
+                .expected_length()
+                .is_none_or(|expected_length| self.buffer_write_pos == expected_length);
+
+            let packet = if !packet_type.is_known() {
+                packet_type.decode(&mut &[][..])
+            }
+            else if self.overflow || !valid_length {
+                tracing::debug!(
+                    ?packet_type,
+                    length = self.buffer_write_pos,
+                    overflow = self.overflow,
+                    "beast: invalid packet length"
+                );
+                None
+            }
+            else {
+                let mut buffer = &self.buffer[..self.buffer_write_pos];
+                tracing::trace!(?buffer, len = buffer.len(), "decode packet");
+                packet_type.decode(&mut buffer)
+            };
+
+            // reset decoder state
+            self.leading_escape_read = next_packet_type.is_some();
+            self.packet_type = next_packet_type;
+            self.buffer_write_pos = 0;
+            self.overflow = false;
+            packet
+        }
+        else {
+            None
+        }
+    }
+}
+
+#[inline(always)]
+fn next_byte(data: &mut &[u8]) -> Option<u8> {
+    let (byte, rest) = data.split_first()?;
+    *data = rest;
+    Some(*byte)
+}
+
+impl<T> Default for Decoder<T> {
+    fn default() -> Self {
+        Self {
+            leading_escape_read: false,
+            packet_type: None,
+            buffer: [0; PACKET_BUFFER_SIZE],
+            buffer_write_pos: 0,
+            read_incomplete_escape: false,
+            overflow: false,
+        }
+    }
+}
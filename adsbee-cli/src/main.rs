@@ -14,14 +14,47 @@ use std::{
 
 use adsbee_api_client::ApiClient;
 use adsbee_api_server::{
+    airline::AirlineTable,
     api::Api,
     database::Database,
+    metrics::Metrics,
+    output::{
+        self,
+        asterix::{
+            self,
+            AsterixServerConfig,
+        },
+        beast::{
+            BeastServerConfig,
+            ClientOptions,
+        },
+        cot::{
+            self,
+            CotServerConfig,
+            CotTransport,
+        },
+        gdl90::{
+            self,
+            Gdl90ServerConfig,
+        },
+        mqtt::{
+            self,
+            MqttConfig,
+            MqttQos,
+            MqttTopics,
+        },
+        sbs::SbsServerConfig,
+    },
     source::{
         history::index_archive_day_from_directory,
         tar1090_db::update_aircraft_db,
     },
     tracker::{
         Tracker,
+        alerts::{
+            self,
+            AlertsConfig,
+        },
         state::State,
     },
 };
@@ -29,13 +62,17 @@ use adsbee_api_types::{
     flights::AircraftQuery,
     live::SubscriptionFilter,
 };
+use adsbee_avr as avr;
 use adsbee_beast as beast;
 use adsbee_mode_s as mode_s;
 use adsbee_rtlsdr as rtlsdr;
 use adsbee_sbs as sbs;
 use adsbee_types::{
+    AirlineDesignator,
+    Callsign,
     IcaoAddress,
     Squawk,
+    geo::LatLon,
 };
 use byteorder::{
     BigEndian,
@@ -58,9 +95,11 @@ use futures_util::{
 use tokio::{
     io::{
         AsyncRead,
+        AsyncWrite,
         BufReader,
     },
     net::TcpStream,
+    task::JoinSet,
 };
 use uuid::Uuid;
 
@@ -87,14 +126,118 @@ async fn main() -> Result<(), Error> {
         Command::Serve {
             database_url,
             listen_address,
+            airlines,
+            qnh,
+            alerts,
+            beast_output,
+            beast_receiver_id,
+            beast_reduce,
+            sbs_output,
+            gdl90,
+            asterix,
+            cot,
+            mqtt,
         } => {
             let database = Database::connect(&database_url).await?;
-            let api = Api::new(Default::default(), database, Tracker::new());
-            api.serve(listen_address).await?;
+            let tracker = Tracker::new();
+            if qnh.is_some() {
+                tracker.set_qnh(qnh).await;
+            }
+            let mut api =
+                Api::new(Default::default(), database, tracker).with_metrics(Metrics::install()?);
+            if let Some(airlines) = airlines {
+                api = api.with_airlines(AirlineTable::from_path(airlines)?);
+            }
+            // outputs run until shutdown, so any of them returning early is an
+            // error
+            let mut outputs = JoinSet::new();
+            if let Some(alerts) = alerts {
+                let config = AlertsConfig::from_path(alerts)?;
+                let tagged =
+                    alerts::tagged_aircraft(&config.rules, &mut api.database.transaction().await?)
+                        .await?;
+                api.tracker.set_alert_rules(config.rules, tagged).await;
+                for webhook in config.webhooks {
+                    outputs.spawn(output::webhook::serve(
+                        webhook,
+                        api.tracker.clone(),
+                        api.shutdown.clone(),
+                    ));
+                }
+            }
+            if let Some(listen_address) = beast_output {
+                outputs.spawn(output::beast::serve(
+                    BeastServerConfig {
+                        listen_address,
+                        client_options: ClientOptions {
+                            receiver_id: beast_receiver_id,
+                            reduce: beast_reduce,
+                            ..Default::default()
+                        },
+                        reduce: Default::default(),
+                    },
+                    api.tracker.clone(),
+                    api.shutdown.clone(),
+                ));
+            }
+            if let Some(listen_address) = sbs_output {
+                outputs.spawn(output::sbs::serve(
+                    SbsServerConfig { listen_address },
+                    api.tracker.clone(),
+                    api.shutdown.clone(),
+                ));
+            }
+            if let Some(config) = gdl90.config() {
+                outputs.spawn(output::gdl90::serve(
+                    config,
+                    api.tracker.clone(),
+                    api.shutdown.clone(),
+                ));
+            }
+            if let Some(config) = asterix.config() {
+                outputs.spawn(output::asterix::serve(
+                    config,
+                    api.tracker.clone(),
+                    api.shutdown.clone(),
+                ));
+            }
+            if let Some(config) = cot.config() {
+                let military = api
+                    .database
+                    .transaction()
+                    .await?
+                    .get_tagged_aircraft("military")
+                    .await?;
+                outputs.spawn(output::cot::serve(
+                    config,
+                    api.tracker.clone(),
+                    military,
+                    api.shutdown.clone(),
+                ));
+            }
+            if let Some(config) = mqtt.config() {
+                outputs.spawn(output::mqtt::serve(
+                    config,
+                    api.tracker.clone(),
+                    api.shutdown.clone(),
+                ));
+            }
+            let result = tokio::select! {
+                result = api.serve(listen_address) => result.map_err(Error::from),
+                Some(result) = outputs.join_next() => {
+                    result.map_err(Error::from).and_then(|result| result.map_err(Error::from))
+                }
+            };
+            api.shutdown.cancel();
+            result?;
+            while let Some(result) = outputs.join_next().await {
+                result??;
+            }
         }
         Command::Live {
             icao,
             callsign,
+            airline,
             squawk,
         } => {
             let api = ApiClient::from_url("https://localhost:8080".parse().unwrap());
@@ -105,6 +248,7 @@ async fn main() -> Result<(), Error> {
                     aircraft: AircraftQuery {
                         icao,
                         callsign,
+                        airline,
                         squawk,
                     },
                     area: vec![],
@@ -126,33 +270,67 @@ async fn main() -> Result<(), Error> {
             )
             .await?;
         }
-        Command::BeastClient(args) => {
+        Command::AvrClient(args) => {
             let mut frame_processor = FrameProcessor::default();
-
-            //todo!();
-            // todo: try sending:
-            // P
-            // 5 heartbeats
-            // WO
-            args.run(beast::output::Reader::new, |_i, packet| {
-                match packet {
-                    beast::output::OutputPacket::ModeAc { data, .. } => {
-                        println!("modeac: {data:?}");
-                    }
-                    beast::output::OutputPacket::ModeSLong { data, .. } => {
-                        frame_processor.handle_mode_s_data(&data);
-                    }
-                    beast::output::OutputPacket::ModeSShort { data, .. } => {
-                        frame_processor.handle_mode_s_data(&data);
+            args.run(
+                |connection| avr::Reader::new(connection),
+                |_i, frame| {
+                    match frame.data {
+                        avr::FrameData::ModeAc(data) => println!("modeac: {data:?}"),
+                        avr::FrameData::ModeSShort(data) => {
+                            frame_processor.handle_mode_s_data(&data);
+                        }
+                        avr::FrameData::ModeSLong(data) => {
+                            frame_processor.handle_mode_s_data(&data);
+                        }
                     }
-                    _ => todo!("{packet:?}"),
-                }
-                Ok::<(), Error>(())
-            })
+                    Ok::<(), Error>(())
+                },
+            )
             .await?;
-
             frame_processor.finish();
         }
+        Command::BeastClient(args) => {
+            if let Some((input, output)) = args.client.connect().await? {
+                let mut frame_processor = FrameProcessor::default();
+
+                // keep the writer around, so the connection stays open for writing
+                let _writer = if let Some(output) = output {
+                    let mut writer = beast::input::Writer::new(output);
+                    writer.configure(&args.configuration()).await?;
+                    Some(writer)
+                }
+                else {
+                    None
+                };
+
+                args.client
+                    .process(
+                        beast::output::Reader::new(BufReader::new(input)),
+                        |_i, packet| {
+                            match packet {
+                                beast::output::OutputPacket::ModeAc { data, .. } => {
+                                    println!("modeac: {data:?}");
+                                }
+                                beast::output::OutputPacket::ModeSLong { data, .. } => {
+                                    frame_processor.handle_mode_s_data(&data);
+                                }
+                                beast::output::OutputPacket::ModeSShort { data, .. } => {
+                                    frame_processor.handle_mode_s_data(&data);
+                                }
+                                beast::output::OutputPacket::DipSwitches(dip_switches) => {
+                                    println!("dip switches: {dip_switches:08b}");
+                                }
+                                _ => println!("{packet:?}"),
+                            }
+                            Ok::<(), Error>(())
+                        },
+                    )
+                    .await?;
+
+                frame_processor.finish();
+            }
+        }
         Command::RtlSdr {
             dump,
             address,
@@ -261,19 +439,57 @@ enum Command {
 
         #[clap(short, long, default_value = "localhost:8080")]
         listen_address: String,
+
+        /// CSV file with airlines (icao,name,callsign,country)
+        #[clap(long)]
+        airlines: Option<PathBuf>,
+
+        /// Local QNH in hPa, used to correct pressure altitudes
+        #[clap(long)]
+        qnh: Option<f64>,
+
+        /// JSON file with alert rules and the webhooks to deliver their alerts
+        /// to
+        #[clap(long)]
+        alerts: Option<PathBuf>,
+
+        /// Serve BEAST output on this address
+        #[clap(long)]
+        beast_output: Option<String>,
+        /// Send the receiver ID before each frame on the BEAST output
+        #[clap(long)]
+        beast_receiver_id: bool,
+        /// Only forward as many frames on the BEAST output as a map needs
+        #[clap(long)]
+        beast_reduce: bool,
+        /// Serve SBS (BaseStation) output on this address
+        #[clap(long)]
+        sbs_output: Option<String>,
+        #[clap(flatten)]
+        gdl90: Box<Gdl90Args>,
+        #[clap(flatten)]
+        asterix: Box<AsterixArgs>,
+        #[clap(flatten)]
+        cot: Box<CotArgs>,
+        #[clap(flatten)]
+        mqtt: Box<MqttArgs>,
     },
     Live {
         #[clap(short, long)]
         icao: Vec<IcaoAddress>,
 
         #[clap(short, long)]
-        callsign: Vec<String>,
+        callsign: Vec<Callsign>,
+
+        #[clap(short, long)]
+        airline: Vec<AirlineDesignator>,
 
         #[clap(short, long)]
         squawk: Vec<Squawk>,
     },
     SbsClient(ClientTestArgs),
-    BeastClient(ClientTestArgs),
+    AvrClient(ClientTestArgs),
+    BeastClient(BeastClientArgs),
     RtlSdr {
         /// Dump all verified frames to file.
         #[clap(short = 'D', long)]
@@ -308,21 +524,42 @@ struct ClientTestArgs {
 }
 
 impl ClientTestArgs {
-    pub async fn run<T, F, R, E1, P, E2>(&self, f: F, mut p: P) -> Result<(), Error>
+    pub async fn run<T, F, R, E1, P, E2>(&self, f: F, p: P) -> Result<(), Error>
     where
         F: FnOnce(BufReader<Pin<Box<dyn AsyncRead>>>) -> R,
         R: Stream<Item = Result<T, E1>>,
         Error: From<E1> + From<E2>,
         P: FnMut(usize, T) -> Result<(), E2>,
     {
-        let input: Pin<Box<dyn AsyncRead>> = match (&self.address, &self.file) {
-            (Some(address), None) => Box::pin(TcpStream::connect(&address).await?),
-            (None, Some(file)) => Box::pin(tokio::fs::File::open(&file).await?),
-            (Some(_), Some(_)) => bail!("Only one of --address or --file can be used."),
-            (None, None) => return Ok(()),
-        };
+        if let Some((input, _output)) = self.connect().await? {
+            self.process(f(BufReader::new(input)), p).await?;
+        }
+        Ok(())
+    }
+
+    /// Opens the input. The output is only available for TCP connections.
+    pub async fn connect(
+        &self,
+    ) -> Result<Option<(Pin<Box<dyn AsyncRead>>, Option<Pin<Box<dyn AsyncWrite>>>)>, Error> {
+        let connection: (Pin<Box<dyn AsyncRead>>, Option<Pin<Box<dyn AsyncWrite>>>) =
+            match (&self.address, &self.file) {
+                (Some(address), None) => {
+                    let (input, output) = TcpStream::connect(&address).await?.into_split();
+                    (Box::pin(input), Some(Box::pin(output)))
+                }
+                (None, Some(file)) => (Box::pin(tokio::fs::File::open(&file).await?), None),
+                (Some(_), Some(_)) => bail!("Only one of --address or --file can be used."),
+                (None, None) => return Ok(None),
+            };
+        Ok(Some(connection))
+    }
 
-        let reader = f(BufReader::new(input));
+    pub async fn process<T, R, E1, P, E2>(&self, reader: R, mut p: P) -> Result<(), Error>
+    where
+        R: Stream<Item = Result<T, E1>>,
+        Error: From<E1> + From<E2>,
+        P: FnMut(usize, T) -> Result<(), E2>,
+    {
         pin_mut!(reader);
 
         let mut i = 0;
@@ -338,6 +575,239 @@ impl ClientTestArgs {
     }
 }
 
+#[derive(Debug, clap::Args)]
+struct BeastClientArgs {
+    #[clap(flatten)]
+    client: ClientTestArgs,
+
+    /// Enable Mode A/C
+    #[clap(long)]
+    mode_ac: bool,
+
+    /// Disable MLAT timestamps
+    #[clap(long)]
+    no_timestamps: bool,
+
+    /// Disable CRC check
+    #[clap(long)]
+    no_crc_check: bool,
+}
+
+impl BeastClientArgs {
+    fn configuration(&self) -> beast::input::Configuration {
+        beast::input::Configuration {
+            mlat_timestamps: !self.no_timestamps,
+            crc_check: !self.no_crc_check,
+            mode_ac: self.mode_ac,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+struct Gdl90Args {
+    /// Send GDL90 traffic to this address, e.g. `255.255.255.255:4000`
+    #[clap(long, requires = "gdl90_position")]
+    gdl90_output: Option<String>,
+
+    /// Position GDL90 traffic is filtered around, as `latitude,longitude`
+    #[clap(long, value_parser = parse_lat_lon)]
+    gdl90_position: Option<LatLon>,
+
+    /// Geometric altitude of the GDL90 position in ft
+    #[clap(long)]
+    gdl90_altitude: Option<i32>,
+
+    /// Maximum distance of GDL90 traffic in nm
+    #[clap(long, default_value_t = gdl90::DEFAULT_MAX_RANGE)]
+    gdl90_max_range: f64,
+
+    /// Maximum altitude difference of GDL90 traffic in ft
+    #[clap(long)]
+    gdl90_max_altitude_difference: Option<i32>,
+}
+
+impl Gdl90Args {
+    fn config(&self) -> Option<Gdl90ServerConfig> {
+        Some(Gdl90ServerConfig {
+            target_address: self.gdl90_output.clone()?,
+            position: self.gdl90_position?,
+            altitude: self.gdl90_altitude,
+            max_range: self.gdl90_max_range,
+            max_altitude_difference: self.gdl90_max_altitude_difference,
+        })
+    }
+}
+
+#[derive(Debug, clap::Args)]
+struct AsterixArgs {
+    /// Send ASTERIX CAT021 target reports to this unicast or multicast
+    /// address, e.g. `239.0.0.1:8600`
+    #[clap(long, requires_all = ["asterix_sac", "asterix_sic"])]
+    asterix_output: Option<String>,
+
+    /// System area code of the ASTERIX data source
+    #[clap(long)]
+    asterix_sac: Option<u8>,
+
+    /// System identification code of the ASTERIX data source
+    #[clap(long)]
+    asterix_sic: Option<u8>,
+
+    /// Interval between ASTERIX updates in seconds
+    #[clap(long, default_value_t = output::DEFAULT_INTERVAL, value_parser = parse_positive_seconds)]
+    asterix_interval: f64,
+
+    /// Time-to-live of ASTERIX multicast datagrams
+    #[clap(long, default_value_t = asterix::DEFAULT_MULTICAST_TTL)]
+    asterix_multicast_ttl: u32,
+}
+
+impl AsterixArgs {
+    fn config(&self) -> Option<AsterixServerConfig> {
+        Some(AsterixServerConfig {
+            target_address: self.asterix_output.clone()?,
+            sac: self.asterix_sac?,
+            sic: self.asterix_sic?,
+            interval: self.asterix_interval,
+            multicast_ttl: self.asterix_multicast_ttl,
+        })
+    }
+}
+
+#[derive(Debug, clap::Args)]
+struct CotArgs {
+    /// Send CoT events as UDP datagrams to this address, e.g. `239.2.3.1:6969`
+    #[clap(long, conflicts_with = "cot_tcp_output")]
+    cot_udp_output: Option<String>,
+
+    /// Stream CoT events to TCP clients connecting to this address
+    #[clap(long)]
+    cot_tcp_output: Option<String>,
+
+    /// Interval between CoT updates in seconds
+    #[clap(long, default_value_t = output::DEFAULT_INTERVAL, value_parser = parse_positive_seconds)]
+    cot_interval: f64,
+
+    /// Time after an aircraft was last seen, after which its CoT event is
+    /// stale, in seconds
+    #[clap(long, default_value_t = cot::DEFAULT_STALE)]
+    cot_stale: f64,
+}
+
+impl CotArgs {
+    fn config(&self) -> Option<CotServerConfig> {
+        let transport = match (&self.cot_udp_output, &self.cot_tcp_output) {
+            (Some(target_address), _) => {
+                CotTransport::Udp {
+                    target_address: target_address.clone(),
+                }
+            }
+            (None, Some(listen_address)) => {
+                CotTransport::Tcp {
+                    listen_address: listen_address.clone(),
+                }
+            }
+            (None, None) => return None,
+        };
+        Some(CotServerConfig {
+            transport,
+            interval: self.cot_interval,
+            stale: self.cot_stale,
+        })
+    }
+}
+
+#[derive(Debug, clap::Args)]
+struct MqttArgs {
+    /// Publish aircraft to the MQTT broker on this host
+    #[clap(long)]
+    mqtt_host: Option<String>,
+
+    #[clap(long, default_value_t = mqtt::DEFAULT_PORT)]
+    mqtt_port: u16,
+
+    #[clap(long, default_value = mqtt::DEFAULT_CLIENT_ID)]
+    mqtt_client_id: String,
+
+    #[clap(long)]
+    mqtt_username: Option<String>,
+
+    #[clap(long, env = "MQTT_PASSWORD", hide_env_values = true)]
+    mqtt_password: Option<String>,
+
+    /// Interval between MQTT updates in seconds
+    #[clap(long, default_value_t = output::DEFAULT_INTERVAL, value_parser = parse_positive_seconds)]
+    mqtt_interval: f64,
+
+    /// Time after which aircraft that weren't seen are removed from MQTT, in
+    /// seconds
+    #[clap(long, default_value_t = mqtt::DEFAULT_EXPIRE)]
+    mqtt_expire: f64,
+
+    /// QoS to publish with: 0 (at_most_once), 1 (at_least_once) or 2
+    /// (exactly_once)
+    #[clap(long, default_value = "0")]
+    mqtt_qos: MqttQos,
+
+    /// Topic for the retained aircraft state. `{icao}` is replaced with the
+    /// aircraft's ICAO address.
+    #[clap(long, default_value = mqtt::DEFAULT_AIRCRAFT_TOPIC)]
+    mqtt_topic_aircraft: String,
+
+    /// Topic for aircraft that start squawking an emergency code
+    #[clap(long, default_value = mqtt::DEFAULT_EMERGENCY_TOPIC)]
+    mqtt_topic_emergency: String,
+
+    /// Topic for aircraft that are seen for the first time
+    #[clap(long, default_value = mqtt::DEFAULT_NEW_AIRCRAFT_TOPIC)]
+    mqtt_topic_new_aircraft: String,
+}
+
+impl MqttArgs {
+    fn config(&self) -> Option<MqttConfig> {
+        Some(MqttConfig {
+            host: self.mqtt_host.clone()?,
+            port: self.mqtt_port,
+            client_id: self.mqtt_client_id.clone(),
+            username: self.mqtt_username.clone(),
+            password: self.mqtt_password.clone(),
+            topics: MqttTopics {
+                aircraft: self.mqtt_topic_aircraft.clone(),
+                emergency: self.mqtt_topic_emergency.clone(),
+                new_aircraft: self.mqtt_topic_new_aircraft.clone(),
+            },
+            qos: self.mqtt_qos,
+            interval: self.mqtt_interval,
+            expire: self.mqtt_expire,
+        })
+    }
+}
+
+/// Parses an interval, which must be positive.
+fn parse_positive_seconds(s: &str) -> Result<f64, String> {
+    let seconds = s.parse::<f64>().map_err(|error| error.to_string())?;
+    if seconds > 0.0 && seconds.is_finite() {
+        Ok(seconds)
+    }
+    else {
+        Err("expected a positive number of seconds".to_owned())
+    }
+}
+
+fn parse_lat_lon(s: &str) -> Result<LatLon, String> {
+    let (latitude, longitude) = s
+        .split_once(',')
+        .ok_or_else(|| "expected `latitude,longitude`".to_owned())?;
+    let parse = |value: &str| {
+        value
+            .trim()
+            .parse::<f64>()
+            .map_err(|error| error.to_string())
+    };
+    Ok(LatLon::new(parse(latitude)?, parse(longitude)?))
+}
+
 #[allow(dead_code)]
 fn make_test(data: &[u8], frame: &mode_s::Frame) {
     let mut bytes_str = String::new();
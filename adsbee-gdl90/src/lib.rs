@@ -0,0 +1,353 @@
+//! GDL90 format
+//!
+//! GDL90 is the format Garmin's GDL 90 UAT transceiver uses to send traffic
+//! to displays. Most EFB apps (ForeFlight, SkyDemon, ...) accept it over UDP,
+//! usually on port 4000.
+//!
+//! Messages are framed with a flag byte (`0x7E`) at the start and end, and end
+//! with a CRC. Flag and control-escape bytes in the message are escaped.
+//!
+//! Only the messages needed to show traffic are implemented: heartbeat,
+//! traffic report and ownship geometric altitude.
+//!
+//! - [GDL 90 Data Interface Specification][1]
+//!
+//! [1]: https://www.faa.gov/sites/faa.gov/files/air_traffic/technology/adsb/archival/GDL90_Public_ICD_RevA.PDF
+
+use adsbee_mode_s::adsb::EmergencyPriorityStatus;
+use adsbee_types::Callsign;
+use bytes::BufMut;
+
+/// Marks the start and end of a message.
+const FLAG: u8 = 0x7e;
+
+/// Escapes flag and control-escape bytes in the message.
+const CONTROL_ESCAPE: u8 = 0x7d;
+
+/// Longest message payload, including the message ID (traffic report).
+const MAX_PAYLOAD_LENGTH: usize = 28;
+
+/// CRC-CCITT lookup table, see 2.2.3
+const CRC_TABLE: [u16; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = (crc << 1) ^ if crc & 0x8000 != 0 { 0x1021 } else { 0 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Calculates the frame check sequence over a message payload.
+pub fn crc(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc: u16, byte| {
+        CRC_TABLE[usize::from(crc >> 8)] ^ (crc << 8) ^ u16::from(*byte)
+    })
+}
+
+/// Frames a message payload (message ID and data), i.e. appends the CRC,
+/// escapes it, and adds flag bytes.
+pub fn encode_frame<B: BufMut>(payload: &[u8], buffer: &mut B) {
+    // the CRC is sent LSB first
+    let crc = crc(payload).to_le_bytes();
+
+    buffer.put_u8(FLAG);
+    for byte in payload.iter().chain(&crc) {
+        if *byte == FLAG || *byte == CONTROL_ESCAPE {
+            buffer.put_u8(CONTROL_ESCAPE);
+            buffer.put_u8(byte ^ 0x20);
+        }
+        else {
+            buffer.put_u8(*byte);
+        }
+    }
+    buffer.put_u8(FLAG);
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    Heartbeat(Heartbeat),
+    TrafficReport(TrafficReport),
+    OwnshipGeometricAltitude(OwnshipGeometricAltitude),
+}
+
+impl Message {
+    pub fn message_id(&self) -> u8 {
+        match self {
+            Self::Heartbeat(_) => 0,
+            Self::TrafficReport(_) => 20,
+            Self::OwnshipGeometricAltitude(_) => 11,
+        }
+    }
+
+    /// Encodes the message ID and data, without framing.
+    pub fn encode_payload<B: BufMut>(&self, buffer: &mut B) {
+        buffer.put_u8(self.message_id());
+        match self {
+            Self::Heartbeat(heartbeat) => heartbeat.encode(buffer),
+            Self::TrafficReport(traffic_report) => traffic_report.encode(buffer),
+            Self::OwnshipGeometricAltitude(geometric_altitude) => geometric_altitude.encode(buffer),
+        }
+    }
+
+    /// Encodes the framed message.
+    pub fn encode<B: BufMut>(&self, buffer: &mut B) {
+        let mut payload = [0; MAX_PAYLOAD_LENGTH];
+        let mut payload_buffer = &mut payload[..];
+        self.encode_payload(&mut payload_buffer);
+        let length = MAX_PAYLOAD_LENGTH - payload_buffer.len();
+        encode_frame(&payload[..length], buffer);
+    }
+}
+
+/// Heartbeat message, see 3.1
+///
+/// This should be sent once per second.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Heartbeat {
+    pub gps_position_valid: bool,
+    pub maintenance_required: bool,
+    pub ident: bool,
+    pub gps_battery_low: bool,
+    pub utc_ok: bool,
+    /// Seconds since 0000Z. Only the lower 17 bits are sent.
+    pub timestamp: u32,
+    /// Number of UAT uplink messages received in the last second.
+    pub uplink_messages: u8,
+    /// Number of UAT basic and long messages received in the last second.
+    pub basic_and_long_messages: u16,
+}
+
+impl Heartbeat {
+    fn encode<B: BufMut>(&self, buffer: &mut B) {
+        // bit 0 is "UAT initialized", which is always set
+        let status_1 = (u8::from(self.gps_position_valid) << 7)
+            | (u8::from(self.maintenance_required) << 6)
+            | (u8::from(self.ident) << 5)
+            | (u8::from(self.gps_battery_low) << 3)
+            | 0x01;
+        let status_2 = ((((self.timestamp >> 16) & 1) as u8) << 7) | u8::from(self.utc_ok);
+        buffer.put_u8(status_1);
+        buffer.put_u8(status_2);
+        buffer.put_u16_le(self.timestamp as u16);
+        buffer.put_u16(
+            (u16::from(self.uplink_messages.min(0x1f)) << 11)
+                | self.basic_and_long_messages.min(0x3ff),
+        );
+    }
+}
+
+/// Traffic report, see 3.5
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrafficReport {
+    pub traffic_alert: bool,
+    pub address_type: AddressType,
+    /// 24 bit participant address
+    pub address: u32,
+    /// in degrees
+    pub latitude: f64,
+    /// in degrees
+    pub longitude: f64,
+    /// Pressure altitude in ft
+    pub pressure_altitude: Option<i32>,
+    pub airborne: bool,
+    /// Whether the report is extrapolated, instead of updated from a received
+    /// message.
+    pub extrapolated: bool,
+    /// What [`TrafficReport::track`] is.
+    pub track_type: TrackType,
+    /// Track or heading in degrees
+    pub track: f64,
+    /// Navigation integrity category
+    pub nic: u8,
+    /// Navigation accuracy category for position
+    pub nac_p: u8,
+    /// Horizontal velocity in kt
+    pub horizontal_velocity: Option<f64>,
+    /// Vertical velocity in ft/min
+    pub vertical_velocity: Option<i32>,
+    /// ADS-B emitter category, 0 if unknown
+    pub emitter_category: u8,
+    pub callsign: Option<Callsign>,
+    pub emergency_priority_status: EmergencyPriorityStatus,
+}
+
+impl TrafficReport {
+    fn encode<B: BufMut>(&self, buffer: &mut B) {
+        buffer.put_u8((u8::from(self.traffic_alert) << 4) | self.address_type as u8);
+        put_u24(buffer, self.address);
+        put_u24(buffer, encode_angle(self.latitude));
+        put_u24(buffer, encode_angle(self.longitude));
+
+        let altitude = self.pressure_altitude.map_or(0xfff, |altitude| {
+            ((altitude + 1000) / 25).clamp(0, 0xffe) as u16
+        });
+        let misc = (u8::from(self.airborne) << 3)
+            | (u8::from(self.extrapolated) << 2)
+            | self.track_type as u8;
+        buffer.put_u16((altitude << 4) | u16::from(misc));
+
+        buffer.put_u8((self.nic.min(0xf) << 4) | self.nac_p.min(0xf));
+
+        let horizontal_velocity = self
+            .horizontal_velocity
+            .map_or(0xfff, |velocity| velocity.round().clamp(0.0, 4094.0) as u32);
+        // in units of 64 ft/min
+        let vertical_velocity = self.vertical_velocity.map_or(0x800, |velocity| {
+            (velocity / 64).clamp(-510, 510) as u32 & 0xfff
+        });
+        put_u24(buffer, (horizontal_velocity << 12) | vertical_velocity);
+
+        buffer.put_u8((self.track.rem_euclid(360.0) * 256.0 / 360.0) as u8);
+        buffer.put_u8(self.emitter_category);
+
+        match &self.callsign {
+            Some(callsign) => buffer.put_slice(callsign.as_str().as_bytes()),
+            None => buffer.put_bytes(b' ', Callsign::LENGTH),
+        }
+
+        buffer.put_u8(self.emergency_priority_status.as_u8() << 4);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressType {
+    AdsbIcao = 0,
+    AdsbSelfAssigned = 1,
+    TisbIcao = 2,
+    TisbTrackFile = 3,
+    SurfaceVehicle = 4,
+    GroundStationBeacon = 5,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrackType {
+    Invalid = 0,
+    TrueTrack = 1,
+    MagneticHeading = 2,
+    TrueHeading = 3,
+}
+
+/// Ownship geometric altitude, see 3.8
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnshipGeometricAltitude {
+    /// Height above the WGS84 ellipsoid in ft
+    pub altitude: i32,
+    pub vertical_warning: bool,
+    /// Vertical figure of merit in m, `None` if not available
+    pub vertical_figure_of_merit: Option<u16>,
+}
+
+impl OwnshipGeometricAltitude {
+    fn encode<B: BufMut>(&self, buffer: &mut B) {
+        // in units of 5 ft
+        buffer.put_i16((self.altitude / 5).clamp(i16::MIN.into(), i16::MAX.into()) as i16);
+        let vertical_figure_of_merit = self
+            .vertical_figure_of_merit
+            .map_or(0x7fff, |merit| merit.min(0x7eee));
+        buffer.put_u16((u16::from(self.vertical_warning) << 15) | vertical_figure_of_merit);
+    }
+}
+
+/// Encodes latitude or longitude as 24 bit signed binary fraction.
+fn encode_angle(degrees: f64) -> u32 {
+    ((degrees * f64::from(1 << 23) / 180.0) as i32 as u32) & 0xff_ffff
+}
+
+fn put_u24<B: BufMut>(buffer: &mut B, value: u32) {
+    buffer.put_slice(&value.to_be_bytes()[1..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use adsbee_mode_s::adsb::EmergencyPriorityStatus;
+
+    use crate::{
+        AddressType,
+        Heartbeat,
+        Message,
+        OwnshipGeometricAltitude,
+        TrackType,
+        TrafficReport,
+        encode_frame,
+    };
+
+    #[test]
+    fn it_frames_the_example() {
+        // 2.2.3
+        let mut buffer = vec![];
+        encode_frame(b"\x00\x81\x41\xdb\xd0\x08\x02", &mut buffer);
+        assert_eq!(buffer, b"\x7e\x00\x81\x41\xdb\xd0\x08\x02\xb3\x8b\x7e");
+    }
+
+    #[test]
+    fn it_escapes_flag_bytes() {
+        let mut buffer = vec![];
+        encode_frame(b"\x14\x7e\x7d", &mut buffer);
+        assert_eq!(&buffer[..6], b"\x7e\x14\x7d\x5e\x7d\x5d");
+    }
+
+    #[test]
+    fn it_encodes_the_traffic_report_example() {
+        // 3.5.4
+        let message = Message::TrafficReport(TrafficReport {
+            traffic_alert: false,
+            address_type: AddressType::AdsbIcao,
+            address: 0o52642511,
+            latitude: 44.90708,
+            longitude: -122.99488,
+            pressure_altitude: Some(5000),
+            airborne: true,
+            extrapolated: false,
+            track_type: TrackType::TrueTrack,
+            track: 45.0,
+            nic: 10,
+            nac_p: 9,
+            horizontal_velocity: Some(123.0),
+            vertical_velocity: Some(64),
+            emitter_category: 1,
+            callsign: Some("N825V".parse().unwrap()),
+            emergency_priority_status: EmergencyPriorityStatus::NO_EMERGENCY,
+        });
+
+        let mut buffer = vec![];
+        message.encode_payload(&mut buffer);
+        assert_eq!(
+            buffer,
+            b"\x14\x00\xab\x45\x49\x1f\xef\x15\xa8\x89\x78\x0f\x09\xa9\x07\xb0\x01\x20\x01\x4e\x38\x32\x35\x56\x20\x20\x20\x00"
+        );
+    }
+
+    #[test]
+    fn it_encodes_heartbeats() {
+        let message = Message::Heartbeat(Heartbeat {
+            gps_position_valid: true,
+            utc_ok: true,
+            timestamp: 0x1d0db,
+            ..Default::default()
+        });
+
+        let mut buffer = vec![];
+        message.encode_payload(&mut buffer);
+        assert_eq!(buffer, b"\x00\x81\x81\xdb\xd0\x00\x00");
+    }
+
+    #[test]
+    fn it_encodes_geometric_altitude() {
+        let message = Message::OwnshipGeometricAltitude(OwnshipGeometricAltitude {
+            altitude: 1000,
+            vertical_warning: false,
+            vertical_figure_of_merit: None,
+        });
+
+        let mut buffer = vec![];
+        message.encode_payload(&mut buffer);
+        assert_eq!(buffer, b"\x0b\x00\xc8\x7f\xff");
+    }
+}
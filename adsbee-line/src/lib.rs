@@ -0,0 +1,526 @@
+//! Line based framing
+//!
+//! Readers, writer and an incremental decoder for formats that send one
+//! message per line, such as SBS and AVR. A format only needs to implement
+//! [`Line`] for its message type.
+//!
+//! Lines end with either `\n` or `\r\n`. Empty lines are skipped, since readsb
+//! sends them as heartbeat messages.
+
+use std::{
+    fmt::{
+        Debug,
+        Display,
+        Write as _,
+    },
+    marker::PhantomData,
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+    },
+};
+
+use bytes::{
+    Buf,
+    BytesMut,
+};
+use futures_util::{
+    Sink,
+    Stream,
+};
+use pin_project_lite::pin_project;
+use tokio::io::{
+    AsyncRead,
+    AsyncWrite,
+    ReadBuf,
+};
+
+/// this can be larger for more efficient reads, although the underlying reader
+/// is probably buffered.
+const RECEIVE_BUFFER_SIZE: usize = 1024;
+
+/// the writer flushes its buffer when it holds at least this many bytes.
+const WRITE_BUFFER_SIZE: usize = 1024;
+
+#[derive(Debug, thiserror::Error)]
+#[error("line decode error")]
+pub enum Error {
+    Io(#[from] std::io::Error),
+    MaxLineLengthExceeded,
+}
+
+/// A message that is sent as one line.
+pub trait Line: Sized {
+    type Error: From<Error>;
+
+    /// Lines longer than this are skipped.
+    const MAX_LENGTH: usize;
+
+    /// The [`Writer`] terminates each line with this.
+    const LINE_ENDING: &'static str;
+
+    /// Parses a non-empty line. The line ending is already stripped.
+    fn parse_line(line: &[u8]) -> Result<Self, Self::Error>;
+}
+
+/// Incremental decoder that doesn't do any I/O.
+///
+/// Data can be passed in chunks of any size. Partially received lines are
+/// kept until the rest of the line is passed in.
+#[derive(Debug)]
+pub struct Decoder<T> {
+    line: Vec<u8>,
+    /// the line didn't fit into the buffer
+    overflow: bool,
+    _line: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for Decoder<T> {
+    fn default() -> Self {
+        Self {
+            line: vec![],
+            overflow: false,
+            _line: PhantomData,
+        }
+    }
+}
+
+impl<T: Line> Decoder<T> {
+    /// Decodes the next line from `data`, and advances it past the consumed
+    /// bytes.
+    ///
+    /// Returns `None` once all of `data` has been consumed without completing
+    /// a line. The next invocation of this method will resume decoding.
+    pub fn decode(&mut self, data: &mut &[u8]) -> Option<Result<T, T::Error>> {
+        loop {
+            // note: we split lines at either `\r` or `\n`, so `\r\n` will produce an
+            // empty line, which we ignore.
+            let Some(newline) = data
+                .iter()
+                .position(|byte| *byte == b'\r' || *byte == b'\n')
+            else {
+                self.push(data);
+                *data = &[];
+                return None;
+            };
+
+            let line = &data[..newline];
+            *data = &data[newline + 1..];
+
+            if std::mem::take(&mut self.overflow) {
+                self.line.clear();
+                return Some(Err(Error::MaxLineLengthExceeded.into()));
+            }
+
+            // only copy the line if it was split across chunks
+            let result = if self.line.is_empty() {
+                parse_non_empty(line)
+            }
+            else {
+                self.push(line);
+                if std::mem::take(&mut self.overflow) {
+                    self.line.clear();
+                    return Some(Err(Error::MaxLineLengthExceeded.into()));
+                }
+                let result = parse_non_empty(&self.line);
+                self.line.clear();
+                result
+            };
+
+            if result.is_some() {
+                return result;
+            }
+        }
+    }
+
+    /// Decodes all lines in `data`.
+    pub fn decode_all<'a>(
+        &'a mut self,
+        mut data: &'a [u8],
+    ) -> impl Iterator<Item = Result<T, T::Error>> + 'a {
+        std::iter::from_fn(move || self.decode(&mut data))
+    }
+
+    fn push(&mut self, data: &[u8]) {
+        if self.overflow {
+            return;
+        }
+
+        if self.line.len() + data.len() <= T::MAX_LENGTH {
+            self.line.extend_from_slice(data);
+        }
+        else {
+            self.overflow = true;
+        }
+    }
+}
+
+fn parse_non_empty<T: Line>(line: &[u8]) -> Option<Result<T, T::Error>> {
+    (!line.trim_ascii().is_empty()).then(|| T::parse_line(line))
+}
+
+/// Receive buffer and decoder shared by the readers.
+#[derive(Debug)]
+struct ReadState<T> {
+    buffer: [u8; RECEIVE_BUFFER_SIZE],
+    read_pos: usize,
+    write_pos: usize,
+    decoder: Decoder<T>,
+}
+
+impl<T> Default for ReadState<T> {
+    fn default() -> Self {
+        Self {
+            buffer: [0; RECEIVE_BUFFER_SIZE],
+            read_pos: 0,
+            write_pos: 0,
+            decoder: Decoder::default(),
+        }
+    }
+}
+
+impl<T: Line> ReadState<T> {
+    /// Reads using `read` until a line is decoded.
+    ///
+    /// `read` reads into the buffer it's given and returns the number of bytes
+    /// read. 0 bytes read means the underlying reader reached EOF.
+    fn poll_next_line(
+        &mut self,
+        mut read: impl FnMut(&mut [u8]) -> Poll<Result<usize, std::io::Error>>,
+    ) -> Poll<Option<Result<T, T::Error>>> {
+        loop {
+            if self.read_pos < self.write_pos {
+                let mut data = &self.buffer[self.read_pos..self.write_pos];
+                let line = self.decoder.decode(&mut data);
+                self.read_pos = self.write_pos - data.len();
+
+                if let Some(line) = line {
+                    return Poll::Ready(Some(line));
+                }
+            }
+            else {
+                match read(&mut self.buffer) {
+                    Poll::Pending => return Poll::Pending,
+                    Poll::Ready(Err(error)) => {
+                        return Poll::Ready(Some(Err(Error::from(error).into())));
+                    }
+                    Poll::Ready(Ok(0)) => return Poll::Ready(None),
+                    Poll::Ready(Ok(num_bytes_read)) => {
+                        self.read_pos = 0;
+                        self.write_pos = num_bytes_read;
+                    }
+                }
+            }
+        }
+    }
+}
+
+pin_project! {
+    /// Reads lines from a tokio [`AsyncRead`].
+    #[derive(Debug)]
+    pub struct Reader<R, T> {
+        #[pin]
+        reader: R,
+        state: ReadState<T>,
+    }
+}
+
+impl<R, T> Reader<R, T> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            state: ReadState::default(),
+        }
+    }
+}
+
+impl<R: AsyncRead, T: Line> Stream for Reader<R, T> {
+    type Item = Result<T, T::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let mut reader = this.reader;
+        this.state.poll_next_line(|buffer| {
+            let mut read_buf = ReadBuf::new(buffer);
+            reader
+                .as_mut()
+                .poll_read(cx, &mut read_buf)
+                .map_ok(|()| read_buf.filled().len())
+        })
+    }
+}
+
+#[cfg(feature = "futures-io")]
+pin_project! {
+    /// Reads lines from a [`futures_io::AsyncRead`].
+    #[derive(Debug)]
+    pub struct FuturesReader<R, T> {
+        #[pin]
+        reader: R,
+        state: ReadState<T>,
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl<R, T> FuturesReader<R, T> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            state: ReadState::default(),
+        }
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl<R: futures_io::AsyncRead, T: Line> Stream for FuturesReader<R, T> {
+    type Item = Result<T, T::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let mut reader = this.reader;
+        this.state
+            .poll_next_line(|buffer| reader.as_mut().poll_read(cx, buffer))
+    }
+}
+
+/// Reads lines from a blocking [`std::io::Read`].
+#[derive(Debug)]
+pub struct BlockingReader<R, T> {
+    reader: R,
+    state: ReadState<T>,
+}
+
+impl<R, T> BlockingReader<R, T> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            state: ReadState::default(),
+        }
+    }
+}
+
+impl<R: std::io::Read, T: Line> Iterator for BlockingReader<R, T> {
+    type Item = Result<T, T::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let reader = &mut self.reader;
+        let poll = self.state.poll_next_line(|buffer| {
+            loop {
+                match reader.read(buffer) {
+                    Err(error) if error.kind() == std::io::ErrorKind::Interrupted => {}
+                    result => return Poll::Ready(result),
+                }
+            }
+        });
+
+        match poll {
+            Poll::Ready(result) => result,
+            Poll::Pending => unreachable!("blocking reads are never pending"),
+        }
+    }
+}
+
+pin_project! {
+    /// Writes lines to a tokio [`AsyncWrite`].
+    ///
+    /// Each line is formatted with [`Display`] and terminated with
+    /// [`Line::LINE_ENDING`]. Lines are buffered, so make sure to flush the
+    /// sink.
+    #[derive(Debug)]
+    pub struct Writer<W, T> {
+        #[pin]
+        writer: W,
+        write_buffer: BytesMut,
+        _line: PhantomData<fn(T)>,
+    }
+}
+
+impl<W, T> Writer<W, T> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            write_buffer: BytesMut::with_capacity(WRITE_BUFFER_SIZE),
+            _line: PhantomData,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: AsyncWrite, T> Writer<W, T> {
+    /// Writes the buffer to the writer until it's empty.
+    fn poll_write_buffer(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let mut this = self.project();
+
+        while !this.write_buffer.is_empty() {
+            match this.writer.as_mut().poll_write(cx, this.write_buffer) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(error)) => return Poll::Ready(Err(error.into())),
+                Poll::Ready(Ok(0)) => {
+                    return Poll::Ready(Err(
+                        std::io::Error::from(std::io::ErrorKind::WriteZero).into()
+                    ));
+                }
+                Poll::Ready(Ok(num_bytes_written)) => this.write_buffer.advance(num_bytes_written),
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite, T: Line + Display> Sink<T> for Writer<W, T> {
+    type Error = T::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.write_buffer.len() >= WRITE_BUFFER_SIZE {
+            self.poll_write_buffer(cx).map_err(Into::into)
+        }
+        else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        let this = self.project();
+        write!(this.write_buffer, "{item}{}", T::LINE_ENDING)
+            .expect("writing to BytesMut can't fail");
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.as_mut().poll_write_buffer(cx) {
+            Poll::Ready(Ok(())) => {
+                self.project()
+                    .writer
+                    .poll_flush(cx)
+                    .map_err(|error| Error::from(error).into())
+            }
+            poll => poll.map_err(Into::into),
+        }
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.as_mut().poll_flush(cx) {
+            Poll::Ready(Ok(())) => {
+                self.project()
+                    .writer
+                    .poll_shutdown(cx)
+                    .map_err(|error| Error::from(error).into())
+            }
+            poll => poll,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::{
+        self,
+        Display,
+    };
+
+    use futures_util::{
+        SinkExt,
+        TryStreamExt,
+    };
+
+    use crate::{
+        BlockingReader,
+        Decoder,
+        Error,
+        Line,
+        Reader,
+        Writer,
+    };
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct TestLine(String);
+
+    impl Line for TestLine {
+        type Error = Error;
+
+        const MAX_LENGTH: usize = 16;
+        const LINE_ENDING: &'static str = "\r\n";
+
+        fn parse_line(line: &[u8]) -> Result<Self, Self::Error> {
+            Ok(Self(String::from_utf8(line.to_owned()).unwrap()))
+        }
+    }
+
+    impl Display for TestLine {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+
+    const EXAMPLE: &str = "foo\r\nbar\n\r\n  \nbaz\r\n";
+
+    fn expected() -> Vec<TestLine> {
+        ["foo", "bar", "baz"]
+            .into_iter()
+            .map(|line| TestLine(line.to_owned()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn it_decodes_a_stream() {
+        let lines = Reader::<_, TestLine>::new(EXAMPLE.as_bytes())
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(lines, expected());
+    }
+
+    #[test]
+    fn it_decodes_in_chunks() {
+        for chunk_size in 1..EXAMPLE.len() {
+            let mut decoder = Decoder::<TestLine>::default();
+            let mut lines = vec![];
+            for chunk in EXAMPLE.as_bytes().chunks(chunk_size) {
+                lines.extend(decoder.decode_all(chunk).map(Result::unwrap));
+            }
+            assert_eq!(lines, expected());
+        }
+    }
+
+    #[test]
+    fn it_skips_long_lines() {
+        let mut data = vec![b'x'; 100];
+        data.extend_from_slice(b"\r\nfoo\r\n");
+
+        let mut decoder = Decoder::<TestLine>::default();
+        let mut lines = data
+            .chunks(7)
+            .flat_map(|chunk| decoder.decode_all(chunk).collect::<Vec<_>>());
+        assert!(matches!(
+            lines.next(),
+            Some(Err(Error::MaxLineLengthExceeded))
+        ));
+        assert_eq!(lines.next().unwrap().unwrap(), TestLine("foo".to_owned()));
+        assert!(lines.next().is_none());
+    }
+
+    #[test]
+    fn it_reads_blocking() {
+        let lines = BlockingReader::<_, TestLine>::new(EXAMPLE.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(lines, expected());
+    }
+
+    #[tokio::test]
+    async fn it_writes_lines() {
+        let mut writer = Writer::new(vec![]);
+        for line in expected() {
+            writer.feed(line).await.unwrap();
+        }
+        writer.close().await.unwrap();
+
+        let written = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(written, "foo\r\nbar\r\nbaz\r\n");
+    }
+}
@@ -23,6 +23,8 @@ use std::{
     ops::Not,
 };
 
+use adsbee_types::geo::LatLon;
+
 use crate::VerticalStatus;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -110,6 +112,12 @@ pub struct Position {
     pub longitude: f64,
 }
 
+impl From<Position> for LatLon {
+    fn from(value: Position) -> Self {
+        LatLon::new(value.latitude, value.longitude)
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum DecodeError {
     #[error("messages must be from the same longitude zone")]
@@ -4,14 +4,15 @@ pub mod cpr;
 
 use std::{
     f64::consts::TAU,
-    fmt::{
-        Debug,
-        Display,
-    },
-    str::FromStr,
+    fmt::Debug,
 };
 
 use adsbee_types::Squawk;
+pub use adsbee_types::{
+    Callsign,
+    CallsignFromStrError,
+    valid_callsign_char,
+};
 use bytes::Buf;
 
 use crate::{
@@ -178,6 +179,7 @@ impl SurfacePosition {
 /// 2.2.3.2.3
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct AirbornePosition {
+    pub type_code: u8,
     pub altitude_type: AltitudeType,
     pub surveillance_status: SurveillanceStatus,
     pub single_antenna_flag: bool,
@@ -199,6 +201,7 @@ impl AirbornePosition {
         let d = bytes[2] & 0b00001000 != 0;
 
         Self {
+            type_code,
             altitude_type: AltitudeType::from_type_code(type_code),
             surveillance_status: SurveillanceStatus(a),
             single_antenna_flag: b,
@@ -212,6 +215,31 @@ impl AirbornePosition {
         self.altitude_code
             .map(|ac| self.altitude_type.altitude(ac.decode()))
     }
+
+    /// Navigation Integrity Category
+    ///
+    /// This depends on the type code and NIC supplement A from the aircraft
+    /// operational status. In version 2 the single antenna flag is NIC
+    /// supplement B.
+    ///
+    /// See 2.2.3.2.3.1 and table 2-14 (page 61)
+    pub fn nic(&self, nic_supplement_a: bool) -> u8 {
+        let nic_supplement_b = self.single_antenna_flag;
+        match self.type_code {
+            9 | 20 => 11,
+            10 | 21 => 10,
+            11 if nic_supplement_a && nic_supplement_b => 9,
+            11 => 8,
+            12 => 7,
+            13 => 6,
+            14 => 5,
+            15 => 4,
+            16 if nic_supplement_a && nic_supplement_b => 3,
+            16 => 2,
+            17 => 1,
+            _ => 0,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -242,7 +270,7 @@ impl AirborneVelocity {
         let b = bytes[0] & 0b0100_0000 != 0;
         let c = (bytes[0] & 0b0011_1000) >> 3;
         let d = bytes[0] & 0b0000_0100 != 0;
-        let e = (u16::from(bytes[0] & 0b1100_0000) << 3) | u16::from(bytes[1]);
+        let e = (u16::from(bytes[0] & 0b0000_0011) << 8) | u16::from(bytes[1]);
         let f = bytes[2] & 0b1000_0000 != 0;
         let g = (u16::from(bytes[2] & 0b0111_1111) << 3) | u16::from(bytes[3] >> 5);
         let h = bytes[3] & 0b0001_0000 != 0;
@@ -634,6 +662,28 @@ impl SilSupplement {
     }
 }
 
+/// Horizontal Reference Direction
+///
+/// Reference for the heading and track angle reported by the aircraft.
+///
+/// 2.2.3.2.7.2.13
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HorizontalReferenceDirection {
+    TrueNorth,
+    MagneticNorth,
+}
+
+impl HorizontalReferenceDirection {
+    fn from_bit(bit: bool) -> Self {
+        if bit {
+            Self::MagneticNorth
+        }
+        else {
+            Self::TrueNorth
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum SelectedAltitudeType {
     McpFcu,
@@ -655,7 +705,7 @@ pub enum AircraftOperationalStatus {
         gva: Gva,
         sil: Sil,
         nic_baro: bool,
-        hrd: bool,
+        hrd: HorizontalReferenceDirection,
         sil_supplement: SilSupplement,
         reserved_56: bool,
     },
@@ -672,7 +722,7 @@ pub enum AircraftOperationalStatus {
         reserved: u8,
         sil: Sil,
         track_heading: bool,
-        hrd: bool,
+        hrd: HorizontalReferenceDirection,
         sil_supplement: SilSupplement,
         reserved_56: bool,
     },
@@ -684,6 +734,13 @@ pub enum AircraftOperationalStatus {
 }
 
 impl AircraftOperationalStatus {
+    pub fn hrd(&self) -> Option<HorizontalReferenceDirection> {
+        match self {
+            Self::Airborne { hrd, .. } | Self::Surface { hrd, .. } => Some(*hrd),
+            Self::Reserved { .. } => None,
+        }
+    }
+
     pub fn decode<B: Buf>(buffer: &mut B, bits_6_to_8: u8) -> Self {
         let sub_type = bits_6_to_8;
 
@@ -704,7 +761,7 @@ impl AircraftOperationalStatus {
             let f = byte_5 >> 6;
             let sil = Sil((byte_5 & 0b00110000) >> 4); // g
             let h = byte_5 & 0b00001000 != 0;
-            let hrd = byte_5 & 0b00000100 != 0; // i
+            let hrd = HorizontalReferenceDirection::from_bit(byte_5 & 0b00000100 != 0); // i
             let sil_supplement = SilSupplement::from_bit(byte_5 & 0b00000010 != 0);
             let reserved_56 = byte_5 & 0b00000001 != 0;
 
@@ -1264,6 +1321,33 @@ impl WakeVortexCategory {
         }
     }
 
+    /// Returns the type code and category this was decoded from.
+    pub const fn type_code_and_category(&self) -> (u8, u8) {
+        match *self {
+            Self::Reserved {
+                type_code,
+                category,
+            } => (type_code, category),
+            Self::NoCategoryInformation { type_code } => (type_code, 0),
+            Self::SurfaceEmergencyVehicle => (2, 1),
+            Self::SurfaceServiceVehicle => (2, 3),
+            Self::GroundObstruction { category } => (2, category),
+            Self::GliderSailplane => (3, 1),
+            Self::LighterThanAir => (3, 2),
+            Self::ParachutistSkydiver => (3, 3),
+            Self::UltralightHangGliderParaGlider => (3, 4),
+            Self::UnmannedAerialVehicle => (3, 6),
+            Self::SpaceTransatmospherricVehicle => (3, 7),
+            Self::Light => (4, 1),
+            Self::Medium1 => (4, 2),
+            Self::Medium2 => (4, 3),
+            Self::HighVortexAirrcraft => (4, 4),
+            Self::Heavy => (4, 5),
+            Self::HighPerformance => (4, 6),
+            Self::Rotorcraft => (4, 7),
+        }
+    }
+
     pub const fn from_type_code_and_category(type_code: u8, category: u8) -> Option<Self> {
         if type_code & 0b11100000 == 0 && category & 0b00000111 == 0 {
             Some(Self::from_type_code_and_category_unchecked(
@@ -1300,6 +1384,8 @@ impl EncodedCallsign {
     }
 
     /// Decodes the callsign into a small string
+    ///
+    /// This rejects invalid characters, interior spaces and empty callsigns.
     pub fn decode(&self) -> Result<Callsign, InvalidCallsign> {
         let expanded = self.expand();
         let mut characters = [0; 8];
@@ -1317,7 +1403,28 @@ impl EncodedCallsign {
             }
         }
 
-        Ok(Callsign { characters })
+        let callsign = Callsign::from_bytes_unchecked(characters);
+        match callsign.validate() {
+            Ok(()) => Ok(callsign),
+            Err(
+                CallsignFromStrError::InteriorSpace { position }
+                | CallsignFromStrError::InvalidChar { position, .. },
+            ) => {
+                Err(InvalidCallsign {
+                    expanded,
+                    position,
+                    character: expanded[position],
+                })
+            }
+            Err(_) => {
+                // empty callsign
+                Err(InvalidCallsign {
+                    expanded,
+                    position: 0,
+                    character: expanded[0],
+                })
+            }
+        }
     }
 
     // Decodes the callsign into a small string and ignores invalid characters
@@ -1330,9 +1437,7 @@ impl EncodedCallsign {
             *byte = resolved;
         }
 
-        Callsign {
-            characters: expanded,
-        }
+        Callsign::from_bytes_unchecked(expanded)
     }
 }
 
@@ -1368,90 +1473,6 @@ pub struct InvalidCallsign {
     pub character: u8,
 }
 
-/// A decoded callsign.
-///
-/// This is basically a small string (without heap allocation).
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct Callsign {
-    // note: we only ever fill this with valid ASCII characters
-    characters: [u8; Self::LENGTH],
-}
-
-impl Callsign {
-    const LENGTH: usize = 8;
-
-    pub fn as_str(&self) -> &str {
-        // we check this, so we might use the unsafe variant here
-        std::str::from_utf8(&self.characters).expect("bug: invalid utf-8 in callsign")
-    }
-}
-
-impl Debug for Callsign {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Callsign(\"{}\")", self.as_str())
-    }
-}
-
-impl Display for Callsign {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.as_str())
-    }
-}
-
-impl FromStr for Callsign {
-    type Err = CallsignFromStrError;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let n = s.len();
-        if n > Self::LENGTH {
-            return Err(CallsignFromStrError::InvalidLength(n));
-        }
-
-        let mut characters = [0u8; Self::LENGTH];
-        for (i, c) in s.chars().enumerate() {
-            if !valid_callsign_char(c) {
-                return Err(CallsignFromStrError::InvalidChar {
-                    position: i,
-                    character: c,
-                });
-            }
-            characters[i] = c.try_into().unwrap();
-        }
-
-        Ok(Self { characters })
-    }
-}
-
-impl AsRef<[u8]> for Callsign {
-    fn as_ref(&self) -> &[u8] {
-        &self.characters[..]
-    }
-}
-
-impl AsRef<[u8; Self::LENGTH]> for Callsign {
-    fn as_ref(&self) -> &[u8; Self::LENGTH] {
-        &self.characters
-    }
-}
-
-impl AsRef<str> for Callsign {
-    fn as_ref(&self) -> &str {
-        self.as_str()
-    }
-}
-
-#[derive(Clone, Debug, thiserror::Error)]
-pub enum CallsignFromStrError {
-    #[error("Invalid character in callsign: '{character}' at position {position}")]
-    InvalidChar { position: usize, character: char },
-    #[error("Invalid length for callsign: {0}")]
-    InvalidLength(usize),
-}
-
-pub fn valid_callsign_char(c: char) -> bool {
-    c.is_ascii_uppercase() || c.is_ascii_digit() || c == ' '
-}
-
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Movement(u8);
 
@@ -1877,7 +1898,8 @@ impl MagneticHeading {
 
     /// Magnetic heading as 360/1024 of a degree
     ///
-    /// Clockwise from true magnetic north.
+    /// Clockwise from magnetic north, unless the aircraft announces true north
+    /// as [`HorizontalReferenceDirection`].
     pub fn as_u16(&self) -> u16 {
         self.0
     }
@@ -1988,8 +2010,8 @@ impl AltitudeDifferenceValue {
         self.0
     }
 
-    pub fn as_ft(&self) -> u8 {
-        (self.0 - 1) * 25
+    pub fn as_ft(&self) -> u16 {
+        (u16::from(self.0) - 1) * 25
     }
 }
 
@@ -1999,6 +2021,17 @@ pub struct AltitudeDifference {
     pub value: Option<AltitudeDifferenceValue>,
 }
 
+impl AltitudeDifference {
+    /// GNSS altitude minus barometric altitude in ft
+    pub fn as_ft(&self) -> Option<i32> {
+        let value = i32::from(self.value?.as_ft());
+        match self.sign {
+            AltitudeDifferenceSign::GnssAboveBarometric => Some(value),
+            AltitudeDifferenceSign::GnssBelowBarometric => Some(-value),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct TurnIndicator(u8);
 
@@ -2114,14 +2147,17 @@ mod tests {
                             ground_speed.direction_east_west,
                             DirectionEastWest::EastToWest
                         ); // d
-                        assert_eq!(ground_speed.velocity_east_west, Some(Velocity(1))); // e
+                        assert_eq!(ground_speed.velocity_east_west, Some(Velocity(257))); // e
                         assert_eq!(
                             ground_speed.direction_north_south,
                             DirectionNorthSouth::SouthToNorth
                         ); // f
                         assert_eq!(ground_speed.velocity_north_south, Some(Velocity(331))); // g
 
-                        assert_eq!(ground_speed.velocity_east_west.unwrap().as_knots(false), 0);
+                        assert_eq!(
+                            ground_speed.velocity_east_west.unwrap().as_knots(false),
+                            256
+                        );
                         assert_eq!(
                             ground_speed.velocity_north_south.unwrap().as_knots(false),
                             330
@@ -2143,6 +2179,7 @@ mod tests {
                     velocity.altitude_difference.sign,
                     AltitudeDifferenceSign::GnssBelowBarometric
                 );
+                assert_eq!(velocity.altitude_difference.as_ft(), Some(-75));
             }
             _ => panic!("unexpected frame: {frame:?}"),
         }
@@ -2167,6 +2204,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn it_round_trips_wake_vortex_categories() {
+        for type_code in 1..=4 {
+            for category in 0..=7 {
+                let wake_vortex_category =
+                    WakeVortexCategory::from_type_code_and_category_unchecked(type_code, category);
+                assert_eq!(
+                    wake_vortex_category.type_code_and_category(),
+                    (type_code, category)
+                );
+            }
+        }
+    }
+
     #[test]
     fn it_decodes_aircraft_status_mode_a() {
         let bytes = b"\x8d\xa0\xda\xdb\xe1\x02\x8b\x00\x00\x00\x00\xfe\xad\x7b";
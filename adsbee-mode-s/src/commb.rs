@@ -0,0 +1,346 @@
+//! Comm-B registers (Mode-S EHS/MRAR)
+//!
+//! Comm-B replies (DF 20, 21) carry a 56 bit message from one of the
+//! transponder's registers, but don't say which. We decode the message as each
+//! of the registers we know and reject those that are inconsistent. This is
+//! the same approach pyModeS takes, so the message might still be ambiguous.
+//! Use [`Register::candidates`] to get all plausible interpretations.
+//!
+//! - [ICAO Doc 9871][1] (Appendix A)
+//! - [The 1090 Megahertz Riddle][2]
+//!
+//! [1]: https://www.icao.int/airnavigation/IMP/Documents/Doc%209871%20-%20Technical%20Provisions%20for%20Mode%20S%20-%20Advanced%20Edition%20(Ed%202).pdf
+//! [2]: https://mode-s.org/1090mhz/content/mode-s/6-mrar.html
+
+/// A decoded Comm-B register
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Register {
+    /// BDS 4,4
+    MeteorologicalRoutineAirReport(MeteorologicalRoutineAirReport),
+    /// BDS 5,0
+    TrackAndTurnReport(TrackAndTurnReport),
+    /// BDS 6,0
+    HeadingAndSpeedReport(HeadingAndSpeedReport),
+}
+
+impl Register {
+    /// Returns all registers the message can be decoded as.
+    pub fn candidates(message: &[u8; 7]) -> impl Iterator<Item = Register> {
+        MeteorologicalRoutineAirReport::decode(message)
+            .map(Self::MeteorologicalRoutineAirReport)
+            .into_iter()
+            .chain(TrackAndTurnReport::decode(message).map(Self::TrackAndTurnReport))
+            .chain(HeadingAndSpeedReport::decode(message).map(Self::HeadingAndSpeedReport))
+    }
+
+    /// Infers the register, if the message is unambiguous.
+    pub fn infer(message: &[u8; 7]) -> Option<Register> {
+        let mut candidates = Self::candidates(message);
+        let register = candidates.next()?;
+        candidates.next().is_none().then_some(register)
+    }
+}
+
+/// BDS 4,4 Meteorological routine air report
+///
+/// Doc 9871 Table A-2-68
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeteorologicalRoutineAirReport {
+    /// Figure of merit / source
+    pub figure_of_merit: u8,
+    /// Wind speed in kt and direction in degrees (clockwise from true north,
+    /// direction the wind is coming from)
+    pub wind: Option<(f64, f64)>,
+    /// Static air temperature in °C
+    pub static_air_temperature: f64,
+    /// Average static pressure in hPa
+    pub static_pressure: Option<f64>,
+    pub turbulence: Option<u8>,
+    /// Humidity in %
+    pub humidity: Option<f64>,
+}
+
+impl MeteorologicalRoutineAirReport {
+    pub fn decode(message: &[u8; 7]) -> Option<Self> {
+        let bits = Bits::new(message)?;
+
+        if !bits.status_consistent(5, 6, 23)
+            || !bits.status_consistent(35, 36, 46)
+            || !bits.status_consistent(47, 48, 49)
+            || !bits.status_consistent(50, 51, 56)
+        {
+            return None;
+        }
+
+        let figure_of_merit = bits.get(1, 4) as u8;
+        if figure_of_merit > 4 {
+            return None;
+        }
+
+        let wind = bits.flag(5).then(|| {
+            (
+                bits.get(6, 14) as f64,
+                bits.get(15, 23) as f64 * 180.0 / 256.0,
+            )
+        });
+        if wind.is_some_and(|(speed, _)| speed > 250.0) {
+            return None;
+        }
+
+        let static_air_temperature = bits.signed(24, 25, 34) as f64 * 0.25;
+        if !(-80.0..=60.0).contains(&static_air_temperature) {
+            return None;
+        }
+
+        Some(Self {
+            figure_of_merit,
+            wind,
+            static_air_temperature,
+            static_pressure: bits.flag(35).then(|| bits.get(36, 46) as f64),
+            turbulence: bits.flag(47).then(|| bits.get(48, 49) as u8),
+            humidity: bits
+                .flag(50)
+                .then(|| bits.get(51, 56) as f64 * 100.0 / 64.0),
+        })
+    }
+}
+
+/// BDS 5,0 Track and turn report
+///
+/// Doc 9871 Table A-2-80
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrackAndTurnReport {
+    /// Roll angle in degrees, positive is right wing down
+    pub roll_angle: Option<f64>,
+    /// True track angle in degrees
+    pub true_track_angle: Option<f64>,
+    /// Ground speed in kt
+    pub ground_speed: Option<f64>,
+    /// Track angle rate in degrees/s
+    pub track_angle_rate: Option<f64>,
+    /// True airspeed in kt
+    pub true_airspeed: Option<f64>,
+}
+
+impl TrackAndTurnReport {
+    pub fn decode(message: &[u8; 7]) -> Option<Self> {
+        let bits = Bits::new(message)?;
+
+        if !bits.status_consistent(1, 3, 11)
+            || !bits.status_consistent(12, 14, 23)
+            || !bits.status_consistent(24, 25, 34)
+            || !bits.status_consistent(35, 37, 45)
+            || !bits.status_consistent(46, 47, 56)
+        {
+            return None;
+        }
+
+        let roll_angle = bits
+            .flag(1)
+            .then(|| bits.signed(2, 3, 11) as f64 * 45.0 / 256.0);
+        let true_track_angle = bits
+            .flag(12)
+            .then(|| (bits.signed(13, 14, 23) as f64 * 90.0 / 512.0).rem_euclid(360.0));
+        let ground_speed = bits.flag(24).then(|| bits.get(25, 34) as f64 * 2.0);
+        let track_angle_rate = bits
+            .flag(35)
+            .then(|| bits.signed(36, 37, 45) as f64 * 8.0 / 256.0);
+        let true_airspeed = bits.flag(46).then(|| bits.get(47, 56) as f64 * 2.0);
+
+        if roll_angle.is_some_and(|roll_angle| roll_angle.abs() > 50.0)
+            || ground_speed.is_some_and(|ground_speed| ground_speed > 600.0)
+            || true_airspeed.is_some_and(|true_airspeed| true_airspeed > 500.0)
+        {
+            return None;
+        }
+
+        if let (Some(ground_speed), Some(true_airspeed)) = (ground_speed, true_airspeed)
+            && (ground_speed - true_airspeed).abs() > 200.0
+        {
+            return None;
+        }
+
+        Some(Self {
+            roll_angle,
+            true_track_angle,
+            ground_speed,
+            track_angle_rate,
+            true_airspeed,
+        })
+    }
+}
+
+/// BDS 6,0 Heading and speed report
+///
+/// Doc 9871 Table A-2-96
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeadingAndSpeedReport {
+    /// Magnetic heading in degrees
+    pub magnetic_heading: Option<f64>,
+    /// Indicated airspeed in kt
+    pub indicated_airspeed: Option<f64>,
+    pub mach: Option<f64>,
+    /// Barometric altitude rate in ft/min
+    pub barometric_altitude_rate: Option<f64>,
+    /// Inertial vertical velocity in ft/min
+    pub inertial_vertical_velocity: Option<f64>,
+}
+
+impl HeadingAndSpeedReport {
+    pub fn decode(message: &[u8; 7]) -> Option<Self> {
+        let bits = Bits::new(message)?;
+
+        if !bits.status_consistent(1, 3, 12)
+            || !bits.status_consistent(13, 14, 23)
+            || !bits.status_consistent(24, 25, 34)
+            || !bits.status_consistent(35, 36, 45)
+            || !bits.status_consistent(46, 47, 56)
+        {
+            return None;
+        }
+
+        let magnetic_heading = bits
+            .flag(1)
+            .then(|| (bits.signed(2, 3, 12) as f64 * 90.0 / 512.0).rem_euclid(360.0));
+        let indicated_airspeed = bits.flag(13).then(|| bits.get(14, 23) as f64);
+        let mach = bits
+            .flag(24)
+            .then(|| bits.get(25, 34) as f64 * 2.048 / 512.0);
+        let barometric_altitude_rate = bits.flag(35).then(|| bits.signed(36, 37, 45) as f64 * 32.0);
+        let inertial_vertical_velocity =
+            bits.flag(46).then(|| bits.signed(47, 48, 56) as f64 * 32.0);
+
+        if indicated_airspeed.is_some_and(|indicated_airspeed| indicated_airspeed > 500.0)
+            || mach.is_some_and(|mach| mach > 1.0)
+            || barometric_altitude_rate.is_some_and(|rate| rate.abs() > 6000.0)
+            || inertial_vertical_velocity.is_some_and(|rate| rate.abs() > 6000.0)
+        {
+            return None;
+        }
+
+        Some(Self {
+            magnetic_heading,
+            indicated_airspeed,
+            mach,
+            barometric_altitude_rate,
+            inertial_vertical_velocity,
+        })
+    }
+}
+
+/// Helper to access the bits of a Comm-B message.
+///
+/// Bits are numbered 1 to 56 like in Doc 9871.
+#[derive(Clone, Copy, Debug)]
+struct Bits(u64);
+
+impl Bits {
+    /// Returns `None` for an all-zero message, which is valid for any register.
+    fn new(message: &[u8; 7]) -> Option<Self> {
+        let mut bytes = [0; 8];
+        bytes[1..].copy_from_slice(message);
+        let bits = u64::from_be_bytes(bytes);
+        (bits != 0).then_some(Self(bits))
+    }
+
+    /// Returns bits `first..=last`
+    fn get(&self, first: u32, last: u32) -> u64 {
+        let length = last - first + 1;
+        (self.0 >> (56 - last)) & ((1 << length) - 1)
+    }
+
+    fn flag(&self, bit: u32) -> bool {
+        self.get(bit, bit) != 0
+    }
+
+    /// Returns bits `first..=last` as a two's complement number with the sign
+    /// bit at `sign`.
+    fn signed(&self, sign: u32, first: u32, last: u32) -> i64 {
+        let value = self.get(first, last) as i64;
+        if self.flag(sign) {
+            value - (1 << (last - first + 1))
+        }
+        else {
+            value
+        }
+    }
+
+    /// If the status bit is not set, the value bits must be all zero.
+    fn status_consistent(&self, status: u32, first: u32, last: u32) -> bool {
+        self.flag(status) || self.get(first, last) == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use crate::{
+        Frame,
+        commb::{
+            HeadingAndSpeedReport,
+            MeteorologicalRoutineAirReport,
+            Register,
+            TrackAndTurnReport,
+        },
+    };
+
+    fn message(frame: &str) -> [u8; 7] {
+        let bytes = (0..frame.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&frame[i..i + 2], 16).unwrap())
+            .collect::<Vec<u8>>();
+        match Frame::decode(&mut &bytes[..]).unwrap() {
+            Frame::CommBAltitudeReply(reply) => reply.message,
+            Frame::CommBIdentityReply(reply) => reply.message,
+            frame => panic!("unexpected frame: {frame:?}"),
+        }
+    }
+
+    // test vectors are from pyModeS
+
+    #[test]
+    fn it_decodes_track_and_turn_report() {
+        let report = TrackAndTurnReport::decode(&message("A000139381951536E024D4CCF6B5")).unwrap();
+        assert_abs_diff_eq!(report.roll_angle.unwrap(), 2.1, epsilon = 0.01);
+        assert_abs_diff_eq!(report.true_track_angle.unwrap(), 114.258, epsilon = 0.001);
+        assert_eq!(report.ground_speed.unwrap(), 438.0);
+        assert_abs_diff_eq!(report.track_angle_rate.unwrap(), 0.125, epsilon = 0.001);
+        assert_eq!(report.true_airspeed.unwrap(), 424.0);
+    }
+
+    #[test]
+    fn it_decodes_heading_and_speed_report() {
+        let report =
+            HeadingAndSpeedReport::decode(&message("A00004128F39F91A7E27C46ADC21")).unwrap();
+        assert_abs_diff_eq!(report.magnetic_heading.unwrap(), 42.715, epsilon = 0.001);
+        assert_eq!(report.indicated_airspeed.unwrap(), 252.0);
+        assert_abs_diff_eq!(report.mach.unwrap(), 0.42, epsilon = 0.001);
+        assert_eq!(report.barometric_altitude_rate.unwrap(), -1920.0);
+        assert_eq!(report.inertial_vertical_velocity.unwrap(), -1920.0);
+    }
+
+    #[test]
+    fn it_decodes_meteorological_routine_air_report() {
+        let report =
+            MeteorologicalRoutineAirReport::decode(&message("A0001692185BD5CF400000DFC696"))
+                .unwrap();
+        let (speed, direction) = report.wind.unwrap();
+        assert_eq!(speed, 22.0);
+        assert_abs_diff_eq!(direction, 344.5, epsilon = 0.1);
+        assert_abs_diff_eq!(report.static_air_temperature, -48.75, epsilon = 0.01);
+    }
+
+    #[test]
+    fn it_infers_registers() {
+        assert!(matches!(
+            Register::infer(&message("A000139381951536E024D4CCF6B5")),
+            Some(Register::TrackAndTurnReport(_))
+        ));
+        assert!(matches!(
+            Register::infer(&message("A00004128F39F91A7E27C46ADC21")),
+            Some(Register::HeadingAndSpeedReport(_))
+        ));
+        assert!(Register::infer(&[0; 7]).is_none());
+    }
+}
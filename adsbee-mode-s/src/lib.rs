@@ -16,6 +16,7 @@
 
 pub mod acas;
 pub mod adsb;
+pub mod commb;
 pub mod tisb;
 pub mod util;
 
@@ -192,6 +193,10 @@ impl Frame {
     }
 
     /// Decodes a Mode-S frame and calculates its CRC checksum.
+    ///
+    /// The checksum is the CRC of the frame XOR its parity field. It's 0 for
+    /// valid frames with a plain parity, and the address for frames with
+    /// address/parity overlay.
     pub fn decode_and_calculate_checksum<B: Buf>(
         buffer: &mut B,
     ) -> Result<FrameWithChecksum, DecodeError> {
@@ -200,13 +205,19 @@ impl Frame {
         let mut buffer = CrcBuf {
             inner: buffer,
             digest: CRC.digest(),
+            parity: [0; 3],
         };
 
         let frame = Self::decode(&mut buffer)?;
 
         let checksum = buffer.digest.finalize().to_be_bytes();
         assert_eq!(checksum[0], 0);
-        let checksum = Checksum([checksum[1], checksum[2], checksum[3]]);
+        let parity = buffer.parity;
+        let checksum = Checksum([
+            checksum[1] ^ parity[0],
+            checksum[2] ^ parity[1],
+            checksum[3] ^ parity[2],
+        ]);
 
         Ok(FrameWithChecksum { frame, checksum })
     }
@@ -295,6 +306,27 @@ pub struct FrameWithChecksum {
 }
 
 impl FrameWithChecksum {
+    /// Returns the address of the transponder that sent this frame.
+    ///
+    /// For frames with address/parity overlay, the address is the checksum.
+    /// This is only correct if the frame isn't corrupted, which we can't check.
+    /// You might want to only accept addresses that have been seen in frames
+    /// that announce them (e.g. [`AllCallReply`]).
+    pub fn address(&self) -> Option<IcaoAddress> {
+        match &self.frame {
+            Frame::ShortAirAirSurveillance(_)
+            | Frame::SurveillanceAltitudeReply(_)
+            | Frame::SurveillanceIdentityReply(_)
+            | Frame::LongAirAirSurveillance(_)
+            | Frame::CommBAltitudeReply(_)
+            | Frame::CommBIdentityReply(_) => Some(IcaoAddress::from_bytes(self.checksum.0)),
+            Frame::AllCallReply(AllCallReply {
+                address_announced, ..
+            }) => Some(*address_announced),
+            frame => frame.adsb().map(|(address, _)| *address),
+        }
+    }
+
     /// Tries to check if the frame is not corrupted.
     ///
     /// This is only possible for some downlink types (e.g. ADS-B), because some
@@ -967,21 +999,10 @@ pub struct Parity(pub [u8; 3]);
 /// Adress parity
 ///
 /// This is a regular parity overlayed (XOR) with an ICAO address. Assuming the
-/// frame was received uncorrupted, we can recover the address from this.
+/// frame was received uncorrupted, the address is the frame's [`Checksum`].
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct AddressParity(pub [u8; 3]);
 
-impl AddressParity {
-    /// The
-    pub fn recover_address(&self, frame_checksum: &Checksum) -> IcaoAddress {
-        IcaoAddress::from_bytes([
-            self.0[0] ^ frame_checksum.0[0],
-            self.0[1] ^ frame_checksum.0[1],
-            self.0[2] ^ frame_checksum.0[2],
-        ])
-    }
-}
-
 /// The checksum of a frame.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Checksum(pub [u8; 3]);
@@ -1324,11 +1345,17 @@ pub struct CommBAltitudeReply {
     pub downlink_request: DownlinkRequest,
     pub utility_message: UtilityMessage,
     pub altitude_code: AltitudeCode,
-    pub message: [u8; 7], // todo
+    /// Comm-B message. See [`commb`].
+    pub message: [u8; 7],
     pub data_parity: Parity,
 }
 
 impl CommBAltitudeReply {
+    /// Infers the Comm-B register, if the message is unambiguous.
+    pub fn register(&self) -> Option<commb::Register> {
+        commb::Register::infer(&self.message)
+    }
+
     pub fn decode<B: Buf>(buffer: &mut B, bits_6_to_8: u8) -> Self {
         let (flight_status, downlink_request, utility_message, code) =
             decode_surveillance_reply_body(bits_6_to_8, buffer.get_bytes());
@@ -1349,11 +1376,17 @@ pub struct CommBIdentityReply {
     pub downlink_request: DownlinkRequest,
     pub utility_message: UtilityMessage,
     pub identity_code: IdentityCode,
-    pub message: [u8; 7], // todo
+    /// Comm-B message. See [`commb`].
+    pub message: [u8; 7],
     pub data_parity: Parity,
 }
 
 impl CommBIdentityReply {
+    /// Infers the Comm-B register, if the message is unambiguous.
+    pub fn register(&self) -> Option<commb::Register> {
+        commb::Register::infer(&self.message)
+    }
+
     pub fn decode<B: Buf>(buffer: &mut B, bits_6_to_8: u8) -> Self {
         let (flight_status, downlink_request, utility_message, code) =
             decode_surveillance_reply_body(bits_6_to_8, buffer.get_bytes());
@@ -1390,6 +1423,7 @@ mod tests {
         AltitudeCode,
         AltitudeUnit,
         Capability,
+        Checksum,
         ExtendedSquitter,
         Frame,
     };
@@ -1444,4 +1478,21 @@ mod tests {
             _ => panic!("unexpected frame: {frame:?}"),
         }
     }
+
+    #[test]
+    fn it_calculates_checksums() {
+        // DF17 with a valid parity
+        let bytes = b"\x8d\x40\x74\xb5\x23\x15\xa6\x76\xdd\x13\xa0\x66\x29\x67";
+        let frame = Frame::decode_and_calculate_checksum(&mut &bytes[..]).unwrap();
+        assert_eq!(frame.checksum, Checksum::VALID);
+        assert_eq!(frame.check(), Some(true));
+
+        // DF20 with address/parity overlay, from pyModeS
+        let bytes = b"\xa0\x00\x18\x39\xca\x38\x00\x31\x58\x00\x00\x74\x48\xd9";
+        let frame = Frame::decode_and_calculate_checksum(&mut &bytes[..]).unwrap();
+        assert_eq!(
+            frame.address(),
+            Some(IcaoAddress::from_u32_unchecked(0x400940))
+        );
+    }
 }
@@ -139,9 +139,13 @@ pub const CRC_24_MODES: crc::Algorithm<u32> = crc::Algorithm {
 };
 
 /// Wraps a [`Buf`] that calculates the CRC checksum of the read data.
+/// Calculates the CRC of everything read from `inner`, except the last 3 bytes,
+/// which are the parity field of a frame.
 pub struct CrcBuf<'a, B> {
     pub inner: B,
     pub digest: crc::Digest<'a, u32>,
+    /// The last 3 bytes read
+    pub parity: [u8; 3],
 }
 
 impl<'a, B: Buf> Buf for CrcBuf<'a, B> {
@@ -154,8 +158,11 @@ impl<'a, B: Buf> Buf for CrcBuf<'a, B> {
     }
 
     fn advance(&mut self, cnt: usize) {
-        let chunk = &self.inner.chunk()[..cnt];
-        self.digest.update(chunk);
+        // the CRC starts with 0, so the leading zeros don't change it
+        for &byte in &self.inner.chunk()[..cnt] {
+            self.digest.update(&self.parity[..1]);
+            self.parity = [self.parity[1], self.parity[2], byte];
+        }
         self.inner.advance(cnt);
     }
 }
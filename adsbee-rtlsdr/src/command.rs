@@ -1,32 +1,30 @@
 use std::process::Stdio;
 
-use tokio::{
-    io::{
-        AsyncBufReadExt,
-        BufReader,
-    },
-    process::{
-        ChildStdout,
-        Command,
-    },
+use adsbee_avr as avr;
+use futures_util::TryStreamExt;
+use tokio::process::{
+    Child,
+    ChildStdout,
+    Command,
 };
 
-use crate::RawFrame;
+use crate::Frame;
 
 #[derive(Debug, thiserror::Error)]
 #[error("rtl_adsb error")]
 pub enum Error {
     Io(#[from] std::io::Error),
-    InvalidLine(String),
+    Avr(#[from] avr::Error),
 }
 
 /// Quick and dirty demodulator.
 ///
-/// Spawns `rtl_adsb` and reads its output.
+/// Spawns `rtl_adsb` and reads its output, which is in AVR format.
 #[derive(Debug)]
 pub struct RtlAdsbCommand {
-    stdout: BufReader<ChildStdout>,
-    buffer: String,
+    /// the process is killed when this is dropped
+    _process: Child,
+    reader: avr::Reader<ChildStdout>,
 }
 
 impl RtlAdsbCommand {
@@ -38,36 +36,24 @@ impl RtlAdsbCommand {
             .stderr(Stdio::null())
             .kill_on_drop(true)
             .spawn()?;
-        let stdout = BufReader::new(process.stdout.take().expect("missing stdout"));
+        let stdout = process.stdout.take().expect("missing stdout");
         Ok(Self {
-            stdout,
-            buffer: String::with_capacity(128),
+            _process: process,
+            reader: avr::Reader::new(stdout),
         })
     }
 
-    pub async fn next(&mut self) -> Result<Option<RawFrame>, Error> {
-        loop {
-            self.buffer.clear();
-            if self.stdout.read_line(&mut self.buffer).await? == 0 {
-                return Ok(None);
-            }
+    pub async fn next(&mut self) -> Result<Option<Frame>, Error> {
+        let Some(frame) = self.reader.try_next().await?
+        else {
+            return Ok(None);
+        };
 
-            let line = self.buffer.trim();
-            match line.len() {
-                16 => {
-                    let mut data = [0; 7];
-                    hex::decode_to_slice(&self.buffer[1..15], &mut data)
-                        .map_err(|_| Error::InvalidLine(line.to_owned()))?;
-                    return Ok(Some(RawFrame::ModeSShort { data }));
-                }
-                30 => {
-                    let mut data = [0; 14];
-                    hex::decode_to_slice(&self.buffer[1..29], &mut data)
-                        .map_err(|_| Error::InvalidLine(line.to_owned()))?;
-                    return Ok(Some(RawFrame::ModeSLong { data }));
-                }
-                _ => {}
-            }
-        }
+        let frame = match frame.data {
+            avr::FrameData::ModeAc(data) => Frame::ModeAc { data },
+            avr::FrameData::ModeSShort(data) => Frame::ModeSShort { data },
+            avr::FrameData::ModeSLong(data) => Frame::ModeSLong { data },
+        };
+        Ok(Some(frame))
     }
 }
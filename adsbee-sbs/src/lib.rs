@@ -7,176 +7,76 @@
 //! [3]: https://web.archive.org/web/20150107063617/http://www.homepages.mcb.net/bones/SBS/Article/Barebones42_Socket_Data.htm
 
 use std::{
-    pin::Pin,
+    fmt::{
+        self,
+        Display,
+    },
     str::{
         FromStr,
         Utf8Error,
     },
-    task::{
-        Context,
-        Poll,
-    },
 };
 
+use adsbee_line as line;
+use adsbee_mode_s::{
+    self as mode_s,
+    adsb,
+};
 use adsbee_types::{
+    Callsign,
+    CallsignFromStrError,
     IcaoAddress,
     Squawk,
     SquawkFromStrError,
 };
 use chrono::{
     DateTime,
+    Datelike,
     NaiveDate,
     NaiveTime,
+    Timelike,
     Utc,
 };
-use futures_util::Stream;
-use pin_project_lite::pin_project;
-use tokio::io::{
-    AsyncRead,
-    ReadBuf,
-};
 
-const RECEIVE_BUFFER_SIZE: usize = 1024;
+/// Lines longer than this are skipped.
+const MAX_LINE_LENGTH: usize = 1024;
 
 #[derive(Debug, thiserror::Error)]
 #[error("sbs decode error")]
 pub enum Error {
-    Io(#[from] std::io::Error),
-    MaxLineLengthExceeded,
+    Line(#[from] line::Error),
     InvalidEncoding(#[from] Utf8Error),
     InvalidMessage(#[from] MessageFromStrError),
 }
 
-pin_project! {
-    #[derive(Debug)]
-    pub struct Reader<R> {
-        #[pin]
-        reader: R,
-        receive_buffer: ReceiveBuffer,
-    }
-}
+/// Incremental decoder for SBS messages. See [`line::Decoder`].
+pub type Decoder = line::Decoder<Message>;
 
-impl<R: AsyncRead> Reader<R> {
-    pub fn new(reader: R) -> Self {
-        Self {
-            reader,
-            receive_buffer: ReceiveBuffer::default(),
-        }
-    }
-}
-
-impl<R: AsyncRead> Stream for Reader<R> {
-    type Item = Result<Message, Error>;
+/// Reads SBS messages from a tokio `AsyncRead`.
+pub type Reader<R> = line::Reader<R, Message>;
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        loop {
-            let this = self.as_mut().project();
+/// Reads SBS messages from a `futures_io::AsyncRead`.
+#[cfg(feature = "futures-io")]
+pub type FuturesReader<R> = line::FuturesReader<R, Message>;
 
-            if let Some(line) = this.receive_buffer.next_line() {
-                // note: SBS seems to use `\r\n` for newlines, but we split lines at either. so
-                // we accept `\r` or `\n`, and `\r\n` will produce an empty line, which we
-                // ignore.
-                //
-                // note: readsb also sends empty lines as heartbeat messages
-                // https://github.com/wiedehopf/readsb/blob/75decb53c0e66f4c12cf24127578a3fe7d919219/net_io.c#L110
-                if !line.is_empty() {
-                    match str::from_utf8(line) {
-                        Ok(line) => {
-                            tracing::trace!("parsing: {line}");
-                            match line.parse() {
-                                Ok(message) => {
-                                    return Poll::Ready(Some(Ok(message)));
-                                }
-                                Err(error) => return Poll::Ready(Some(Err(error.into()))),
-                            }
-                        }
-                        Err(error) => {
-                            return Poll::Ready(Some(Err(error.into())));
-                        }
-                    }
-                }
-            }
-            else {
-                this.receive_buffer.prepare_read();
-                let mut read_buf =
-                    ReadBuf::new(&mut this.receive_buffer.buffer[this.receive_buffer.write_pos..]);
-                match this.reader.poll_read(cx, &mut read_buf) {
-                    Poll::Pending => return Poll::Pending,
-                    Poll::Ready(Err(error)) => return Poll::Ready(Some(Err(error.into()))),
-                    Poll::Ready(Ok(())) => {
-                        let num_bytes_read = read_buf.filled().len();
-                        if num_bytes_read == 0 {
-                            return Poll::Ready(None);
-                        }
+/// Reads SBS messages from a blocking [`std::io::Read`].
+pub type BlockingReader<R> = line::BlockingReader<R, Message>;
 
-                        this.receive_buffer.write_pos += num_bytes_read;
-                    }
-                }
-            }
-        }
-    }
-}
+/// Writes SBS messages to a tokio `AsyncWrite`.
+///
+/// Each message is terminated with `\r\n`, like readsb does.
+pub type Writer<W> = line::Writer<W, Message>;
 
-#[derive(Debug)]
-struct ReceiveBuffer {
-    buffer: [u8; RECEIVE_BUFFER_SIZE],
-    read_pos: usize,
-    write_pos: usize,
-    no_newline_until: usize,
-}
+impl line::Line for Message {
+    type Error = Error;
 
-impl ReceiveBuffer {
-    fn scan_for_newline(&mut self) -> Option<usize> {
-        tracing::trace!(
-            ?self.read_pos, ?self.write_pos, ?self.no_newline_until,
-            "scanning for newline",
-        );
+    const MAX_LENGTH: usize = MAX_LINE_LENGTH;
+    const LINE_ENDING: &'static str = "\r\n";
 
-        if let Some(index) = self.buffer[self.no_newline_until..self.write_pos]
-            .iter()
-            .position(|byte| *byte == b'\r' || *byte == b'\n')
-        {
-            let index = index + self.no_newline_until;
-            self.no_newline_until = index;
-            Some(index)
-        }
-        else {
-            self.no_newline_until = self.write_pos;
-            None
-        }
-    }
-
-    fn next_line(&mut self) -> Option<&[u8]> {
-        if let Some(newline) = self.scan_for_newline() {
-            let start = self.read_pos;
-            self.read_pos = newline + 1;
-            self.no_newline_until = self.read_pos;
-            Some(&self.buffer[start..newline])
-        }
-        else {
-            None
-        }
-    }
-
-    fn prepare_read(&mut self) {
-        if self.read_pos < self.write_pos && self.read_pos > 0 {
-            // move data
-            self.buffer.copy_within(self.read_pos..self.write_pos, 0);
-            self.write_pos -= self.read_pos;
-            self.no_newline_until -= self.read_pos;
-            self.read_pos = 0;
-        }
-    }
-}
-
-impl Default for ReceiveBuffer {
-    fn default() -> Self {
-        Self {
-            buffer: [0; RECEIVE_BUFFER_SIZE],
-            read_pos: 0,
-            write_pos: 0,
-            no_newline_until: 0,
-        }
+    fn parse_line(line: &[u8]) -> Result<Self, Self::Error> {
+        let line = str::from_utf8(line)?;
+        tracing::trace!("parsing: {line}");
+        Ok(line.parse()?)
     }
 }
 
@@ -189,7 +89,7 @@ pub enum Message {
         flight_id: u32,
         time_generated: DateTime<Utc>,
         time_logged: DateTime<Utc>,
-        callsign: String,
+        callsign: Callsign,
     },
     NewId {
         session_id: u32,
@@ -198,7 +98,7 @@ pub enum Message {
         flight_id: u32,
         time_generated: DateTime<Utc>,
         time_logged: DateTime<Utc>,
-        callsign: String,
+        callsign: Callsign,
     },
     NewAircraft {
         session_id: u32,
@@ -268,6 +168,114 @@ impl Message {
     }
 }
 
+impl Display for Message {
+    /// Formats the message as a line, without the line ending.
+    ///
+    /// `MSG` lines are formatted exactly like readsb formats them.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SelectionChange {
+                session_id,
+                aircraft_id,
+                hex_ident,
+                flight_id,
+                time_generated,
+                time_logged,
+                callsign,
+            } => {
+                write!(
+                    f,
+                    "SEL,,{session_id},{aircraft_id},{},{flight_id},{},{},{callsign}",
+                    HexIdent(hex_ident),
+                    DateAndTime(time_generated),
+                    DateAndTime(time_logged),
+                )
+            }
+            Self::NewId {
+                session_id,
+                aircraft_id,
+                hex_ident,
+                flight_id,
+                time_generated,
+                time_logged,
+                callsign,
+            } => {
+                write!(
+                    f,
+                    "ID,,{session_id},{aircraft_id},{},{flight_id},{},{},{callsign}",
+                    HexIdent(hex_ident),
+                    DateAndTime(time_generated),
+                    DateAndTime(time_logged),
+                )
+            }
+            Self::NewAircraft {
+                session_id,
+                aircraft_id,
+                hex_ident,
+                flight_id,
+                time_generated,
+                time_logged,
+            } => {
+                write!(
+                    f,
+                    "AIR,,{session_id},{aircraft_id},{},{flight_id},{},{}",
+                    HexIdent(hex_ident),
+                    DateAndTime(time_generated),
+                    DateAndTime(time_logged),
+                )
+            }
+            Self::StatusChange {
+                session_id,
+                aircraft_id,
+                hex_ident,
+                flight_id,
+                time_generated,
+                time_logged,
+                status_change,
+            } => {
+                write!(
+                    f,
+                    "STA,,{session_id},{aircraft_id},{},{flight_id},{},{},{status_change}",
+                    HexIdent(hex_ident),
+                    DateAndTime(time_generated),
+                    DateAndTime(time_logged),
+                )
+            }
+            Self::Click {
+                session_id,
+                time_generated,
+                time_logged,
+            } => {
+                write!(
+                    f,
+                    "CLK,,{session_id},-1,,-1,{},{}",
+                    DateAndTime(time_generated),
+                    DateAndTime(time_logged),
+                )
+            }
+            Self::Transmission {
+                session_id,
+                aircraft_id,
+                hex_ident,
+                flight_id,
+                time_generated,
+                time_logged,
+                transmission,
+            } => {
+                write!(
+                    f,
+                    "MSG,{},{session_id},{aircraft_id},{},{flight_id},{},{},{}",
+                    transmission.transmission_type(),
+                    HexIdent(hex_ident),
+                    DateAndTime(time_generated),
+                    DateAndTime(time_logged),
+                    transmission.fields(),
+                )
+            }
+        }
+    }
+}
+
 impl FromStr for Message {
     type Err = MessageFromStrError;
 
@@ -350,7 +358,8 @@ impl FromStr for Message {
         let message = match message_type {
             "SEL" => {
                 let callsign =
-                    parse_callsign(fields.next().ok_or(MessageFromStrError::Truncated)?)?;
+                    parse_callsign(fields.next().ok_or(MessageFromStrError::Truncated)?)?
+                        .ok_or(MessageFromStrError::MissingCallsign)?;
                 Self::SelectionChange {
                     session_id,
                     aircraft_id: aircraft_id()?,
@@ -363,7 +372,8 @@ impl FromStr for Message {
             }
             "ID" => {
                 let callsign =
-                    parse_callsign(fields.next().ok_or(MessageFromStrError::Truncated)?)?;
+                    parse_callsign(fields.next().ok_or(MessageFromStrError::Truncated)?)?
+                        .ok_or(MessageFromStrError::MissingCallsign)?;
                 Self::NewId {
                     session_id,
                     aircraft_id: aircraft_id()?,
@@ -421,112 +431,64 @@ impl FromStr for Message {
                 let spi = fields.next().ok_or(MessageFromStrError::Truncated)?;
                 let is_on_ground = fields.next().ok_or(MessageFromStrError::Truncated)?;
 
-                fn parse_bool(s: &str) -> Option<bool> {
+                fn parse_flag<T: From<bool>>(
+                    s: &str,
+                    error: impl FnOnce(String) -> MessageFromStrError,
+                ) -> Result<Option<T>, MessageFromStrError> {
                     match s {
-                        "0" => Some(false),
-                        "-1" => Some(true),
-                        _ => None,
+                        "" => Ok(None),
+                        "0" => Ok(Some(false.into())),
+                        "-1" => Ok(Some(true.into())),
+                        _ => Err(error(s.to_owned())),
                     }
                 }
 
                 let altitude = || {
-                    if altitude.is_empty() {
-                        Ok(None)
-                    }
-                    else {
-                        altitude
-                            .parse()
-                            .map_err(|_| {
-                                MessageFromStrError::InvalidAltitude {
-                                    value: altitude.to_owned(),
-                                }
-                            })
-                            .map(Some)
-                    }
-                };
-                let ground_speed = || {
-                    ground_speed.parse().map_err(|_| {
-                        MessageFromStrError::InvalidGroundSpeed {
-                            value: ground_speed.to_owned(),
-                        }
+                    parse_optional(altitude, |value| {
+                        MessageFromStrError::InvalidAltitude { value }
                     })
                 };
-                let track = || {
-                    track.parse().map_err(|_| {
-                        MessageFromStrError::InvalidTrack {
-                            value: track.to_owned(),
-                        }
+                let ground_speed = || {
+                    parse_optional(ground_speed, |value| {
+                        MessageFromStrError::InvalidGroundSpeed { value }
                     })
                 };
+                let track =
+                    || parse_optional(track, |value| MessageFromStrError::InvalidTrack { value });
                 let latitude = || {
-                    latitude.parse().map_err(|_| {
-                        MessageFromStrError::InvalidLatitude {
-                            value: latitude.to_owned(),
-                        }
+                    parse_optional(latitude, |value| {
+                        MessageFromStrError::InvalidLatitude { value }
                     })
                 };
                 let longitude = || {
-                    longitude.parse().map_err(|_| {
-                        MessageFromStrError::InvalidLongitude {
-                            value: longitude.to_owned(),
-                        }
+                    parse_optional(longitude, |value| {
+                        MessageFromStrError::InvalidLongitude { value }
                     })
                 };
                 let vertical_rate = || {
-                    vertical_rate.parse().map_err(|_| {
-                        MessageFromStrError::InvalidVerticalRate {
-                            value: vertical_rate.to_owned(),
-                        }
+                    parse_optional(vertical_rate, |value| {
+                        MessageFromStrError::InvalidVerticalRate { value }
                     })
                 };
                 let squawk = || squawk.parse();
-                let alert = || {
-                    alert.parse().map_err(|_| {
-                        MessageFromStrError::InvalidAlert {
-                            value: alert.to_owned(),
-                        }
-                    })
-                };
+                let alert =
+                    || parse_flag(alert, |value| MessageFromStrError::InvalidAlert { value });
                 let emergency = || {
-                    emergency.parse().map_err(|_| {
-                        MessageFromStrError::InvalidEmergency {
-                            value: emergency.to_owned(),
-                        }
+                    parse_flag(emergency, |value| {
+                        MessageFromStrError::InvalidEmergency { value }
                     })
                 };
-                let spi = || {
-                    if spi.is_empty() {
-                        Ok(None)
-                    }
-                    else {
-                        parse_bool(spi)
-                            .ok_or_else(|| {
-                                MessageFromStrError::InvalidSpi {
-                                    value: spi.to_owned(),
-                                }
-                            })
-                            .map(Some)
-                    }
-                };
+                let spi = || parse_flag(spi, |value| MessageFromStrError::InvalidSpi { value });
                 let is_on_ground = || {
-                    if is_on_ground.is_empty() {
-                        Ok(None)
-                    }
-                    else {
-                        parse_bool(is_on_ground)
-                            .ok_or_else(|| {
-                                MessageFromStrError::InvalidIsOnGround {
-                                    value: is_on_ground.to_owned(),
-                                }
-                            })
-                            .map(Some)
-                    }
+                    parse_flag(is_on_ground, |value| {
+                        MessageFromStrError::InvalidIsOnGround { value }
+                    })
                 };
 
                 let transmission = match transmission_type {
                     "1" => {
                         Transmission::EsIdentificationAndCategory {
-                            callsign: callsign.to_owned(),
+                            callsign: callsign.ok_or(MessageFromStrError::MissingCallsign)?,
                         }
                     }
                     "2" => {
@@ -627,6 +589,14 @@ pub enum MessageFromStrError {
     InvalidAircraftId {
         value: String,
     },
+    #[error("invalid callsign: {value}")]
+    InvalidCallsign {
+        value: String,
+        #[source]
+        error: CallsignFromStrError,
+    },
+    #[error("missing callsign")]
+    MissingCallsign,
     #[error("invalid hex ident: {value}")]
     InvalidHexIdent {
         value: String,
@@ -735,60 +705,104 @@ impl FromStr for StatusChange {
     }
 }
 
+impl Display for StatusChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::PositionLost => "PL",
+            Self::SignalLost => "SL",
+            Self::Remove => "RM",
+            Self::Delete => "AD",
+            Self::Ok => "OK",
+        };
+        write!(f, "{s}")
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 #[error("invalid status change")]
 pub struct StatusChangeFromStrError {
     pub input: String,
 }
 
-fn parse_callsign(s: &str) -> Result<String, MessageFromStrError> {
-    // todo: check the callsign
-    Ok(s.replace('@', " "))
+/// Parses an optional field. Empty fields are `None`.
+fn parse_optional<T: FromStr>(
+    s: &str,
+    error: impl FnOnce(String) -> MessageFromStrError,
+) -> Result<Option<T>, MessageFromStrError> {
+    if s.is_empty() {
+        Ok(None)
+    }
+    else {
+        s.parse().map(Some).map_err(|_| error(s.to_owned()))
+    }
+}
+
+/// Parses a callsign field. Empty fields are `None`.
+///
+/// readsb might pad callsigns with `@`, which we strip together with trailing
+/// spaces. Anything else must be a valid callsign.
+fn parse_callsign(s: &str) -> Result<Option<Callsign>, MessageFromStrError> {
+    let trimmed = s.trim_end_matches(['@', ' ']);
+    if trimmed.is_empty() {
+        Ok(None)
+    }
+    else {
+        trimmed.parse().map(Some).map_err(|error| {
+            MessageFromStrError::InvalidCallsign {
+                value: s.to_owned(),
+                error,
+            }
+        })
+    }
 }
 
+/// The fields of `MSG` messages.
+///
+/// Which fields are populated depends on the transmission type. Fields that
+/// the sender didn't know are `None`.
 #[derive(Clone, Debug)]
 pub enum Transmission {
     EsIdentificationAndCategory {
-        callsign: String,
+        callsign: Callsign,
     },
     EsSurfacePosition {
-        altitude: Option<u32>,
-        ground_speed: f64,
-        track: f64,
-        latitude: f64,
-        longitude: f64,
+        altitude: Option<i32>,
+        ground_speed: Option<f64>,
+        track: Option<f64>,
+        latitude: Option<f64>,
+        longitude: Option<f64>,
         is_on_ground: Option<bool>,
     },
     EsAirbornePosition {
-        altitude: Option<u32>,
-        latitude: f64,
-        longitude: f64,
-        alert: Alert,
-        emergency: Emergency,
-        spi: Option<bool>,
+        altitude: Option<i32>,
+        latitude: Option<f64>,
+        longitude: Option<f64>,
+        alert: Option<Alert>,
+        emergency: Option<Emergency>,
+        spi: Option<Spi>,
         is_on_ground: Option<bool>,
     },
     EsAirborneVelocity {
-        ground_speed: f64,
-        track: f64,
-        vertical_rate: i32,
+        ground_speed: Option<f64>,
+        track: Option<f64>,
+        vertical_rate: Option<i32>,
     },
     SurveillanceAltMessage {
-        altitude: Option<u32>,
-        alert: Alert,
-        spi: Option<bool>,
+        altitude: Option<i32>,
+        alert: Option<Alert>,
+        spi: Option<Spi>,
         is_on_ground: Option<bool>,
     },
     SurveillanceIdMessage {
-        altitude: Option<u32>,
+        altitude: Option<i32>,
         squawk: Squawk,
-        alert: Alert,
-        emergency: Emergency,
-        spi: Option<bool>,
+        alert: Option<Alert>,
+        emergency: Option<Emergency>,
+        spi: Option<Spi>,
         is_on_ground: Option<bool>,
     },
     AirToAirMessage {
-        altitude: Option<u32>,
+        altitude: Option<i32>,
         is_on_ground: Option<bool>,
     },
     AllCallReply {
@@ -796,17 +810,381 @@ pub enum Transmission {
     },
 }
 
-// todo: what do the values mean? make this an enum
-pub type Alert = u8;
-pub type Emergency = u16;
+impl Transmission {
+    /// The transmission type field, 1 to 8.
+    pub fn transmission_type(&self) -> u8 {
+        match self {
+            Self::EsIdentificationAndCategory { .. } => 1,
+            Self::EsSurfacePosition { .. } => 2,
+            Self::EsAirbornePosition { .. } => 3,
+            Self::EsAirborneVelocity { .. } => 4,
+            Self::SurveillanceAltMessage { .. } => 5,
+            Self::SurveillanceIdMessage { .. } => 6,
+            Self::AirToAirMessage { .. } => 7,
+            Self::AllCallReply { .. } => 8,
+        }
+    }
+
+    fn fields(&self) -> TransmissionFields<'_> {
+        let mut fields = TransmissionFields::default();
+        match self {
+            Self::EsIdentificationAndCategory { callsign } => {
+                fields.callsign = Some(callsign);
+            }
+            Self::EsSurfacePosition {
+                altitude,
+                ground_speed,
+                track,
+                latitude,
+                longitude,
+                is_on_ground,
+            } => {
+                fields.altitude = *altitude;
+                fields.ground_speed = *ground_speed;
+                fields.track = *track;
+                fields.latitude = *latitude;
+                fields.longitude = *longitude;
+                fields.is_on_ground = *is_on_ground;
+            }
+            Self::EsAirbornePosition {
+                altitude,
+                latitude,
+                longitude,
+                alert,
+                emergency,
+                spi,
+                is_on_ground,
+            } => {
+                fields.altitude = *altitude;
+                fields.latitude = *latitude;
+                fields.longitude = *longitude;
+                fields.alert = *alert;
+                fields.emergency = *emergency;
+                fields.spi = *spi;
+                fields.is_on_ground = *is_on_ground;
+            }
+            Self::EsAirborneVelocity {
+                ground_speed,
+                track,
+                vertical_rate,
+            } => {
+                fields.ground_speed = *ground_speed;
+                fields.track = *track;
+                fields.vertical_rate = *vertical_rate;
+            }
+            Self::SurveillanceAltMessage {
+                altitude,
+                alert,
+                spi,
+                is_on_ground,
+            } => {
+                fields.altitude = *altitude;
+                fields.alert = *alert;
+                fields.spi = *spi;
+                fields.is_on_ground = *is_on_ground;
+            }
+            Self::SurveillanceIdMessage {
+                altitude,
+                squawk,
+                alert,
+                emergency,
+                spi,
+                is_on_ground,
+            } => {
+                fields.altitude = *altitude;
+                fields.squawk = Some(*squawk);
+                fields.alert = *alert;
+                fields.emergency = *emergency;
+                fields.spi = *spi;
+                fields.is_on_ground = *is_on_ground;
+            }
+            Self::AirToAirMessage {
+                altitude,
+                is_on_ground,
+            } => {
+                fields.altitude = *altitude;
+                fields.is_on_ground = *is_on_ground;
+            }
+            Self::AllCallReply { is_on_ground } => {
+                fields.is_on_ground = *is_on_ground;
+            }
+        }
+        fields
+    }
+}
+
+/// Fields 11 to 22 of a `MSG` message.
+#[derive(Debug, Default)]
+struct TransmissionFields<'a> {
+    callsign: Option<&'a Callsign>,
+    altitude: Option<i32>,
+    ground_speed: Option<f64>,
+    track: Option<f64>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    vertical_rate: Option<i32>,
+    squawk: Option<Squawk>,
+    alert: Option<Alert>,
+    emergency: Option<Emergency>,
+    spi: Option<Spi>,
+    is_on_ground: Option<bool>,
+}
+
+impl Display for TransmissionFields<'_> {
+    /// Formats the fields like readsb does.
+    ///
+    /// <https://github.com/wiedehopf/readsb/blob/75decb53c0e66f4c12cf24127578a3fe7d919219/net_io.c#L3318>
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(callsign) = self.callsign {
+            // readsb sends the callsign with its padding
+            write!(f, "{}", callsign.as_str())?;
+        }
+        write!(f, ",")?;
+        if let Some(altitude) = self.altitude {
+            write!(f, "{altitude}")?;
+        }
+        write!(f, ",")?;
+        if let Some(ground_speed) = self.ground_speed {
+            write!(f, "{ground_speed:.0}")?;
+        }
+        write!(f, ",")?;
+        if let Some(track) = self.track {
+            write!(f, "{track:.0}")?;
+        }
+        write!(f, ",")?;
+        match (self.latitude, self.longitude) {
+            (Some(latitude), Some(longitude)) => write!(f, "{latitude:.5},{longitude:.5},")?,
+            _ => write!(f, ",,")?,
+        }
+        if let Some(vertical_rate) = self.vertical_rate {
+            write!(f, "{vertical_rate}")?;
+        }
+        write!(f, ",")?;
+        if let Some(squawk) = self.squawk {
+            write!(f, "{squawk}")?;
+        }
+        write!(f, ",")?;
+        if let Some(alert) = self.alert {
+            write!(f, "{}", Flag(alert.into()))?;
+        }
+        write!(f, ",")?;
+        if let Some(emergency) = self.emergency {
+            write!(f, "{}", Flag(emergency.into()))?;
+        }
+        write!(f, ",")?;
+        if let Some(spi) = self.spi {
+            write!(f, "{}", Flag(spi.into()))?;
+        }
+        write!(f, ",")?;
+        if let Some(is_on_ground) = self.is_on_ground {
+            write!(f, "{}", Flag(is_on_ground))?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats a flag as `-1` (true) or `0` (false).
+struct Flag(bool);
+
+impl Display for Flag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 {
+            write!(f, "-1")
+        }
+        else {
+            write!(f, "0")
+        }
+    }
+}
+
+/// Formats an address the way readsb does: upper case, and prefixed with `~`
+/// if it's not an ICAO address.
+struct HexIdent<'a>(&'a IcaoAddress);
+
+impl Display for HexIdent<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.non_icao() {
+            write!(f, "~")?;
+        }
+        write!(f, "{:06X}", u32::from(*self.0))
+    }
+}
+
+/// Formats the date and time fields.
+struct DateAndTime<'a>(&'a DateTime<Utc>);
+
+impl Display for DateAndTime<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = self.0;
+        write!(
+            f,
+            "{:04}/{:02}/{:02},{:02}:{:02}:{:02}.{:03}",
+            time.year(),
+            time.month(),
+            time.day(),
+            time.hour(),
+            time.minute(),
+            time.second(),
+            // leap seconds are represented as nanoseconds >= 1s
+            (time.nanosecond() / 1_000_000).min(999),
+        )
+    }
+}
+
+/// Field 19 of a `MSG` message: whether the squawk was changed.
+///
+/// readsb sets this from the alert bit in the flight status of Mode S replies,
+/// which is set for 18 seconds after the pilot changes the squawk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Alert {
+    /// Encoded as `0`.
+    NoAlert,
+    /// Encoded as `-1`.
+    SquawkChanged,
+}
+
+impl Alert {
+    pub fn from_flight_status(flight_status: mode_s::FlightStatus) -> Self {
+        flight_status.alert().into()
+    }
+}
+
+impl From<bool> for Alert {
+    fn from(value: bool) -> Self {
+        if value {
+            Self::SquawkChanged
+        }
+        else {
+            Self::NoAlert
+        }
+    }
+}
+
+impl From<Alert> for bool {
+    fn from(value: Alert) -> Self {
+        value == Alert::SquawkChanged
+    }
+}
+
+/// Field 20 of a `MSG` message: whether an emergency squawk is set.
+///
+/// The emergency squawks are 7500 (hijacking), 7600 (radio failure) and 7700
+/// (general emergency).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Emergency {
+    /// Encoded as `0`.
+    NoEmergency,
+    /// Encoded as `-1`.
+    Emergency,
+}
+
+impl Emergency {
+    pub fn from_squawk(squawk: Squawk) -> Self {
+        adsb::EmergencyPriorityStatus::from_squawk(squawk)
+            .is_some()
+            .into()
+    }
+
+    /// Returns the ADS-B emergency status.
+    ///
+    /// SBS doesn't say which emergency it is, so this uses the squawk if it's
+    /// known, and falls back to a general emergency.
+    pub fn emergency_priority_status(
+        &self,
+        squawk: Option<Squawk>,
+    ) -> adsb::EmergencyPriorityStatus {
+        match self {
+            Self::NoEmergency => adsb::EmergencyPriorityStatus::NO_EMERGENCY,
+            Self::Emergency => {
+                squawk
+                    .and_then(adsb::EmergencyPriorityStatus::from_squawk)
+                    .unwrap_or(adsb::EmergencyPriorityStatus::GENERAL_EMERGENCY)
+            }
+        }
+    }
+}
+
+/// Only the statuses that correspond to an emergency squawk set the flag.
+/// Lifeguard, minimum fuel and downed aircraft don't (same as readsb).
+impl From<adsb::EmergencyPriorityStatus> for Emergency {
+    fn from(value: adsb::EmergencyPriorityStatus) -> Self {
+        matches!(
+            value,
+            adsb::EmergencyPriorityStatus::GENERAL_EMERGENCY
+                | adsb::EmergencyPriorityStatus::NO_COMMUNICATIONS
+                | adsb::EmergencyPriorityStatus::UNLAWFUL_INTERFERENCE
+        )
+        .into()
+    }
+}
+
+impl From<bool> for Emergency {
+    fn from(value: bool) -> Self {
+        if value {
+            Self::Emergency
+        }
+        else {
+            Self::NoEmergency
+        }
+    }
+}
+
+impl From<Emergency> for bool {
+    fn from(value: Emergency) -> Self {
+        value == Emergency::Emergency
+    }
+}
+
+/// Field 21 of a `MSG` message: whether the pilot pressed the ident button.
+///
+/// This is the special position identification (SPI) bit in the flight status
+/// of Mode S replies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Spi {
+    /// Encoded as `0`.
+    NoIdent,
+    /// Encoded as `-1`.
+    Ident,
+}
+
+impl Spi {
+    pub fn from_flight_status(flight_status: mode_s::FlightStatus) -> Self {
+        flight_status.spi().into()
+    }
+}
+
+impl From<bool> for Spi {
+    fn from(value: bool) -> Self {
+        if value { Self::Ident } else { Self::NoIdent }
+    }
+}
+
+impl From<Spi> for bool {
+    fn from(value: Spi) -> Self {
+        value == Spi::Ident
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    use futures_util::TryStreamExt;
+    use adsbee_line as line;
+    use adsbee_mode_s::adsb;
+    use futures_util::{
+        SinkExt,
+        TryStreamExt,
+    };
 
     use crate::{
+        Alert,
+        BlockingReader,
+        Decoder,
+        Emergency,
+        Error,
         Message,
         Reader,
+        Spi,
+        Transmission,
+        Writer,
     };
 
     const EXAMPLE: &'static str = r#"SEL,,496,2286,4CA4E5,27215,2010/02/19,18:06:07.710,2010/02/19,18:06:07.710,RYR1427
@@ -822,6 +1200,17 @@ MSG,5,496,329,394A65,27868,2010/02/19,17:58:12.644,2010/02/19,17:58:13.368,,1000
 MSG,6,496,237,4CA215,27864,2010/02/19,17:58:12.846,2010/02/19,17:58:13.368,,33325,,,,,,0271,0,0,0,0
 MSG,7,496,742,51106E,27929,2011/03/06,07:57:36.523,2011/03/06,07:57:37.054,,3775,,,,,,,,,,0
 MSG,8,496,194,405F4E,27884,2010/02/19,17:58:13.244,2010/02/19,17:58:13.368,,,,,,,,,,,,0
+"#;
+
+    /// Lines as readsb sends them
+    const READSB_EXAMPLE: &str = r#"MSG,1,1,1,4CA2D6,1,2024/05/01,12:00:00.123,2024/05/01,12:00:00.150,RYR1427 ,,,,,,,,,,,
+MSG,2,1,1,4CA2D6,1,2024/05/01,12:00:00.123,2024/05/01,12:00:00.150,,,12,183,51.47017,-0.45420,,,,,,-1
+MSG,3,1,1,4CA2D6,1,2024/05/01,12:00:00.123,2024/05/01,12:00:00.150,,37000,,,51.45735,-1.02826,,,,,,0
+MSG,4,1,1,4CA2D6,1,2024/05/01,12:00:00.123,2024/05/01,12:00:00.150,,,450,273,,,-832,,,,,
+MSG,5,1,1,~4CA2D6,1,2024/05/01,12:00:00.123,2024/05/01,12:00:00.150,,37025,,,,,,,0,,0,0
+MSG,6,1,1,4CA2D6,1,2024/05/01,12:00:00.123,2024/05/01,12:00:00.150,,,,,,,,7700,0,-1,0,0
+MSG,7,1,1,4CA2D6,1,2024/05/01,12:00:00.123,2024/05/01,12:00:00.150,,21000,,,,,,,,,,0
+MSG,8,1,1,4CA2D6,1,2024/05/01,12:00:00.123,2024/05/01,12:00:00.150,,,,,,,,,,,,-1
 "#;
 
     #[test]
@@ -833,6 +1222,32 @@ MSG,8,496,194,405F4E,27884,2010/02/19,17:58:13.244,2010/02/19,17:58:13.368,,,,,,
         }
     }
 
+    #[test]
+    fn it_validates_callsigns() {
+        let message: Message = "MSG,1,145,256,7404F2,11267,2008/11/28,23:48:18.611,2008/11/28,23:53:19.161,RJA1118 ,,,,,,,,,,,"
+            .parse()
+            .unwrap();
+        let Message::Transmission {
+            transmission: Transmission::EsIdentificationAndCategory { callsign },
+            ..
+        } = message
+        else {
+            panic!("unexpected message: {message:?}");
+        };
+        assert_eq!(callsign.trimmed(), "RJA1118");
+
+        assert!(
+            "MSG,1,145,256,7404F2,11267,2008/11/28,23:48:18.611,2008/11/28,23:53:19.161,RJA 1118,,,,,,,,,,,"
+                .parse::<Message>()
+                .is_err()
+        );
+        assert!(
+            "SEL,,496,2286,4CA4E5,27215,2010/02/19,18:06:07.710,2010/02/19,18:06:07.710,"
+                .parse::<Message>()
+                .is_err()
+        );
+    }
+
     #[tokio::test]
     async fn it_decodes_a_stream() {
         let mut reader = Reader::new(EXAMPLE.as_bytes());
@@ -841,4 +1256,127 @@ MSG,8,496,194,405F4E,27884,2010/02/19,17:58:13.244,2010/02/19,17:58:13.368,,,,,,
             println!("{message:?}");
         }
     }
+
+    #[test]
+    fn it_decodes_in_chunks() {
+        let mut decoder = Decoder::default();
+        let mut count = 0;
+        for chunk in EXAMPLE.as_bytes().chunks(7) {
+            for message in decoder.decode_all(chunk) {
+                message.unwrap();
+                count += 1;
+            }
+        }
+        assert_eq!(count, EXAMPLE.lines().count());
+    }
+
+    #[test]
+    fn it_skips_long_lines() {
+        let mut data = vec![b'x'; 2000];
+        data.extend_from_slice(
+            b"\r\nCLK,,496,-1,,-1,2010/02/19,18:18:19.036,2010/02/19,18:18:19.036\r\n",
+        );
+
+        let mut decoder = Decoder::default();
+        let mut messages = data
+            .chunks(100)
+            .flat_map(|chunk| decoder.decode_all(chunk).collect::<Vec<_>>());
+        assert!(matches!(
+            messages.next(),
+            Some(Err(Error::Line(line::Error::MaxLineLengthExceeded)))
+        ));
+        assert!(matches!(messages.next(), Some(Ok(Message::Click { .. }))));
+        assert!(messages.next().is_none());
+    }
+
+    #[test]
+    fn it_reads_blocking() {
+        let messages = BlockingReader::new(EXAMPLE.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(messages.len(), EXAMPLE.lines().count());
+    }
+
+    #[test]
+    fn it_formats_like_readsb() {
+        for line in READSB_EXAMPLE.lines() {
+            let message: Message = line.parse().unwrap();
+            assert_eq!(message.to_string(), line);
+        }
+    }
+
+    #[test]
+    fn it_formats_other_message_types() {
+        for line in EXAMPLE.lines().filter(|line| !line.starts_with("MSG")) {
+            let message: Message = line.parse().unwrap();
+            assert_eq!(message.to_string(), line);
+        }
+    }
+
+    #[test]
+    fn it_parses_alert_emergency_and_spi() {
+        let line = READSB_EXAMPLE
+            .lines()
+            .find(|line| line.starts_with("MSG,6,"))
+            .unwrap();
+        let Message::Transmission {
+            transmission:
+                Transmission::SurveillanceIdMessage {
+                    squawk,
+                    alert,
+                    emergency,
+                    spi,
+                    ..
+                },
+            ..
+        } = line.parse().unwrap()
+        else {
+            panic!("not an id message");
+        };
+
+        assert_eq!(alert, Some(Alert::NoAlert));
+        assert_eq!(emergency, Some(Emergency::Emergency));
+        assert_eq!(spi, Some(Spi::NoIdent));
+        assert_eq!(emergency, Some(Emergency::from_squawk(squawk)));
+        assert_eq!(
+            emergency.unwrap().emergency_priority_status(Some(squawk)),
+            adsb::EmergencyPriorityStatus::GENERAL_EMERGENCY
+        );
+        assert_eq!(
+            Emergency::from(adsb::EmergencyPriorityStatus::NO_EMERGENCY),
+            Emergency::NoEmergency
+        );
+    }
+
+    #[test]
+    fn it_only_sets_emergency_for_emergency_squawk_statuses() {
+        for status in [
+            adsb::EmergencyPriorityStatus::GENERAL_EMERGENCY,
+            adsb::EmergencyPriorityStatus::NO_COMMUNICATIONS,
+            adsb::EmergencyPriorityStatus::UNLAWFUL_INTERFERENCE,
+        ] {
+            assert_eq!(Emergency::from(status), Emergency::Emergency);
+        }
+
+        for status in [
+            adsb::EmergencyPriorityStatus::NO_EMERGENCY,
+            adsb::EmergencyPriorityStatus::LIFEGUARD_MEDICAL_EMERGENCY,
+            adsb::EmergencyPriorityStatus::MINIMAL_FUEL,
+            adsb::EmergencyPriorityStatus::DOWNED_AIRCRAFT,
+        ] {
+            assert_eq!(Emergency::from(status), Emergency::NoEmergency);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_writes_lines() {
+        let mut writer = Writer::new(vec![]);
+        for line in READSB_EXAMPLE.lines() {
+            writer.feed(line.parse().unwrap()).await.unwrap();
+        }
+        writer.close().await.unwrap();
+
+        let written = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(written, READSB_EXAMPLE.replace('\n', "\r\n"));
+    }
 }
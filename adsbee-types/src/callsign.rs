@@ -0,0 +1,426 @@
+use std::{
+    fmt::{
+        Debug,
+        Display,
+    },
+    str::FromStr,
+};
+
+#[cfg(feature = "serde")]
+use serde_with::{
+    DeserializeFromStr,
+    SerializeDisplay,
+};
+
+/// A callsign (aircraft identification).
+///
+/// This is basically a small string (without heap allocation). Callsigns are
+/// up to 8 characters and padded with trailing spaces.
+///
+/// A callsign is valid, if it only contains uppercase letters and digits,
+/// followed by optional padding. Callsigns decoded permissively from
+/// transponder messages might not be valid. Use [`Callsign::validate`] to check
+/// them.
+///
+/// Displaying a callsign omits the padding.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(SerializeDisplay, DeserializeFromStr))]
+pub struct Callsign {
+    // note: we only ever fill this with valid ASCII characters
+    characters: [u8; Self::LENGTH],
+}
+
+impl Callsign {
+    pub const LENGTH: usize = 8;
+
+    /// Creates a callsign from its characters, without validating them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the bytes is not an ASCII character.
+    pub fn from_bytes_unchecked(characters: [u8; Self::LENGTH]) -> Self {
+        assert!(characters.is_ascii(), "callsign must be ASCII");
+        Self { characters }
+    }
+
+    /// Returns the callsign including the padding.
+    pub fn as_str(&self) -> &str {
+        // we check this, so we might use the unsafe variant here
+        std::str::from_utf8(&self.characters).expect("bug: invalid utf-8 in callsign")
+    }
+
+    /// Returns the callsign without the padding.
+    pub fn trimmed(&self) -> &str {
+        self.as_str().trim_end_matches(' ')
+    }
+
+    /// Checks that the callsign only contains valid characters and no
+    /// interior spaces.
+    pub fn validate(&self) -> Result<(), CallsignFromStrError> {
+        validate_callsign(&self.characters)
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.validate().is_ok()
+    }
+
+    /// Parses the callsign into airline designator and flight number.
+    ///
+    /// This returns `None` if the callsign isn't a valid callsign or doesn't
+    /// follow the `<3 letter ICAO designator><flight number>` scheme (e.g. a
+    /// registration).
+    pub fn flight_designator(&self) -> Option<FlightDesignator> {
+        self.is_valid()
+            .then(|| self.trimmed().parse().ok())
+            .flatten()
+    }
+}
+
+impl Debug for Callsign {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Callsign(\"{}\")", self.as_str())
+    }
+}
+
+impl Display for Callsign {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.trimmed())
+    }
+}
+
+impl FromStr for Callsign {
+    type Err = CallsignFromStrError;
+
+    /// Parses a callsign strictly.
+    ///
+    /// The callsign may be padded with trailing spaces, but may not contain any
+    /// other spaces.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let n = s.len();
+        if n > Self::LENGTH {
+            return Err(CallsignFromStrError::InvalidLength(n));
+        }
+
+        let mut characters = [b' '; Self::LENGTH];
+        for (i, c) in s.chars().enumerate() {
+            if !valid_callsign_char(c) {
+                return Err(CallsignFromStrError::InvalidChar {
+                    position: i,
+                    character: c,
+                });
+            }
+            characters[i] = c.try_into().unwrap();
+        }
+
+        validate_callsign(&characters)?;
+
+        Ok(Self { characters })
+    }
+}
+
+impl AsRef<[u8]> for Callsign {
+    fn as_ref(&self) -> &[u8] {
+        &self.characters[..]
+    }
+}
+
+impl AsRef<[u8; Self::LENGTH]> for Callsign {
+    fn as_ref(&self) -> &[u8; Self::LENGTH] {
+        &self.characters
+    }
+}
+
+impl AsRef<str> for Callsign {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum CallsignFromStrError {
+    #[error("Invalid character in callsign: '{character}' at position {position}")]
+    InvalidChar { position: usize, character: char },
+    #[error("Invalid length for callsign: {0}")]
+    InvalidLength(usize),
+    #[error("Interior space in callsign at position {position}")]
+    InteriorSpace { position: usize },
+    #[error("Empty callsign")]
+    Empty,
+}
+
+pub fn valid_callsign_char(c: char) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || c == ' '
+}
+
+fn validate_callsign(characters: &[u8; Callsign::LENGTH]) -> Result<(), CallsignFromStrError> {
+    let mut padding_start = None;
+
+    for (i, c) in characters.iter().enumerate() {
+        if !valid_callsign_char(*c as char) {
+            return Err(CallsignFromStrError::InvalidChar {
+                position: i,
+                character: *c as char,
+            });
+        }
+
+        if *c == b' ' {
+            padding_start.get_or_insert(i);
+        }
+        else if let Some(position) = padding_start {
+            return Err(CallsignFromStrError::InteriorSpace { position });
+        }
+    }
+
+    if padding_start == Some(0) {
+        Err(CallsignFromStrError::Empty)
+    }
+    else {
+        Ok(())
+    }
+}
+
+/// 3-letter ICAO airline designator (ICAO Doc 8585)
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(SerializeDisplay, DeserializeFromStr))]
+pub struct AirlineDesignator {
+    characters: [u8; 3],
+}
+
+impl AirlineDesignator {
+    pub fn from_bytes(characters: [u8; 3]) -> Option<Self> {
+        characters
+            .iter()
+            .all(u8::is_ascii_uppercase)
+            .then_some(Self { characters })
+    }
+
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.characters).expect("bug: invalid utf-8 in airline designator")
+    }
+}
+
+impl Display for AirlineDesignator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Debug for AirlineDesignator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AirlineDesignator({})", self.as_str())
+    }
+}
+
+impl FromStr for AirlineDesignator {
+    type Err = AirlineDesignatorFromStrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.as_bytes()
+            .try_into()
+            .ok()
+            .and_then(Self::from_bytes)
+            .ok_or_else(|| {
+                AirlineDesignatorFromStrError {
+                    input: s.to_owned(),
+                }
+            })
+    }
+}
+
+#[derive(Clone, Debug, thiserror::Error)]
+#[error("Invalid airline designator: {input}")]
+pub struct AirlineDesignatorFromStrError {
+    pub input: String,
+}
+
+/// Flight number following the airline designator in a callsign.
+///
+/// ICAO allows up to 4 characters, but since callsigns can be 8 characters
+/// long, we allow up to 5.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FlightNumber {
+    characters: [u8; Self::MAX_LENGTH],
+    length: u8,
+}
+
+impl FlightNumber {
+    pub const MAX_LENGTH: usize = Callsign::LENGTH - 3;
+
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.characters[..usize::from(self.length)])
+            .expect("bug: invalid utf-8 in flight number")
+    }
+}
+
+impl Display for FlightNumber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Debug for FlightNumber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "FlightNumber({})", self.as_str())
+    }
+}
+
+/// A callsign split into airline designator and flight number, e.g. `DLH4AB`
+/// is `DLH` / `4AB`.
+///
+/// The flight number must start with a digit and may only contain uppercase
+/// letters and digits. This rejects registrations like `DABCD`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(SerializeDisplay, DeserializeFromStr))]
+pub struct FlightDesignator {
+    pub airline: AirlineDesignator,
+    pub flight_number: FlightNumber,
+}
+
+impl Display for FlightDesignator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.airline, self.flight_number)
+    }
+}
+
+impl Debug for FlightDesignator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "FlightDesignator({} {})",
+            self.airline, self.flight_number
+        )
+    }
+}
+
+impl FromStr for FlightDesignator {
+    type Err = FlightDesignatorFromStrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || {
+            FlightDesignatorFromStrError {
+                input: s.to_owned(),
+            }
+        };
+
+        let bytes = s.as_bytes();
+        if bytes.len() < 4 || bytes.len() > Callsign::LENGTH {
+            return Err(err());
+        }
+
+        let airline =
+            AirlineDesignator::from_bytes([bytes[0], bytes[1], bytes[2]]).ok_or_else(err)?;
+
+        let flight_number = &bytes[3..];
+        if !flight_number[0].is_ascii_digit()
+            || !flight_number
+                .iter()
+                .all(|c| c.is_ascii_digit() || c.is_ascii_uppercase())
+        {
+            return Err(err());
+        }
+
+        let mut characters = [0; FlightNumber::MAX_LENGTH];
+        characters[..flight_number.len()].copy_from_slice(flight_number);
+
+        Ok(Self {
+            airline,
+            flight_number: FlightNumber {
+                characters,
+                length: flight_number.len() as u8,
+            },
+        })
+    }
+}
+
+#[derive(Clone, Debug, thiserror::Error)]
+#[error("Invalid flight designator: {input}")]
+pub struct FlightDesignatorFromStrError {
+    pub input: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        AirlineDesignator,
+        Callsign,
+        CallsignFromStrError,
+    };
+
+    #[test]
+    fn it_parses_valid_callsigns() {
+        let callsign: Callsign = "DLH4AB".parse().unwrap();
+        assert_eq!(callsign.as_str(), "DLH4AB  ");
+        assert_eq!(callsign.trimmed(), "DLH4AB");
+        assert_eq!(callsign.to_string(), "DLH4AB");
+
+        let padded: Callsign = "DLH4AB  ".parse().unwrap();
+        assert_eq!(callsign, padded);
+    }
+
+    #[test]
+    fn it_rejects_invalid_callsigns() {
+        assert_eq!(
+            "DLH 4AB".parse::<Callsign>().unwrap_err(),
+            CallsignFromStrError::InteriorSpace { position: 3 }
+        );
+        assert_eq!(
+            " DLH4AB".parse::<Callsign>().unwrap_err(),
+            CallsignFromStrError::InteriorSpace { position: 0 }
+        );
+        assert_eq!(
+            "dlh4ab".parse::<Callsign>().unwrap_err(),
+            CallsignFromStrError::InvalidChar {
+                position: 0,
+                character: 'd'
+            }
+        );
+        assert_eq!(
+            "DLH4AB@".parse::<Callsign>().unwrap_err(),
+            CallsignFromStrError::InvalidChar {
+                position: 6,
+                character: '@'
+            }
+        );
+        assert_eq!(
+            "".parse::<Callsign>().unwrap_err(),
+            CallsignFromStrError::Empty
+        );
+        assert_eq!(
+            "DLH4AB123".parse::<Callsign>().unwrap_err(),
+            CallsignFromStrError::InvalidLength(9)
+        );
+    }
+
+    #[test]
+    fn it_validates_unchecked_callsigns() {
+        assert!(Callsign::from_bytes_unchecked(*b"EZY67QN ").is_valid());
+        assert!(!Callsign::from_bytes_unchecked(*b"EZY 7QN ").is_valid());
+        assert!(!Callsign::from_bytes_unchecked(*b"EZY67QN@").is_valid());
+        assert!(!Callsign::from_bytes_unchecked(*b"        ").is_valid());
+    }
+
+    #[test]
+    fn it_parses_flight_designators() {
+        let callsign: Callsign = "DLH4AB".parse().unwrap();
+        let flight = callsign.flight_designator().unwrap();
+        assert_eq!(flight.airline, "DLH".parse::<AirlineDesignator>().unwrap());
+        assert_eq!(flight.flight_number.as_str(), "4AB");
+        assert_eq!(flight.to_string(), "DLH4AB");
+
+        let callsign: Callsign = "EZY67QN".parse().unwrap();
+        let flight = callsign.flight_designator().unwrap();
+        assert_eq!(flight.airline.as_str(), "EZY");
+        assert_eq!(flight.flight_number.as_str(), "67QN");
+    }
+
+    #[test]
+    fn it_doesnt_parse_registrations_as_flight_designators() {
+        for registration in ["DABCD", "N123AB", "GABCD", "HB1", "DLH"] {
+            let callsign: Callsign = registration.parse().unwrap();
+            assert!(
+                callsign.flight_designator().is_none(),
+                "{registration} parsed as flight designator"
+            );
+        }
+    }
+}
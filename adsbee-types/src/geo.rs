@@ -0,0 +1,595 @@
+//! Geodesy on the WGS84 ellipsoid
+//!
+//! All angles (latitude, longitude, bearing) are in degrees, all distances and
+//! altitudes are in m.
+//!
+//! - [`LatLon`]: distance, bearing and destination point. The spherical
+//!   (haversine) formulas are fast and accurate to about 0.5%, Vincenty's
+//!   formula is accurate to within a millimeter on the ellipsoid.
+//! - [`Geodetic`] and [`Ecef`]: conversion between geodetic and earth-centered,
+//!   earth-fixed coordinates.
+//! - [`EnuFrame`]: local east-north-up frame, e.g. around a receiver.
+//! - [`BoundingBox`]: latitude/longitude box that can wrap around the
+//!   antimeridian.
+//! - [`Polygon`]: latitude/longitude polygon, e.g. an area of interest.
+
+/// WGS84 semi-major axis in m
+pub const WGS84_A: f64 = 6378137.0;
+
+/// WGS84 flattening
+pub const WGS84_F: f64 = 1.0 / 298.257223563;
+
+/// WGS84 semi-minor axis in m
+pub const WGS84_B: f64 = WGS84_A * (1.0 - WGS84_F);
+
+/// WGS84 first eccentricity squared
+pub const WGS84_E2: f64 = WGS84_F * (2.0 - WGS84_F);
+
+/// Mean earth radius in m, used for spherical approximations
+pub const EARTH_MEAN_RADIUS: f64 = 6371008.8;
+
+/// Maximum number of iterations for Vincenty's formula
+const VINCENTY_MAX_ITERATIONS: usize = 200;
+
+/// Normalizes a longitude to [-180, 180).
+pub fn normalize_longitude(longitude: f64) -> f64 {
+    (longitude + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// Normalizes a bearing to [0, 360).
+pub fn normalize_bearing(bearing: f64) -> f64 {
+    bearing.rem_euclid(360.0)
+}
+
+/// A point on the earth's surface.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LatLon {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl LatLon {
+    pub const fn new(latitude: f64, longitude: f64) -> Self {
+        Self {
+            latitude,
+            longitude,
+        }
+    }
+
+    /// Great-circle distance in m, using the haversine formula on a sphere.
+    pub fn haversine_distance(&self, other: &LatLon) -> f64 {
+        let latitude1 = self.latitude.to_radians();
+        let latitude2 = other.latitude.to_radians();
+        let delta_latitude = latitude2 - latitude1;
+        let delta_longitude = (other.longitude - self.longitude).to_radians();
+
+        let a = (0.5 * delta_latitude).sin().powi(2)
+            + latitude1.cos() * latitude2.cos() * (0.5 * delta_longitude).sin().powi(2);
+        2.0 * EARTH_MEAN_RADIUS * a.sqrt().min(1.0).asin()
+    }
+
+    /// Geodesic distance in m on the WGS84 ellipsoid, using Vincenty's inverse
+    /// formula.
+    ///
+    /// Returns `None` if the formula doesn't converge, which can happen for
+    /// nearly antipodal points.
+    pub fn vincenty_distance(&self, other: &LatLon) -> Option<f64> {
+        let reduced_latitude1 = ((1.0 - WGS84_F) * self.latitude.to_radians().tan()).atan();
+        let reduced_latitude2 = ((1.0 - WGS84_F) * other.latitude.to_radians().tan()).atan();
+        let (sin_u1, cos_u1) = reduced_latitude1.sin_cos();
+        let (sin_u2, cos_u2) = reduced_latitude2.sin_cos();
+        let delta_longitude = (other.longitude - self.longitude).to_radians();
+
+        let mut lambda = delta_longitude;
+        for _ in 0..VINCENTY_MAX_ITERATIONS {
+            let (sin_lambda, cos_lambda) = lambda.sin_cos();
+            let sin_sigma =
+                (cos_u2 * sin_lambda).hypot(cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda);
+            if sin_sigma == 0.0 {
+                // coincident points
+                return Some(0.0);
+            }
+            let cos_sigma = sin_u1 * sin_u2 + cos_u1 * cos_u2 * cos_lambda;
+            let sigma = sin_sigma.atan2(cos_sigma);
+            let sin_alpha = cos_u1 * cos_u2 * sin_lambda / sin_sigma;
+            let cos2_alpha = 1.0 - sin_alpha * sin_alpha;
+            let cos_2sigma_m = if cos2_alpha == 0.0 {
+                // equatorial line
+                0.0
+            }
+            else {
+                cos_sigma - 2.0 * sin_u1 * sin_u2 / cos2_alpha
+            };
+            let c = WGS84_F / 16.0 * cos2_alpha * (4.0 + WGS84_F * (4.0 - 3.0 * cos2_alpha));
+
+            let previous_lambda = lambda;
+            lambda = delta_longitude
+                + (1.0 - c)
+                    * WGS84_F
+                    * sin_alpha
+                    * (sigma
+                        + c * sin_sigma
+                            * (cos_2sigma_m
+                                + c * cos_sigma * (-1.0 + 2.0 * cos_2sigma_m * cos_2sigma_m)));
+
+            if (lambda - previous_lambda).abs() < 1e-12 {
+                let u2 = cos2_alpha * (WGS84_A * WGS84_A - WGS84_B * WGS84_B) / (WGS84_B * WGS84_B);
+                let a = 1.0 + u2 / 16384.0 * (4096.0 + u2 * (-768.0 + u2 * (320.0 - 175.0 * u2)));
+                let b = u2 / 1024.0 * (256.0 + u2 * (-128.0 + u2 * (74.0 - 47.0 * u2)));
+                let delta_sigma = b
+                    * sin_sigma
+                    * (cos_2sigma_m
+                        + b / 4.0
+                            * (cos_sigma * (-1.0 + 2.0 * cos_2sigma_m * cos_2sigma_m)
+                                - b / 6.0
+                                    * cos_2sigma_m
+                                    * (-3.0 + 4.0 * sin_sigma * sin_sigma)
+                                    * (-3.0 + 4.0 * cos_2sigma_m * cos_2sigma_m)));
+                return Some(WGS84_B * a * (sigma - delta_sigma));
+            }
+        }
+
+        None
+    }
+
+    /// Initial bearing (forward azimuth) in degrees on the great circle to
+    /// `other`, clockwise from true north.
+    pub fn initial_bearing(&self, other: &LatLon) -> f64 {
+        let latitude1 = self.latitude.to_radians();
+        let latitude2 = other.latitude.to_radians();
+        let delta_longitude = (other.longitude - self.longitude).to_radians();
+
+        let y = delta_longitude.sin() * latitude2.cos();
+        let x = latitude1.cos() * latitude2.sin()
+            - latitude1.sin() * latitude2.cos() * delta_longitude.cos();
+        normalize_bearing(y.atan2(x).to_degrees())
+    }
+
+    /// Point reached by travelling `distance` (m) along the great circle with
+    /// initial `bearing` (degrees).
+    pub fn destination(&self, bearing: f64, distance: f64) -> LatLon {
+        let latitude = self.latitude.to_radians();
+        let longitude = self.longitude.to_radians();
+        let bearing = bearing.to_radians();
+        let angular_distance = distance / EARTH_MEAN_RADIUS;
+
+        let destination_latitude = (latitude.sin() * angular_distance.cos()
+            + latitude.cos() * angular_distance.sin() * bearing.cos())
+        .asin();
+        let destination_longitude = longitude
+            + (bearing.sin() * angular_distance.sin() * latitude.cos())
+                .atan2(angular_distance.cos() - latitude.sin() * destination_latitude.sin());
+
+        LatLon {
+            latitude: destination_latitude.to_degrees(),
+            longitude: normalize_longitude(destination_longitude.to_degrees()),
+        }
+    }
+}
+
+/// Geodetic coordinates on the WGS84 ellipsoid.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Geodetic {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Height above the ellipsoid in m
+    pub altitude: f64,
+}
+
+impl Geodetic {
+    pub const fn new(latitude: f64, longitude: f64, altitude: f64) -> Self {
+        Self {
+            latitude,
+            longitude,
+            altitude,
+        }
+    }
+
+    pub fn lat_lon(&self) -> LatLon {
+        LatLon {
+            latitude: self.latitude,
+            longitude: self.longitude,
+        }
+    }
+
+    pub fn to_ecef(&self) -> Ecef {
+        let (sin_latitude, cos_latitude) = self.latitude.to_radians().sin_cos();
+        let (sin_longitude, cos_longitude) = self.longitude.to_radians().sin_cos();
+        let prime_vertical_radius = WGS84_A / (1.0 - WGS84_E2 * sin_latitude * sin_latitude).sqrt();
+
+        Ecef {
+            x: (prime_vertical_radius + self.altitude) * cos_latitude * cos_longitude,
+            y: (prime_vertical_radius + self.altitude) * cos_latitude * sin_longitude,
+            z: (prime_vertical_radius * (1.0 - WGS84_E2) + self.altitude) * sin_latitude,
+        }
+    }
+}
+
+impl From<Geodetic> for LatLon {
+    fn from(value: Geodetic) -> Self {
+        value.lat_lon()
+    }
+}
+
+/// Earth-centered, earth-fixed cartesian coordinates in m.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ecef {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Ecef {
+    /// Converts to geodetic coordinates.
+    ///
+    /// This uses Heikkinen's closed-form solution, which is accurate to well
+    /// below a millimeter for points near the earth's surface.
+    pub fn to_geodetic(&self) -> Geodetic {
+        let a2 = WGS84_A * WGS84_A;
+        let b2 = WGS84_B * WGS84_B;
+        let e4 = WGS84_E2 * WGS84_E2;
+        let second_eccentricity2 = (a2 - b2) / b2;
+
+        let p = self.x.hypot(self.y);
+        let z2 = self.z * self.z;
+
+        let f = 54.0 * b2 * z2;
+        let g = p * p + (1.0 - WGS84_E2) * z2 - WGS84_E2 * (a2 - b2);
+        let c = e4 * f * p * p / (g * g * g);
+        let s = (1.0 + c + (c * c + 2.0 * c).sqrt()).cbrt();
+        let k = s + 1.0 + 1.0 / s;
+        let pp = f / (3.0 * k * k * g * g);
+        let q = (1.0 + 2.0 * e4 * pp).sqrt();
+        let r0 = -pp * WGS84_E2 * p / (1.0 + q)
+            + (0.5 * a2 * (1.0 + 1.0 / q)
+                - pp * (1.0 - WGS84_E2) * z2 / (q * (1.0 + q))
+                - 0.5 * pp * p * p)
+                .max(0.0)
+                .sqrt();
+        let u = (p - WGS84_E2 * r0).hypot(self.z);
+        let v = ((p - WGS84_E2 * r0).powi(2) + (1.0 - WGS84_E2) * z2).sqrt();
+        let z0 = b2 * self.z / (WGS84_A * v);
+
+        Geodetic {
+            latitude: (self.z + second_eccentricity2 * z0).atan2(p).to_degrees(),
+            longitude: self.y.atan2(self.x).to_degrees(),
+            altitude: u * (1.0 - b2 / (WGS84_A * v)),
+        }
+    }
+
+    /// Straight-line distance in m
+    pub fn distance(&self, other: &Ecef) -> f64 {
+        ((self.x - other.x).powi(2) + (self.y - other.y).powi(2) + (self.z - other.z).powi(2))
+            .sqrt()
+    }
+}
+
+impl From<Geodetic> for Ecef {
+    fn from(value: Geodetic) -> Self {
+        value.to_ecef()
+    }
+}
+
+impl From<Ecef> for Geodetic {
+    fn from(value: Ecef) -> Self {
+        value.to_geodetic()
+    }
+}
+
+/// Local east-north-up coordinates in m.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Enu {
+    pub east: f64,
+    pub north: f64,
+    pub up: f64,
+}
+
+impl Enu {
+    /// Slant range in m
+    pub fn range(&self) -> f64 {
+        self.east.hypot(self.north).hypot(self.up)
+    }
+
+    /// Azimuth in degrees, clockwise from true north
+    pub fn azimuth(&self) -> f64 {
+        normalize_bearing(self.east.atan2(self.north).to_degrees())
+    }
+
+    /// Elevation in degrees above the local horizon
+    pub fn elevation(&self) -> f64 {
+        self.up.atan2(self.east.hypot(self.north)).to_degrees()
+    }
+}
+
+/// Local east-north-up frame with the origin at a geodetic position.
+#[derive(Clone, Copy, Debug)]
+pub struct EnuFrame {
+    origin: Geodetic,
+    origin_ecef: Ecef,
+    /// Rows are the east, north and up unit vectors in ECEF.
+    rotation: [[f64; 3]; 3],
+}
+
+impl EnuFrame {
+    pub fn new(origin: Geodetic) -> Self {
+        let (sin_latitude, cos_latitude) = origin.latitude.to_radians().sin_cos();
+        let (sin_longitude, cos_longitude) = origin.longitude.to_radians().sin_cos();
+
+        Self {
+            origin,
+            origin_ecef: origin.to_ecef(),
+            rotation: [
+                [-sin_longitude, cos_longitude, 0.0],
+                [
+                    -sin_latitude * cos_longitude,
+                    -sin_latitude * sin_longitude,
+                    cos_latitude,
+                ],
+                [
+                    cos_latitude * cos_longitude,
+                    cos_latitude * sin_longitude,
+                    sin_latitude,
+                ],
+            ],
+        }
+    }
+
+    pub fn origin(&self) -> &Geodetic {
+        &self.origin
+    }
+
+    pub fn ecef_to_enu(&self, ecef: &Ecef) -> Enu {
+        let d = [
+            ecef.x - self.origin_ecef.x,
+            ecef.y - self.origin_ecef.y,
+            ecef.z - self.origin_ecef.z,
+        ];
+        let [east, north, up] = self
+            .rotation
+            .map(|row| row[0] * d[0] + row[1] * d[1] + row[2] * d[2]);
+        Enu { east, north, up }
+    }
+
+    pub fn enu_to_ecef(&self, enu: &Enu) -> Ecef {
+        let [e, n, u] = self.rotation;
+        let component = |i: usize| e[i] * enu.east + n[i] * enu.north + u[i] * enu.up;
+        Ecef {
+            x: self.origin_ecef.x + component(0),
+            y: self.origin_ecef.y + component(1),
+            z: self.origin_ecef.z + component(2),
+        }
+    }
+
+    pub fn geodetic_to_enu(&self, geodetic: &Geodetic) -> Enu {
+        self.ecef_to_enu(&geodetic.to_ecef())
+    }
+
+    pub fn enu_to_geodetic(&self, enu: &Enu) -> Geodetic {
+        self.enu_to_ecef(enu).to_geodetic()
+    }
+}
+
+/// Latitude/longitude box.
+///
+/// If `west` is greater than `east`, the box wraps around the antimeridian.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BoundingBox {
+    pub south: f64,
+    pub west: f64,
+    pub north: f64,
+    pub east: f64,
+}
+
+impl BoundingBox {
+    pub fn contains(&self, point: &LatLon) -> bool {
+        if point.latitude < self.south || point.latitude > self.north {
+            return false;
+        }
+
+        let west = normalize_longitude(self.west);
+        let east = normalize_longitude(self.east);
+        let longitude = normalize_longitude(point.longitude);
+
+        if west <= east {
+            longitude >= west && longitude <= east
+        }
+        else {
+            longitude >= west || longitude <= east
+        }
+    }
+
+    pub fn crosses_antimeridian(&self) -> bool {
+        normalize_longitude(self.west) > normalize_longitude(self.east)
+    }
+}
+
+/// Simple polygon with vertices in latitude/longitude.
+///
+/// Edges are straight lines in latitude/longitude, which is fine for areas of
+/// a few hundred km. The polygon is closed implicitly. Longitudes are taken
+/// relative to the first vertex, so polygons may cross the antimeridian, but
+/// must span less than 180 degrees of longitude.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct Polygon {
+    pub vertices: Vec<LatLon>,
+}
+
+impl Polygon {
+    /// Returns whether `point` is inside the polygon, using the even-odd rule.
+    pub fn contains(&self, point: &LatLon) -> bool {
+        let Some(origin) = self.vertices.first()
+        else {
+            return false;
+        };
+        let relative_longitude =
+            |point: &LatLon| normalize_longitude(point.longitude - origin.longitude);
+
+        let x = relative_longitude(point);
+        let y = point.latitude;
+        let mut inside = false;
+
+        let mut previous = self.vertices.last().expect("polygon has vertices");
+        for vertex in &self.vertices {
+            let (x1, y1) = (relative_longitude(previous), previous.latitude);
+            let (x2, y2) = (relative_longitude(vertex), vertex.latitude);
+
+            if (y1 > y) != (y2 > y) && x < x1 + (y - y1) / (y2 - y1) * (x2 - x1) {
+                inside = !inside;
+            }
+
+            previous = vertex;
+        }
+
+        inside
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geo::{
+        BoundingBox,
+        EnuFrame,
+        Geodetic,
+        LatLon,
+        Polygon,
+    };
+
+    fn dms(degrees: f64, minutes: f64, seconds: f64) -> f64 {
+        degrees.signum() * (degrees.abs() + minutes / 60.0 + seconds / 3600.0)
+    }
+
+    #[test]
+    fn it_computes_vincenty_distance() {
+        // Flinders Peak to Buninyong, from Vincenty's paper
+        let flinders_peak = LatLon::new(dms(-37.0, 57.0, 3.72030), dms(144.0, 25.0, 29.52440));
+        let buninyong = LatLon::new(dms(-37.0, 39.0, 10.15610), dms(143.0, 55.0, 35.38390));
+
+        let distance = flinders_peak.vincenty_distance(&buninyong).unwrap();
+        assert!((distance - 54972.271).abs() < 0.001, "{distance}");
+
+        let haversine = flinders_peak.haversine_distance(&buninyong);
+        assert!(
+            (haversine - distance).abs() / distance < 0.005,
+            "{haversine}"
+        );
+
+        // the bearing is spherical, so it's only close to the geodesic one
+        let bearing = flinders_peak.initial_bearing(&buninyong);
+        assert!((bearing - dms(306.0, 52.0, 5.37)).abs() < 0.2, "{bearing}");
+    }
+
+    #[test]
+    fn destination_is_inverse_of_distance_and_bearing() {
+        let start = LatLon::new(52.0, 13.0);
+        let destination = start.destination(45.0, 100_000.0);
+
+        assert!((start.haversine_distance(&destination) - 100_000.0).abs() < 1e-3);
+        assert!((start.initial_bearing(&destination) - 45.0).abs() < 1e-9);
+
+        // across the antimeridian
+        let destination = LatLon::new(0.0, 179.5).destination(90.0, 111_195.0);
+        assert!(
+            (destination.longitude + 179.5).abs() < 1e-3,
+            "{destination:?}"
+        );
+    }
+
+    #[test]
+    fn it_converts_between_geodetic_and_ecef() {
+        for geodetic in [
+            Geodetic::new(52.0, 13.0, 10_000.0),
+            Geodetic::new(-33.9, -70.6, 500.0),
+            Geodetic::new(89.9, 179.0, 0.0),
+            Geodetic::new(0.0, 0.0, 0.0),
+        ] {
+            let converted = geodetic.to_ecef().to_geodetic();
+            assert!((converted.latitude - geodetic.latitude).abs() < 1e-9);
+            assert!((converted.longitude - geodetic.longitude).abs() < 1e-9);
+            assert!((converted.altitude - geodetic.altitude).abs() < 1e-3);
+        }
+
+        let ecef = Geodetic::new(0.0, 0.0, 0.0).to_ecef();
+        assert!((ecef.x - 6378137.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn it_converts_to_enu() {
+        let frame = EnuFrame::new(Geodetic::new(52.0, 13.0, 100.0));
+
+        let above = frame.geodetic_to_enu(&Geodetic::new(52.0, 13.0, 1100.0));
+        assert!(above.east.abs() < 1e-6 && above.north.abs() < 1e-6);
+        assert!((above.up - 1000.0).abs() < 1e-6);
+        assert!((above.elevation() - 90.0).abs() < 1e-6);
+
+        let east = frame.geodetic_to_enu(&Geodetic::new(52.0, 13.1, 100.0));
+        assert!((east.azimuth() - 90.0).abs() < 0.1, "{east:?}");
+        assert!(east.up < 0.0);
+
+        let geodetic = frame.enu_to_geodetic(&east);
+        assert!((geodetic.longitude - 13.1).abs() < 1e-9);
+        assert!((geodetic.altitude - 100.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn bbox_handles_antimeridian() {
+        let bbox = BoundingBox {
+            south: -10.0,
+            west: 170.0,
+            north: 10.0,
+            east: -170.0,
+        };
+        assert!(bbox.crosses_antimeridian());
+        assert!(bbox.contains(&LatLon::new(0.0, 175.0)));
+        assert!(bbox.contains(&LatLon::new(0.0, -175.0)));
+        assert!(bbox.contains(&LatLon::new(0.0, 180.0)));
+        assert!(!bbox.contains(&LatLon::new(0.0, 0.0)));
+        assert!(!bbox.contains(&LatLon::new(20.0, 175.0)));
+
+        let bbox = BoundingBox {
+            south: 50.0,
+            west: 5.0,
+            north: 55.0,
+            east: 15.0,
+        };
+        assert!(!bbox.crosses_antimeridian());
+        assert!(bbox.contains(&LatLon::new(52.0, 13.0)));
+        assert!(!bbox.contains(&LatLon::new(52.0, 16.0)));
+    }
+    #[test]
+    fn polygon_contains_points() {
+        // triangle around Berlin
+        let polygon = Polygon {
+            vertices: vec![
+                LatLon::new(52.0, 12.5),
+                LatLon::new(53.0, 13.5),
+                LatLon::new(52.0, 14.5),
+            ],
+        };
+        assert!(polygon.contains(&LatLon::new(52.3, 13.5)));
+        assert!(!polygon.contains(&LatLon::new(52.9, 12.7)));
+        assert!(!polygon.contains(&LatLon::new(51.9, 13.5)));
+
+        // square across the antimeridian
+        let polygon = Polygon {
+            vertices: vec![
+                LatLon::new(-10.0, 170.0),
+                LatLon::new(10.0, 170.0),
+                LatLon::new(10.0, -170.0),
+                LatLon::new(-10.0, -170.0),
+            ],
+        };
+        assert!(polygon.contains(&LatLon::new(0.0, 175.0)));
+        assert!(polygon.contains(&LatLon::new(0.0, -175.0)));
+        assert!(!polygon.contains(&LatLon::new(0.0, 0.0)));
+
+        assert!(!Polygon { vertices: vec![] }.contains(&LatLon::new(0.0, 0.0)));
+    }
+}
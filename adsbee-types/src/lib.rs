@@ -1,5 +1,8 @@
+mod callsign;
+pub mod geo;
 #[cfg(feature = "sqlx")]
 mod sqlx;
+pub mod wmm;
 
 use std::{
     fmt::{
@@ -15,6 +18,17 @@ use serde_with::{
     SerializeDisplay,
 };
 
+pub use crate::callsign::{
+    AirlineDesignator,
+    AirlineDesignatorFromStrError,
+    Callsign,
+    CallsignFromStrError,
+    FlightDesignator,
+    FlightDesignatorFromStrError,
+    FlightNumber,
+    valid_callsign_char,
+};
+
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[cfg_attr(feature = "serde", derive(SerializeDisplay, DeserializeFromStr))]
 pub struct IcaoAddress {
@@ -0,0 +1,360 @@
+//! World Magnetic Model
+//!
+//! Computes the geomagnetic field from a spherical harmonic model. This is used
+//! to convert between magnetic and true headings.
+//!
+//! The embedded coefficients are WMM2025, which is valid from 2025.0 to 2030.0.
+//! Outside this range the secular variation is extrapolated, which gets less
+//! accurate the further away from the epoch.
+//!
+//! <https://www.ncei.noaa.gov/products/world-magnetic-model>
+
+use crate::geo::{
+    WGS84_A,
+    WGS84_E2,
+};
+
+/// Geomagnetic reference radius in km
+const REFERENCE_RADIUS: f64 = 6371.2;
+
+/// A spherical harmonic model of the geomagnetic field.
+#[derive(Clone, Copy, Debug)]
+pub struct MagneticModel {
+    /// Epoch of the model as decimal year
+    pub epoch: f64,
+    /// Model is valid until this decimal year
+    pub valid_until: f64,
+    degree: usize,
+    coefficients: &'static [Coefficient],
+}
+
+/// Gauss coefficients `g` and `h` (nT) and their secular variation (nT/year)
+/// for degree `n` and order `m`.
+#[derive(Clone, Copy, Debug)]
+struct Coefficient {
+    n: usize,
+    m: usize,
+    g: f64,
+    h: f64,
+    g_dot: f64,
+    h_dot: f64,
+}
+
+impl MagneticModel {
+    /// Computes the magnetic field.
+    ///
+    /// # Arguments
+    ///
+    /// - `latitude`, `longitude`: geodetic coordinates in degrees
+    /// - `altitude`: height above the WGS84 ellipsoid in m
+    /// - `year`: time as decimal year (e.g. 2025.5)
+    pub fn field(&self, latitude: f64, longitude: f64, altitude: f64, year: f64) -> MagneticField {
+        let dt = year - self.epoch;
+        // the model uses km
+        let semi_major_axis = WGS84_A / 1000.0;
+        let altitude = altitude / 1000.0;
+
+        // geodetic to geocentric spherical coordinates
+        let latitude = latitude.to_radians();
+        let longitude = longitude.to_radians();
+        let (sin_latitude, cos_latitude) = latitude.sin_cos();
+        let prime_vertical_radius =
+            semi_major_axis / (1.0 - WGS84_E2 * sin_latitude * sin_latitude).sqrt();
+        let p = (prime_vertical_radius + altitude) * cos_latitude;
+        let z = (prime_vertical_radius * (1.0 - WGS84_E2) + altitude) * sin_latitude;
+        let radius = p.hypot(z);
+        let latitude_geocentric = (z / radius).asin();
+
+        // sine and cosine of the colatitude. this is clamped, since the east component
+        // is undefined at the poles.
+        let sin_theta = latitude_geocentric.cos().max(1e-10);
+        let cos_theta = latitude_geocentric.sin();
+
+        let size = self.degree + 1;
+        let mut p = vec![0.0; size * size];
+        let mut dp = vec![0.0; size * size];
+        let index = |n: usize, m: usize| n * size + m;
+
+        // Gauss-normalized associated Legendre functions and their derivatives
+        // with respect to colatitude
+        p[index(0, 0)] = 1.0;
+        for n in 1..size {
+            for m in 0..=n {
+                if n == m {
+                    p[index(n, m)] = sin_theta * p[index(n - 1, m - 1)];
+                    dp[index(n, m)] =
+                        cos_theta * p[index(n - 1, m - 1)] + sin_theta * dp[index(n - 1, m - 1)];
+                }
+                else {
+                    let (p2, dp2, k) = if n >= 2 && m <= n - 2 {
+                        let k =
+                            ((n - 1) * (n - 1) - m * m) as f64 / ((2 * n - 1) * (2 * n - 3)) as f64;
+                        (p[index(n - 2, m)], dp[index(n - 2, m)], k)
+                    }
+                    else {
+                        (0.0, 0.0, 0.0)
+                    };
+                    p[index(n, m)] = cos_theta * p[index(n - 1, m)] - k * p2;
+                    dp[index(n, m)] =
+                        cos_theta * dp[index(n - 1, m)] - sin_theta * p[index(n - 1, m)] - k * dp2;
+                }
+            }
+        }
+
+        // Schmidt semi-normalization factors
+        let mut schmidt = vec![0.0; size * size];
+        schmidt[index(0, 0)] = 1.0;
+        for n in 1..size {
+            schmidt[index(n, 0)] = schmidt[index(n - 1, 0)] * (2 * n - 1) as f64 / n as f64;
+            for m in 1..=n {
+                let factor = if m == 1 { 2.0 } else { 1.0 };
+                schmidt[index(n, m)] = schmidt[index(n, m - 1)]
+                    * (factor * (n - m + 1) as f64 / (n + m) as f64).sqrt();
+            }
+        }
+
+        let mut b_radial = 0.0;
+        let mut b_theta = 0.0;
+        let mut b_phi = 0.0;
+        let ratio = REFERENCE_RADIUS / radius;
+
+        for coefficient in self.coefficients {
+            let Coefficient { n, m, .. } = *coefficient;
+            let s = schmidt[index(n, m)];
+            let g = s * (coefficient.g + dt * coefficient.g_dot);
+            let h = s * (coefficient.h + dt * coefficient.h_dot);
+            let (sin_m_longitude, cos_m_longitude) = (m as f64 * longitude).sin_cos();
+            let scale = ratio.powi(n as i32 + 2);
+
+            let a = g * cos_m_longitude + h * sin_m_longitude;
+            b_radial += scale * (n + 1) as f64 * a * p[index(n, m)];
+            b_theta -= scale * a * dp[index(n, m)];
+            b_phi +=
+                scale * m as f64 * (g * sin_m_longitude - h * cos_m_longitude) * p[index(n, m)];
+        }
+        b_phi /= sin_theta;
+
+        // rotate from geocentric to geodetic
+        let north_geocentric = -b_theta;
+        let down_geocentric = -b_radial;
+        let (sin_psi, cos_psi) = (latitude_geocentric - latitude).sin_cos();
+
+        MagneticField {
+            north: north_geocentric * cos_psi - down_geocentric * sin_psi,
+            east: b_phi,
+            down: north_geocentric * sin_psi + down_geocentric * cos_psi,
+        }
+    }
+
+    /// Magnetic declination in degrees, positive east.
+    ///
+    /// See [`field`](Self::field) for the arguments.
+    pub fn declination(&self, latitude: f64, longitude: f64, altitude: f64, year: f64) -> f64 {
+        self.field(latitude, longitude, altitude, year)
+            .declination()
+    }
+}
+
+/// Geomagnetic field vector in nT
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MagneticField {
+    pub north: f64,
+    pub east: f64,
+    pub down: f64,
+}
+
+impl MagneticField {
+    /// Declination in degrees, positive east.
+    ///
+    /// This is the angle from true north to magnetic north.
+    pub fn declination(&self) -> f64 {
+        self.east.atan2(self.north).to_degrees()
+    }
+
+    /// Inclination in degrees, positive down.
+    pub fn inclination(&self) -> f64 {
+        self.down.atan2(self.horizontal_intensity()).to_degrees()
+    }
+
+    pub fn horizontal_intensity(&self) -> f64 {
+        self.north.hypot(self.east)
+    }
+
+    pub fn total_intensity(&self) -> f64 {
+        self.horizontal_intensity().hypot(self.down)
+    }
+}
+
+/// Magnetic declination in degrees from [`WMM2025`].
+///
+/// See [`MagneticModel::field`] for the arguments.
+pub fn declination(latitude: f64, longitude: f64, altitude: f64, year: f64) -> f64 {
+    WMM2025.declination(latitude, longitude, altitude, year)
+}
+
+/// Converts a time to a decimal year, as used by [`MagneticModel`].
+#[cfg(feature = "chrono")]
+pub fn decimal_year(time: &chrono::DateTime<chrono::Utc>) -> f64 {
+    use chrono::{
+        Datelike,
+        NaiveDate,
+    };
+
+    let year = time.year();
+    let start = NaiveDate::from_ymd_opt(year, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc();
+    let end = NaiveDate::from_ymd_opt(year + 1, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc();
+
+    year as f64 + (*time - start).as_seconds_f64() / (end - start).as_seconds_f64()
+}
+
+macro_rules! coefficients {
+    ($(($n:literal, $m:literal, $g:literal, $h:literal, $g_dot:literal, $h_dot:literal),)*) => {
+        &[$(Coefficient { n: $n, m: $m, g: $g, h: $h, g_dot: $g_dot, h_dot: $h_dot },)*]
+    };
+}
+
+/// World Magnetic Model 2025
+pub const WMM2025: MagneticModel = MagneticModel {
+    epoch: 2025.0,
+    valid_until: 2030.0,
+    degree: 12,
+    coefficients: coefficients![
+        (1, 0, -29351.8, 0.0, 12.0, 0.0),
+        (1, 1, -1410.8, 4545.4, 9.7, -21.5),
+        (2, 0, -2556.6, 0.0, -11.6, 0.0),
+        (2, 1, 2951.1, -3133.6, -5.2, -27.7),
+        (2, 2, 1649.3, -815.1, -8.0, -12.1),
+        (3, 0, 1361.0, 0.0, -1.3, 0.0),
+        (3, 1, -2404.1, -56.6, -4.2, 4.0),
+        (3, 2, 1243.8, 237.5, 0.4, -0.3),
+        (3, 3, 453.6, -549.5, -15.6, -4.1),
+        (4, 0, 895.0, 0.0, -1.6, 0.0),
+        (4, 1, 799.5, 278.6, -2.4, -1.1),
+        (4, 2, 55.7, -133.9, -6.0, 4.1),
+        (4, 3, -281.1, 212.0, 5.6, 1.6),
+        (4, 4, 12.1, -375.6, -7.0, -4.4),
+        (5, 0, -233.2, 0.0, 0.6, 0.0),
+        (5, 1, 368.9, 45.4, 1.4, -0.5),
+        (5, 2, 187.2, 220.2, 0.0, 2.2),
+        (5, 3, -138.7, -122.9, 0.6, 0.4),
+        (5, 4, -142.0, 43.0, 2.2, 1.7),
+        (5, 5, 20.9, 106.1, 0.9, 1.9),
+        (6, 0, 64.4, 0.0, -0.2, 0.0),
+        (6, 1, 63.8, -18.4, -0.4, 0.3),
+        (6, 2, 76.9, 16.8, 0.9, -1.6),
+        (6, 3, -115.7, 48.8, 1.2, -0.4),
+        (6, 4, -40.9, -59.8, -0.9, 0.9),
+        (6, 5, 14.9, 10.9, 0.3, 0.7),
+        (6, 6, -60.7, 72.7, 0.9, 0.9),
+        (7, 0, 79.5, 0.0, 0.0, 0.0),
+        (7, 1, -77.0, -48.9, -0.1, 0.6),
+        (7, 2, -8.8, -14.4, -0.1, 0.5),
+        (7, 3, 59.3, -1.0, 0.5, -0.8),
+        (7, 4, 15.8, 23.4, -0.1, 0.0),
+        (7, 5, 2.5, -7.4, -0.8, -1.0),
+        (7, 6, -11.1, -25.1, -0.8, 0.6),
+        (7, 7, 14.2, -2.3, 0.8, -0.2),
+        (8, 0, 23.2, 0.0, -0.1, 0.0),
+        (8, 1, 10.8, 7.1, 0.2, -0.2),
+        (8, 2, -17.5, -12.6, 0.0, 0.5),
+        (8, 3, 2.0, 11.4, 0.5, -0.4),
+        (8, 4, -21.7, -9.7, -0.1, 0.4),
+        (8, 5, 16.9, 12.7, 0.3, -0.5),
+        (8, 6, 15.0, 0.7, 0.2, -0.6),
+        (8, 7, -16.8, -5.2, 0.0, 0.3),
+        (8, 8, 0.9, 3.9, 0.2, 0.2),
+        (9, 0, 4.6, 0.0, 0.0, 0.0),
+        (9, 1, 7.8, -24.8, -0.1, -0.3),
+        (9, 2, 3.0, 12.2, 0.1, 0.3),
+        (9, 3, -0.2, 8.3, 0.3, -0.3),
+        (9, 4, -2.5, -3.3, -0.3, 0.3),
+        (9, 5, -13.1, -5.2, 0.0, 0.2),
+        (9, 6, 2.4, 7.2, 0.3, -0.1),
+        (9, 7, 8.6, -0.6, -0.1, -0.2),
+        (9, 8, -8.7, 0.8, 0.1, 0.4),
+        (9, 9, -12.9, 10.0, -0.1, 0.1),
+        (10, 0, -1.3, 0.0, 0.1, 0.0),
+        (10, 1, -6.4, 3.3, 0.0, 0.0),
+        (10, 2, 0.2, 0.0, 0.1, 0.0),
+        (10, 3, 2.0, 2.4, 0.1, -0.2),
+        (10, 4, -1.0, 5.3, 0.0, 0.1),
+        (10, 5, -0.6, -9.1, -0.3, -0.1),
+        (10, 6, -0.9, 0.4, 0.0, 0.1),
+        (10, 7, 1.5, -4.2, -0.1, 0.0),
+        (10, 8, 0.9, -3.8, -0.1, -0.1),
+        (10, 9, -2.7, 0.9, 0.0, 0.2),
+        (10, 10, -3.9, -9.1, 0.0, 0.0),
+        (11, 0, 2.9, 0.0, 0.0, 0.0),
+        (11, 1, -1.5, 0.0, 0.0, 0.0),
+        (11, 2, -2.5, 2.9, 0.0, 0.1),
+        (11, 3, 2.4, -0.6, 0.0, 0.0),
+        (11, 4, -0.6, 0.2, 0.0, 0.1),
+        (11, 5, -0.1, 0.5, -0.1, 0.0),
+        (11, 6, -0.6, -0.3, 0.0, 0.0),
+        (11, 7, -0.1, -1.2, 0.0, 0.1),
+        (11, 8, 1.1, -1.7, -0.1, 0.0),
+        (11, 9, -1.0, -2.9, -0.1, 0.0),
+        (11, 10, -0.2, -1.8, -0.1, 0.0),
+        (11, 11, 2.6, -2.3, -0.1, 0.0),
+        (12, 0, -2.0, 0.0, 0.0, 0.0),
+        (12, 1, -0.2, -1.3, 0.0, 0.0),
+        (12, 2, 0.3, 0.7, 0.0, 0.0),
+        (12, 3, 1.2, 1.0, 0.0, -0.1),
+        (12, 4, -1.3, -1.4, 0.0, 0.1),
+        (12, 5, 0.6, 0.0, 0.0, 0.0),
+        (12, 6, 0.6, 0.6, 0.1, 0.0),
+        (12, 7, 0.5, -0.1, 0.0, 0.0),
+        (12, 8, -0.1, 0.8, 0.0, 0.0),
+        (12, 9, -0.4, 0.1, 0.0, 0.0),
+        (12, 10, -0.2, -1.0, -0.1, 0.0),
+        (12, 11, -1.3, 0.1, 0.0, 0.0),
+        (12, 12, -0.7, 0.2, -0.1, -0.1),
+    ],
+};
+
+#[cfg(test)]
+mod tests {
+    use crate::wmm::{
+        WMM2025,
+        declination,
+    };
+
+    #[test]
+    fn it_matches_test_values() {
+        // from the WMM2025 report
+        let field = WMM2025.field(80.0, 0.0, 0.0, 2025.0);
+        assert!((field.north - 6521.6).abs() < 0.5, "{field:?}");
+        assert!((field.east - 145.9).abs() < 0.5, "{field:?}");
+        assert!((field.down - 54791.5).abs() < 0.5, "{field:?}");
+
+        assert!((declination(80.0, 0.0, 0.0, 2025.0) - 1.28).abs() < 0.01);
+        assert!((declination(0.0, 120.0, 0.0, 2025.0) + 0.16).abs() < 0.01);
+        assert!((declination(-80.0, 240.0, 0.0, 2025.0) - 68.78).abs() < 0.01);
+    }
+
+    #[test]
+    fn it_computes_declination() {
+        for (latitude, longitude, expected) in [
+            // Berlin
+            (52.52, 13.40, 4.9),
+            // New York
+            (40.71, -74.01, -12.5),
+            // San Francisco
+            (37.77, -122.42, 13.0),
+        ] {
+            let declination = declination(latitude, longitude, 0.0, 2025.0);
+            assert!(
+                (declination - expected).abs() < 0.5,
+                "declination at {latitude}, {longitude}: expected {expected}, got {declination}"
+            );
+        }
+    }
+}